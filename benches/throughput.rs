@@ -0,0 +1,64 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use redlike::command::Command;
+use redlike::frame::Frame;
+use redlike::parser::{ParseResult, Parser};
+use std::hint::black_box;
+
+/// The size of each socket read the benchmarks simulate.
+const READ_SIZE: usize = 16 * 1024;
+
+fn set_request(key: &[u8], value: &[u8]) -> Vec<u8> {
+    Frame::Array(Some(vec![
+        Frame::Bulk(Some(b"SET".as_slice().into())),
+        Frame::Bulk(Some(key.to_vec().into())),
+        Frame::Bulk(Some(value.to_vec().into())),
+    ]))
+    .to_bytes()
+}
+
+/// Feeds `input` to a fresh parser in `READ_SIZE` chunks, converting every
+/// frame into a command, and returns how many commands were parsed.
+fn parse_commands(input: &[u8]) -> usize {
+    let mut parser = Parser::new();
+    let mut parsed = 0;
+    for chunk in input.chunks(READ_SIZE) {
+        parser.buffer_mut().extend_from_slice(chunk);
+        let ParseResult::Complete(frames) = parser.parse_buffered() else {
+            panic!("benchmark input failed to parse");
+        };
+        for frame in frames {
+            black_box(Command::try_from(frame).expect("benchmark input is a valid command"));
+            parsed += 1;
+        }
+    }
+    parsed
+}
+
+fn large_values(c: &mut Criterion) {
+    let mut group = c.benchmark_group("large_values");
+    for size in [64 * 1024, 1024 * 1024, 16 * 1024 * 1024] {
+        let input = set_request(b"key", &vec![b'x'; size]);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &input, |b, input| {
+            b.iter(|| assert_eq!(parse_commands(input), 1));
+        });
+    }
+    group.finish();
+}
+
+fn deep_pipelines(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deep_pipelines");
+    for depth in [100, 10_000, 100_000] {
+        let input: Vec<u8> = (0..depth)
+            .flat_map(|i| set_request(format!("key:{i}").as_bytes(), b"value"))
+            .collect();
+        group.throughput(Throughput::Elements(depth as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &input, |b, input| {
+            b.iter(|| assert_eq!(parse_commands(input), depth));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, large_values, deep_pipelines);
+criterion_main!(benches);
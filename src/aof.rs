@@ -0,0 +1,821 @@
+use crate::archive::{blocking, replace_with, temp_file_beside};
+use crate::command::Command;
+use crate::connection::Connection;
+use crate::error::Error;
+use crate::frame::Frame;
+use crate::parser::{ParseError, Parser, ParserLimits};
+use crate::pubsub::Hub;
+use crate::sorted_set::format_score;
+use crate::store::{ListEnd, ServedPop, Store, StoreEntry, Value};
+use bytes::Bytes;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::{fmt, io};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+use tokio_util::sync::CancellationToken;
+
+/// When writes to the append-only file are flushed to disk, as Redis's
+/// `appendfsync`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AppendFsync {
+    /// After every write, before replying.
+    Always,
+    /// Once a second in the background, so at most a second of writes is
+    /// lost in a crash.
+    #[default]
+    EverySec,
+    /// Whenever the operating system decides to.
+    No,
+}
+
+impl FromStr for AppendFsync {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy.to_ascii_lowercase().as_str() {
+            "always" => Ok(AppendFsync::Always),
+            "everysec" => Ok(AppendFsync::EverySec),
+            "no" => Ok(AppendFsync::No),
+            other => Err(format!("unknown appendfsync policy '{other}'")),
+        }
+    }
+}
+
+impl fmt::Display for AppendFsync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppendFsync::Always => write!(f, "always"),
+            AppendFsync::EverySec => write!(f, "everysec"),
+            AppendFsync::No => write!(f, "no"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AofError {
+    ReadFile(io::Error),
+    WriteFile(io::Error),
+    InvalidLog(ParseError),
+    InvalidCommand(Error),
+}
+
+impl fmt::Display for AofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AofError::ReadFile(_) => write!(f, "Unable to read append-only file"),
+            AofError::WriteFile(_) => write!(f, "Unable to write to append-only file"),
+            AofError::InvalidLog(_) => write!(f, "Invalid append-only file format"),
+            AofError::InvalidCommand(e) => {
+                write!(f, "Invalid command in append-only file: {e}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AofError {}
+
+/// The default for [`AutoRewrite::percentage`].
+pub const DEFAULT_AUTO_AOF_REWRITE_PERCENTAGE: u64 = 100;
+/// The default for [`AutoRewrite::min_size`].
+pub const DEFAULT_AUTO_AOF_REWRITE_MIN_SIZE: u64 = 64 * 1024 * 1024;
+
+/// How many elements one command adds when rewriting a collection.
+const REWRITE_ITEMS_PER_COMMAND: usize = 64;
+
+/// When the log is rewritten without a `BGREWRITEAOF`, as Redis's
+/// `auto-aof-rewrite-percentage` and `auto-aof-rewrite-min-size`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoRewrite {
+    /// How far the log may grow past its size after the last rewrite, in
+    /// percent. Zero turns automatic rewrites off.
+    pub percentage: u64,
+    /// The smallest log, in bytes, that is rewritten automatically.
+    pub min_size: u64,
+}
+
+impl Default for AutoRewrite {
+    fn default() -> Self {
+        AutoRewrite {
+            percentage: DEFAULT_AUTO_AOF_REWRITE_PERCENTAGE,
+            min_size: DEFAULT_AUTO_AOF_REWRITE_MIN_SIZE,
+        }
+    }
+}
+
+impl AutoRewrite {
+    fn is_due(&self, size: u64, base_size: u64) -> bool {
+        self.percentage > 0
+            && size >= self.min_size
+            && (size.saturating_sub(base_size)).saturating_mul(100)
+                >= base_size.saturating_mul(self.percentage)
+    }
+}
+
+/// An append-only log of every write to a [`Store`], in RESP.
+///
+/// Connections take the log with [`AppendOnlyFile::lock`] before running a
+/// write and hold it until the write is recorded, so the log has writes in
+/// the order they were made.
+#[derive(Clone)]
+pub struct AppendOnlyFile {
+    state: Arc<Mutex<AofState>>,
+    path: Arc<PathBuf>,
+    store: Store,
+    fsync: AppendFsync,
+    auto_rewrite: AutoRewrite,
+    /// Set when writes have been made since the last background fsync.
+    unsynced: Arc<AtomicBool>,
+    rewriting: Arc<AtomicBool>,
+}
+
+struct AofState {
+    file: File,
+    /// The size of the log in bytes.
+    size: u64,
+    /// The size of the log after the last rewrite, or when it was opened.
+    base_size: u64,
+    /// Writes made while a rewrite runs, to be added to the rewritten log.
+    rewrite_buffer: Option<Vec<u8>>,
+}
+
+impl AppendOnlyFile {
+    /// Opens the log of `store` at `path` for appending, creating it if
+    /// missing.
+    ///
+    /// With [`AppendFsync::EverySec`], a background task flushes it to disk
+    /// until the last handle to it is dropped.
+    pub async fn open(path: &Path, fsync: AppendFsync, store: Store) -> io::Result<AppendOnlyFile> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        let size = file.metadata().await?.len();
+        let aof = AppendOnlyFile {
+            state: Arc::new(Mutex::new(AofState {
+                file,
+                size,
+                base_size: size,
+                rewrite_buffer: None,
+            })),
+            path: Arc::new(path.to_path_buf()),
+            store,
+            fsync,
+            auto_rewrite: AutoRewrite::default(),
+            unsynced: Arc::new(AtomicBool::new(false)),
+            rewriting: Arc::new(AtomicBool::new(false)),
+        };
+        if fsync == AppendFsync::EverySec {
+            tokio::spawn(sync_every_second(
+                Arc::downgrade(&aof.state),
+                aof.unsynced.clone(),
+            ));
+        }
+        Ok(aof)
+    }
+
+    /// Replaces the default thresholds for rewriting the log automatically.
+    pub fn with_auto_rewrite(mut self, auto_rewrite: AutoRewrite) -> Self {
+        self.auto_rewrite = auto_rewrite;
+        self
+    }
+
+    /// Waits for any other write being logged and returns the log.
+    pub async fn lock(&self) -> AofLog {
+        AofLog {
+            state: self.state.clone().lock_owned().await,
+            aof: self.clone(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Flushes everything written so far to disk.
+    pub async fn sync(&self) -> io::Result<()> {
+        self.state.lock().await.file.sync_data().await
+    }
+
+    /// Starts rewriting the log in the background, or returns `None` if a
+    /// rewrite is already running.
+    pub fn start_rewrite(&self) -> Option<JoinHandle<Result<(), AofError>>> {
+        if self.rewriting.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        let aof = self.clone();
+        Some(tokio::spawn(async move {
+            let result = aof.rewrite().await;
+            if let Err(e) = &result {
+                println!("append-only file rewrite failed: {:?}", e);
+            }
+            aof.rewriting.store(false, Ordering::Release);
+            result
+        }))
+    }
+
+    /// Replaces the log with the fewest commands that rebuild the store as
+    /// it is now, followed by any writes logged while that was being written.
+    async fn rewrite(&self) -> Result<(), AofError> {
+        let mut state = self.state.lock().await;
+        state.rewrite_buffer = Some(Vec::new());
+        // No write is half way through being logged while the log is held,
+        // and the store holds off new ones before the log is released, so
+        // the copy matches the end of the log. Writes only wait for the copy;
+        // from then on they are buffered.
+        let entries = self.store.entries_once_locked(move || drop(state)).await;
+        let result = self.replace_log(entries).await;
+        if result.is_err() {
+            self.state.lock().await.rewrite_buffer = None;
+        }
+        result
+    }
+
+    async fn replace_log(&self, entries: Vec<StoreEntry>) -> Result<(), AofError> {
+        let path = self.path.clone();
+        let (mut temp, rewritten_len) = blocking(move || {
+            let mut rewritten = Vec::new();
+            for entry in entries {
+                rewrite_entry(entry, &mut rewritten);
+            }
+            let mut temp = temp_file_beside(&path, "appendonly.")?;
+            temp.as_file_mut().write_all(&rewritten)?;
+            temp.as_file_mut().sync_data()?;
+            Ok((temp, rewritten.len()))
+        })
+        .await
+        .map_err(AofError::WriteFile)?;
+
+        let mut state = self.state.lock().await;
+        let buffered = state.rewrite_buffer.take().unwrap_or_default();
+        let size = (rewritten_len + buffered.len()) as u64;
+        let temp = blocking(move || temp.as_file_mut().write_all(&buffered).map(|()| temp))
+            .await
+            .map_err(AofError::WriteFile)?;
+        replace_with(temp, &self.path)
+            .await
+            .map_err(AofError::WriteFile)?;
+        state.file = OpenOptions::new()
+            .append(true)
+            .open(self.path.as_path())
+            .await
+            .map_err(AofError::WriteFile)?;
+        state.size = size;
+        state.base_size = size;
+        Ok(())
+    }
+}
+
+async fn sync_every_second(state: Weak<Mutex<AofState>>, unsynced: Arc<AtomicBool>) {
+    let mut ticks = interval(Duration::from_secs(1));
+    loop {
+        ticks.tick().await;
+        let Some(state) = state.upgrade() else {
+            return;
+        };
+        if !unsynced.swap(false, Ordering::AcqRel) {
+            continue;
+        }
+        // Syncing a second handle keeps writers from waiting on the disk.
+        let file = match state.lock().await.file.try_clone().await {
+            Ok(file) => file,
+            Err(e) => {
+                println!("append-only file fsync failed: {:?}", e);
+                continue;
+            }
+        };
+        drop(state);
+        if let Err(e) = file.sync_data().await {
+            println!("append-only file fsync failed: {:?}", e);
+        }
+    }
+}
+
+fn encode(args: Vec<Vec<u8>>, buf: &mut Vec<u8>) {
+    Frame::Array(Some(
+        args.into_iter()
+            .map(|arg| Frame::Bulk(Some(Bytes::from(arg))))
+            .collect(),
+    ))
+    .write_to(buf);
+}
+
+/// Appends the commands that recreate `entry` to `buf`.
+fn rewrite_entry(entry: StoreEntry, buf: &mut Vec<u8>) {
+    let StoreEntry {
+        key,
+        value,
+        expiration_time_unix,
+    } = entry;
+    let (command, items): (&[u8], Vec<Vec<Vec<u8>>>) = match value {
+        Value::String(value) => (b"SET", vec![vec![value]]),
+        Value::List(list) => (
+            b"RPUSH",
+            list.into_iter().map(|value| vec![value]).collect(),
+        ),
+        Value::Hash(hash) => (
+            b"HSET",
+            hash.into_iter()
+                .map(|(field, value)| vec![field, value])
+                .collect(),
+        ),
+        Value::Set(set) => (
+            b"SADD",
+            set.into_iter().map(|member| vec![member]).collect(),
+        ),
+        Value::SortedSet(zset) => (
+            b"ZADD",
+            zset.iter()
+                .map(|(score, member)| vec![format_score(score).into_bytes(), member.to_vec()])
+                .collect(),
+        ),
+    };
+    for chunk in items.chunks(REWRITE_ITEMS_PER_COMMAND) {
+        let mut args = vec![command.to_vec(), key.clone()];
+        args.extend(chunk.iter().flatten().cloned());
+        encode(args, buf);
+    }
+    if let Some(at) = expiration_time_unix {
+        encode(
+            vec![b"PEXPIREAT".to_vec(), key, at.to_string().into_bytes()],
+            buf,
+        );
+    }
+}
+
+/// Exclusive access to the append-only file, from [`AppendOnlyFile::lock`].
+///
+/// Commands added are only written by [`AofLog::commit`].
+pub struct AofLog {
+    state: OwnedMutexGuard<AofState>,
+    aof: AppendOnlyFile,
+    pending: Vec<Vec<Vec<u8>>>,
+}
+
+impl AofLog {
+    pub fn append(&mut self, args: Vec<Vec<u8>>) {
+        self.pending.push(args);
+    }
+
+    /// Adds the pops made for blocking commands as the plain list commands
+    /// that redo them.
+    pub fn append_served_pops(&mut self, pops: Vec<ServedPop>) {
+        for pop in pops {
+            let command = match pop.end {
+                ListEnd::Left => b"LPOP",
+                ListEnd::Right => b"RPOP",
+            };
+            self.pending.push(vec![command.to_vec(), pop.key]);
+            if let Some((destination, to)) = pop.destination {
+                let command = match to {
+                    ListEnd::Left => b"LPUSH",
+                    ListEnd::Right => b"RPUSH",
+                };
+                self.pending
+                    .push(vec![command.to_vec(), destination, pop.value]);
+            }
+        }
+    }
+
+    /// Wraps the commands added so far in `MULTI` and `EXEC`, if there are
+    /// any, so they are replayed together.
+    pub fn wrap_in_transaction(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.pending.insert(0, vec![b"MULTI".to_vec()]);
+        self.pending.push(vec![b"EXEC".to_vec()]);
+    }
+
+    /// Writes the added commands to the file, and syncs it before returning
+    /// if the policy is [`AppendFsync::Always`].
+    ///
+    /// Starts a rewrite once the log has grown enough since the last one.
+    pub async fn commit(self) -> io::Result<()> {
+        let AofLog {
+            mut state,
+            aof,
+            pending,
+        } = self;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let mut buf = Vec::new();
+        for args in pending {
+            encode(args, &mut buf);
+        }
+        state.file.write_all(&buf).await?;
+        state.file.flush().await?;
+        match aof.fsync {
+            AppendFsync::Always => state.file.sync_data().await?,
+            AppendFsync::EverySec => aof.unsynced.store(true, Ordering::Release),
+            AppendFsync::No => {}
+        }
+        state.size += buf.len() as u64;
+        if let Some(rewrite_buffer) = &mut state.rewrite_buffer {
+            rewrite_buffer.extend_from_slice(&buf);
+        }
+        let rewrite_due = aof.auto_rewrite.is_due(state.size, state.base_size);
+        drop(state);
+        if rewrite_due {
+            aof.start_rewrite();
+        }
+        Ok(())
+    }
+}
+
+/// Replays the log at `path` into `store`. A missing log leaves the store
+/// as it is.
+///
+/// A command cut short at the end of the log, or a `MULTI` never followed by
+/// its `EXEC`, as a crash mid-write leaves them, is dropped and truncated from
+/// the file so later writes follow the last complete command.
+pub async fn replay(path: PathBuf, store: &Store) -> Result<(), AofError> {
+    let contents = match fs::read(&path).await {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(AofError::ReadFile(error)),
+    };
+
+    // The log is written by the server itself, so request limits don't apply.
+    let mut parser = Parser::with_limits(ParserLimits {
+        max_bulk_len: usize::MAX,
+        max_multibulk_len: usize::MAX,
+        max_nesting_depth: usize::MAX,
+        max_inline_len: usize::MAX,
+    });
+    parser.buffer_mut().extend_from_slice(&contents);
+
+    let mut conn = Connection::new(
+        tokio::io::empty(),
+        tokio::io::sink(),
+        store.clone(),
+        Hub::new(),
+        CancellationToken::new(),
+    );
+    // Where the last command replayed outside a transaction ends, which is
+    // where a transaction still open at the end of the log starts.
+    let mut complete_len = 0;
+    let mut in_transaction = false;
+    while let Some(frame) = parser.next_frame().map_err(AofError::InvalidLog)? {
+        let command = Command::try_from(frame).map_err(AofError::InvalidCommand)?;
+        match command {
+            Command::MULTI => in_transaction = true,
+            Command::EXEC => in_transaction = false,
+            _ => {}
+        }
+        if !in_transaction {
+            complete_len = contents.len() - parser.buffered_len();
+        }
+        conn.replay(command).await;
+    }
+
+    if complete_len < contents.len() {
+        println!(
+            "append-only file ends with an incomplete command or transaction, truncating {} bytes",
+            contents.len() - complete_len
+        );
+        let file = OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .await
+            .map_err(AofError::WriteFile)?;
+        file.set_len(complete_len as u64)
+            .await
+            .map_err(AofError::WriteFile)?;
+        file.sync_all().await.map_err(AofError::WriteFile)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use tempfile::tempdir;
+    use tokio::io::{Empty, Sink, empty, sink};
+
+    fn args(values: &[&[u8]]) -> Vec<Vec<u8>> {
+        values.iter().map(|value| value.to_vec()).collect()
+    }
+
+    fn logged_connection(store: &Store, aof: &AppendOnlyFile) -> Connection<Empty, Sink> {
+        Connection::new(
+            empty(),
+            sink(),
+            store.clone(),
+            Hub::new(),
+            CancellationToken::new(),
+        )
+        .with_append_only_file(aof.clone())
+    }
+
+    async fn run(conn: &mut Connection<Empty, Sink>, values: &[&[u8]]) {
+        let frame = Frame::Array(Some(
+            values
+                .iter()
+                .map(|value| Frame::Bulk(Some(Bytes::copy_from_slice(value))))
+                .collect(),
+        ));
+        conn.replay(Command::try_from(frame).unwrap()).await;
+    }
+
+    /// Asserts both stores hold the same keys and values, with deadlines
+    /// that differ only by clock rounding.
+    async fn assert_same_entries(replayed: &Store, store: &Store) {
+        let sorted = |mut entries: Vec<StoreEntry>| {
+            entries.sort_by(|a, b| a.key.cmp(&b.key));
+            entries
+        };
+        let replayed = sorted(replayed.entries().await);
+        let expected = sorted(store.entries().await);
+        assert_eq!(replayed.len(), expected.len());
+        for (replayed, expected) in replayed.into_iter().zip(expected) {
+            assert_eq!(replayed.key, expected.key);
+            assert_eq!(replayed.value, expected.value);
+            match (replayed.expiration_time_unix, expected.expiration_time_unix) {
+                (Some(a), Some(b)) => assert!(a.abs_diff(b) <= 5),
+                (a, b) => assert_eq!(a, b),
+            }
+        }
+    }
+
+    #[test]
+    fn fsync_policy_parses_redis_names() {
+        assert_eq!("always".parse(), Ok(AppendFsync::Always));
+        assert_eq!("EverySec".parse(), Ok(AppendFsync::EverySec));
+        assert_eq!("no".parse(), Ok(AppendFsync::No));
+        assert!("sometimes".parse::<AppendFsync>().is_err());
+        assert_eq!(AppendFsync::default().to_string(), "everysec");
+    }
+
+    #[tokio::test]
+    async fn committed_commands_are_replayed() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("appendonly.aof");
+        let aof = AppendOnlyFile::open(&path, AppendFsync::Always, Store::new())
+            .await
+            .unwrap();
+
+        let mut log = aof.lock().await;
+        log.append(args(&[b"SET", b"a", b"1"]));
+        log.append(args(&[b"RPUSH", b"list", b"x", b"y"]));
+        log.commit().await.unwrap();
+        let mut log = aof.lock().await;
+        log.append(args(&[b"SET", b"a", b"2"]));
+        log.append(args(&[b"INCR", b"a"]));
+        log.wrap_in_transaction();
+        log.commit().await.unwrap();
+        let mut log = aof.lock().await;
+        log.append_served_pops(vec![ServedPop {
+            key: b"list".to_vec(),
+            end: ListEnd::Left,
+            destination: Some((b"other".to_vec(), ListEnd::Right)),
+            value: b"x".to_vec(),
+        }]);
+        log.commit().await.unwrap();
+
+        let store = Store::new();
+        replay(path, &store).await.unwrap();
+        assert_eq!(store.get(b"a").await.unwrap(), Some(b"3".to_vec()));
+        assert_eq!(
+            store.lrange(b"list", 0, -1).await.unwrap(),
+            vec![b"y".to_vec()]
+        );
+        assert_eq!(
+            store.lrange(b"other", 0, -1).await.unwrap(),
+            vec![b"x".to_vec()]
+        );
+    }
+
+    #[tokio::test]
+    async fn longest_relative_expirations_are_replayed() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("appendonly.aof");
+        let store = Store::new();
+        let aof = AppendOnlyFile::open(&path, AppendFsync::Always, store.clone())
+            .await
+            .unwrap();
+        let mut conn = logged_connection(&store, &aof);
+        // The furthest deadline that is accepted, less a minute of slack for
+        // the clock moving on while the test runs.
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let seconds = (i64::MAX as u64 / 1000 - now - 60).to_string();
+        run(&mut conn, &[b"SET", b"a", b"1", b"EX", seconds.as_bytes()]).await;
+        run(&mut conn, &[b"SET", b"b", b"1"]).await;
+        run(&mut conn, &[b"EXPIRE", b"b", seconds.as_bytes()]).await;
+
+        let replayed = Store::new();
+        replay(path, &replayed).await.unwrap();
+        assert_same_entries(&replayed, &store).await;
+        assert!(replayed.pttl(b"a").await > 0);
+        assert!(replayed.pttl(b"b").await > 0);
+    }
+
+    #[tokio::test]
+    async fn uncommitted_commands_are_not_written() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("appendonly.aof");
+        let aof = AppendOnlyFile::open(&path, AppendFsync::No, Store::new())
+            .await
+            .unwrap();
+
+        let mut log = aof.lock().await;
+        log.append(args(&[b"SET", b"a", b"1"]));
+        drop(log);
+
+        assert!(fs::read(&path).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn replaying_a_missing_log_leaves_the_store_empty() {
+        let dir = tempdir().unwrap();
+        let store = Store::new();
+        replay(dir.path().join("missing.aof"), &store)
+            .await
+            .unwrap();
+        assert_eq!(store.dbsize().await, 0);
+    }
+
+    #[tokio::test]
+    async fn incomplete_last_command_is_truncated() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("appendonly.aof");
+        let complete = b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n";
+        let mut contents = complete.to_vec();
+        contents.extend_from_slice(b"*3\r\n$3\r\nSET\r\n$1\r\nb");
+        fs::write(&path, &contents).await.unwrap();
+
+        let store = Store::new();
+        replay(path.clone(), &store).await.unwrap();
+        assert_eq!(store.get(b"a").await.unwrap(), Some(b"1".to_vec()));
+        assert_eq!(store.get(b"b").await.unwrap(), None);
+        assert_eq!(fs::read(&path).await.unwrap(), complete);
+    }
+
+    #[tokio::test]
+    async fn unfinished_transaction_is_truncated() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("appendonly.aof");
+        // Inline commands are longer than their RESP encoding, so offsets
+        // must come from the bytes actually read.
+        let complete = b"SET  a  1\r\n*1\r\n$5\r\nMULTI\r\n*2\r\n$4\r\nINCR\r\n$1\r\na\r\n*1\r\n$4\r\nEXEC\r\n";
+        let mut contents = complete.to_vec();
+        contents.extend_from_slice(b"*1\r\n$5\r\nMULTI\r\n*2\r\n$4\r\nINCR\r\n$1\r\na\r\n");
+        fs::write(&path, &contents).await.unwrap();
+
+        let store = Store::new();
+        replay(path.clone(), &store).await.unwrap();
+        assert_eq!(store.get(b"a").await.unwrap(), Some(b"2".to_vec()));
+        assert_eq!(fs::read(&path).await.unwrap(), complete);
+
+        let aof = AppendOnlyFile::open(&path, AppendFsync::No, store.clone())
+            .await
+            .unwrap();
+        let mut log = aof.lock().await;
+        log.append(args(&[b"INCR", b"a"]));
+        log.commit().await.unwrap();
+        let replayed = Store::new();
+        replay(path, &replayed).await.unwrap();
+        assert_eq!(replayed.get(b"a").await.unwrap(), Some(b"3".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn corrupt_log_is_rejected() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("appendonly.aof");
+        fs::write(&path, b"*1\r\n$-2\r\n").await.unwrap();
+        assert!(matches!(
+            replay(path.clone(), &Store::new()).await,
+            Err(AofError::InvalidLog(_))
+        ));
+
+        fs::write(&path, b"*1\r\n$3\r\nFOO\r\n").await.unwrap();
+        assert!(matches!(
+            replay(path, &Store::new()).await,
+            Err(AofError::InvalidCommand(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rewrite_compacts_the_log_and_keeps_every_type() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("appendonly.aof");
+        let store = Store::new();
+        let aof = AppendOnlyFile::open(&path, AppendFsync::No, store.clone())
+            .await
+            .unwrap();
+        let mut conn = logged_connection(&store, &aof);
+        for _ in 0..100 {
+            run(&mut conn, &[b"INCR", b"counter"]).await;
+        }
+        for i in 0..100 {
+            run(&mut conn, &[b"RPUSH", b"list", i.to_string().as_bytes()]).await;
+        }
+        run(&mut conn, &[b"SET", b"string", b"value", b"PX", b"100000"]).await;
+        run(&mut conn, &[b"HSET", b"hash", b"f1", b"v1", b"f2", b"v2"]).await;
+        run(&mut conn, &[b"SADD", b"set", b"a", b"b"]).await;
+        run(&mut conn, &[b"ZADD", b"zset", b"1.5", b"a", b"-2", b"b"]).await;
+        run(&mut conn, &[b"SET", b"gone", b"soon"]).await;
+        run(&mut conn, &[b"DEL", b"gone"]).await;
+        let logged = fs::metadata(&path).await.unwrap().len();
+
+        aof.start_rewrite().unwrap().await.unwrap().unwrap();
+
+        assert!(fs::metadata(&path).await.unwrap().len() < logged / 2);
+        let replayed = Store::new();
+        replay(path, &replayed).await.unwrap();
+        assert_same_entries(&replayed, &store).await;
+    }
+
+    #[tokio::test]
+    async fn writes_during_a_rewrite_are_kept() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("appendonly.aof");
+        let store = Store::new();
+        let aof = AppendOnlyFile::open(&path, AppendFsync::No, store.clone())
+            .await
+            .unwrap();
+        let mut conn = logged_connection(&store, &aof);
+        for i in 0..1000 {
+            run(&mut conn, &[b"SADD", b"before", i.to_string().as_bytes()]).await;
+        }
+
+        let rewrite = aof.start_rewrite().unwrap();
+        assert!(aof.start_rewrite().is_none());
+        for i in 0..200 {
+            run(&mut conn, &[b"INCR", b"counter"]).await;
+            run(&mut conn, &[b"RPUSH", b"during", i.to_string().as_bytes()]).await;
+            tokio::task::yield_now().await;
+        }
+        rewrite.await.unwrap().unwrap();
+        run(&mut conn, &[b"INCR", b"counter"]).await;
+
+        let replayed = Store::new();
+        replay(path, &replayed).await.unwrap();
+        assert_same_entries(&replayed, &store).await;
+        assert_eq!(
+            replayed.get(b"counter").await.unwrap(),
+            Some(b"201".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn log_is_rewritten_once_it_doubles() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("appendonly.aof");
+        let store = Store::new();
+        let aof = AppendOnlyFile::open(&path, AppendFsync::No, store.clone())
+            .await
+            .unwrap()
+            .with_auto_rewrite(AutoRewrite {
+                percentage: 100,
+                min_size: 1024,
+            });
+        let mut conn = logged_connection(&store, &aof);
+        let mut logged = Vec::new();
+        for i in 0..500 {
+            let value = i.to_string();
+            run(&mut conn, &[b"SET", b"key", value.as_bytes()]).await;
+            encode(
+                vec![b"SET".to_vec(), b"key".to_vec(), value.into_bytes()],
+                &mut logged,
+            );
+        }
+        while aof.rewriting.load(Ordering::Acquire) {
+            tokio::task::yield_now().await;
+        }
+
+        // Writes that arrive during a rewrite are appended to the new file,
+        // so how much of the log is left depends on timing.
+        assert!(aof.state.lock().await.base_size > 0);
+        assert!(fs::metadata(&path).await.unwrap().len() < logged.len() as u64);
+        let replayed = Store::new();
+        replay(path, &replayed).await.unwrap();
+        assert_eq!(replayed.get(b"key").await.unwrap(), Some(b"499".to_vec()));
+    }
+
+    #[test]
+    fn auto_rewrite_waits_for_growth_and_minimum_size() {
+        let auto = AutoRewrite {
+            percentage: 100,
+            min_size: 100,
+        };
+        assert!(!auto.is_due(99, 0));
+        assert!(auto.is_due(100, 0));
+        assert!(!auto.is_due(399, 200));
+        assert!(auto.is_due(400, 200));
+        let off = AutoRewrite {
+            percentage: 0,
+            ..auto
+        };
+        assert!(!off.is_due(u64::MAX, 0));
+    }
+}
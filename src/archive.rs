@@ -1,17 +1,33 @@
+use crate::archive_format::{self, CorruptArchive};
 use crate::store::RestoreError;
 use crate::store::Store;
 use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{fmt, path::PathBuf};
-use tempfile::Builder;
+use tempfile::{Builder, NamedTempFile};
 use tokio::fs;
 use tokio::fs::File;
 use tokio::fs::rename;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+
+/// The default save rules, as Redis: save after an hour if a key changed,
+/// after five minutes if 100 did, or after a minute if 10000 did.
+pub const DEFAULT_SAVE_RULES: &str = "3600 1 300 100 60 10000";
+
+/// How long to wait after a failed scheduled save before trying again.
+const SAVE_RETRY_DELAY: Duration = Duration::from_secs(5);
 
 #[derive(Debug)]
 pub enum ArchiveError {
     ReadFile(std::io::Error),
     InvalidArchive(RestoreError),
-    InvalidStore(serde_json::Error),
+    /// A binary archive is damaged or was written by a newer version.
+    Corrupted(CorruptArchive),
     WriteFile(std::io::Error),
 }
 
@@ -27,8 +43,8 @@ impl fmt::Display for ArchiveError {
             ArchiveError::InvalidArchive(_) => {
                 write!(f, "Invalid archive format")
             }
-            ArchiveError::InvalidStore(_) => {
-                write!(f, "Unable to serialize store")
+            ArchiveError::Corrupted(e) => {
+                write!(f, "Corrupted archive file: {e}")
             }
         }
     }
@@ -36,11 +52,214 @@ impl fmt::Display for ArchiveError {
 
 impl std::error::Error for ArchiveError {}
 
+/// Save once `seconds` have passed and `changes` writes have been made
+/// since the last save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveRule {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+/// When the archive is saved automatically, as Redis's `save` option: pairs
+/// of seconds and changes, such as `3600 1 300 100`. No rules turns
+/// automatic saves off.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SaveRules(Vec<SaveRule>);
+
+impl SaveRules {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns whether any rule is met, `elapsed` after the last save with
+    /// `changes` writes made since.
+    pub fn are_met(&self, changes: u64, elapsed: Duration) -> bool {
+        self.0
+            .iter()
+            .any(|rule| changes >= rule.changes && elapsed.as_secs() >= rule.seconds)
+    }
+}
+
+impl FromStr for SaveRules {
+    type Err = String;
+
+    fn from_str(rules: &str) -> Result<Self, Self::Err> {
+        let values = rules
+            .split_whitespace()
+            .map(|value| {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid save rule value '{value}'"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if values.len() % 2 != 0 {
+            return Err("save rules must be pairs of seconds and changes".into());
+        }
+        Ok(SaveRules(
+            values
+                .chunks(2)
+                .map(|pair| SaveRule {
+                    seconds: pair[0],
+                    changes: pair[1],
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl fmt::Display for SaveRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rules: Vec<String> = self
+            .0
+            .iter()
+            .map(|rule| format!("{} {}", rule.seconds, rule.changes))
+            .collect();
+        f.write_str(&rules.join(" "))
+    }
+}
+
+/// Saves a store's archive and remembers how the last save went.
+///
+/// Clones share that status, so every connection sees the same one.
+#[derive(Clone)]
+pub struct Snapshots {
+    store: Store,
+    path: Option<Arc<PathBuf>>,
+    rules: SaveRules,
+    status: Arc<StdMutex<SaveStatus>>,
+    /// Held while a save runs, so saves never overlap.
+    saving: Arc<Mutex<()>>,
+    /// Cleared by `SHUTDOWN NOSAVE`.
+    save_on_shutdown: Arc<AtomicBool>,
+}
+
+struct SaveStatus {
+    last_save: SystemTime,
+    last_save_at: Instant,
+    last_attempt_at: Instant,
+    last_error: Option<String>,
+}
+
+impl Snapshots {
+    /// Tracks saves of `store` to `path`. Without a path, nothing is saved.
+    pub fn new(store: Store, path: Option<PathBuf>) -> Snapshots {
+        let now = Instant::now();
+        Snapshots {
+            store,
+            path: path.map(Arc::new),
+            rules: SaveRules::default(),
+            status: Arc::new(StdMutex::new(SaveStatus {
+                last_save: SystemTime::now(),
+                last_save_at: now,
+                last_attempt_at: now,
+                last_error: None,
+            })),
+            saving: Arc::new(Mutex::new(())),
+            save_on_shutdown: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Sets the rules that [`Snapshots::is_due`] checks.
+    pub fn with_save_rules(mut self, rules: SaveRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Returns whether the store should be saved under the save rules and no
+    /// save is running. After a failed save, waits a few seconds before
+    /// saying so again.
+    pub fn is_due(&self) -> bool {
+        let status = self.status.lock().unwrap();
+        self.is_enabled()
+            && !self.is_saving()
+            && (status.last_error.is_none() || status.last_attempt_at.elapsed() >= SAVE_RETRY_DELAY)
+            && self
+                .rules
+                .are_met(self.store.dirty(), status.last_save_at.elapsed())
+    }
+
+    pub fn is_saving(&self) -> bool {
+        self.saving.try_lock().is_err()
+    }
+
+    /// Returns the number of writes made since the last successful save.
+    pub fn changes_since_last_save(&self) -> u64 {
+        self.store.dirty()
+    }
+
+    /// Returns the Unix time in seconds of the last successful save, or of
+    /// startup if there has not been one.
+    pub fn last_save(&self) -> u64 {
+        let last_save = self.status.lock().unwrap().last_save;
+        last_save
+            .duration_since(UNIX_EPOCH)
+            .expect("Time before UNIX epoch")
+            .as_secs()
+    }
+
+    /// Returns why the last save failed, or `None` if it succeeded.
+    pub fn last_error(&self) -> Option<String> {
+        self.status.lock().unwrap().last_error.clone()
+    }
+
+    /// Returns whether the server should save the archive as it shuts down.
+    pub fn saves_on_shutdown(&self) -> bool {
+        self.save_on_shutdown.load(Ordering::Acquire)
+    }
+
+    /// Stops the server from saving the archive as it shuts down.
+    pub fn skip_save_on_shutdown(&self) {
+        self.save_on_shutdown.store(false, Ordering::Release);
+    }
+
+    /// Saves the store to the archive, after any save already running.
+    pub async fn save(&self) -> Result<(), ArchiveError> {
+        let saving = self.saving.clone().lock_owned().await;
+        self.save_while(saving).await
+    }
+
+    /// Starts saving the store in the background, or returns `None` if a
+    /// save is already running.
+    pub fn start_save(&self) -> Option<JoinHandle<Result<(), ArchiveError>>> {
+        let saving = self.saving.clone().try_lock_owned().ok()?;
+        let snapshots = self.clone();
+        Some(tokio::spawn(async move {
+            let result = snapshots.save_while(saving).await;
+            if let Err(e) = &result {
+                println!("background save failed: {:?}", e);
+            }
+            result
+        }))
+    }
+
+    async fn save_while(&self, _saving: OwnedMutexGuard<()>) -> Result<(), ArchiveError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let changes = self.store.dirty();
+        let result = save(path.to_path_buf(), self.store.clone()).await;
+        let mut status = self.status.lock().unwrap();
+        status.last_attempt_at = Instant::now();
+        match &result {
+            Ok(()) => {
+                self.store.mark_saved(changes);
+                status.last_save = SystemTime::now();
+                status.last_save_at = status.last_attempt_at;
+                status.last_error = None;
+            }
+            Err(e) => status.last_error = Some(e.to_string()),
+        }
+        result
+    }
+}
+
 pub async fn load(path: PathBuf) -> Result<Store, ArchiveError> {
     match fs::read(&path).await {
-        Ok(contents) => Store::restore(contents.as_slice())
-            .await
-            .map_err(ArchiveError::InvalidArchive),
+        Ok(contents) => restore(&contents).await,
         Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
             // Only treat it as first-run if the parent dir is usable.
             match path.parent() {
@@ -54,25 +273,70 @@ pub async fn load(path: PathBuf) -> Result<Store, ArchiveError> {
     }
 }
 
+/// Rebuilds a store from a binary archive, or from a JSON one written before
+/// the binary format existed.
+async fn restore(contents: &[u8]) -> Result<Store, ArchiveError> {
+    if !archive_format::is_binary(contents) {
+        return Store::restore(contents)
+            .await
+            .map_err(ArchiveError::InvalidArchive);
+    }
+    let entries = archive_format::decode(contents).map_err(ArchiveError::Corrupted)?;
+    Store::from_entries(entries)
+        .await
+        .map_err(|e| ArchiveError::InvalidArchive(e.into()))
+}
+
 pub async fn save(path: PathBuf, store: Store) -> Result<(), ArchiveError> {
-    let bytes = store.dump().await.map_err(ArchiveError::InvalidStore)?;
-    save_bytes(&path, &bytes)
+    let entries = store.entries().await;
+    let archive_path = path.clone();
+    // Encoding and writing the whole store would hold up a runtime worker.
+    let temp_archive = blocking(move || {
+        let bytes = archive_format::encode(entries);
+        let mut temp_archive = temp_file_beside(&archive_path, "archive.")?;
+        temp_archive.as_file_mut().write_all(&bytes)?;
+        Ok(temp_archive)
+    })
+    .await
+    .map_err(ArchiveError::WriteFile)?;
+    replace_with(temp_archive, &path)
         .await
-        .map_err(ArchiveError::WriteFile)?;
-    Ok(())
+        .map_err(ArchiveError::WriteFile)
 }
 
-async fn save_bytes(path: &std::path::Path, bytes: &[u8]) -> std::io::Result<()> {
+/// Runs file work that blocks on a thread set aside for it, so the runtime's
+/// workers keep serving clients.
+pub(crate) async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> std::io::Result<T> + Send + 'static,
+) -> std::io::Result<T> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+/// Creates a temporary file in the directory of `path`, so it can later be
+/// renamed over `path` with [`replace_with`].
+pub(crate) fn temp_file_beside(
+    path: &std::path::Path,
+    prefix: &str,
+) -> std::io::Result<NamedTempFile> {
     let parent = path.parent().unwrap_or(std::path::Path::new("."));
-    let mut temp_archive = Builder::new()
-        .prefix("archive.")
+    Builder::new()
+        .prefix(prefix)
         .suffix(".tmp")
-        .tempfile_in(parent)?;
+        .tempfile_in(parent)
+}
 
-    temp_archive.as_file_mut().write_all(bytes)?;
-    temp_archive.as_file_mut().sync_all()?;
+/// Syncs `temp` and atomically renames it over `path`, then syncs the
+/// directory so the rename itself survives a crash.
+pub(crate) async fn replace_with(
+    mut temp: NamedTempFile,
+    path: &std::path::Path,
+) -> std::io::Result<()> {
+    let parent = path.parent().unwrap_or(std::path::Path::new("."));
+    let temp = blocking(move || temp.as_file_mut().sync_all().map(|()| temp)).await?;
 
-    rename(temp_archive.into_temp_path(), &path).await?;
+    rename(temp.into_temp_path(), &path).await?;
 
     #[cfg(unix)]
     {
@@ -91,16 +355,17 @@ mod tests {
     use tempfile::{NamedTempFile, TempDir, tempdir};
     use tokio::time::{self, Duration};
 
-    use crate::archive::save;
+    use crate::archive::{SaveRule, SaveRules, Snapshots, save};
+    use crate::archive_format::{CorruptArchive, MAGIC};
     use crate::{
         archive::{ArchiveError, load},
-        store::Store,
+        store::{ListEnd, SetOptions, Store},
     };
     #[tokio::test]
     async fn load_missing_file_with_relative_filename_returns_new_store() {
         let file_path = PathBuf::new().join("test-archive");
         let store = load(file_path).await.unwrap();
-        assert!(store.get(&b"missing-key".to_vec()).await.is_none());
+        assert!(store.get(b"missing-key").await.unwrap().is_none());
     }
 
     #[tokio::test]
@@ -108,7 +373,7 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let file_path = temp_dir.path().join("test-archive");
         let store = load(file_path).await.unwrap();
-        assert!(store.get(&b"missing-key".to_vec()).await.is_none());
+        assert!(store.get(b"missing-key").await.unwrap().is_none());
     }
 
     #[tokio::test]
@@ -142,10 +407,13 @@ mod tests {
         let store = Store::new();
         let temp_dir = tempdir().unwrap();
         let path = temp_dir.path().join("archive");
-        store.set(key.clone(), value.clone()).await;
+        store
+            .set(key.clone(), value.clone(), SetOptions::default())
+            .await
+            .unwrap();
         save(path.clone(), store).await.unwrap();
         let store = load(path).await.unwrap();
-        assert_eq!(store.get(&key).await.unwrap(), value);
+        assert_eq!(store.get(&key).await.unwrap().unwrap(), value);
     }
 
     #[tokio::test]
@@ -160,16 +428,22 @@ mod tests {
         let value_b = b"my_value_b".to_vec();
 
         let store = Store::new();
-        store.set(key_a.clone(), value_a.clone()).await;
+        store
+            .set(key_a.clone(), value_a.clone(), SetOptions::default())
+            .await
+            .unwrap();
         save(path.clone(), store).await.unwrap();
 
         let store = Store::new();
-        store.set(key_b.clone(), value_b.clone()).await;
+        store
+            .set(key_b.clone(), value_b.clone(), SetOptions::default())
+            .await
+            .unwrap();
         save(path.clone(), store).await.unwrap();
 
         let store = load(path).await.unwrap();
-        assert!(store.get(&key_a).await.is_none());
-        assert_eq!(store.get(&key_b).await.unwrap(), value_b);
+        assert!(store.get(&key_a).await.unwrap().is_none());
+        assert_eq!(store.get(&key_b).await.unwrap().unwrap(), value_b);
     }
 
     #[tokio::test]
@@ -200,14 +474,17 @@ mod tests {
         ];
 
         for (key, value) in &entries {
-            store.set(key.clone(), value.clone()).await;
+            store
+                .set(key.clone(), value.clone(), SetOptions::default())
+                .await
+                .unwrap();
         }
 
         save(path.clone(), store).await.unwrap();
         let store = load(path).await.unwrap();
 
         for (key, value) in entries {
-            assert_eq!(store.get(&key).await.unwrap(), value);
+            assert_eq!(store.get(&key).await.unwrap().unwrap(), value);
         }
     }
 
@@ -222,12 +499,29 @@ mod tests {
         let expired_key = b"expired-key".to_vec();
 
         store
-            .set(persistent_key.clone(), b"persistent-value".to_vec())
-            .await;
-        store.set(live_key.clone(), b"live-value".to_vec()).await;
+            .set(
+                persistent_key.clone(),
+                b"persistent-value".to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
+        store
+            .set(
+                live_key.clone(),
+                b"live-value".to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
         store
-            .set(expired_key.clone(), b"expired-value".to_vec())
-            .await;
+            .set(
+                expired_key.clone(),
+                b"expired-value".to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
 
         assert_eq!(1, store.expire(live_key.clone(), 5).await);
         assert_eq!(1, store.expire(expired_key.clone(), 0).await);
@@ -236,16 +530,68 @@ mod tests {
         let store = load(path).await.unwrap();
 
         assert_eq!(
-            store.get(&persistent_key).await.unwrap(),
+            store.get(&persistent_key).await.unwrap().unwrap(),
             b"persistent-value".to_vec()
         );
-        assert_eq!(store.get(&live_key).await.unwrap(), b"live-value".to_vec());
+        assert_eq!(
+            store.get(&live_key).await.unwrap().unwrap(),
+            b"live-value".to_vec()
+        );
         assert!(store.ttl(live_key.clone()).await > 0);
-        assert!(store.get(&expired_key).await.is_none());
+        assert!(store.get(&expired_key).await.unwrap().is_none());
         assert_eq!(store.ttl(expired_key).await, -2);
 
         time::advance(Duration::from_secs(5)).await;
-        assert!(store.get(&live_key).await.is_none());
+        assert!(store.get(&live_key).await.unwrap().is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn round_trip_preserves_lists_and_their_ttl() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("archive");
+        let store = Store::new();
+
+        let list_key = b"list-key".to_vec();
+        let values = vec![b"first".to_vec(), b"second".to_vec()];
+        store
+            .push(list_key.clone(), values.clone(), ListEnd::Right)
+            .await
+            .unwrap();
+        assert_eq!(1, store.expire(list_key.clone(), 5).await);
+
+        save(path.clone(), store).await.unwrap();
+        let store = load(path).await.unwrap();
+
+        assert_eq!(store.lrange(&list_key, 0, -1).await.unwrap(), values);
+        assert!(store.ttl(list_key.clone()).await > 0);
+
+        time::advance(Duration::from_secs(5)).await;
+        assert_eq!(store.llen(&list_key).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn round_trip_preserves_sorted_set_scores() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("archive");
+        let store = Store::new();
+
+        let key = b"board".to_vec();
+        let members = vec![
+            (f64::NEG_INFINITY, b"bottom".to_vec()),
+            (0.1, b"tenth".to_vec()),
+            (2.5, b"middle".to_vec()),
+            (f64::INFINITY, b"top".to_vec()),
+        ];
+        store
+            .zadd(key.clone(), members.clone(), Default::default())
+            .await
+            .unwrap();
+
+        save(path.clone(), store).await.unwrap();
+        let store = load(path).await.unwrap();
+
+        let expected: Vec<_> = members.into_iter().map(|(s, m)| (m, s)).collect();
+        assert_eq!(store.zrange(&key, 0, -1).await.unwrap(), expected);
     }
 
     #[tokio::test]
@@ -256,7 +602,7 @@ mod tests {
         save(path.clone(), Store::new()).await.unwrap();
         let store = load(path).await.unwrap();
 
-        assert!(store.get(&b"missing-key".to_vec()).await.is_none());
+        assert!(store.get(b"missing-key").await.unwrap().is_none());
     }
 
     #[tokio::test]
@@ -266,28 +612,172 @@ mod tests {
 
         let first = Store::new();
         first
-            .set(b"first-key".to_vec(), b"first-value".to_vec())
-            .await;
+            .set(
+                b"first-key".to_vec(),
+                b"first-value".to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
         save(path.clone(), first).await.unwrap();
 
         let second = Store::new();
         second
-            .set(b"second-key".to_vec(), b"second-value".to_vec())
-            .await;
+            .set(
+                b"second-key".to_vec(),
+                b"second-value".to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
         save(path.clone(), second).await.unwrap();
 
         let third = Store::new();
         third
-            .set(b"third-key".to_vec(), b"third-value".to_vec())
-            .await;
+            .set(
+                b"third-key".to_vec(),
+                b"third-value".to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
         save(path.clone(), third).await.unwrap();
 
         let store = load(path).await.unwrap();
-        assert!(store.get(&b"first-key".to_vec()).await.is_none());
-        assert!(store.get(&b"second-key".to_vec()).await.is_none());
+        assert!(store.get(b"first-key").await.unwrap().is_none());
+        assert!(store.get(b"second-key").await.unwrap().is_none());
         assert_eq!(
-            store.get(&b"third-key".to_vec()).await.unwrap(),
+            store.get(b"third-key").await.unwrap().unwrap(),
             b"third-value".to_vec()
         );
     }
+
+    #[tokio::test]
+    async fn saved_archives_are_binary() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("archive");
+        save(path.clone(), Store::new()).await.unwrap();
+        assert!(std::fs::read(path).unwrap().starts_with(MAGIC));
+    }
+
+    #[tokio::test]
+    async fn json_archives_still_load() {
+        let store = Store::new();
+        store
+            .set(b"key".to_vec(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        let mut json_archive = NamedTempFile::new().unwrap();
+        json_archive
+            .write_all(&store.dump().await.unwrap())
+            .unwrap();
+
+        let store = load(json_archive.path().into()).await.unwrap();
+        assert_eq!(store.get(b"key").await.unwrap(), Some(b"value".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn damaged_binary_archive_returns_corrupted_error() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("archive");
+        let store = Store::new();
+        store
+            .set(b"key".to_vec(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        save(path.clone(), store).await.unwrap();
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.len() - 5;
+        bytes[last] ^= 0xFF;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            load(path.clone()).await,
+            Err(ArchiveError::Corrupted(
+                CorruptArchive::ChecksumMismatch { .. }
+            ))
+        ));
+
+        std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(matches!(load(path).await, Err(ArchiveError::Corrupted(_))));
+    }
+
+    #[test]
+    fn save_rules_parse_as_pairs() {
+        let rules: SaveRules = "3600 1  300 100".parse().unwrap();
+        assert_eq!(
+            rules,
+            SaveRules(vec![
+                SaveRule {
+                    seconds: 3600,
+                    changes: 1
+                },
+                SaveRule {
+                    seconds: 300,
+                    changes: 100
+                },
+            ])
+        );
+        assert_eq!(rules.to_string(), "3600 1 300 100");
+        assert!("".parse::<SaveRules>().unwrap().is_empty());
+        assert!("3600".parse::<SaveRules>().is_err());
+        assert!("3600 one".parse::<SaveRules>().is_err());
+    }
+
+    #[test]
+    fn save_rules_need_both_time_and_changes() {
+        let rules: SaveRules = "60 10 300 1".parse().unwrap();
+        assert!(!rules.are_met(0, Duration::from_secs(1000)));
+        assert!(!rules.are_met(9, Duration::from_secs(299)));
+        assert!(rules.are_met(10, Duration::from_secs(60)));
+        assert!(rules.are_met(1, Duration::from_secs(300)));
+        assert!(!SaveRules::default().are_met(u64::MAX, Duration::MAX));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn snapshots_save_when_a_rule_is_met() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("archive");
+        let store = Store::new();
+        let snapshots = Snapshots::new(store.clone(), Some(path.clone()))
+            .with_save_rules("10 2".parse().unwrap());
+        let started = snapshots.last_save();
+
+        for key in [b"a", b"b"] {
+            store
+                .set(key.to_vec(), b"v".to_vec(), SetOptions::default())
+                .await
+                .unwrap();
+        }
+        assert!(!snapshots.is_due());
+        time::advance(Duration::from_secs(10)).await;
+        assert!(snapshots.is_due());
+
+        snapshots.save().await.unwrap();
+        assert_eq!(snapshots.changes_since_last_save(), 0);
+        assert!(snapshots.last_save() >= started);
+        assert_eq!(snapshots.last_error(), None);
+        assert!(!snapshots.is_due());
+        assert!(load(path).await.unwrap().get(b"a").await.unwrap().is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_saves_are_reported_and_retried_later() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("does_not_exist").join("archive");
+        let store = Store::new();
+        let snapshots =
+            Snapshots::new(store.clone(), Some(path)).with_save_rules("0 1".parse().unwrap());
+        store
+            .set(b"a".to_vec(), b"v".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+
+        assert!(snapshots.save().await.is_err());
+        assert!(snapshots.last_error().is_some());
+        assert_eq!(snapshots.changes_since_last_save(), 1);
+        assert!(!snapshots.is_due());
+        time::advance(Duration::from_secs(5)).await;
+        assert!(snapshots.is_due());
+    }
 }
@@ -0,0 +1,456 @@
+use crate::store::{StoreEntry, Value};
+use std::fmt;
+
+/// The first bytes of every binary archive.
+pub const MAGIC: &[u8; 8] = b"REDLIKE\0";
+
+/// The layout version written by [`encode`].
+pub const VERSION: u16 = 1;
+
+const HEADER_LEN: usize = MAGIC.len() + size_of::<u16>();
+const CHECKSUM_LEN: usize = size_of::<u32>();
+
+const STRING: u8 = 0;
+const LIST: u8 = 1;
+const HASH: u8 = 2;
+const SET: u8 = 3;
+const SORTED_SET: u8 = 4;
+
+/// Why a binary archive could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CorruptArchive {
+    /// The archive does not start with [`MAGIC`].
+    MissingMagic,
+    /// The archive was written by a newer version of the format.
+    UnsupportedVersion(u16),
+    /// The archive's contents do not match its trailing checksum.
+    ChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// The archive ends before the data it describes.
+    Truncated,
+    /// A length does not fit in memory.
+    InvalidLength,
+    UnknownType(u8),
+    /// A sorted set member has a NaN score.
+    InvalidScore,
+    /// A list, hash, set or sorted set has no elements.
+    EmptyCollection,
+    /// Data follows the last entry.
+    TrailingBytes,
+}
+
+impl fmt::Display for CorruptArchive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CorruptArchive::MissingMagic => write!(f, "missing archive header"),
+            CorruptArchive::UnsupportedVersion(version) => {
+                write!(f, "unsupported archive version {version}")
+            }
+            CorruptArchive::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: expected {expected:08x}, found {actual:08x}"
+            ),
+            CorruptArchive::Truncated => write!(f, "archive is truncated"),
+            CorruptArchive::InvalidLength => write!(f, "archive contains an invalid length"),
+            CorruptArchive::UnknownType(tag) => write!(f, "unknown value type {tag}"),
+            CorruptArchive::InvalidScore => {
+                write!(f, "archive contains an invalid sorted set score")
+            }
+            CorruptArchive::EmptyCollection => write!(f, "archive contains an empty collection"),
+            CorruptArchive::TrailingBytes => write!(f, "unexpected data after the last entry"),
+        }
+    }
+}
+
+impl std::error::Error for CorruptArchive {}
+
+/// Returns whether `bytes` start like a binary archive rather than an older
+/// JSON one.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Encodes `entries` as a binary archive.
+///
+/// The archive is [`MAGIC`], the [`VERSION`] as a little-endian `u16`, the
+/// number of entries, each entry, and a CRC-32 of everything before it as a
+/// little-endian `u32`. Counts and lengths are LEB128 varints. Each entry is
+/// a type tag, a flag and little-endian `u64` Unix millisecond expiry if it
+/// has one, the key, then the value: a string's bytes, or a count of list or
+/// set members, hash fields and values, or sorted set members and their `f64`
+/// scores.
+pub fn encode(entries: Vec<StoreEntry>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(MAGIC);
+    buf.extend_from_slice(&VERSION.to_le_bytes());
+    write_len(&mut buf, entries.len());
+    for entry in entries {
+        write_entry(&mut buf, entry);
+    }
+    let checksum = crc32(&buf);
+    buf.extend_from_slice(&checksum.to_le_bytes());
+    buf
+}
+
+fn write_entry(buf: &mut Vec<u8>, entry: StoreEntry) {
+    let StoreEntry {
+        key,
+        value,
+        expiration_time_unix,
+    } = entry;
+    buf.push(match value {
+        Value::String(_) => STRING,
+        Value::List(_) => LIST,
+        Value::Hash(_) => HASH,
+        Value::Set(_) => SET,
+        Value::SortedSet(_) => SORTED_SET,
+    });
+    match expiration_time_unix {
+        None => buf.push(0),
+        Some(expiration) => {
+            buf.push(1);
+            let expiration = u64::try_from(expiration).unwrap_or(u64::MAX);
+            buf.extend_from_slice(&expiration.to_le_bytes());
+        }
+    }
+    write_bytes(buf, &key);
+    match value {
+        Value::String(value) => write_bytes(buf, &value),
+        Value::List(list) => {
+            write_len(buf, list.len());
+            list.iter().for_each(|value| write_bytes(buf, value));
+        }
+        Value::Hash(hash) => {
+            write_len(buf, hash.len());
+            for (field, value) in &hash {
+                write_bytes(buf, field);
+                write_bytes(buf, value);
+            }
+        }
+        Value::Set(set) => {
+            write_len(buf, set.len());
+            set.iter().for_each(|member| write_bytes(buf, member));
+        }
+        Value::SortedSet(zset) => {
+            write_len(buf, zset.len());
+            for (score, member) in zset.iter() {
+                write_bytes(buf, member);
+                buf.extend_from_slice(&score.to_le_bytes());
+            }
+        }
+    }
+}
+
+fn write_len(buf: &mut Vec<u8>, len: usize) {
+    let mut len = len as u64;
+    while len >= 0x80 {
+        buf.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+    buf.push(len as u8);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_len(buf, bytes.len());
+    buf.extend_from_slice(bytes);
+}
+
+/// Decodes a binary archive written by [`encode`], checking its header and
+/// checksum.
+pub fn decode(bytes: &[u8]) -> Result<Vec<StoreEntry>, CorruptArchive> {
+    if !is_binary(bytes) {
+        return Err(CorruptArchive::MissingMagic);
+    }
+    if bytes.len() < HEADER_LEN + CHECKSUM_LEN {
+        return Err(CorruptArchive::Truncated);
+    }
+    let version = u16::from_le_bytes([bytes[MAGIC.len()], bytes[MAGIC.len() + 1]]);
+    if version != VERSION {
+        return Err(CorruptArchive::UnsupportedVersion(version));
+    }
+    let (contents, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    let expected = u32::from_le_bytes(checksum.try_into().expect("checksum is four bytes"));
+    let actual = crc32(contents);
+    if expected != actual {
+        return Err(CorruptArchive::ChecksumMismatch { expected, actual });
+    }
+
+    let mut reader = Reader {
+        bytes: &contents[HEADER_LEN..],
+    };
+    let count = reader.length()?;
+    let mut entries = Vec::with_capacity(count.min(reader.bytes.len()));
+    for _ in 0..count {
+        entries.push(reader.entry()?);
+    }
+    if !reader.bytes.is_empty() {
+        return Err(CorruptArchive::TrailingBytes);
+    }
+    Ok(entries)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CorruptArchive> {
+        if len > self.bytes.len() {
+            return Err(CorruptArchive::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, CorruptArchive> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, CorruptArchive> {
+        let bytes = self.take(size_of::<u64>())?;
+        Ok(u64::from_le_bytes(bytes.try_into().expect("eight bytes")))
+    }
+
+    fn length(&mut self) -> Result<usize, CorruptArchive> {
+        let mut len: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            len |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return usize::try_from(len).map_err(|_| CorruptArchive::InvalidLength);
+            }
+        }
+        Err(CorruptArchive::InvalidLength)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, CorruptArchive> {
+        let len = self.length()?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    /// Reads a count of `item`s, none of which can be shorter than a byte.
+    fn items<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, CorruptArchive>,
+    ) -> Result<Vec<T>, CorruptArchive> {
+        let count = self.length()?;
+        let mut items = Vec::with_capacity(count.min(self.bytes.len()));
+        for _ in 0..count {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn entry(&mut self) -> Result<StoreEntry, CorruptArchive> {
+        let tag = self.u8()?;
+        let expiration_time_unix = match self.u8()? {
+            0 => None,
+            _ => Some(u128::from(self.u64()?)),
+        };
+        let key = self.bytes()?;
+        let value = match tag {
+            STRING => Value::String(self.bytes()?),
+            LIST => Value::List(self.items(Self::bytes)?.into()),
+            HASH => Value::Hash(
+                self.items(|reader| Ok((reader.bytes()?, reader.bytes()?)))?
+                    .into_iter()
+                    .collect(),
+            ),
+            SET => Value::Set(self.items(Self::bytes)?.into_iter().collect()),
+            SORTED_SET => Value::SortedSet(
+                self.items(|reader| {
+                    let member = reader.bytes()?;
+                    let score = f64::from_bits(reader.u64()?);
+                    match score.is_nan() {
+                        true => Err(CorruptArchive::InvalidScore),
+                        false => Ok((member, score)),
+                    }
+                })?
+                .into_iter()
+                .collect(),
+            ),
+            tag => return Err(CorruptArchive::UnknownType(tag)),
+        };
+        if value.is_empty_collection() {
+            return Err(CorruptArchive::EmptyCollection);
+        }
+        Ok(StoreEntry {
+            key,
+            value,
+            expiration_time_unix,
+        })
+    }
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The CRC-32 (IEEE) checksum of `bytes`, as used by zlib and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc = CRC_TABLE[((crc ^ u32::from(byte)) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet, VecDeque};
+
+    fn entries() -> Vec<StoreEntry> {
+        vec![
+            StoreEntry {
+                key: b"string".to_vec(),
+                value: Value::String(b"hello\x00\xFF".to_vec()),
+                expiration_time_unix: Some(1_700_000_000_000),
+            },
+            StoreEntry {
+                key: b"list".to_vec(),
+                value: Value::List(VecDeque::from([b"a".to_vec(), vec![0; 300]])),
+                expiration_time_unix: None,
+            },
+            StoreEntry {
+                key: b"hash".to_vec(),
+                value: Value::Hash(HashMap::from([(b"f".to_vec(), b"v".to_vec())])),
+                expiration_time_unix: None,
+            },
+            StoreEntry {
+                key: b"\xF4\xFF".to_vec(),
+                value: Value::Set(HashSet::from([b"m".to_vec(), Vec::new()])),
+                expiration_time_unix: None,
+            },
+            StoreEntry {
+                key: b"zset".to_vec(),
+                value: Value::SortedSet(
+                    [
+                        (b"a".to_vec(), 1.5),
+                        (b"b".to_vec(), f64::NEG_INFINITY),
+                        (b"c".to_vec(), f64::INFINITY),
+                    ]
+                    .into_iter()
+                    .collect(),
+                ),
+                expiration_time_unix: Some(u128::from(u64::MAX)),
+            },
+        ]
+    }
+
+    #[test]
+    fn crc32_matches_the_standard_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn encoded_entries_decode_unchanged() {
+        let bytes = encode(entries());
+        assert!(is_binary(&bytes));
+        assert_eq!(decode(&bytes), Ok(entries()));
+        assert_eq!(decode(&encode(Vec::new())), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn flipped_bits_fail_the_checksum() {
+        let mut bytes = encode(entries());
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0x01;
+        assert!(matches!(
+            decode(&bytes),
+            Err(CorruptArchive::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn truncated_archives_are_rejected() {
+        let bytes = encode(entries());
+        assert_eq!(decode(&bytes[..HEADER_LEN]), Err(CorruptArchive::Truncated));
+        assert!(matches!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(CorruptArchive::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        let mut bytes = encode(entries());
+        bytes[MAGIC.len()..HEADER_LEN].copy_from_slice(&2u16.to_le_bytes());
+        assert_eq!(decode(&bytes), Err(CorruptArchive::UnsupportedVersion(2)));
+        assert_eq!(
+            decode(b"{\"entries\":[]}"),
+            Err(CorruptArchive::MissingMagic)
+        );
+    }
+
+    #[test]
+    fn contents_that_pass_the_checksum_are_still_validated() {
+        let with_checksum = |mut contents: Vec<u8>| {
+            let checksum = crc32(&contents);
+            contents.extend_from_slice(&checksum.to_le_bytes());
+            contents
+        };
+        let header = || {
+            let mut contents = MAGIC.to_vec();
+            contents.extend_from_slice(&VERSION.to_le_bytes());
+            contents
+        };
+
+        let mut empty_list = header();
+        empty_list.extend_from_slice(&[1, LIST, 0, 1, b'k', 0]);
+        assert_eq!(
+            decode(&with_checksum(empty_list)),
+            Err(CorruptArchive::EmptyCollection)
+        );
+
+        let mut unknown_type = header();
+        unknown_type.extend_from_slice(&[1, 9, 0, 1, b'k']);
+        assert_eq!(
+            decode(&with_checksum(unknown_type)),
+            Err(CorruptArchive::UnknownType(9))
+        );
+
+        let mut short = header();
+        short.extend_from_slice(&[1, STRING, 0, 5, b'k']);
+        assert_eq!(
+            decode(&with_checksum(short)),
+            Err(CorruptArchive::Truncated)
+        );
+
+        let mut trailing = header();
+        trailing.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            decode(&with_checksum(trailing)),
+            Err(CorruptArchive::TrailingBytes)
+        );
+
+        let mut nan = header();
+        nan.extend_from_slice(&[1, SORTED_SET, 0, 1, b'z', 1, 1, b'm']);
+        nan.extend_from_slice(&f64::NAN.to_le_bytes());
+        assert_eq!(
+            decode(&with_checksum(nan)),
+            Err(CorruptArchive::InvalidScore)
+        );
+    }
+}
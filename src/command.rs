@@ -1,20 +1,619 @@
 use crate::error::Error;
-use crate::frame::Frame;
+use crate::frame::{Frame, Protocol};
+use crate::sorted_set::{
+    ScoreBound, ZAddComparison, ZAddCondition, ZAddOptions, format_score, parse_score,
+};
+use crate::store::{
+    ExpireCondition, ListEnd, ScanFilter, SetCondition, SetExpiration, SetOptions, parse_float,
+};
+use bytes::Bytes;
 use std::str;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Debug)]
 pub enum Command {
     PING,
-    GET { key: Vec<u8> },
-    SET { key: Vec<u8>, value: Vec<u8> },
-    DEL { key: Vec<u8> },
-    EXPIRE { key: Vec<u8>, value: u64 },
-    TTL { key: Vec<u8> },
+    GET {
+        key: Bytes,
+    },
+    SET {
+        key: Bytes,
+        value: Bytes,
+        options: SetOptions,
+    },
+    DEL {
+        keys: Vec<Bytes>,
+    },
+    UNLINK {
+        keys: Vec<Bytes>,
+    },
+    EXISTS {
+        keys: Vec<Bytes>,
+    },
+    TYPE {
+        key: Bytes,
+    },
+    RENAME {
+        key: Bytes,
+        new_key: Bytes,
+    },
+    RENAMENX {
+        key: Bytes,
+        new_key: Bytes,
+    },
+    COPY {
+        source: Bytes,
+        destination: Bytes,
+        replace: bool,
+    },
+    RANDOMKEY,
+    DBSIZE,
+    SCAN {
+        cursor: u64,
+        count: Option<u64>,
+        filter: ScanFilter,
+    },
+    KEYS {
+        pattern: Bytes,
+    },
+    MGET {
+        keys: Vec<Bytes>,
+    },
+    MSET {
+        pairs: Vec<(Bytes, Bytes)>,
+    },
+    MSETNX {
+        pairs: Vec<(Bytes, Bytes)>,
+    },
+    EXPIRE {
+        key: Bytes,
+        value: u64,
+        conditions: Vec<ExpireCondition>,
+    },
+    PEXPIRE {
+        key: Bytes,
+        value: u64,
+        conditions: Vec<ExpireCondition>,
+    },
+    EXPIREAT {
+        key: Bytes,
+        timestamp: u64,
+        conditions: Vec<ExpireCondition>,
+    },
+    PEXPIREAT {
+        key: Bytes,
+        timestamp: u64,
+        conditions: Vec<ExpireCondition>,
+    },
+    TTL {
+        key: Bytes,
+    },
+    PTTL {
+        key: Bytes,
+    },
+    EXPIRETIME {
+        key: Bytes,
+    },
+    PEXPIRETIME {
+        key: Bytes,
+    },
+    PERSIST {
+        key: Bytes,
+    },
+    INCR {
+        key: Bytes,
+    },
+    DECR {
+        key: Bytes,
+    },
+    INCRBY {
+        key: Bytes,
+        increment: i64,
+    },
+    DECRBY {
+        key: Bytes,
+        decrement: i64,
+    },
+    INCRBYFLOAT {
+        key: Bytes,
+        increment: f64,
+    },
+    LPUSH {
+        key: Bytes,
+        values: Vec<Bytes>,
+    },
+    RPUSH {
+        key: Bytes,
+        values: Vec<Bytes>,
+    },
+    LPOP {
+        key: Bytes,
+        count: Option<u64>,
+    },
+    RPOP {
+        key: Bytes,
+        count: Option<u64>,
+    },
+    /// `timeout` is `None` when the client asked to block forever.
+    BLPOP {
+        keys: Vec<Bytes>,
+        timeout: Option<Duration>,
+    },
+    BRPOP {
+        keys: Vec<Bytes>,
+        timeout: Option<Duration>,
+    },
+    BLMOVE {
+        source: Bytes,
+        destination: Bytes,
+        from: ListEnd,
+        to: ListEnd,
+        timeout: Option<Duration>,
+    },
+    LRANGE {
+        key: Bytes,
+        start: i64,
+        stop: i64,
+    },
+    LLEN {
+        key: Bytes,
+    },
+    HSET {
+        key: Bytes,
+        fields: Vec<(Bytes, Bytes)>,
+    },
+    HGET {
+        key: Bytes,
+        field: Bytes,
+    },
+    HDEL {
+        key: Bytes,
+        fields: Vec<Bytes>,
+    },
+    HGETALL {
+        key: Bytes,
+    },
+    HINCRBY {
+        key: Bytes,
+        field: Bytes,
+        increment: i64,
+    },
+    HLEN {
+        key: Bytes,
+    },
+    HEXISTS {
+        key: Bytes,
+        field: Bytes,
+    },
+    SADD {
+        key: Bytes,
+        members: Vec<Bytes>,
+    },
+    SREM {
+        key: Bytes,
+        members: Vec<Bytes>,
+    },
+    SMEMBERS {
+        key: Bytes,
+    },
+    SISMEMBER {
+        key: Bytes,
+        member: Bytes,
+    },
+    SCARD {
+        key: Bytes,
+    },
+    SINTER {
+        keys: Vec<Bytes>,
+    },
+    SUNION {
+        keys: Vec<Bytes>,
+    },
+    SDIFF {
+        keys: Vec<Bytes>,
+    },
+    SINTERSTORE {
+        destination: Bytes,
+        keys: Vec<Bytes>,
+    },
+    SUNIONSTORE {
+        destination: Bytes,
+        keys: Vec<Bytes>,
+    },
+    SDIFFSTORE {
+        destination: Bytes,
+        keys: Vec<Bytes>,
+    },
+    ZADD {
+        key: Bytes,
+        options: ZAddOptions,
+        members: Vec<(f64, Bytes)>,
+    },
+    ZRANGE {
+        key: Bytes,
+        start: i64,
+        stop: i64,
+        with_scores: bool,
+    },
+    ZRANGEBYSCORE {
+        key: Bytes,
+        min: ScoreBound,
+        max: ScoreBound,
+        with_scores: bool,
+        limit: Option<(i64, i64)>,
+    },
+    ZRANK {
+        key: Bytes,
+        member: Bytes,
+    },
+    ZINCRBY {
+        key: Bytes,
+        increment: f64,
+        member: Bytes,
+    },
+    ZREM {
+        key: Bytes,
+        members: Vec<Bytes>,
+    },
+    ZSCORE {
+        key: Bytes,
+        member: Bytes,
+    },
+    ZCARD {
+        key: Bytes,
+    },
+    MULTI,
+    EXEC,
+    DISCARD,
+    WATCH {
+        keys: Vec<Bytes>,
+    },
+    UNWATCH,
+    SUBSCRIBE {
+        channels: Vec<Bytes>,
+    },
+    UNSUBSCRIBE {
+        channels: Vec<Bytes>,
+    },
+    PSUBSCRIBE {
+        patterns: Vec<Bytes>,
+    },
+    PUNSUBSCRIBE {
+        patterns: Vec<Bytes>,
+    },
+    PUBLISH {
+        channel: Bytes,
+        message: Bytes,
+    },
+    HELLO {
+        protocol: Option<Protocol>,
+    },
+    INFO {
+        sections: Vec<Bytes>,
+    },
+    BGREWRITEAOF,
+    SAVE,
+    BGSAVE,
+    LASTSAVE,
+    SHUTDOWN {
+        /// `false` for `SHUTDOWN NOSAVE`, which skips the final save.
+        save: bool,
+    },
     QUIT,
     NOOP,
 }
 
-fn bulk_args(value: &Frame) -> Result<Vec<&[u8]>, Error> {
+impl Command {
+    /// Returns the arguments that redo this command, or `None` if it does not
+    /// write to the keyspace.
+    ///
+    /// Relative expirations become absolute ones, so replaying the arguments
+    /// later gives keys the same deadline. The blocking pops return `None`,
+    /// since what they write depends on when they are served; the store
+    /// records those pops with [`crate::store::Store::record_served_pops`].
+    pub fn write_args(&self) -> Option<Vec<Vec<u8>>> {
+        let mut args = Vec::new();
+        let mut arg = |value: &[u8]| args.push(value.to_vec());
+        match self {
+            Command::SET {
+                key,
+                value,
+                options,
+            } => {
+                arg(b"SET");
+                arg(key);
+                arg(value);
+                match options.condition {
+                    Some(SetCondition::IfMissing) => arg(b"NX"),
+                    Some(SetCondition::IfExists) => arg(b"XX"),
+                    None => {}
+                }
+                match options.expiration {
+                    Some(SetExpiration::After(duration)) => {
+                        arg(b"PXAT");
+                        arg(unix_millis_after(duration).to_string().as_bytes());
+                    }
+                    Some(SetExpiration::AtUnixMillis(millis)) => {
+                        arg(b"PXAT");
+                        arg(millis.to_string().as_bytes());
+                    }
+                    Some(SetExpiration::Keep) => arg(b"KEEPTTL"),
+                    None => {}
+                }
+            }
+            Command::DEL { keys } | Command::UNLINK { keys } => {
+                arg(b"DEL");
+                keys.iter().for_each(|key| arg(key));
+            }
+            Command::RENAME { key, new_key } => {
+                [&b"RENAME"[..], key, new_key].into_iter().for_each(arg);
+            }
+            Command::RENAMENX { key, new_key } => {
+                [&b"RENAMENX"[..], key, new_key].into_iter().for_each(arg);
+            }
+            Command::COPY {
+                source,
+                destination,
+                replace,
+            } => {
+                [&b"COPY"[..], source, destination]
+                    .into_iter()
+                    .for_each(&mut arg);
+                if *replace {
+                    arg(b"REPLACE");
+                }
+            }
+            Command::MSET { pairs } | Command::MSETNX { pairs } => {
+                arg(match self {
+                    Command::MSET { .. } => b"MSET",
+                    _ => b"MSETNX",
+                });
+                for (key, value) in pairs {
+                    arg(key);
+                    arg(value);
+                }
+            }
+            Command::EXPIRE {
+                key,
+                value,
+                conditions,
+            }
+            | Command::PEXPIRE {
+                key,
+                value,
+                conditions,
+            } => {
+                let duration = match self {
+                    Command::EXPIRE { .. } => Duration::from_secs(*value),
+                    _ => Duration::from_millis(*value),
+                };
+                arg(b"PEXPIREAT");
+                arg(key);
+                arg(unix_millis_after(duration).to_string().as_bytes());
+                conditions
+                    .iter()
+                    .for_each(|c| arg(expire_condition_name(c)));
+            }
+            Command::EXPIREAT {
+                key,
+                timestamp,
+                conditions,
+            }
+            | Command::PEXPIREAT {
+                key,
+                timestamp,
+                conditions,
+            } => {
+                arg(match self {
+                    Command::EXPIREAT { .. } => b"EXPIREAT",
+                    _ => b"PEXPIREAT",
+                });
+                arg(key);
+                arg(timestamp.to_string().as_bytes());
+                conditions
+                    .iter()
+                    .for_each(|c| arg(expire_condition_name(c)));
+            }
+            Command::PERSIST { key } => [&b"PERSIST"[..], key].into_iter().for_each(arg),
+            Command::INCR { key } => [&b"INCR"[..], key].into_iter().for_each(arg),
+            Command::DECR { key } => [&b"DECR"[..], key].into_iter().for_each(arg),
+            Command::INCRBY { key, increment } => {
+                [&b"INCRBY"[..], key, increment.to_string().as_bytes()]
+                    .into_iter()
+                    .for_each(arg);
+            }
+            Command::DECRBY { key, decrement } => {
+                [&b"DECRBY"[..], key, decrement.to_string().as_bytes()]
+                    .into_iter()
+                    .for_each(arg);
+            }
+            Command::INCRBYFLOAT { key, increment } => {
+                [&b"INCRBYFLOAT"[..], key, increment.to_string().as_bytes()]
+                    .into_iter()
+                    .for_each(arg);
+            }
+            Command::LPUSH { key, values } | Command::RPUSH { key, values } => {
+                arg(match self {
+                    Command::LPUSH { .. } => b"LPUSH",
+                    _ => b"RPUSH",
+                });
+                arg(key);
+                values.iter().for_each(|value| arg(value));
+            }
+            Command::LPOP { key, count } | Command::RPOP { key, count } => {
+                arg(match self {
+                    Command::LPOP { .. } => b"LPOP",
+                    _ => b"RPOP",
+                });
+                arg(key);
+                if let Some(count) = count {
+                    arg(count.to_string().as_bytes());
+                }
+            }
+            Command::HSET { key, fields } => {
+                arg(b"HSET");
+                arg(key);
+                for (field, value) in fields {
+                    arg(field);
+                    arg(value);
+                }
+            }
+            Command::HDEL { key, fields } => {
+                arg(b"HDEL");
+                arg(key);
+                fields.iter().for_each(|field| arg(field));
+            }
+            Command::HINCRBY {
+                key,
+                field,
+                increment,
+            } => {
+                [
+                    &b"HINCRBY"[..],
+                    key,
+                    field,
+                    increment.to_string().as_bytes(),
+                ]
+                .into_iter()
+                .for_each(arg);
+            }
+            Command::SADD { key, members }
+            | Command::SREM { key, members }
+            | Command::ZREM { key, members } => {
+                arg(match self {
+                    Command::SADD { .. } => b"SADD",
+                    Command::SREM { .. } => b"SREM",
+                    _ => b"ZREM",
+                });
+                arg(key);
+                members.iter().for_each(|member| arg(member));
+            }
+            Command::SINTERSTORE { destination, keys }
+            | Command::SUNIONSTORE { destination, keys }
+            | Command::SDIFFSTORE { destination, keys } => {
+                arg(match self {
+                    Command::SINTERSTORE { .. } => b"SINTERSTORE",
+                    Command::SUNIONSTORE { .. } => b"SUNIONSTORE",
+                    _ => b"SDIFFSTORE",
+                });
+                arg(destination);
+                keys.iter().for_each(|key| arg(key));
+            }
+            Command::ZADD {
+                key,
+                options,
+                members,
+            } => {
+                arg(b"ZADD");
+                arg(key);
+                match options.condition {
+                    Some(ZAddCondition::OnlyNew) => arg(b"NX"),
+                    Some(ZAddCondition::OnlyExisting) => arg(b"XX"),
+                    None => {}
+                }
+                match options.comparison {
+                    Some(ZAddComparison::GreaterThan) => arg(b"GT"),
+                    Some(ZAddComparison::LessThan) => arg(b"LT"),
+                    None => {}
+                }
+                for (score, member) in members {
+                    arg(format_score(*score).as_bytes());
+                    arg(member);
+                }
+            }
+            Command::ZINCRBY {
+                key,
+                increment,
+                member,
+            } => {
+                [
+                    &b"ZINCRBY"[..],
+                    key,
+                    format_score(*increment).as_bytes(),
+                    member,
+                ]
+                .into_iter()
+                .for_each(arg);
+            }
+            Command::BLPOP { .. } | Command::BRPOP { .. } | Command::BLMOVE { .. } => return None,
+            Command::PING
+            | Command::GET { .. }
+            | Command::EXISTS { .. }
+            | Command::TYPE { .. }
+            | Command::RANDOMKEY
+            | Command::DBSIZE
+            | Command::SCAN { .. }
+            | Command::KEYS { .. }
+            | Command::MGET { .. }
+            | Command::TTL { .. }
+            | Command::PTTL { .. }
+            | Command::EXPIRETIME { .. }
+            | Command::PEXPIRETIME { .. }
+            | Command::LRANGE { .. }
+            | Command::LLEN { .. }
+            | Command::HGET { .. }
+            | Command::HGETALL { .. }
+            | Command::HLEN { .. }
+            | Command::HEXISTS { .. }
+            | Command::SMEMBERS { .. }
+            | Command::SISMEMBER { .. }
+            | Command::SCARD { .. }
+            | Command::SINTER { .. }
+            | Command::SUNION { .. }
+            | Command::SDIFF { .. }
+            | Command::ZRANGE { .. }
+            | Command::ZRANGEBYSCORE { .. }
+            | Command::ZRANK { .. }
+            | Command::ZSCORE { .. }
+            | Command::ZCARD { .. }
+            | Command::MULTI
+            | Command::EXEC
+            | Command::DISCARD
+            | Command::WATCH { .. }
+            | Command::UNWATCH
+            | Command::SUBSCRIBE { .. }
+            | Command::UNSUBSCRIBE { .. }
+            | Command::PSUBSCRIBE { .. }
+            | Command::PUNSUBSCRIBE { .. }
+            | Command::PUBLISH { .. }
+            | Command::HELLO { .. }
+            | Command::INFO { .. }
+            | Command::BGREWRITEAOF
+            | Command::SAVE
+            | Command::BGSAVE
+            | Command::LASTSAVE
+            | Command::SHUTDOWN { .. }
+            | Command::QUIT
+            | Command::NOOP => return None,
+        }
+        Some(args)
+    }
+}
+
+/// Whether a deadline `millis` from now still fits the signed Unix
+/// milliseconds that [`Command::write_args`] logs it as.
+fn deadline_fits(millis: u64) -> bool {
+    i64::try_from(unix_millis_after(Duration::from_millis(millis))).is_ok()
+}
+
+fn unix_millis_after(duration: Duration) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    u64::try_from(now.saturating_add(duration).as_millis()).unwrap_or(u64::MAX)
+}
+
+fn expire_condition_name(condition: &ExpireCondition) -> &'static [u8] {
+    match condition {
+        ExpireCondition::IfNone => b"NX",
+        ExpireCondition::IfSome => b"XX",
+        ExpireCondition::IfGreater => b"GT",
+        ExpireCondition::IfLess => b"LT",
+    }
+}
+
+/// Takes the arguments of a command frame, sharing their bytes with it.
+fn bulk_args(value: &Frame) -> Result<Vec<Bytes>, Error> {
     let args = match value {
         Frame::Array(Some(inner)) if !inner.is_empty() => inner,
         _ => return Err(Error::InvalidCommandFrame),
@@ -22,12 +621,15 @@ fn bulk_args(value: &Frame) -> Result<Vec<&[u8]>, Error> {
 
     args.iter()
         .map(|arg| match arg {
-            Frame::Bulk(Some(inner)) => Ok(inner.as_slice()),
+            Frame::Bulk(Some(inner)) => Ok(inner.clone()),
             _ => Err(Error::InvalidCommandFrame),
         })
         .collect()
 }
 
+const NOT_AN_INTEGER: &str = "value is not an integer or out of range";
+const NOT_A_FLOAT: &str = "value is not a valid float";
+
 fn wrong_arity(command: &str, given: usize, expected: usize) -> Error {
     Error::WrongArity {
         command: command.to_string(),
@@ -36,521 +638,2868 @@ fn wrong_arity(command: &str, given: usize, expected: usize) -> Error {
     }
 }
 
-fn parse_ping(argv: &[&[u8]]) -> Result<Command, Error> {
+fn parse_ping(argv: &[Bytes]) -> Result<Command, Error> {
     match argv {
         [] => Ok(Command::PING),
         _ => Err(wrong_arity("PING", argv.len(), 0)),
     }
 }
 
-fn parse_quit(argv: &[&[u8]]) -> Result<Command, Error> {
+fn parse_quit(argv: &[Bytes]) -> Result<Command, Error> {
     match argv {
         [] => Ok(Command::QUIT),
         _ => Err(wrong_arity("QUIT", argv.len(), 0)),
     }
 }
 
-fn parse_get(argv: &[&[u8]]) -> Result<Command, Error> {
+fn parse_get(argv: &[Bytes]) -> Result<Command, Error> {
     match argv {
-        [key] => Ok(Command::GET { key: key.to_vec() }),
+        [key] => Ok(Command::GET { key: key.clone() }),
         _ => Err(wrong_arity("GET", argv.len(), 1)),
     }
 }
 
-fn parse_set(argv: &[&[u8]]) -> Result<Command, Error> {
+fn parse_set(argv: &[Bytes]) -> Result<Command, Error> {
+    let [key, value, rest @ ..] = argv else {
+        return Err(wrong_arity("SET", argv.len(), 2));
+    };
+
+    let mut options = SetOptions::default();
+    let mut rest = rest;
+    while let [option, tail @ ..] = rest {
+        rest = tail;
+        if option.eq_ignore_ascii_case(b"nx") || option.eq_ignore_ascii_case(b"xx") {
+            let condition = if option.eq_ignore_ascii_case(b"nx") {
+                SetCondition::IfMissing
+            } else {
+                SetCondition::IfExists
+            };
+            if options.condition.is_some_and(|c| c != condition) {
+                return Err(Error::SyntaxError);
+            }
+            options.condition = Some(condition);
+        } else if option.eq_ignore_ascii_case(b"get") {
+            options.get = true;
+        } else if option.eq_ignore_ascii_case(b"keepttl") {
+            if options.expiration.is_some_and(|e| e != SetExpiration::Keep) {
+                return Err(Error::SyntaxError);
+            }
+            options.expiration = Some(SetExpiration::Keep);
+        } else {
+            let [time, tail @ ..] = rest else {
+                return Err(Error::SyntaxError);
+            };
+            if options.expiration.is_some() {
+                return Err(Error::SyntaxError);
+            }
+            rest = tail;
+            options.expiration = Some(parse_set_expiration(option, time)?);
+        }
+    }
+
+    Ok(Command::SET {
+        key: key.clone(),
+        value: value.clone(),
+        options,
+    })
+}
+
+/// Parses an `EX`, `PX`, `EXAT` or `PXAT` option and its time argument.
+fn parse_set_expiration(option: &[u8], time: &[u8]) -> Result<SetExpiration, Error> {
+    let unit_millis = if option.eq_ignore_ascii_case(b"ex") || option.eq_ignore_ascii_case(b"exat")
+    {
+        1000
+    } else if option.eq_ignore_ascii_case(b"px") || option.eq_ignore_ascii_case(b"pxat") {
+        1
+    } else {
+        return Err(Error::SyntaxError);
+    };
+    let millis = parse_i64_arg(time)?
+        .checked_mul(unit_millis)
+        .filter(|millis| *millis > 0)
+        .ok_or(Error::InvalidExpireTime("set"))? as u64;
+    if option.len() == 2 {
+        if !deadline_fits(millis) {
+            return Err(Error::InvalidExpireTime("set"));
+        }
+        Ok(SetExpiration::After(Duration::from_millis(millis)))
+    } else {
+        Ok(SetExpiration::AtUnixMillis(millis))
+    }
+}
+
+fn parse_del(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::DEL {
+        keys: parse_keys("DEL", argv)?,
+    })
+}
+
+fn parse_unlink(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::UNLINK {
+        keys: parse_keys("UNLINK", argv)?,
+    })
+}
+
+fn parse_exists(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::EXISTS {
+        keys: parse_keys("EXISTS", argv)?,
+    })
+}
+
+fn parse_type(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::TYPE { key: key.clone() }),
+        _ => Err(wrong_arity("TYPE", argv.len(), 1)),
+    }
+}
+
+fn parse_rename(argv: &[Bytes]) -> Result<Command, Error> {
     match argv {
-        [key, value] => Ok(Command::SET {
-            key: key.to_vec(),
-            value: value.to_vec(),
+        [key, new_key] => Ok(Command::RENAME {
+            key: key.clone(),
+            new_key: new_key.clone(),
         }),
-        _ => Err(wrong_arity("SET", argv.len(), 2)),
+        _ => Err(wrong_arity("RENAME", argv.len(), 2)),
     }
 }
 
-fn parse_del(argv: &[&[u8]]) -> Result<Command, Error> {
+fn parse_renamenx(argv: &[Bytes]) -> Result<Command, Error> {
     match argv {
-        [key] => Ok(Command::DEL { key: key.to_vec() }),
-        _ => Err(wrong_arity("DEL", argv.len(), 1)),
+        [key, new_key] => Ok(Command::RENAMENX {
+            key: key.clone(),
+            new_key: new_key.clone(),
+        }),
+        _ => Err(wrong_arity("RENAMENX", argv.len(), 2)),
     }
 }
 
-fn parse_u64_arg(value: &[u8]) -> Result<u64, Error> {
-    str::from_utf8(value)
-        .map_err(|_| Error::WrongArgumentType)?
-        .parse::<u64>()
-        .map_err(|_| Error::WrongArgumentType)
+fn parse_copy(argv: &[Bytes]) -> Result<Command, Error> {
+    let (source, destination, replace) = match argv {
+        [source, destination] => (source, destination, false),
+        [source, destination, option] if option.eq_ignore_ascii_case(b"replace") => {
+            (source, destination, true)
+        }
+        [_, _, ..] => return Err(Error::SyntaxError),
+        _ => return Err(wrong_arity("COPY", argv.len(), 2)),
+    };
+    Ok(Command::COPY {
+        source: source.clone(),
+        destination: destination.clone(),
+        replace,
+    })
 }
 
-fn parse_expire(argv: &[&[u8]]) -> Result<Command, Error> {
+fn parse_randomkey(argv: &[Bytes]) -> Result<Command, Error> {
     match argv {
-        [key, value] => Ok(Command::EXPIRE {
-            key: key.to_vec(),
-            value: parse_u64_arg(value)?,
-        }),
-        _ => Err(wrong_arity("EXPIRE", argv.len(), 2)),
+        [] => Ok(Command::RANDOMKEY),
+        _ => Err(wrong_arity("RANDOMKEY", argv.len(), 0)),
     }
 }
 
-fn parse_ttl(argv: &[&[u8]]) -> Result<Command, Error> {
+fn parse_info(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::INFO {
+        sections: argv.to_vec(),
+    })
+}
+
+fn parse_bgrewriteaof(argv: &[Bytes]) -> Result<Command, Error> {
     match argv {
-        [key] => Ok(Command::TTL { key: key.to_vec() }),
-        _ => Err(wrong_arity("TTL", argv.len(), 1)),
+        [] => Ok(Command::BGREWRITEAOF),
+        _ => Err(wrong_arity("BGREWRITEAOF", argv.len(), 0)),
     }
 }
 
-impl TryFrom<&Frame> for Command {
-    type Error = Error;
+fn parse_save(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [] => Ok(Command::SAVE),
+        _ => Err(wrong_arity("SAVE", argv.len(), 0)),
+    }
+}
 
-    fn try_from(value: &Frame) -> Result<Self, Self::Error> {
-        let args = bulk_args(value)?;
-        let (cmd, argv) = args.split_first().ok_or(Error::InvalidCommandFrame)?;
+fn parse_bgsave(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [] => Ok(Command::BGSAVE),
+        _ => Err(wrong_arity("BGSAVE", argv.len(), 0)),
+    }
+}
 
-        if cmd.eq_ignore_ascii_case(b"ping") {
-            return parse_ping(argv);
-        }
-        if cmd.eq_ignore_ascii_case(b"quit") {
-            return parse_quit(argv);
-        }
-        if cmd.eq_ignore_ascii_case(b"get") {
-            return parse_get(argv);
-        }
-        if cmd.eq_ignore_ascii_case(b"set") {
-            return parse_set(argv);
-        }
-        if cmd.eq_ignore_ascii_case(b"del") {
-            return parse_del(argv);
-        }
-        if cmd.eq_ignore_ascii_case(b"expire") {
-            return parse_expire(argv);
-        }
-        if cmd.eq_ignore_ascii_case(b"ttl") {
-            return parse_ttl(argv);
+fn parse_lastsave(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [] => Ok(Command::LASTSAVE),
+        _ => Err(wrong_arity("LASTSAVE", argv.len(), 0)),
+    }
+}
+
+fn parse_shutdown(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [] => Ok(Command::SHUTDOWN { save: true }),
+        [option] if option.eq_ignore_ascii_case(b"save") => Ok(Command::SHUTDOWN { save: true }),
+        [option] if option.eq_ignore_ascii_case(b"nosave") => Ok(Command::SHUTDOWN { save: false }),
+        _ => Err(Error::SyntaxError),
+    }
+}
+
+fn parse_dbsize(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [] => Ok(Command::DBSIZE),
+        _ => Err(wrong_arity("DBSIZE", argv.len(), 0)),
+    }
+}
+
+fn parse_scan(argv: &[Bytes]) -> Result<Command, Error> {
+    let [cursor, options @ ..] = argv else {
+        return Err(wrong_arity("SCAN", argv.len(), 1));
+    };
+    let cursor = parse_u64_arg(cursor)?;
+    let mut count = None;
+    let mut filter = ScanFilter::default();
+    for option in options.chunks(2) {
+        match option {
+            [name, pattern] if name.eq_ignore_ascii_case(b"match") => {
+                filter.pattern = Some(pattern.clone());
+            }
+            [name, value] if name.eq_ignore_ascii_case(b"count") => match parse_u64_arg(value)? {
+                0 => return Err(Error::SyntaxError),
+                value => count = Some(value),
+            },
+            [name, type_name] if name.eq_ignore_ascii_case(b"type") => {
+                filter.type_name = Some(type_name.clone());
+            }
+            _ => return Err(Error::SyntaxError),
         }
+    }
+    Ok(Command::SCAN {
+        cursor,
+        count,
+        filter,
+    })
+}
 
-        Err(Error::UnknownCommand)
+fn parse_keys_command(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [pattern] => Ok(Command::KEYS {
+            pattern: pattern.clone(),
+        }),
+        _ => Err(wrong_arity("KEYS", argv.len(), 1)),
     }
 }
 
-impl TryFrom<Frame> for Command {
-    type Error = Error;
+fn parse_mget(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::MGET {
+        keys: parse_keys("MGET", argv)?,
+    })
+}
 
-    fn try_from(value: Frame) -> Result<Self, Self::Error> {
-        Command::try_from(&value)
+type KeyValuePairs = Vec<(Bytes, Bytes)>;
+
+fn parse_pairs(command: &str, argv: &[Bytes]) -> Result<KeyValuePairs, Error> {
+    if argv.is_empty() || !argv.len().is_multiple_of(2) {
+        return Err(wrong_arity(command, argv.len(), 2));
     }
+    Ok(argv
+        .chunks_exact(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+fn parse_mset(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::MSET {
+        pairs: parse_pairs("MSET", argv)?,
+    })
+}
 
-    fn bulk(value: &[u8]) -> Frame {
-        Frame::Bulk(Some(value.to_vec()))
+fn parse_msetnx(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::MSETNX {
+        pairs: parse_pairs("MSETNX", argv)?,
+    })
+}
+
+fn parse_u64_arg(value: &[u8]) -> Result<u64, Error> {
+    str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .ok_or(Error::WrongArgumentType(NOT_AN_INTEGER))
+}
+
+fn parse_i64_arg(value: &[u8]) -> Result<i64, Error> {
+    str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .ok_or(Error::WrongArgumentType(NOT_AN_INTEGER))
+}
+
+/// Parses the `key time [NX|XX|GT|LT ...]` arguments shared by the `EXPIRE`
+/// family, checking that `time` is still representable in milliseconds.
+fn parse_expire_args(
+    command: &'static str,
+    argv: &[Bytes],
+    unit_millis: u64,
+) -> Result<(Bytes, u64, Vec<ExpireCondition>), Error> {
+    let [key, time, flags @ ..] = argv else {
+        return Err(wrong_arity(&command.to_uppercase(), argv.len(), 2));
+    };
+    let time = parse_u64_arg(time)?;
+    time.checked_mul(unit_millis)
+        .filter(|millis| i64::try_from(*millis).is_ok())
+        .ok_or(Error::InvalidExpireTime(command))?;
+
+    let mut conditions = Vec::new();
+    for flag in flags {
+        let condition = if flag.eq_ignore_ascii_case(b"nx") {
+            ExpireCondition::IfNone
+        } else if flag.eq_ignore_ascii_case(b"xx") {
+            ExpireCondition::IfSome
+        } else if flag.eq_ignore_ascii_case(b"gt") {
+            ExpireCondition::IfGreater
+        } else if flag.eq_ignore_ascii_case(b"lt") {
+            ExpireCondition::IfLess
+        } else {
+            return Err(Error::SyntaxError);
+        };
+        if !conditions.contains(&condition) {
+            conditions.push(condition);
+        }
+    }
+    let has = |condition| conditions.contains(&condition);
+    if has(ExpireCondition::IfNone) && conditions.len() > 1 {
+        return Err(Error::IncompatibleOptions(
+            "NX and XX, GT or LT options at the same time are not compatible",
+        ));
     }
+    if has(ExpireCondition::IfGreater) && has(ExpireCondition::IfLess) {
+        return Err(Error::IncompatibleOptions(
+            "GT and LT options at the same time are not compatible",
+        ));
+    }
+    Ok((key.clone(), time, conditions))
+}
 
-    #[test]
-    fn get_command_parses() {
-        let frame = Frame::Array(Some(vec![bulk(b"GET"), bulk(b"mykey")]));
+fn parse_expire(argv: &[Bytes]) -> Result<Command, Error> {
+    let (key, value, conditions) = parse_expire_args("expire", argv, 1000)?;
+    if !deadline_fits(value * 1000) {
+        return Err(Error::InvalidExpireTime("expire"));
+    }
+    Ok(Command::EXPIRE {
+        key,
+        value,
+        conditions,
+    })
+}
 
-        let command = Command::try_from(frame).unwrap();
-        assert_eq!(
-            command,
-            Command::GET {
-                key: b"mykey".to_vec()
-            }
-        );
+fn parse_pexpire(argv: &[Bytes]) -> Result<Command, Error> {
+    let (key, value, conditions) = parse_expire_args("pexpire", argv, 1)?;
+    if !deadline_fits(value) {
+        return Err(Error::InvalidExpireTime("pexpire"));
     }
+    Ok(Command::PEXPIRE {
+        key,
+        value,
+        conditions,
+    })
+}
 
-    #[test]
-    fn ping_command_parses() {
-        let frame = Frame::Array(Some(vec![bulk(b"PING")]));
+fn parse_expireat(argv: &[Bytes]) -> Result<Command, Error> {
+    let (key, timestamp, conditions) = parse_expire_args("expireat", argv, 1000)?;
+    Ok(Command::EXPIREAT {
+        key,
+        timestamp,
+        conditions,
+    })
+}
 
-        let command = Command::try_from(frame).unwrap();
-        assert_eq!(command, Command::PING);
+fn parse_pexpireat(argv: &[Bytes]) -> Result<Command, Error> {
+    let (key, timestamp, conditions) = parse_expire_args("pexpireat", argv, 1)?;
+    Ok(Command::PEXPIREAT {
+        key,
+        timestamp,
+        conditions,
+    })
+}
+
+fn parse_ttl(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::TTL { key: key.clone() }),
+        _ => Err(wrong_arity("TTL", argv.len(), 1)),
     }
+}
 
-    #[test]
-    fn quit_command_parses() {
-        let frame = Frame::Array(Some(vec![bulk(b"QUIT")]));
+fn parse_pttl(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::PTTL { key: key.clone() }),
+        _ => Err(wrong_arity("PTTL", argv.len(), 1)),
+    }
+}
 
-        let command = Command::try_from(frame).unwrap();
-        assert_eq!(command, Command::QUIT);
+fn parse_expiretime(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::EXPIRETIME { key: key.clone() }),
+        _ => Err(wrong_arity("EXPIRETIME", argv.len(), 1)),
     }
+}
 
-    #[test]
-    fn set_command_parses() {
-        let frame = Frame::Array(Some(vec![bulk(b"SET"), bulk(b"mykey"), bulk(b"myvalue")]));
+fn parse_pexpiretime(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::PEXPIRETIME { key: key.clone() }),
+        _ => Err(wrong_arity("PEXPIRETIME", argv.len(), 1)),
+    }
+}
 
-        let command = Command::try_from(frame).unwrap();
-        assert_eq!(
-            command,
-            Command::SET {
-                key: b"mykey".to_vec(),
-                value: b"myvalue".to_vec(),
-            }
-        );
+fn parse_persist(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::PERSIST { key: key.clone() }),
+        _ => Err(wrong_arity("PERSIST", argv.len(), 1)),
     }
+}
 
-    #[test]
-    fn del_command_parses() {
-        let frame = Frame::Array(Some(vec![bulk(b"DEL"), bulk(b"mykey")]));
+fn parse_incr(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::INCR { key: key.clone() }),
+        _ => Err(wrong_arity("INCR", argv.len(), 1)),
+    }
+}
 
-        let command = Command::try_from(frame).unwrap();
-        assert_eq!(
-            command,
-            Command::DEL {
-                key: b"mykey".to_vec()
-            }
-        );
+fn parse_decr(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::DECR { key: key.clone() }),
+        _ => Err(wrong_arity("DECR", argv.len(), 1)),
     }
+}
 
-    #[test]
-    fn expire_command_parses() {
-        let frame = Frame::Array(Some(vec![bulk(b"EXPIRE"), bulk(b"mykey"), bulk(b"123")]));
+fn parse_incrby(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, increment] => Ok(Command::INCRBY {
+            key: key.clone(),
+            increment: parse_i64_arg(increment)?,
+        }),
+        _ => Err(wrong_arity("INCRBY", argv.len(), 2)),
+    }
+}
 
-        let command = Command::try_from(frame).unwrap();
-        assert_eq!(
-            command,
-            Command::EXPIRE {
-                key: b"mykey".to_vec(),
-                value: 123,
-            }
-        );
+fn parse_decrby(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, decrement] => Ok(Command::DECRBY {
+            key: key.clone(),
+            decrement: parse_i64_arg(decrement)?,
+        }),
+        _ => Err(wrong_arity("DECRBY", argv.len(), 2)),
     }
+}
 
-    #[test]
-    fn ttl_command_parses() {
-        let frame = Frame::Array(Some(vec![bulk(b"TTL"), bulk(b"mykey")]));
+fn parse_incrbyfloat(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, increment] => Ok(Command::INCRBYFLOAT {
+            key: key.clone(),
+            increment: parse_float(increment).ok_or(Error::WrongArgumentType(NOT_A_FLOAT))?,
+        }),
+        _ => Err(wrong_arity("INCRBYFLOAT", argv.len(), 2)),
+    }
+}
 
-        let command = Command::try_from(frame).unwrap();
-        assert_eq!(
-            command,
-            Command::TTL {
-                key: b"mykey".to_vec()
-            }
-        );
+fn parse_lpush(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, values @ ..] if !values.is_empty() => Ok(Command::LPUSH {
+            key: key.clone(),
+            values: values.to_vec(),
+        }),
+        _ => Err(wrong_arity("LPUSH", argv.len(), 2)),
     }
+}
 
-    #[test]
-    fn command_name_is_case_insensitive() {
-        let ping = Frame::Array(Some(vec![bulk(b"pInG")]));
-        let quit = Frame::Array(Some(vec![bulk(b"qUiT")]));
-        let get = Frame::Array(Some(vec![bulk(b"gEt"), bulk(b"mykey")]));
-        let set = Frame::Array(Some(vec![bulk(b"SeT"), bulk(b"mykey"), bulk(b"myvalue")]));
-        let del = Frame::Array(Some(vec![bulk(b"dEl"), bulk(b"mykey")]));
-        let expire = Frame::Array(Some(vec![bulk(b"eXpIrE"), bulk(b"mykey"), bulk(b"60")]));
-        let ttl = Frame::Array(Some(vec![bulk(b"TtL"), bulk(b"mykey")]));
+fn parse_rpush(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, values @ ..] if !values.is_empty() => Ok(Command::RPUSH {
+            key: key.clone(),
+            values: values.to_vec(),
+        }),
+        _ => Err(wrong_arity("RPUSH", argv.len(), 2)),
+    }
+}
 
-        assert_eq!(Command::try_from(ping).unwrap(), Command::PING);
-        assert_eq!(Command::try_from(quit).unwrap(), Command::QUIT);
+fn parse_lpop(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::LPOP {
+            key: key.clone(),
+            count: None,
+        }),
+        [key, count] => Ok(Command::LPOP {
+            key: key.clone(),
+            count: Some(parse_u64_arg(count)?),
+        }),
+        _ => Err(wrong_arity("LPOP", argv.len(), 1)),
+    }
+}
+
+fn parse_rpop(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::RPOP {
+            key: key.clone(),
+            count: None,
+        }),
+        [key, count] => Ok(Command::RPOP {
+            key: key.clone(),
+            count: Some(parse_u64_arg(count)?),
+        }),
+        _ => Err(wrong_arity("RPOP", argv.len(), 1)),
+    }
+}
+
+/// Parses a blocking timeout in seconds, where `0` means no timeout.
+fn parse_timeout_arg(arg: &[u8]) -> Result<Option<Duration>, Error> {
+    let seconds = parse_float(arg).ok_or(Error::InvalidTimeout(
+        "timeout is not a float or out of range",
+    ))?;
+    if seconds < 0.0 {
+        return Err(Error::InvalidTimeout("timeout is negative"));
+    }
+    match seconds {
+        0.0 => Ok(None),
+        seconds => Duration::try_from_secs_f64(seconds)
+            .map(Some)
+            .map_err(|_| Error::InvalidTimeout("timeout is out of range")),
+    }
+}
+
+fn parse_list_end(arg: &[u8]) -> Result<ListEnd, Error> {
+    if arg.eq_ignore_ascii_case(b"left") {
+        Ok(ListEnd::Left)
+    } else if arg.eq_ignore_ascii_case(b"right") {
+        Ok(ListEnd::Right)
+    } else {
+        Err(Error::SyntaxError)
+    }
+}
+
+fn parse_blocking_pop(
+    command: &str,
+    argv: &[Bytes],
+) -> Result<(Vec<Bytes>, Option<Duration>), Error> {
+    match argv {
+        [keys @ .., timeout] if !keys.is_empty() => {
+            Ok((keys.to_vec(), parse_timeout_arg(timeout)?))
+        }
+        _ => Err(wrong_arity(command, argv.len(), 2)),
+    }
+}
+
+fn parse_blpop(argv: &[Bytes]) -> Result<Command, Error> {
+    let (keys, timeout) = parse_blocking_pop("BLPOP", argv)?;
+    Ok(Command::BLPOP { keys, timeout })
+}
+
+fn parse_brpop(argv: &[Bytes]) -> Result<Command, Error> {
+    let (keys, timeout) = parse_blocking_pop("BRPOP", argv)?;
+    Ok(Command::BRPOP { keys, timeout })
+}
+
+fn parse_blmove(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [source, destination, from, to, timeout] => Ok(Command::BLMOVE {
+            source: source.clone(),
+            destination: destination.clone(),
+            from: parse_list_end(from)?,
+            to: parse_list_end(to)?,
+            timeout: parse_timeout_arg(timeout)?,
+        }),
+        _ => Err(wrong_arity("BLMOVE", argv.len(), 5)),
+    }
+}
+
+fn parse_lrange(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, start, stop] => Ok(Command::LRANGE {
+            key: key.clone(),
+            start: parse_i64_arg(start)?,
+            stop: parse_i64_arg(stop)?,
+        }),
+        _ => Err(wrong_arity("LRANGE", argv.len(), 3)),
+    }
+}
+
+fn parse_llen(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::LLEN { key: key.clone() }),
+        _ => Err(wrong_arity("LLEN", argv.len(), 1)),
+    }
+}
+
+fn parse_hset(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, pairs @ ..] if !pairs.is_empty() && pairs.len() % 2 == 0 => Ok(Command::HSET {
+            key: key.clone(),
+            fields: pairs
+                .chunks_exact(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect(),
+        }),
+        _ => Err(wrong_arity("HSET", argv.len(), 3)),
+    }
+}
+
+fn parse_hget(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, field] => Ok(Command::HGET {
+            key: key.clone(),
+            field: field.clone(),
+        }),
+        _ => Err(wrong_arity("HGET", argv.len(), 2)),
+    }
+}
+
+fn parse_hdel(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, fields @ ..] if !fields.is_empty() => Ok(Command::HDEL {
+            key: key.clone(),
+            fields: fields.to_vec(),
+        }),
+        _ => Err(wrong_arity("HDEL", argv.len(), 2)),
+    }
+}
+
+fn parse_hgetall(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::HGETALL { key: key.clone() }),
+        _ => Err(wrong_arity("HGETALL", argv.len(), 1)),
+    }
+}
+
+fn parse_hincrby(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, field, increment] => Ok(Command::HINCRBY {
+            key: key.clone(),
+            field: field.clone(),
+            increment: parse_i64_arg(increment)?,
+        }),
+        _ => Err(wrong_arity("HINCRBY", argv.len(), 3)),
+    }
+}
+
+fn parse_hlen(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::HLEN { key: key.clone() }),
+        _ => Err(wrong_arity("HLEN", argv.len(), 1)),
+    }
+}
+
+fn parse_hexists(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, field] => Ok(Command::HEXISTS {
+            key: key.clone(),
+            field: field.clone(),
+        }),
+        _ => Err(wrong_arity("HEXISTS", argv.len(), 2)),
+    }
+}
+
+fn parse_sadd(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, members @ ..] if !members.is_empty() => Ok(Command::SADD {
+            key: key.clone(),
+            members: members.to_vec(),
+        }),
+        _ => Err(wrong_arity("SADD", argv.len(), 2)),
+    }
+}
+
+fn parse_srem(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, members @ ..] if !members.is_empty() => Ok(Command::SREM {
+            key: key.clone(),
+            members: members.to_vec(),
+        }),
+        _ => Err(wrong_arity("SREM", argv.len(), 2)),
+    }
+}
+
+fn parse_smembers(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::SMEMBERS { key: key.clone() }),
+        _ => Err(wrong_arity("SMEMBERS", argv.len(), 1)),
+    }
+}
+
+fn parse_sismember(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, member] => Ok(Command::SISMEMBER {
+            key: key.clone(),
+            member: member.clone(),
+        }),
+        _ => Err(wrong_arity("SISMEMBER", argv.len(), 2)),
+    }
+}
+
+fn parse_scard(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::SCARD { key: key.clone() }),
+        _ => Err(wrong_arity("SCARD", argv.len(), 1)),
+    }
+}
+
+fn parse_keys(command: &str, argv: &[Bytes]) -> Result<Vec<Bytes>, Error> {
+    match argv {
+        [] => Err(wrong_arity(command, argv.len(), 1)),
+        keys => Ok(keys.to_vec()),
+    }
+}
+
+fn parse_destination_and_keys(command: &str, argv: &[Bytes]) -> Result<(Bytes, Vec<Bytes>), Error> {
+    match argv {
+        [destination, keys @ ..] if !keys.is_empty() => Ok((destination.clone(), keys.to_vec())),
+        _ => Err(wrong_arity(command, argv.len(), 2)),
+    }
+}
+
+fn parse_sinter(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::SINTER {
+        keys: parse_keys("SINTER", argv)?,
+    })
+}
+
+fn parse_sunion(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::SUNION {
+        keys: parse_keys("SUNION", argv)?,
+    })
+}
+
+fn parse_sdiff(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::SDIFF {
+        keys: parse_keys("SDIFF", argv)?,
+    })
+}
+
+fn parse_sinterstore(argv: &[Bytes]) -> Result<Command, Error> {
+    let (destination, keys) = parse_destination_and_keys("SINTERSTORE", argv)?;
+    Ok(Command::SINTERSTORE { destination, keys })
+}
+
+fn parse_sunionstore(argv: &[Bytes]) -> Result<Command, Error> {
+    let (destination, keys) = parse_destination_and_keys("SUNIONSTORE", argv)?;
+    Ok(Command::SUNIONSTORE { destination, keys })
+}
+
+fn parse_sdiffstore(argv: &[Bytes]) -> Result<Command, Error> {
+    let (destination, keys) = parse_destination_and_keys("SDIFFSTORE", argv)?;
+    Ok(Command::SDIFFSTORE { destination, keys })
+}
+
+fn parse_score_arg(value: &[u8]) -> Result<f64, Error> {
+    parse_score(value).ok_or(Error::WrongArgumentType(NOT_A_FLOAT))
+}
+
+fn parse_score_bound(value: &[u8]) -> Result<ScoreBound, Error> {
+    match value.strip_prefix(b"(") {
+        Some(rest) => Ok(ScoreBound::Exclusive(parse_score_arg(rest)?)),
+        None => Ok(ScoreBound::Inclusive(parse_score_arg(value)?)),
+    }
+}
+
+fn parse_zadd(argv: &[Bytes]) -> Result<Command, Error> {
+    let [key, rest @ ..] = argv else {
+        return Err(wrong_arity("ZADD", argv.len(), 3));
+    };
+    if rest.len() < 2 {
+        return Err(wrong_arity("ZADD", argv.len(), 3));
+    }
+
+    let mut options = ZAddOptions::default();
+    let mut rest = rest;
+    while let [flag, tail @ ..] = rest {
+        if flag.eq_ignore_ascii_case(b"nx") {
+            options.condition = Some(ZAddCondition::OnlyNew);
+        } else if flag.eq_ignore_ascii_case(b"xx") {
+            options.condition = Some(ZAddCondition::OnlyExisting);
+        } else if flag.eq_ignore_ascii_case(b"gt") {
+            options.comparison = Some(ZAddComparison::GreaterThan);
+        } else if flag.eq_ignore_ascii_case(b"lt") {
+            options.comparison = Some(ZAddComparison::LessThan);
+        } else if flag.eq_ignore_ascii_case(b"ch") {
+            options.changed = true;
+        } else {
+            break;
+        }
+        rest = tail;
+    }
+
+    let has = |flag: &[u8]| {
+        argv[1..argv.len() - rest.len()]
+            .iter()
+            .any(|arg| arg.eq_ignore_ascii_case(flag))
+    };
+    if has(b"nx") && has(b"xx") {
+        return Err(Error::IncompatibleOptions(
+            "XX and NX options at the same time are not compatible",
+        ));
+    }
+    if (has(b"gt") && has(b"lt")) || (has(b"nx") && options.comparison.is_some()) {
+        return Err(Error::IncompatibleOptions(
+            "GT, LT, and/or NX options at the same time are not compatible",
+        ));
+    }
+    if rest.is_empty() || rest.len() % 2 != 0 {
+        return Err(Error::SyntaxError);
+    }
+
+    let members = rest
+        .chunks_exact(2)
+        .map(|pair| Ok((parse_score_arg(&pair[0])?, pair[1].clone())))
+        .collect::<Result<_, Error>>()?;
+    Ok(Command::ZADD {
+        key: key.clone(),
+        options,
+        members,
+    })
+}
+
+fn parse_zrange(argv: &[Bytes]) -> Result<Command, Error> {
+    let (key, start, stop, with_scores) = match argv {
+        [key, start, stop] => (key, start, stop, false),
+        [key, start, stop, option] if option.eq_ignore_ascii_case(b"withscores") => {
+            (key, start, stop, true)
+        }
+        [_, _, _, _] => return Err(Error::SyntaxError),
+        _ => return Err(wrong_arity("ZRANGE", argv.len(), 3)),
+    };
+    Ok(Command::ZRANGE {
+        key: key.clone(),
+        start: parse_i64_arg(start)?,
+        stop: parse_i64_arg(stop)?,
+        with_scores,
+    })
+}
+
+fn parse_zrangebyscore(argv: &[Bytes]) -> Result<Command, Error> {
+    let [key, min, max, options @ ..] = argv else {
+        return Err(wrong_arity("ZRANGEBYSCORE", argv.len(), 3));
+    };
+    let mut options = options;
+    let mut with_scores = false;
+    let mut limit = None;
+    loop {
+        match options {
+            [] => break,
+            [option, rest @ ..] if option.eq_ignore_ascii_case(b"withscores") => {
+                with_scores = true;
+                options = rest;
+            }
+            [option, offset, count, rest @ ..] if option.eq_ignore_ascii_case(b"limit") => {
+                limit = Some((parse_i64_arg(offset)?, parse_i64_arg(count)?));
+                options = rest;
+            }
+            _ => return Err(Error::SyntaxError),
+        }
+    }
+    Ok(Command::ZRANGEBYSCORE {
+        key: key.clone(),
+        min: parse_score_bound(min)?,
+        max: parse_score_bound(max)?,
+        with_scores,
+        limit,
+    })
+}
+
+fn parse_zrank(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, member] => Ok(Command::ZRANK {
+            key: key.clone(),
+            member: member.clone(),
+        }),
+        _ => Err(wrong_arity("ZRANK", argv.len(), 2)),
+    }
+}
+
+fn parse_zincrby(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, increment, member] => Ok(Command::ZINCRBY {
+            key: key.clone(),
+            increment: parse_score_arg(increment)?,
+            member: member.clone(),
+        }),
+        _ => Err(wrong_arity("ZINCRBY", argv.len(), 3)),
+    }
+}
+
+fn parse_zrem(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, members @ ..] if !members.is_empty() => Ok(Command::ZREM {
+            key: key.clone(),
+            members: members.to_vec(),
+        }),
+        _ => Err(wrong_arity("ZREM", argv.len(), 2)),
+    }
+}
+
+fn parse_zscore(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key, member] => Ok(Command::ZSCORE {
+            key: key.clone(),
+            member: member.clone(),
+        }),
+        _ => Err(wrong_arity("ZSCORE", argv.len(), 2)),
+    }
+}
+
+fn parse_zcard(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [key] => Ok(Command::ZCARD { key: key.clone() }),
+        _ => Err(wrong_arity("ZCARD", argv.len(), 1)),
+    }
+}
+
+fn parse_multi(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [] => Ok(Command::MULTI),
+        _ => Err(wrong_arity("MULTI", argv.len(), 0)),
+    }
+}
+
+fn parse_exec(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [] => Ok(Command::EXEC),
+        _ => Err(wrong_arity("EXEC", argv.len(), 0)),
+    }
+}
+
+fn parse_discard(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [] => Ok(Command::DISCARD),
+        _ => Err(wrong_arity("DISCARD", argv.len(), 0)),
+    }
+}
+
+fn parse_watch(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::WATCH {
+        keys: parse_keys("WATCH", argv)?,
+    })
+}
+
+fn parse_unwatch(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [] => Ok(Command::UNWATCH),
+        _ => Err(wrong_arity("UNWATCH", argv.len(), 0)),
+    }
+}
+
+fn parse_subscribe(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::SUBSCRIBE {
+        channels: parse_keys("SUBSCRIBE", argv)?,
+    })
+}
+
+fn parse_unsubscribe(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::UNSUBSCRIBE {
+        channels: argv.to_vec(),
+    })
+}
+
+fn parse_psubscribe(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::PSUBSCRIBE {
+        patterns: parse_keys("PSUBSCRIBE", argv)?,
+    })
+}
+
+fn parse_punsubscribe(argv: &[Bytes]) -> Result<Command, Error> {
+    Ok(Command::PUNSUBSCRIBE {
+        patterns: argv.to_vec(),
+    })
+}
+
+fn parse_publish(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [channel, message] => Ok(Command::PUBLISH {
+            channel: channel.clone(),
+            message: message.clone(),
+        }),
+        _ => Err(wrong_arity("PUBLISH", argv.len(), 2)),
+    }
+}
+
+fn parse_hello(argv: &[Bytes]) -> Result<Command, Error> {
+    match argv {
+        [] => Ok(Command::HELLO { protocol: None }),
+        [version] => match parse_i64_arg(version)? {
+            2 => Ok(Command::HELLO {
+                protocol: Some(Protocol::Resp2),
+            }),
+            3 => Ok(Command::HELLO {
+                protocol: Some(Protocol::Resp3),
+            }),
+            _ => Err(Error::UnsupportedProtocol),
+        },
+        _ => Err(Error::SyntaxError),
+    }
+}
+
+impl TryFrom<&Frame> for Command {
+    type Error = Error;
+
+    fn try_from(value: &Frame) -> Result<Self, Self::Error> {
+        let args = bulk_args(value)?;
+        let (cmd, argv) = args.split_first().ok_or(Error::InvalidCommandFrame)?;
+
+        if cmd.eq_ignore_ascii_case(b"ping") {
+            return parse_ping(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"quit") {
+            return parse_quit(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"get") {
+            return parse_get(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"set") {
+            return parse_set(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"del") {
+            return parse_del(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"unlink") {
+            return parse_unlink(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"exists") {
+            return parse_exists(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"type") {
+            return parse_type(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"rename") {
+            return parse_rename(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"renamenx") {
+            return parse_renamenx(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"copy") {
+            return parse_copy(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"randomkey") {
+            return parse_randomkey(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"dbsize") {
+            return parse_dbsize(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"scan") {
+            return parse_scan(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"keys") {
+            return parse_keys_command(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"mget") {
+            return parse_mget(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"mset") {
+            return parse_mset(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"msetnx") {
+            return parse_msetnx(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"expire") {
+            return parse_expire(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"pexpire") {
+            return parse_pexpire(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"expireat") {
+            return parse_expireat(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"pexpireat") {
+            return parse_pexpireat(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"ttl") {
+            return parse_ttl(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"pttl") {
+            return parse_pttl(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"expiretime") {
+            return parse_expiretime(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"pexpiretime") {
+            return parse_pexpiretime(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"persist") {
+            return parse_persist(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"incr") {
+            return parse_incr(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"decr") {
+            return parse_decr(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"incrby") {
+            return parse_incrby(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"decrby") {
+            return parse_decrby(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"incrbyfloat") {
+            return parse_incrbyfloat(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"lpush") {
+            return parse_lpush(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"rpush") {
+            return parse_rpush(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"lpop") {
+            return parse_lpop(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"rpop") {
+            return parse_rpop(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"blpop") {
+            return parse_blpop(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"brpop") {
+            return parse_brpop(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"blmove") {
+            return parse_blmove(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"lrange") {
+            return parse_lrange(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"llen") {
+            return parse_llen(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"hset") {
+            return parse_hset(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"hget") {
+            return parse_hget(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"hdel") {
+            return parse_hdel(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"hgetall") {
+            return parse_hgetall(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"hincrby") {
+            return parse_hincrby(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"hlen") {
+            return parse_hlen(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"hexists") {
+            return parse_hexists(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"sadd") {
+            return parse_sadd(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"srem") {
+            return parse_srem(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"smembers") {
+            return parse_smembers(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"sismember") {
+            return parse_sismember(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"scard") {
+            return parse_scard(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"sinter") {
+            return parse_sinter(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"sunion") {
+            return parse_sunion(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"sdiff") {
+            return parse_sdiff(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"sinterstore") {
+            return parse_sinterstore(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"sunionstore") {
+            return parse_sunionstore(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"sdiffstore") {
+            return parse_sdiffstore(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"zadd") {
+            return parse_zadd(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"zrange") {
+            return parse_zrange(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"zrangebyscore") {
+            return parse_zrangebyscore(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"zrank") {
+            return parse_zrank(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"zincrby") {
+            return parse_zincrby(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"zrem") {
+            return parse_zrem(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"zscore") {
+            return parse_zscore(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"zcard") {
+            return parse_zcard(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"multi") {
+            return parse_multi(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"exec") {
+            return parse_exec(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"discard") {
+            return parse_discard(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"watch") {
+            return parse_watch(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"unwatch") {
+            return parse_unwatch(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"subscribe") {
+            return parse_subscribe(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"unsubscribe") {
+            return parse_unsubscribe(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"psubscribe") {
+            return parse_psubscribe(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"punsubscribe") {
+            return parse_punsubscribe(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"publish") {
+            return parse_publish(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"hello") {
+            return parse_hello(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"info") {
+            return parse_info(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"bgrewriteaof") {
+            return parse_bgrewriteaof(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"save") {
+            return parse_save(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"bgsave") {
+            return parse_bgsave(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"lastsave") {
+            return parse_lastsave(argv);
+        }
+        if cmd.eq_ignore_ascii_case(b"shutdown") {
+            return parse_shutdown(argv);
+        }
+
+        let argv: Vec<&[u8]> = argv.iter().map(Bytes::as_ref).collect();
+        Err(Error::unknown_command(cmd, &argv))
+    }
+}
+
+impl TryFrom<Frame> for Command {
+    type Error = Error;
+
+    fn try_from(value: Frame) -> Result<Self, Self::Error> {
+        Command::try_from(&value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(value: &[u8]) -> Frame {
+        Frame::Bulk(Some(Bytes::copy_from_slice(value)))
+    }
+
+    #[test]
+    fn get_command_parses() {
+        let frame = Frame::Array(Some(vec![bulk(b"GET"), bulk(b"mykey")]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::GET {
+                key: Bytes::from_static(b"mykey")
+            }
+        );
+    }
+
+    #[test]
+    fn ping_command_parses() {
+        let frame = Frame::Array(Some(vec![bulk(b"PING")]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(command, Command::PING);
+    }
+
+    #[test]
+    fn quit_command_parses() {
+        let frame = Frame::Array(Some(vec![bulk(b"QUIT")]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(command, Command::QUIT);
+    }
+
+    #[test]
+    fn set_command_parses() {
+        let frame = Frame::Array(Some(vec![bulk(b"SET"), bulk(b"mykey"), bulk(b"myvalue")]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::SET {
+                key: Bytes::from_static(b"mykey"),
+                value: Bytes::from_static(b"myvalue"),
+                options: SetOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn del_command_parses() {
+        let frame = Frame::Array(Some(vec![bulk(b"DEL"), bulk(b"mykey")]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::DEL {
+                keys: vec![Bytes::from_static(b"mykey")]
+            }
+        );
+    }
+
+    #[test]
+    fn expire_command_parses() {
+        let frame = Frame::Array(Some(vec![bulk(b"EXPIRE"), bulk(b"mykey"), bulk(b"123")]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::EXPIRE {
+                key: Bytes::from_static(b"mykey"),
+                value: 123,
+                conditions: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn ttl_command_parses() {
+        let frame = Frame::Array(Some(vec![bulk(b"TTL"), bulk(b"mykey")]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::TTL {
+                key: Bytes::from_static(b"mykey")
+            }
+        );
+    }
+
+    #[test]
+    fn lpush_command_parses_multiple_values() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"LPUSH"),
+            bulk(b"mylist"),
+            bulk(b"a"),
+            bulk(b"b"),
+        ]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::LPUSH {
+                key: Bytes::from_static(b"mylist"),
+                values: vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")],
+            }
+        );
+    }
+
+    #[test]
+    fn rpush_command_parses() {
+        let frame = Frame::Array(Some(vec![bulk(b"RPUSH"), bulk(b"mylist"), bulk(b"a")]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::RPUSH {
+                key: Bytes::from_static(b"mylist"),
+                values: vec![Bytes::from_static(b"a")],
+            }
+        );
+    }
+
+    #[test]
+    fn lpop_command_parses_with_and_without_count() {
+        let without_count = Frame::Array(Some(vec![bulk(b"LPOP"), bulk(b"mylist")]));
+        let with_count = Frame::Array(Some(vec![bulk(b"LPOP"), bulk(b"mylist"), bulk(b"3")]));
+
+        assert_eq!(
+            Command::try_from(without_count).unwrap(),
+            Command::LPOP {
+                key: Bytes::from_static(b"mylist"),
+                count: None,
+            }
+        );
+        assert_eq!(
+            Command::try_from(with_count).unwrap(),
+            Command::LPOP {
+                key: Bytes::from_static(b"mylist"),
+                count: Some(3),
+            }
+        );
+    }
+
+    #[test]
+    fn rpop_command_parses() {
+        let frame = Frame::Array(Some(vec![bulk(b"RPOP"), bulk(b"mylist")]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::RPOP {
+                key: Bytes::from_static(b"mylist"),
+                count: None,
+            }
+        );
+    }
+
+    #[test]
+    fn lrange_command_parses_negative_indexes() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"LRANGE"),
+            bulk(b"mylist"),
+            bulk(b"0"),
+            bulk(b"-1"),
+        ]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::LRANGE {
+                key: Bytes::from_static(b"mylist"),
+                start: 0,
+                stop: -1,
+            }
+        );
+    }
+
+    #[test]
+    fn llen_command_parses() {
+        let frame = Frame::Array(Some(vec![bulk(b"LLEN"), bulk(b"mylist")]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::LLEN {
+                key: Bytes::from_static(b"mylist")
+            }
+        );
+    }
+
+    #[test]
+    fn command_name_is_case_insensitive() {
+        let ping = Frame::Array(Some(vec![bulk(b"pInG")]));
+        let quit = Frame::Array(Some(vec![bulk(b"qUiT")]));
+        let get = Frame::Array(Some(vec![bulk(b"gEt"), bulk(b"mykey")]));
+        let set = Frame::Array(Some(vec![bulk(b"SeT"), bulk(b"mykey"), bulk(b"myvalue")]));
+        let del = Frame::Array(Some(vec![bulk(b"dEl"), bulk(b"mykey")]));
+        let expire = Frame::Array(Some(vec![bulk(b"eXpIrE"), bulk(b"mykey"), bulk(b"60")]));
+        let ttl = Frame::Array(Some(vec![bulk(b"TtL"), bulk(b"mykey")]));
+
+        assert_eq!(Command::try_from(ping).unwrap(), Command::PING);
+        assert_eq!(Command::try_from(quit).unwrap(), Command::QUIT);
+        assert_eq!(
+            Command::try_from(get).unwrap(),
+            Command::GET {
+                key: Bytes::from_static(b"mykey")
+            }
+        );
+        assert_eq!(
+            Command::try_from(set).unwrap(),
+            Command::SET {
+                key: Bytes::from_static(b"mykey"),
+                value: Bytes::from_static(b"myvalue"),
+                options: SetOptions::default(),
+            }
+        );
+        assert_eq!(
+            Command::try_from(del).unwrap(),
+            Command::DEL {
+                keys: vec![Bytes::from_static(b"mykey")]
+            }
+        );
+        assert_eq!(
+            Command::try_from(expire).unwrap(),
+            Command::EXPIRE {
+                key: Bytes::from_static(b"mykey"),
+                value: 60,
+                conditions: vec![],
+            }
+        );
+        assert_eq!(
+            Command::try_from(ttl).unwrap(),
+            Command::TTL {
+                key: Bytes::from_static(b"mykey")
+            }
+        );
+    }
+
+    #[test]
+    fn binary_key_and_value_are_preserved() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"SET"),
+            bulk(b"\0key\xff"),
+            bulk(b"va\0lue\xfe"),
+        ]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::SET {
+                key: Bytes::from_static(b"\0key\xff"),
+                value: Bytes::from_static(b"va\0lue\xfe"),
+                options: SetOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn non_array_frame_is_invalid() {
+        assert!(matches!(
+            Command::try_from(Frame::Bulk(Some(Bytes::from_static(b"GET")))),
+            Err(Error::InvalidCommandFrame)
+        ));
+    }
+
+    #[test]
+    fn nil_array_is_invalid() {
+        assert!(matches!(
+            Command::try_from(Frame::Array(None)),
+            Err(Error::InvalidCommandFrame)
+        ));
+    }
+
+    #[test]
+    fn empty_array_is_invalid() {
+        assert!(matches!(
+            Command::try_from(Frame::Array(Some(vec![]))),
+            Err(Error::InvalidCommandFrame)
+        ));
+    }
+
+    #[test]
+    fn non_bulk_element_is_invalid() {
+        let frame = Frame::Array(Some(vec![bulk(b"GET"), Frame::Integer(1)]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::InvalidCommandFrame)
+        ));
+    }
+
+    #[test]
+    fn nil_bulk_element_is_invalid() {
+        let frame = Frame::Array(Some(vec![bulk(b"GET"), Frame::Bulk(None)]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::InvalidCommandFrame)
+        ));
+    }
+
+    #[test]
+    fn unknown_command_returns_unknown_command() {
+        let frame = Frame::Array(Some(vec![bulk(b"FOO"), bulk(b"bar")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::UnknownCommand { command, args }) if command == "FOO" && args == ["bar"]
+        ));
+    }
+
+    #[test]
+    fn get_with_missing_key_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"GET")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 0,
+                expected: 1,
+            }) if command == "GET"
+        ));
+    }
+
+    #[test]
+    fn ping_with_extra_args_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"PING"), bulk(b"extra")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 1,
+                expected: 0,
+            }) if command == "PING"
+        ));
+    }
+
+    #[test]
+    fn quit_with_extra_args_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"QUIT"), bulk(b"extra")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 1,
+                expected: 0,
+            }) if command == "QUIT"
+        ));
+    }
+
+    #[test]
+    fn get_with_extra_args_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"GET"), bulk(b"key"), bulk(b"extra")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 2,
+                expected: 1,
+            }) if command == "GET"
+        ));
+    }
+
+    #[test]
+    fn set_with_missing_value_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"SET"), bulk(b"key")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 1,
+                expected: 2,
+            }) if command == "SET"
+        ));
+    }
+
+    #[test]
+    fn set_with_unknown_option_returns_syntax_error() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"SET"),
+            bulk(b"key"),
+            bulk(b"value"),
+            bulk(b"extra"),
+        ]));
+
+        assert!(matches!(Command::try_from(frame), Err(Error::SyntaxError)));
+    }
+
+    #[test]
+    fn del_with_missing_key_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"DEL")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 0,
+                expected: 1,
+            }) if command == "DEL"
+        ));
+    }
+
+    #[test]
+    fn del_command_parses_multiple_keys() {
+        let frame = Frame::Array(Some(vec![bulk(b"DEL"), bulk(b"key"), bulk(b"other")]));
+
+        assert_eq!(
+            Command::try_from(frame).unwrap(),
+            Command::DEL {
+                keys: vec![Bytes::from_static(b"key"), Bytes::from_static(b"other")]
+            }
+        );
+    }
+
+    #[test]
+    fn expire_with_missing_ttl_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"EXPIRE"), bulk(b"key")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 1,
+                expected: 2,
+            }) if command == "EXPIRE"
+        ));
+    }
+
+    #[test]
+    fn expire_with_unknown_flag_returns_syntax_error() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"EXPIRE"),
+            bulk(b"key"),
+            bulk(b"60"),
+            bulk(b"extra"),
+        ]));
+
+        assert!(matches!(Command::try_from(frame), Err(Error::SyntaxError)));
+    }
+
+    #[test]
+    fn expire_command_parses_flags() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"EXPIRE"),
+            bulk(b"key"),
+            bulk(b"60"),
+            bulk(b"xx"),
+            bulk(b"GT"),
+        ]));
+
+        assert_eq!(
+            Command::try_from(frame).unwrap(),
+            Command::EXPIRE {
+                key: Bytes::from_static(b"key"),
+                value: 60,
+                conditions: vec![ExpireCondition::IfSome, ExpireCondition::IfGreater],
+            }
+        );
+    }
+
+    #[test]
+    fn expire_with_incompatible_flags_returns_error() {
+        for flags in [[b"NX", b"GT"], [b"GT", b"LT"]] {
+            let mut argv = vec![bulk(b"PEXPIRE"), bulk(b"key"), bulk(b"60")];
+            argv.extend(flags.iter().map(|f| bulk(*f)));
+
+            assert!(matches!(
+                Command::try_from(Frame::Array(Some(argv))),
+                Err(Error::IncompatibleOptions(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn expire_with_overflowing_seconds_returns_invalid_expire_time() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"EXPIRE"),
+            bulk(b"key"),
+            bulk(b"18446744073709551"),
+        ]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::InvalidExpireTime("expire"))
+        ));
+    }
+
+    #[test]
+    fn pexpireat_command_parses() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"PEXPIREAT"),
+            bulk(b"key"),
+            bulk(b"1700000000123"),
+        ]));
+
+        assert_eq!(
+            Command::try_from(frame).unwrap(),
+            Command::PEXPIREAT {
+                key: Bytes::from_static(b"key"),
+                timestamp: 1_700_000_000_123,
+                conditions: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn expire_with_non_numeric_ttl_returns_wrong_argument_type() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"EXPIRE"),
+            bulk(b"key"),
+            bulk(b"not-a-number"),
+        ]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArgumentType(_))
+        ));
+    }
+
+    #[test]
+    fn expire_with_non_utf8_ttl_returns_wrong_argument_type() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"EXPIRE"),
+            bulk(b"key"),
+            Frame::Bulk(Some(Bytes::from_static(&[0xff, 0xfe]))),
+        ]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArgumentType(_))
+        ));
+    }
+
+    #[test]
+    fn ttl_with_missing_key_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"TTL")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 0,
+                expected: 1,
+            }) if command == "TTL"
+        ));
+    }
+
+    #[test]
+    fn ttl_with_extra_args_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"TTL"), bulk(b"key"), bulk(b"extra")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 2,
+                expected: 1,
+            }) if command == "TTL"
+        ));
+    }
+
+    #[test]
+    fn lpush_without_values_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"LPUSH"), bulk(b"mylist")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 1,
+                expected: 2,
+            }) if command == "LPUSH"
+        ));
+    }
+
+    #[test]
+    fn lpop_with_negative_count_returns_wrong_argument_type() {
+        let frame = Frame::Array(Some(vec![bulk(b"LPOP"), bulk(b"mylist"), bulk(b"-1")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArgumentType(_))
+        ));
+    }
+
+    #[test]
+    fn lrange_with_non_numeric_index_returns_wrong_argument_type() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"LRANGE"),
+            bulk(b"mylist"),
+            bulk(b"zero"),
+            bulk(b"-1"),
+        ]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArgumentType(_))
+        ));
+    }
+
+    #[test]
+    fn lrange_with_missing_stop_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"LRANGE"), bulk(b"mylist"), bulk(b"0")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 2,
+                expected: 3,
+            }) if command == "LRANGE"
+        ));
+    }
+
+    #[test]
+    fn hset_command_parses_field_value_pairs() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"HSET"),
+            bulk(b"user"),
+            bulk(b"name"),
+            bulk(b"ada"),
+            bulk(b"lang"),
+            bulk(b"rust"),
+        ]));
+
+        let command = Command::try_from(frame).unwrap();
         assert_eq!(
-            Command::try_from(get).unwrap(),
-            Command::GET {
-                key: b"mykey".to_vec()
+            command,
+            Command::HSET {
+                key: Bytes::from_static(b"user"),
+                fields: vec![
+                    (Bytes::from_static(b"name"), Bytes::from_static(b"ada")),
+                    (Bytes::from_static(b"lang"), Bytes::from_static(b"rust")),
+                ],
             }
         );
+    }
+
+    #[test]
+    fn hset_with_unpaired_field_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"HSET"),
+            bulk(b"user"),
+            bulk(b"name"),
+            bulk(b"ada"),
+            bulk(b"lang"),
+        ]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 4,
+                expected: 3,
+            }) if command == "HSET"
+        ));
+    }
+
+    #[test]
+    fn hdel_command_parses_multiple_fields() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"HDEL"),
+            bulk(b"user"),
+            bulk(b"name"),
+            bulk(b"lang"),
+        ]));
+
+        let command = Command::try_from(frame).unwrap();
         assert_eq!(
-            Command::try_from(set).unwrap(),
-            Command::SET {
-                key: b"mykey".to_vec(),
-                value: b"myvalue".to_vec(),
+            command,
+            Command::HDEL {
+                key: Bytes::from_static(b"user"),
+                fields: vec![Bytes::from_static(b"name"), Bytes::from_static(b"lang")],
             }
         );
+    }
+
+    #[test]
+    fn hincrby_command_parses_negative_increment() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"HINCRBY"),
+            bulk(b"user"),
+            bulk(b"visits"),
+            bulk(b"-2"),
+        ]));
+
+        let command = Command::try_from(frame).unwrap();
         assert_eq!(
-            Command::try_from(del).unwrap(),
-            Command::DEL {
-                key: b"mykey".to_vec()
+            command,
+            Command::HINCRBY {
+                key: Bytes::from_static(b"user"),
+                field: Bytes::from_static(b"visits"),
+                increment: -2,
+            }
+        );
+    }
+
+    #[test]
+    fn hincrby_with_non_numeric_increment_returns_wrong_argument_type() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"HINCRBY"),
+            bulk(b"user"),
+            bulk(b"visits"),
+            bulk(b"one"),
+        ]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArgumentType(_))
+        ));
+    }
+
+    #[test]
+    fn single_key_hash_commands_parse() {
+        let hget = Frame::Array(Some(vec![bulk(b"HGET"), bulk(b"user"), bulk(b"name")]));
+        let hgetall = Frame::Array(Some(vec![bulk(b"HGETALL"), bulk(b"user")]));
+        let hlen = Frame::Array(Some(vec![bulk(b"HLEN"), bulk(b"user")]));
+        let hexists = Frame::Array(Some(vec![bulk(b"HEXISTS"), bulk(b"user"), bulk(b"name")]));
+
+        assert_eq!(
+            Command::try_from(hget).unwrap(),
+            Command::HGET {
+                key: Bytes::from_static(b"user"),
+                field: Bytes::from_static(b"name"),
             }
         );
         assert_eq!(
-            Command::try_from(expire).unwrap(),
-            Command::EXPIRE {
-                key: b"mykey".to_vec(),
-                value: 60,
+            Command::try_from(hgetall).unwrap(),
+            Command::HGETALL {
+                key: Bytes::from_static(b"user")
             }
         );
         assert_eq!(
-            Command::try_from(ttl).unwrap(),
-            Command::TTL {
-                key: b"mykey".to_vec()
+            Command::try_from(hlen).unwrap(),
+            Command::HLEN {
+                key: Bytes::from_static(b"user")
+            }
+        );
+        assert_eq!(
+            Command::try_from(hexists).unwrap(),
+            Command::HEXISTS {
+                key: Bytes::from_static(b"user"),
+                field: Bytes::from_static(b"name"),
             }
         );
     }
 
     #[test]
-    fn binary_key_and_value_are_preserved() {
+    fn sadd_command_parses_multiple_members() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"SADD"),
+            bulk(b"cohort"),
+            bulk(b"alice"),
+            bulk(b"bob"),
+        ]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::SADD {
+                key: Bytes::from_static(b"cohort"),
+                members: vec![Bytes::from_static(b"alice"), Bytes::from_static(b"bob")],
+            }
+        );
+    }
+
+    #[test]
+    fn sismember_command_parses() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"SISMEMBER"),
+            bulk(b"cohort"),
+            bulk(b"alice"),
+        ]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::SISMEMBER {
+                key: Bytes::from_static(b"cohort"),
+                member: Bytes::from_static(b"alice"),
+            }
+        );
+    }
+
+    #[test]
+    fn set_algebra_commands_parse_keys() {
+        let sinter = Frame::Array(Some(vec![bulk(b"SINTER"), bulk(b"a"), bulk(b"b")]));
+        let sdiffstore = Frame::Array(Some(vec![
+            bulk(b"SDIFFSTORE"),
+            bulk(b"dest"),
+            bulk(b"a"),
+            bulk(b"b"),
+        ]));
+
+        assert_eq!(
+            Command::try_from(sinter).unwrap(),
+            Command::SINTER {
+                keys: vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")],
+            }
+        );
+        assert_eq!(
+            Command::try_from(sdiffstore).unwrap(),
+            Command::SDIFFSTORE {
+                destination: Bytes::from_static(b"dest"),
+                keys: vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")],
+            }
+        );
+    }
+
+    #[test]
+    fn sunion_without_keys_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"SUNION")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 0,
+                expected: 1,
+            }) if command == "SUNION"
+        ));
+    }
+
+    #[test]
+    fn sunionstore_without_source_keys_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"SUNIONSTORE"), bulk(b"dest")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity {
+                command,
+                given: 1,
+                expected: 2,
+            }) if command == "SUNIONSTORE"
+        ));
+    }
+
+    #[test]
+    fn zadd_command_parses_flags_and_pairs() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"ZADD"),
+            bulk(b"board"),
+            bulk(b"xx"),
+            bulk(b"CH"),
+            bulk(b"1.5"),
+            bulk(b"alice"),
+            bulk(b"-inf"),
+            bulk(b"bob"),
+        ]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::ZADD {
+                key: Bytes::from_static(b"board"),
+                options: ZAddOptions {
+                    condition: Some(ZAddCondition::OnlyExisting),
+                    comparison: None,
+                    changed: true,
+                },
+                members: vec![
+                    (1.5, Bytes::from_static(b"alice")),
+                    (f64::NEG_INFINITY, Bytes::from_static(b"bob"))
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn zadd_with_nx_and_xx_returns_incompatible_options() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"ZADD"),
+            bulk(b"board"),
+            bulk(b"NX"),
+            bulk(b"XX"),
+            bulk(b"1"),
+            bulk(b"alice"),
+        ]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::IncompatibleOptions(_))
+        ));
+    }
+
+    #[test]
+    fn zadd_with_nx_and_gt_returns_incompatible_options() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"ZADD"),
+            bulk(b"board"),
+            bulk(b"NX"),
+            bulk(b"GT"),
+            bulk(b"1"),
+            bulk(b"alice"),
+        ]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::IncompatibleOptions(_))
+        ));
+    }
+
+    #[test]
+    fn zadd_with_unpaired_score_returns_syntax_error() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"ZADD"),
+            bulk(b"board"),
+            bulk(b"1"),
+            bulk(b"alice"),
+            bulk(b"2"),
+        ]));
+
+        assert!(matches!(Command::try_from(frame), Err(Error::SyntaxError)));
+    }
+
+    #[test]
+    fn zadd_with_nan_score_returns_wrong_argument_type() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"ZADD"),
+            bulk(b"board"),
+            bulk(b"nan"),
+            bulk(b"alice"),
+        ]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArgumentType(_))
+        ));
+    }
+
+    #[test]
+    fn zrange_command_parses_withscores() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"ZRANGE"),
+            bulk(b"board"),
+            bulk(b"0"),
+            bulk(b"-1"),
+            bulk(b"WithScores"),
+        ]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::ZRANGE {
+                key: Bytes::from_static(b"board"),
+                start: 0,
+                stop: -1,
+                with_scores: true,
+            }
+        );
+    }
+
+    #[test]
+    fn zrangebyscore_command_parses_bounds_and_options() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"ZRANGEBYSCORE"),
+            bulk(b"board"),
+            bulk(b"(1"),
+            bulk(b"+inf"),
+            bulk(b"LIMIT"),
+            bulk(b"0"),
+            bulk(b"10"),
+            bulk(b"WITHSCORES"),
+        ]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::ZRANGEBYSCORE {
+                key: Bytes::from_static(b"board"),
+                min: ScoreBound::Exclusive(1.0),
+                max: ScoreBound::Inclusive(f64::INFINITY),
+                with_scores: true,
+                limit: Some((0, 10)),
+            }
+        );
+    }
+
+    #[test]
+    fn zrangebyscore_with_incomplete_limit_returns_syntax_error() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"ZRANGEBYSCORE"),
+            bulk(b"board"),
+            bulk(b"0"),
+            bulk(b"1"),
+            bulk(b"LIMIT"),
+            bulk(b"0"),
+        ]));
+
+        assert!(matches!(Command::try_from(frame), Err(Error::SyntaxError)));
+    }
+
+    #[test]
+    fn zincrby_command_parses() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"ZINCRBY"),
+            bulk(b"board"),
+            bulk(b"2.5"),
+            bulk(b"alice"),
+        ]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::ZINCRBY {
+                key: Bytes::from_static(b"board"),
+                increment: 2.5,
+                member: Bytes::from_static(b"alice"),
+            }
+        );
+    }
+
+    #[test]
+    fn incrby_command_parses_signed_increment() {
+        let frame = Frame::Array(Some(vec![bulk(b"incrby"), bulk(b"counter"), bulk(b"-5")]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::INCRBY {
+                key: Bytes::from_static(b"counter"),
+                increment: -5,
+            }
+        );
+    }
+
+    #[test]
+    fn decr_command_with_extra_argument_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"DECR"), bulk(b"counter"), bulk(b"1")]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArity { .. })
+        ));
+    }
+
+    #[test]
+    fn incrbyfloat_command_rejects_non_finite_increment() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"INCRBYFLOAT"),
+            bulk(b"counter"),
+            bulk(b"inf"),
+        ]));
+
+        assert!(matches!(
+            Command::try_from(frame),
+            Err(Error::WrongArgumentType(_))
+        ));
+    }
+
+    #[test]
+    fn set_command_parses_condition_expiration_and_get() {
         let frame = Frame::Array(Some(vec![
             bulk(b"SET"),
-            bulk(b"\0key\xff"),
-            bulk(b"va\0lue\xfe"),
+            bulk(b"lock"),
+            bulk(b"owner"),
+            bulk(b"nx"),
+            bulk(b"PX"),
+            bulk(b"30000"),
+            bulk(b"Get"),
+        ]));
+
+        let command = Command::try_from(frame).unwrap();
+        assert_eq!(
+            command,
+            Command::SET {
+                key: Bytes::from_static(b"lock"),
+                value: Bytes::from_static(b"owner"),
+                options: SetOptions {
+                    condition: Some(SetCondition::IfMissing),
+                    expiration: Some(SetExpiration::After(Duration::from_secs(30))),
+                    get: true,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn set_command_converts_exat_to_unix_millis() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"SET"),
+            bulk(b"key"),
+            bulk(b"value"),
+            bulk(b"EXAT"),
+            bulk(b"1700000000"),
         ]));
 
         let command = Command::try_from(frame).unwrap();
         assert_eq!(
             command,
             Command::SET {
-                key: b"\0key\xff".to_vec(),
-                value: b"va\0lue\xfe".to_vec(),
+                key: Bytes::from_static(b"key"),
+                value: Bytes::from_static(b"value"),
+                options: SetOptions {
+                    expiration: Some(SetExpiration::AtUnixMillis(1_700_000_000_000)),
+                    ..Default::default()
+                },
             }
         );
     }
 
     #[test]
-    fn non_array_frame_is_invalid() {
-        assert!(matches!(
-            Command::try_from(Frame::Bulk(Some(b"GET".to_vec()))),
-            Err(Error::InvalidCommandFrame)
-        ));
-    }
-
-    #[test]
-    fn nil_array_is_invalid() {
-        assert!(matches!(
-            Command::try_from(Frame::Array(None)),
-            Err(Error::InvalidCommandFrame)
-        ));
+    fn set_command_with_conflicting_options_returns_syntax_error() {
+        let conflicts: [&[&[u8]]; 4] = [
+            &[b"NX", b"XX"],
+            &[b"EX", b"10", b"PX", b"10"],
+            &[b"KEEPTTL", b"EX", b"10"],
+            &[b"EX"],
+        ];
+        for options in conflicts {
+            let mut argv = vec![bulk(b"SET"), bulk(b"key"), bulk(b"value")];
+            argv.extend(options.iter().map(|o| bulk(o)));
+
+            assert!(matches!(
+                Command::try_from(Frame::Array(Some(argv))),
+                Err(Error::SyntaxError)
+            ));
+        }
     }
 
     #[test]
-    fn empty_array_is_invalid() {
-        assert!(matches!(
-            Command::try_from(Frame::Array(Some(vec![]))),
-            Err(Error::InvalidCommandFrame)
-        ));
+    fn relative_expirations_past_the_last_unix_millisecond_are_rejected() {
+        for argv in [
+            vec![bulk(b"EXPIRE"), bulk(b"key"), bulk(b"9223372036854775")],
+            vec![bulk(b"PEXPIRE"), bulk(b"key"), bulk(b"9223372036854775807")],
+            vec![
+                bulk(b"SET"),
+                bulk(b"key"),
+                bulk(b"value"),
+                bulk(b"EX"),
+                bulk(b"9223372036854775"),
+            ],
+        ] {
+            assert!(matches!(
+                Command::try_from(Frame::Array(Some(argv))),
+                Err(Error::InvalidExpireTime(_))
+            ));
+        }
     }
 
     #[test]
-    fn non_bulk_element_is_invalid() {
-        let frame = Frame::Array(Some(vec![bulk(b"GET"), Frame::Integer(1)]));
+    fn set_command_with_non_positive_expiration_returns_invalid_expire_time() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"SET"),
+            bulk(b"key"),
+            bulk(b"value"),
+            bulk(b"EX"),
+            bulk(b"0"),
+        ]));
 
         assert!(matches!(
             Command::try_from(frame),
-            Err(Error::InvalidCommandFrame)
+            Err(Error::InvalidExpireTime("set"))
         ));
     }
 
     #[test]
-    fn nil_bulk_element_is_invalid() {
-        let frame = Frame::Array(Some(vec![bulk(b"GET"), Frame::Bulk(None)]));
+    fn mset_command_parses_pairs() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"MSET"),
+            bulk(b"a"),
+            bulk(b"1"),
+            bulk(b"b"),
+            bulk(b"2"),
+        ]));
 
-        assert!(matches!(
-            Command::try_from(frame),
-            Err(Error::InvalidCommandFrame)
-        ));
+        assert_eq!(
+            Command::try_from(frame).unwrap(),
+            Command::MSET {
+                pairs: vec![
+                    (Bytes::from_static(b"a"), Bytes::from_static(b"1")),
+                    (Bytes::from_static(b"b"), Bytes::from_static(b"2"))
+                ]
+            }
+        );
     }
 
     #[test]
-    fn unknown_command_returns_unknown_command() {
-        let frame = Frame::Array(Some(vec![bulk(b"FOO"), bulk(b"bar")]));
+    fn msetnx_with_unpaired_key_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"MSETNX"),
+            bulk(b"a"),
+            bulk(b"1"),
+            bulk(b"b"),
+        ]));
 
         assert!(matches!(
             Command::try_from(frame),
-            Err(Error::UnknownCommand)
+            Err(Error::WrongArity { command, .. }) if command == "MSETNX"
         ));
     }
 
     #[test]
-    fn get_with_missing_key_returns_wrong_arity() {
-        let frame = Frame::Array(Some(vec![bulk(b"GET")]));
+    fn mget_command_parses_keys() {
+        let frame = Frame::Array(Some(vec![bulk(b"mget"), bulk(b"a"), bulk(b"b")]));
 
-        assert!(matches!(
-            Command::try_from(frame),
-            Err(Error::WrongArity {
-                command,
-                given: 0,
-                expected: 1,
-            }) if command == "GET"
-        ));
+        assert_eq!(
+            Command::try_from(frame).unwrap(),
+            Command::MGET {
+                keys: vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")]
+            }
+        );
     }
 
     #[test]
-    fn ping_with_extra_args_returns_wrong_arity() {
-        let frame = Frame::Array(Some(vec![bulk(b"PING"), bulk(b"extra")]));
+    fn copy_command_parses_replace() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"COPY"),
+            bulk(b"src"),
+            bulk(b"dst"),
+            bulk(b"replace"),
+        ]));
 
-        assert!(matches!(
-            Command::try_from(frame),
-            Err(Error::WrongArity {
-                command,
-                given: 1,
-                expected: 0,
-            }) if command == "PING"
-        ));
+        assert_eq!(
+            Command::try_from(frame).unwrap(),
+            Command::COPY {
+                source: Bytes::from_static(b"src"),
+                destination: Bytes::from_static(b"dst"),
+                replace: true,
+            }
+        );
     }
 
     #[test]
-    fn quit_with_extra_args_returns_wrong_arity() {
-        let frame = Frame::Array(Some(vec![bulk(b"QUIT"), bulk(b"extra")]));
+    fn copy_with_unknown_option_returns_syntax_error() {
+        let frame = Frame::Array(Some(vec![
+            bulk(b"COPY"),
+            bulk(b"src"),
+            bulk(b"dst"),
+            bulk(b"DB"),
+            bulk(b"1"),
+        ]));
 
-        assert!(matches!(
-            Command::try_from(frame),
-            Err(Error::WrongArity {
-                command,
-                given: 1,
-                expected: 0,
-            }) if command == "QUIT"
-        ));
+        assert!(matches!(Command::try_from(frame), Err(Error::SyntaxError)));
     }
 
     #[test]
-    fn get_with_extra_args_returns_wrong_arity() {
-        let frame = Frame::Array(Some(vec![bulk(b"GET"), bulk(b"key"), bulk(b"extra")]));
+    fn rename_with_missing_new_key_returns_wrong_arity() {
+        let frame = Frame::Array(Some(vec![bulk(b"RENAME"), bulk(b"key")]));
 
         assert!(matches!(
             Command::try_from(frame),
-            Err(Error::WrongArity {
-                command,
-                given: 2,
-                expected: 1,
-            }) if command == "GET"
+            Err(Error::WrongArity { command, .. }) if command == "RENAME"
         ));
     }
 
     #[test]
-    fn set_with_missing_value_returns_wrong_arity() {
-        let frame = Frame::Array(Some(vec![bulk(b"SET"), bulk(b"key")]));
+    fn dbsize_command_parses() {
+        let frame = Frame::Array(Some(vec![bulk(b"dbsize")]));
 
-        assert!(matches!(
-            Command::try_from(frame),
-            Err(Error::WrongArity {
-                command,
-                given: 1,
-                expected: 2,
-            }) if command == "SET"
-        ));
+        assert_eq!(Command::try_from(frame).unwrap(), Command::DBSIZE);
     }
 
     #[test]
-    fn set_with_extra_args_returns_wrong_arity() {
+    fn info_command_parses_any_sections() {
+        let frame = Frame::Array(Some(vec![bulk(b"INFO")]));
+        assert_eq!(
+            Command::try_from(frame).unwrap(),
+            Command::INFO { sections: vec![] }
+        );
+
         let frame = Frame::Array(Some(vec![
-            bulk(b"SET"),
-            bulk(b"key"),
-            bulk(b"value"),
-            bulk(b"extra"),
+            bulk(b"info"),
+            bulk(b"persistence"),
+            bulk(b"all"),
         ]));
-
-        assert!(matches!(
-            Command::try_from(frame),
-            Err(Error::WrongArity {
-                command,
-                given: 3,
-                expected: 2,
-            }) if command == "SET"
-        ));
+        assert_eq!(
+            Command::try_from(frame).unwrap(),
+            Command::INFO {
+                sections: vec![
+                    Bytes::from_static(b"persistence"),
+                    Bytes::from_static(b"all")
+                ]
+            }
+        );
     }
 
     #[test]
-    fn del_with_missing_key_returns_wrong_arity() {
-        let frame = Frame::Array(Some(vec![bulk(b"DEL")]));
-
+    fn save_commands_parse() {
+        let parse = |args: &[&[u8]]| {
+            Command::try_from(Frame::Array(Some(args.iter().map(|a| bulk(a)).collect())))
+        };
+        assert_eq!(parse(&[b"save"]).unwrap(), Command::SAVE);
+        assert_eq!(parse(&[b"BGSAVE"]).unwrap(), Command::BGSAVE);
+        assert_eq!(parse(&[b"LastSave"]).unwrap(), Command::LASTSAVE);
         assert!(matches!(
-            Command::try_from(frame),
-            Err(Error::WrongArity {
-                command,
-                given: 0,
-                expected: 1,
-            }) if command == "DEL"
+            parse(&[b"BGSAVE", b"SCHEDULE"]),
+            Err(Error::WrongArity { command, .. }) if command == "BGSAVE"
         ));
     }
 
     #[test]
-    fn del_with_extra_args_returns_wrong_arity() {
-        let frame = Frame::Array(Some(vec![bulk(b"DEL"), bulk(b"key"), bulk(b"extra")]));
-
+    fn shutdown_command_parses_save_option() {
+        let parse = |args: &[&[u8]]| {
+            Command::try_from(Frame::Array(Some(args.iter().map(|a| bulk(a)).collect())))
+        };
+        assert_eq!(
+            parse(&[b"SHUTDOWN"]).unwrap(),
+            Command::SHUTDOWN { save: true }
+        );
+        assert_eq!(
+            parse(&[b"shutdown", b"save"]).unwrap(),
+            Command::SHUTDOWN { save: true }
+        );
+        assert_eq!(
+            parse(&[b"SHUTDOWN", b"NOSAVE"]).unwrap(),
+            Command::SHUTDOWN { save: false }
+        );
         assert!(matches!(
-            Command::try_from(frame),
-            Err(Error::WrongArity {
-                command,
-                given: 2,
-                expected: 1,
-            }) if command == "DEL"
+            parse(&[b"SHUTDOWN", b"NOW"]),
+            Err(Error::SyntaxError)
+        ));
+        assert!(matches!(
+            parse(&[b"SHUTDOWN", b"SAVE", b"NOSAVE"]),
+            Err(Error::SyntaxError)
         ));
     }
 
     #[test]
-    fn expire_with_missing_ttl_returns_wrong_arity() {
-        let frame = Frame::Array(Some(vec![bulk(b"EXPIRE"), bulk(b"key")]));
+    fn bgrewriteaof_command_parses() {
+        let frame = Frame::Array(Some(vec![bulk(b"bgrewriteaof")]));
+        assert_eq!(Command::try_from(frame).unwrap(), Command::BGREWRITEAOF);
 
+        let frame = Frame::Array(Some(vec![bulk(b"BGREWRITEAOF"), bulk(b"now")]));
         assert!(matches!(
             Command::try_from(frame),
-            Err(Error::WrongArity {
-                command,
-                given: 1,
-                expected: 2,
-            }) if command == "EXPIRE"
+            Err(Error::WrongArity { command, .. }) if command == "BGREWRITEAOF"
         ));
     }
 
     #[test]
-    fn expire_with_extra_args_returns_wrong_arity() {
+    fn scan_command_parses_options_in_any_order() {
         let frame = Frame::Array(Some(vec![
-            bulk(b"EXPIRE"),
-            bulk(b"key"),
-            bulk(b"60"),
-            bulk(b"extra"),
+            bulk(b"SCAN"),
+            bulk(b"42"),
+            bulk(b"type"),
+            bulk(b"hash"),
+            bulk(b"COUNT"),
+            bulk(b"100"),
+            bulk(b"MATCH"),
+            bulk(b"user:*"),
         ]));
 
-        assert!(matches!(
-            Command::try_from(frame),
-            Err(Error::WrongArity {
-                command,
-                given: 3,
-                expected: 2,
-            }) if command == "EXPIRE"
-        ));
+        assert_eq!(
+            Command::try_from(frame).unwrap(),
+            Command::SCAN {
+                cursor: 42,
+                count: Some(100),
+                filter: ScanFilter {
+                    pattern: Some(Bytes::from_static(b"user:*")),
+                    type_name: Some(Bytes::from_static(b"hash")),
+                },
+            }
+        );
     }
 
     #[test]
-    fn expire_with_non_numeric_ttl_returns_wrong_argument_type() {
-        let frame = Frame::Array(Some(vec![
-            bulk(b"EXPIRE"),
-            bulk(b"key"),
-            bulk(b"not-a-number"),
-        ]));
+    fn scan_with_dangling_or_zero_count_returns_syntax_error() {
+        for options in [&[b"COUNT".as_slice()][..], &[b"COUNT", b"0"]] {
+            let mut argv = vec![bulk(b"SCAN"), bulk(b"0")];
+            argv.extend(options.iter().map(|o| bulk(o)));
+
+            assert!(matches!(
+                Command::try_from(Frame::Array(Some(argv))),
+                Err(Error::SyntaxError)
+            ));
+        }
+    }
 
+    #[test]
+    fn transaction_commands_parse() {
+        let parse = |args: &[&[u8]]| {
+            Command::try_from(Frame::Array(Some(args.iter().map(|a| bulk(a)).collect())))
+        };
+        assert_eq!(parse(&[b"multi"]).unwrap(), Command::MULTI);
+        assert_eq!(parse(&[b"EXEC"]).unwrap(), Command::EXEC);
+        assert_eq!(parse(&[b"Discard"]).unwrap(), Command::DISCARD);
+        assert_eq!(parse(&[b"UNWATCH"]).unwrap(), Command::UNWATCH);
+        assert_eq!(
+            parse(&[b"WATCH", b"a", b"b"]).unwrap(),
+            Command::WATCH {
+                keys: vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")]
+            }
+        );
         assert!(matches!(
-            Command::try_from(frame),
-            Err(Error::WrongArgumentType)
+            parse(&[b"WATCH"]),
+            Err(Error::WrongArity { command, .. }) if command == "WATCH"
+        ));
+        assert!(matches!(
+            parse(&[b"MULTI", b"extra"]),
+            Err(Error::WrongArity { command, .. }) if command == "MULTI"
         ));
     }
 
     #[test]
-    fn expire_with_non_utf8_ttl_returns_wrong_argument_type() {
-        let frame = Frame::Array(Some(vec![
-            bulk(b"EXPIRE"),
-            bulk(b"key"),
-            Frame::Bulk(Some(vec![0xff, 0xfe])),
-        ]));
-
+    fn pubsub_commands_parse() {
+        let parse = |args: &[&[u8]]| {
+            Command::try_from(Frame::Array(Some(args.iter().map(|a| bulk(a)).collect())))
+        };
+        assert_eq!(
+            parse(&[b"SUBSCRIBE", b"a", b"b"]).unwrap(),
+            Command::SUBSCRIBE {
+                channels: vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")]
+            }
+        );
+        assert_eq!(
+            parse(&[b"psubscribe", b"news.*"]).unwrap(),
+            Command::PSUBSCRIBE {
+                patterns: vec![Bytes::from_static(b"news.*")]
+            }
+        );
+        assert_eq!(
+            parse(&[b"UNSUBSCRIBE"]).unwrap(),
+            Command::UNSUBSCRIBE { channels: vec![] }
+        );
+        assert_eq!(
+            parse(&[b"PUNSUBSCRIBE", b"news.*"]).unwrap(),
+            Command::PUNSUBSCRIBE {
+                patterns: vec![Bytes::from_static(b"news.*")]
+            }
+        );
+        assert_eq!(
+            parse(&[b"PUBLISH", b"a", b"hello"]).unwrap(),
+            Command::PUBLISH {
+                channel: Bytes::from_static(b"a"),
+                message: Bytes::from_static(b"hello")
+            }
+        );
         assert!(matches!(
-            Command::try_from(frame),
-            Err(Error::WrongArgumentType)
+            parse(&[b"SUBSCRIBE"]),
+            Err(Error::WrongArity { command, .. }) if command == "SUBSCRIBE"
+        ));
+        assert!(matches!(
+            parse(&[b"PUBLISH", b"a"]),
+            Err(Error::WrongArity { command, .. }) if command == "PUBLISH"
         ));
     }
 
     #[test]
-    fn ttl_with_missing_key_returns_wrong_arity() {
-        let frame = Frame::Array(Some(vec![bulk(b"TTL")]));
-
+    fn blocking_pop_commands_parse_keys_and_timeout() {
+        let parse = |args: &[&[u8]]| {
+            Command::try_from(Frame::Array(Some(args.iter().map(|a| bulk(a)).collect())))
+        };
+        assert_eq!(
+            parse(&[b"BLPOP", b"a", b"b", b"0"]).unwrap(),
+            Command::BLPOP {
+                keys: vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")],
+                timeout: None
+            }
+        );
+        assert_eq!(
+            parse(&[b"brpop", b"a", b"0.5"]).unwrap(),
+            Command::BRPOP {
+                keys: vec![Bytes::from_static(b"a")],
+                timeout: Some(Duration::from_millis(500))
+            }
+        );
+        assert_eq!(
+            parse(&[b"BLMOVE", b"src", b"dst", b"left", b"RIGHT", b"2"]).unwrap(),
+            Command::BLMOVE {
+                source: Bytes::from_static(b"src"),
+                destination: Bytes::from_static(b"dst"),
+                from: ListEnd::Left,
+                to: ListEnd::Right,
+                timeout: Some(Duration::from_secs(2))
+            }
+        );
         assert!(matches!(
-            Command::try_from(frame),
-            Err(Error::WrongArity {
-                command,
-                given: 0,
-                expected: 1,
-            }) if command == "TTL"
+            parse(&[b"BLPOP", b"a", b"-1"]),
+            Err(Error::InvalidTimeout("timeout is negative"))
+        ));
+        assert!(matches!(
+            parse(&[b"BLPOP", b"a", b"soon"]),
+            Err(Error::InvalidTimeout(
+                "timeout is not a float or out of range"
+            ))
+        ));
+        assert!(matches!(
+            parse(&[b"BLMOVE", b"src", b"dst", b"up", b"left", b"0"]),
+            Err(Error::SyntaxError)
+        ));
+        assert!(matches!(
+            parse(&[b"BLPOP", b"0"]),
+            Err(Error::WrongArity { command, .. }) if command == "BLPOP"
         ));
     }
 
     #[test]
-    fn ttl_with_extra_args_returns_wrong_arity() {
-        let frame = Frame::Array(Some(vec![bulk(b"TTL"), bulk(b"key"), bulk(b"extra")]));
-
+    fn hello_parses_protocol_version() {
+        let parse = |args: &[&[u8]]| {
+            Command::try_from(Frame::Array(Some(args.iter().map(|a| bulk(a)).collect())))
+        };
+        assert_eq!(
+            parse(&[b"HELLO"]).unwrap(),
+            Command::HELLO { protocol: None }
+        );
+        assert_eq!(
+            parse(&[b"hello", b"3"]).unwrap(),
+            Command::HELLO {
+                protocol: Some(Protocol::Resp3)
+            }
+        );
+        assert_eq!(
+            parse(&[b"HELLO", b"2"]).unwrap(),
+            Command::HELLO {
+                protocol: Some(Protocol::Resp2)
+            }
+        );
         assert!(matches!(
-            Command::try_from(frame),
-            Err(Error::WrongArity {
-                command,
-                given: 2,
-                expected: 1,
-            }) if command == "TTL"
+            parse(&[b"HELLO", b"4"]),
+            Err(Error::UnsupportedProtocol)
+        ));
+        assert!(matches!(
+            parse(&[b"HELLO", b"three"]),
+            Err(Error::WrongArgumentType(_))
+        ));
+        assert!(matches!(
+            parse(&[b"HELLO", b"3", b"SETNAME", b"me"]),
+            Err(Error::SyntaxError)
         ));
     }
+
+    #[test]
+    fn write_args_parse_back_to_the_same_command() {
+        let parse = |args: &[&[u8]]| {
+            Command::try_from(Frame::Array(Some(args.iter().map(|a| bulk(a)).collect()))).unwrap()
+        };
+        let commands: [&[&[u8]]; 16] = [
+            &[b"SET", b"k", b"v", b"XX", b"PXAT", b"1700000000000"],
+            &[b"SET", b"k", b"v", b"NX", b"KEEPTTL"],
+            &[b"DEL", b"a", b"b"],
+            &[b"COPY", b"a", b"b", b"REPLACE"],
+            &[b"MSETNX", b"a", b"1", b"b", b"2"],
+            &[b"EXPIREAT", b"k", b"1700000000", b"GT"],
+            &[b"INCRBY", b"k", b"-5"],
+            &[b"INCRBYFLOAT", b"k", b"0.1"],
+            &[b"RPUSH", b"l", b"a", b"b"],
+            &[b"LPOP", b"l", b"2"],
+            &[b"HSET", b"h", b"f", b"v"],
+            &[b"HINCRBY", b"h", b"f", b"3"],
+            &[b"SREM", b"s", b"a"],
+            &[b"SDIFFSTORE", b"d", b"a", b"b"],
+            &[b"ZADD", b"z", b"XX", b"GT", b"1.5", b"a", b"-inf", b"b"],
+            &[b"ZINCRBY", b"z", b"2", b"a"],
+        ];
+        for args in commands {
+            let command = parse(args);
+            let written = command.write_args().expect("command writes");
+            let written: Vec<&[u8]> = written.iter().map(Vec::as_slice).collect();
+            assert_eq!(parse(&written), command, "{:?}", command);
+        }
+    }
+
+    #[test]
+    fn write_args_make_relative_expirations_absolute() {
+        let parse = |args: &[&[u8]]| {
+            Command::try_from(Frame::Array(Some(args.iter().map(|a| bulk(a)).collect()))).unwrap()
+        };
+        let now = unix_millis_after(Duration::ZERO);
+        let deadline = |args: Vec<Vec<u8>>, at: usize| -> u64 {
+            str::from_utf8(&args[at]).unwrap().parse().unwrap()
+        };
+
+        let args = parse(&[b"EXPIRE", b"k", b"10", b"NX"])
+            .write_args()
+            .unwrap();
+        assert_eq!(args[0], b"PEXPIREAT");
+        assert_eq!(args[3], b"NX");
+        assert!((now + 10_000..now + 11_000).contains(&deadline(args, 2)));
+
+        let args = parse(&[b"SET", b"k", b"v", b"PX", b"500"])
+            .write_args()
+            .unwrap();
+        assert_eq!(args[3], b"PXAT");
+        assert!((now + 500..now + 1_500).contains(&deadline(args, 4)));
+    }
+
+    #[test]
+    fn read_and_blocking_commands_have_no_write_args() {
+        let parse = |args: &[&[u8]]| {
+            Command::try_from(Frame::Array(Some(args.iter().map(|a| bulk(a)).collect()))).unwrap()
+        };
+        for args in [
+            &[&b"GET"[..], b"k"][..],
+            &[b"ZRANGE", b"z", b"0", b"-1"],
+            &[b"BLPOP", b"l", b"0"],
+            &[b"PUBLISH", b"c", b"m"],
+            &[b"MULTI"],
+        ] {
+            assert_eq!(parse(args).write_args(), None);
+        }
+    }
 }
@@ -2,6 +2,17 @@ use std::net::IpAddr;
 
 use clap::Parser;
 
+use crate::aof::{
+    AppendFsync, AutoRewrite, DEFAULT_AUTO_AOF_REWRITE_MIN_SIZE,
+    DEFAULT_AUTO_AOF_REWRITE_PERCENTAGE,
+};
+use crate::archive::{DEFAULT_SAVE_RULES, SaveRules};
+use crate::parser::{
+    DEFAULT_MAX_BULK_LEN, DEFAULT_MAX_INLINE_LEN, DEFAULT_MAX_MULTIBULK_LEN,
+    DEFAULT_MAX_NESTING_DEPTH, ParserLimits,
+};
+use crate::pubsub::{DEFAULT_PUBSUB_BUFFER_LEN, KeyspaceEvents};
+
 #[derive(Parser, Debug)]
 pub struct Config {
     #[arg(short, long, env, default_value = "127.0.0.1")]
@@ -10,6 +21,65 @@ pub struct Config {
     pub port: u16,
     #[arg(short = 'r', long, env, default_value = None)]
     pub archive_path: Option<std::path::PathBuf>,
+    /// Save the archive automatically after this many seconds and changes,
+    /// as pairs like Redis's `save` option. An empty value turns it off.
+    #[arg(long, env, default_value = DEFAULT_SAVE_RULES)]
+    pub save: SaveRules,
+    /// Log every write to this append-only file, and rebuild the store from
+    /// it on startup instead of from the archive.
+    #[arg(long, env, default_value = None)]
+    pub aof_path: Option<std::path::PathBuf>,
+    /// When the append-only file is flushed to disk: `always`, `everysec` or
+    /// `no`.
+    #[arg(long, env, default_value_t = AppendFsync::default())]
+    pub appendfsync: AppendFsync,
+    /// Rewrite the append-only file once it has grown by this percentage
+    /// since the last rewrite. `0` turns automatic rewrites off.
+    #[arg(long, env, default_value_t = DEFAULT_AUTO_AOF_REWRITE_PERCENTAGE)]
+    pub auto_aof_rewrite_percentage: u64,
+    /// The smallest append-only file, in bytes, that is rewritten
+    /// automatically.
+    #[arg(long, env, default_value_t = DEFAULT_AUTO_AOF_REWRITE_MIN_SIZE)]
+    pub auto_aof_rewrite_min_size: u64,
+    /// Keyspace notifications to publish, as Redis `notify-keyspace-events`
+    /// flags, e.g. `Ex` for expiry events.
+    #[arg(short = 'n', long, env, default_value = "")]
+    pub notify_keyspace_events: KeyspaceEvents,
+    /// The longest bulk string a client may send, in bytes.
+    #[arg(long, env, default_value_t = DEFAULT_MAX_BULK_LEN)]
+    pub proto_max_bulk_len: usize,
+    /// The most arguments or elements a client may send in one array.
+    #[arg(long, env, default_value_t = DEFAULT_MAX_MULTIBULK_LEN)]
+    pub max_multibulk_len: usize,
+    /// How deeply a client may nest arrays.
+    #[arg(long, env, default_value_t = DEFAULT_MAX_NESTING_DEPTH)]
+    pub max_nesting_depth: usize,
+    /// The longest inline command or protocol line a client may send, in
+    /// bytes.
+    #[arg(long, env, default_value_t = DEFAULT_MAX_INLINE_LEN)]
+    pub max_inline_len: usize,
+    /// How many published messages may wait for one subscriber before it
+    /// is disconnected.
+    #[arg(long, env, default_value_t = DEFAULT_PUBSUB_BUFFER_LEN)]
+    pub pubsub_buffer_len: usize,
+}
+
+impl Config {
+    pub fn parser_limits(&self) -> ParserLimits {
+        ParserLimits {
+            max_bulk_len: self.proto_max_bulk_len,
+            max_multibulk_len: self.max_multibulk_len,
+            max_nesting_depth: self.max_nesting_depth,
+            max_inline_len: self.max_inline_len,
+        }
+    }
+
+    pub fn auto_rewrite(&self) -> AutoRewrite {
+        AutoRewrite {
+            percentage: self.auto_aof_rewrite_percentage,
+            min_size: self.auto_aof_rewrite_min_size,
+        }
+    }
 }
 
 pub fn get_config() -> Config {
@@ -141,6 +211,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn keyspace_events_are_parsed_from_flags() {
+        let config = Config::try_parse_from(["redlike", "--notify-keyspace-events", "Ex"]).unwrap();
+        assert_eq!(config.notify_keyspace_events, "Ex".parse().unwrap());
+
+        let result = Config::try_parse_from(["redlike", "--notify-keyspace-events", "Q"]);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn invalid_port_is_rejected() {
         let result = Config::try_parse_from(["redlike", "--port", "1000"]);
@@ -154,4 +233,101 @@ mod tests {
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn parser_limits_come_from_flags() {
+        let config = Config::try_parse_from([
+            "redlike",
+            "--proto-max-bulk-len",
+            "1024",
+            "--max-multibulk-len",
+            "16",
+            "--max-nesting-depth",
+            "2",
+            "--max-inline-len",
+            "256",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            config.parser_limits(),
+            ParserLimits {
+                max_bulk_len: 1024,
+                max_multibulk_len: 16,
+                max_nesting_depth: 2,
+                max_inline_len: 256,
+            }
+        );
+        assert_eq!(
+            Config::try_parse_from(["redlike"]).unwrap().parser_limits(),
+            ParserLimits::default()
+        );
+    }
+
+    #[test]
+    fn append_only_file_comes_from_flags() {
+        let config = Config::try_parse_from([
+            "redlike",
+            "--aof-path",
+            "/tmp/redlike.aof",
+            "--appendfsync",
+            "always",
+        ])
+        .unwrap();
+
+        assert_eq!(config.aof_path, Some(PathBuf::from("/tmp/redlike.aof")));
+        assert_eq!(config.appendfsync, AppendFsync::Always);
+        assert!(Config::try_parse_from(["redlike", "--appendfsync", "sometimes"]).is_err());
+
+        let _guard = env_lock().lock().unwrap_or_else(|e| e.into_inner());
+        remove_env_var("AOF_PATH");
+        remove_env_var("APPENDFSYNC");
+        let config = Config::try_parse_from(["redlike"]).unwrap();
+        assert_eq!(config.aof_path, None);
+        assert_eq!(config.appendfsync, AppendFsync::EverySec);
+    }
+
+    #[test]
+    fn auto_rewrite_comes_from_flags() {
+        let config = Config::try_parse_from([
+            "redlike",
+            "--auto-aof-rewrite-percentage",
+            "50",
+            "--auto-aof-rewrite-min-size",
+            "1024",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            config.auto_rewrite(),
+            AutoRewrite {
+                percentage: 50,
+                min_size: 1024,
+            }
+        );
+
+        let _guard = env_lock().lock().unwrap_or_else(|e| e.into_inner());
+        remove_env_var("AUTO_AOF_REWRITE_PERCENTAGE");
+        remove_env_var("AUTO_AOF_REWRITE_MIN_SIZE");
+        let config = Config::try_parse_from(["redlike"]).unwrap();
+        assert_eq!(config.auto_rewrite(), AutoRewrite::default());
+    }
+
+    #[test]
+    fn save_rules_come_from_flags() {
+        let config = Config::try_parse_from(["redlike", "--save", "900 1 60 1000"]).unwrap();
+        assert_eq!(config.save.to_string(), "900 1 60 1000");
+        assert!(
+            Config::try_parse_from(["redlike", "--save", ""])
+                .unwrap()
+                .save
+                .is_empty()
+        );
+        assert!(Config::try_parse_from(["redlike", "--save", "900"]).is_err());
+
+        let _guard = env_lock().lock().unwrap_or_else(|e| e.into_inner());
+        remove_env_var("SAVE");
+        let config = Config::try_parse_from(["redlike"]).unwrap();
+        assert_eq!(config.save.to_string(), DEFAULT_SAVE_RULES);
+    }
 }
@@ -1,25 +1,261 @@
 #![allow(clippy::upper_case_acronyms)]
+use crate::aof::{AofLog, AppendOnlyFile};
+use crate::archive::Snapshots;
 use crate::command::Command;
 use crate::error::Error;
-use crate::frame::Frame;
-use crate::parser::{ParseResult, Parser};
-use crate::store::Store;
+use crate::frame::{Frame, Protocol};
+use crate::parser::{ParseError, ParseResult, Parser, ParserLimits};
+use crate::pubsub::{Hub, Message, Subscriber};
+use crate::sorted_set::format_score;
+use crate::store::{
+    BlockingPop, Expiration, ListEnd, SetOperation, SetOutcome, Store, StoreError, Watch,
+};
+use bytes::Bytes;
+use std::time::Duration;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
 use tokio::select;
+use tokio::time::sleep;
 use tokio_util::sync::CancellationToken;
 
+/// How many keys `SCAN` looks at when the client gives no `COUNT`.
+const DEFAULT_SCAN_COUNT: u64 = 10;
+
+/// How much free space to make in the parser's buffer before each read.
+const READ_CHUNK_SIZE: usize = 16 * 1024;
+
+/// How many bytes of encoded replies to hold before writing them out, even
+/// if more pipelined commands from the same read are still to run.
+const MAX_PENDING_OUTPUT: usize = 64 * 1024;
+
 pub struct Connection<R, W> {
     reader: BufReader<R>,
     writer: BufWriter<W>,
     store: Store,
+    hub: Hub,
+    subscriber: Subscriber,
     shutdown_token: CancellationToken,
+    transaction: Option<Transaction>,
+    watch: Option<Watch>,
+    protocol: Protocol,
+    parser_limits: ParserLimits,
+    /// Encoded replies not yet handed to the writer.
+    output: Vec<u8>,
+    aof: Option<AppendOnlyFile>,
+    snapshots: Snapshots,
+}
+
+/// Commands queued between `MULTI` and `EXEC`.
+#[derive(Default)]
+struct Transaction {
+    queued: Vec<Command>,
+    /// Set when a command failed to parse while queueing, so `EXEC` must
+    /// discard the transaction.
+    failed: bool,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+/// The key a blocking pop took a value from, and the value.
+type Popped = (Vec<u8>, Vec<u8>);
+
+#[derive(PartialEq, Debug)]
 enum ProcessOutcome {
     Quit,
     Noop,
     Respond(Frame),
+    RespondAll(Vec<Frame>),
+}
+
+/// Replies with `error`.
+fn reject(error: Error) -> ProcessOutcome {
+    ProcessOutcome::Respond(Frame::SimpleError(error.to_string()))
+}
+
+fn respond<T>(result: Result<T, StoreError>, to_frame: impl FnOnce(T) -> Frame) -> ProcessOutcome {
+    match result {
+        Ok(value) => ProcessOutcome::Respond(to_frame(value)),
+        Err(e) => ProcessOutcome::Respond(Frame::SimpleError(e.to_string())),
+    }
+}
+
+fn bulk_array(values: Vec<Vec<u8>>) -> Frame {
+    Frame::Array(Some(
+        values
+            .into_iter()
+            .map(|v| Frame::Bulk(Some(v.into())))
+            .collect(),
+    ))
+}
+
+fn bulk_set(values: Vec<Vec<u8>>) -> Frame {
+    Frame::Set(
+        values
+            .into_iter()
+            .map(|v| Frame::Bulk(Some(v.into())))
+            .collect(),
+    )
+}
+
+/// Copies command arguments into values the store can keep.
+fn owned(values: Vec<Bytes>) -> Vec<Vec<u8>> {
+    values.into_iter().map(Vec::from).collect()
+}
+
+fn owned_pairs(pairs: Vec<(Bytes, Bytes)>) -> Vec<(Vec<u8>, Vec<u8>)> {
+    pairs
+        .into_iter()
+        .map(|(key, value)| (key.into(), value.into()))
+        .collect()
+}
+
+fn bulk(value: &str) -> Frame {
+    Frame::Bulk(Some(Bytes::copy_from_slice(value.as_bytes())))
+}
+
+/// Builds the `HELLO` reply describing the server and the negotiated
+/// protocol.
+fn hello_reply(protocol: Protocol) -> Frame {
+    let proto = match protocol {
+        Protocol::Resp2 => 2,
+        Protocol::Resp3 => 3,
+    };
+    Frame::Map(vec![
+        (bulk("server"), bulk("redlike")),
+        (bulk("version"), bulk(env!("CARGO_PKG_VERSION"))),
+        (bulk("proto"), Frame::Integer(proto)),
+        (bulk("mode"), bulk("standalone")),
+        (bulk("role"), bulk("master")),
+        (bulk("modules"), Frame::Array(Some(Vec::new()))),
+    ])
+}
+
+/// Builds the `INFO` reply. Only the persistence section is reported, for
+/// no section or for `persistence`, `default`, `all` or `everything`.
+fn info_reply(snapshots: &Snapshots, sections: &[Bytes]) -> Frame {
+    let persistence = sections.is_empty()
+        || sections.iter().any(|section| {
+            [&b"persistence"[..], b"default", b"all", b"everything"]
+                .iter()
+                .any(|name| section.eq_ignore_ascii_case(name))
+        });
+    if !persistence {
+        return Frame::Bulk(Some(Bytes::new()));
+    }
+    let mut info = format!(
+        "# Persistence\r\n\
+         rdb_changes_since_last_save:{}\r\n\
+         rdb_bgsave_in_progress:{}\r\n\
+         rdb_last_save_time:{}\r\n",
+        snapshots.changes_since_last_save(),
+        snapshots.is_saving() as u8,
+        snapshots.last_save(),
+    );
+    match snapshots.last_error() {
+        None => info.push_str("rdb_last_bgsave_status:ok\r\n"),
+        Some(error) => info.push_str(&format!(
+            "rdb_last_bgsave_status:err\r\nrdb_last_bgsave_error:{error}\r\n"
+        )),
+    }
+    Frame::Bulk(Some(info.into()))
+}
+
+/// Builds the reply for LPOP/RPOP: a single bulk string when no count was
+/// given, otherwise an array, with the matching nil type for missing keys.
+fn pop_reply(popped: Option<Vec<Vec<u8>>>, count: Option<u64>) -> Frame {
+    match (popped, count) {
+        (None, None) => Frame::Bulk(None),
+        (None, Some(_)) => Frame::Array(None),
+        (Some(values), None) => Frame::Bulk(values.into_iter().next().map(Bytes::from)),
+        (Some(values), Some(_)) => bulk_array(values),
+    }
+}
+
+/// Builds the BLPOP/BRPOP reply: the key and value, or a nil array on
+/// timeout.
+fn key_value_reply(popped: Option<Popped>) -> Frame {
+    match popped {
+        Some((key, value)) => bulk_array(vec![key, value]),
+        None => Frame::Array(None),
+    }
+}
+
+/// Sleeps for `timeout`, or forever if there is none.
+async fn sleep_for(timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => sleep(timeout).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Builds the confirmation sent for each channel or pattern named by a
+/// (un)subscribe command.
+fn subscription_reply(kind: &str, name: Option<Bytes>, count: usize) -> Frame {
+    Frame::Push(vec![
+        Frame::Bulk(Some(Bytes::copy_from_slice(kind.as_bytes()))),
+        Frame::Bulk(name),
+        Frame::Integer(count as i64),
+    ])
+}
+
+fn message_frame(message: Message) -> Frame {
+    let parts = match message {
+        Message::Message { channel, payload } => vec![b"message".to_vec(), channel, payload],
+        Message::PMessage {
+            pattern,
+            channel,
+            payload,
+        } => vec![b"pmessage".to_vec(), pattern, channel, payload],
+    };
+    Frame::Push(
+        parts
+            .into_iter()
+            .map(|p| Frame::Bulk(Some(p.into())))
+            .collect(),
+    )
+}
+
+/// The error sent before closing a connection whose request broke one of
+/// the parser limits.
+fn limit_exceeded_error(error: &ParseError) -> Option<Error> {
+    let message = match error {
+        ParseError::BulkTooLong => "invalid bulk length",
+        ParseError::MultibulkTooLong => "invalid multibulk length",
+        ParseError::NestingTooDeep => "too many nested aggregates",
+        ParseError::InlineTooLong => "too big inline request",
+        _ => return None,
+    };
+    Some(Error::Protocol(message))
+}
+
+fn may_block(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::BLPOP { .. } | Command::BRPOP { .. } | Command::BLMOVE { .. }
+    )
+}
+
+/// Returns whether `command` may run while the connection is subscribed.
+fn allowed_while_subscribed(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::SUBSCRIBE { .. }
+            | Command::UNSUBSCRIBE { .. }
+            | Command::PSUBSCRIBE { .. }
+            | Command::PUNSUBSCRIBE { .. }
+            | Command::PING
+            | Command::QUIT
+            | Command::NOOP
+    )
+}
+
+fn scored_array(members: Vec<(Vec<u8>, f64)>, with_scores: bool) -> Frame {
+    bulk_array(
+        members
+            .into_iter()
+            .flat_map(|(member, score)| {
+                let score = with_scores.then(|| format_score(score).into_bytes());
+                std::iter::once(member).chain(score)
+            })
+            .collect(),
+    )
 }
 
 impl<R, W> Connection<R, W>
@@ -27,96 +263,881 @@ where
     R: AsyncRead + Unpin,
     W: AsyncWrite + Unpin,
 {
-    pub fn new(reader: R, writer: W, store: Store, shutdown_token: CancellationToken) -> Self {
+    pub fn new(
+        reader: R,
+        writer: W,
+        store: Store,
+        hub: Hub,
+        shutdown_token: CancellationToken,
+    ) -> Self {
         Connection {
             reader: BufReader::new(reader),
             writer: BufWriter::new(writer),
+            snapshots: Snapshots::new(store.clone(), None),
             store,
+            subscriber: hub.subscriber(),
+            hub,
             shutdown_token,
+            transaction: None,
+            watch: None,
+            protocol: Protocol::default(),
+            parser_limits: ParserLimits::default(),
+            output: Vec::new(),
+            aof: None,
         }
     }
 
+    /// Replaces the default limits on request sizes.
+    pub fn with_parser_limits(mut self, parser_limits: ParserLimits) -> Self {
+        self.parser_limits = parser_limits;
+        self
+    }
+
+    /// Records every write this connection makes in `aof`.
+    pub fn with_append_only_file(mut self, aof: AppendOnlyFile) -> Self {
+        self.aof = Some(aof);
+        self
+    }
+
+    /// Reports on and saves to the archive through `snapshots`, shared with
+    /// the rest of the server.
+    pub fn with_snapshots(mut self, snapshots: Snapshots) -> Self {
+        self.snapshots = snapshots;
+        self
+    }
+
+    /// Runs a command read back from the append-only file, ignoring its reply.
+    pub(crate) async fn replay(&mut self, command: Command) {
+        self.process_command(command).await;
+    }
+
     async fn process_command(&mut self, command: Command) -> ProcessOutcome {
+        // RESP3 carries messages as push frames, so the connection stays
+        // usable for other commands while subscribed.
+        if self.protocol == Protocol::Resp2
+            && self.subscriber.count() > 0
+            && !allowed_while_subscribed(&command)
+        {
+            return reject(Error::NotAllowedWhileSubscribed);
+        }
+        if let Some(transaction) = &mut self.transaction {
+            match command {
+                Command::MULTI
+                | Command::EXEC
+                | Command::DISCARD
+                | Command::WATCH { .. }
+                | Command::QUIT
+                | Command::NOOP => {}
+                command => {
+                    transaction.queued.push(command);
+                    return ProcessOutcome::Respond(Frame::SimpleString("QUEUED".into()));
+                }
+            }
+        }
+        match command {
+            Command::MULTI if self.transaction.is_some() => reject(Error::NestedMulti),
+            Command::MULTI => {
+                self.transaction = Some(Transaction::default());
+                ProcessOutcome::Respond(Frame::SimpleString("OK".into()))
+            }
+            Command::EXEC => self.exec().await,
+            Command::DISCARD => match self.transaction.take() {
+                None => reject(Error::DiscardWithoutMulti),
+                Some(_) => {
+                    self.watch = None;
+                    ProcessOutcome::Respond(Frame::SimpleString("OK".into()))
+                }
+            },
+            Command::WATCH { .. } if self.transaction.is_some() => reject(Error::WatchInsideMulti),
+            Command::WATCH { keys } => {
+                self.watch
+                    .get_or_insert_with(|| self.store.watch())
+                    .add(owned(keys));
+                ProcessOutcome::Respond(Frame::SimpleString("OK".into()))
+            }
+            Command::UNWATCH => {
+                self.watch = None;
+                ProcessOutcome::Respond(Frame::SimpleString("OK".into()))
+            }
+            Command::PING if self.protocol == Protocol::Resp2 && self.subscriber.count() > 0 => {
+                ProcessOutcome::Respond(bulk_array(vec![b"pong".to_vec(), Vec::new()]))
+            }
+            Command::SUBSCRIBE { channels } => ProcessOutcome::RespondAll(
+                channels
+                    .into_iter()
+                    .map(|channel| {
+                        let count = self.subscriber.subscribe(channel.to_vec());
+                        subscription_reply("subscribe", Some(channel), count)
+                    })
+                    .collect(),
+            ),
+            Command::PSUBSCRIBE { patterns } => ProcessOutcome::RespondAll(
+                patterns
+                    .into_iter()
+                    .map(|pattern| {
+                        let count = self.subscriber.psubscribe(pattern.to_vec());
+                        subscription_reply("psubscribe", Some(pattern), count)
+                    })
+                    .collect(),
+            ),
+            Command::UNSUBSCRIBE { channels } => {
+                let channels = match channels.is_empty() {
+                    true => self
+                        .subscriber
+                        .channels()
+                        .iter()
+                        .cloned()
+                        .map(Bytes::from)
+                        .collect(),
+                    false => channels,
+                };
+                self.unsubscribe_all("unsubscribe", channels, Subscriber::unsubscribe)
+            }
+            Command::PUNSUBSCRIBE { patterns } => {
+                let patterns = match patterns.is_empty() {
+                    true => self
+                        .subscriber
+                        .patterns()
+                        .iter()
+                        .cloned()
+                        .map(Bytes::from)
+                        .collect(),
+                    false => patterns,
+                };
+                self.unsubscribe_all("punsubscribe", patterns, Subscriber::punsubscribe)
+            }
+            Command::BLPOP { keys, timeout } => {
+                self.block_on_pop(keys, ListEnd::Left, None, timeout, key_value_reply)
+                    .await
+            }
+            Command::BRPOP { keys, timeout } => {
+                self.block_on_pop(keys, ListEnd::Right, None, timeout, key_value_reply)
+                    .await
+            }
+            Command::BLMOVE {
+                source,
+                destination,
+                from,
+                to,
+                timeout,
+            } => {
+                self.block_on_pop(
+                    vec![source],
+                    from,
+                    Some((destination.into(), to)),
+                    timeout,
+                    |popped| Frame::Bulk(popped.map(|(_, value)| value.into())),
+                )
+                .await
+            }
+            command => {
+                // Only writes are logged, so reads don't wait for the log.
+                let args = self.aof.as_ref().and_then(|_| command.write_args());
+                let mut log = match args {
+                    Some(_) => self.lock_aof().await,
+                    None => None,
+                };
+                let guard = self.store.command_guard().await;
+                let outcome = self.execute(command).await;
+                if let Some(log) = &mut log {
+                    self.record(log, args, &outcome);
+                }
+                drop(guard);
+                self.commit(log, outcome).await
+            }
+        }
+    }
+
+    /// Takes the append-only file, if writes are being logged.
+    async fn lock_aof(&self) -> Option<AofLog> {
+        match &self.aof {
+            Some(aof) => Some(aof.lock().await),
+            None => None,
+        }
+    }
+
+    /// Adds a command's `args` to `log` unless it failed, followed by any
+    /// pops it served to blocked clients.
+    fn record(&self, log: &mut AofLog, args: Option<Vec<Vec<u8>>>, outcome: &ProcessOutcome) {
+        if let Some(args) = args
+            && !matches!(outcome, ProcessOutcome::Respond(Frame::SimpleError(_)))
+        {
+            log.append(args);
+        }
+        log.append_served_pops(self.store.take_served_pops());
+    }
+
+    /// Writes out `log`, replacing `outcome` with an error if that fails so
+    /// the client never sees a write acknowledged that was not logged.
+    ///
+    /// The store has already applied the logged commands by then and keeps
+    /// them, so the error says the write was applied; it is lost only if the
+    /// server restarts from the log.
+    async fn commit(&self, log: Option<AofLog>, outcome: ProcessOutcome) -> ProcessOutcome {
+        let Some(log) = log else {
+            return outcome;
+        };
+        match log.commit().await {
+            Ok(()) => outcome,
+            Err(e) => reject(Error::AofWrite(e)),
+        }
+    }
+
+    /// Pops from the first non-empty list in `keys`, waiting up to `timeout`
+    /// for a value if they are all empty, and replies with `reply`.
+    ///
+    /// Transactions are only held off while checking the lists, not while
+    /// waiting. Shutting down the server ends the wait as if it timed out.
+    async fn block_on_pop(
+        &mut self,
+        keys: Vec<Bytes>,
+        end: ListEnd,
+        destination: Option<(Vec<u8>, ListEnd)>,
+        timeout: Option<Duration>,
+        reply: fn(Option<Popped>) -> Frame,
+    ) -> ProcessOutcome {
+        let mut log = self.lock_aof().await;
+        let guard = self.store.command_guard().await;
+        let pop = self.store.pop_or_block(&keys, end, destination).await;
+        drop(guard);
+        if let Some(log) = &mut log {
+            log.append_served_pops(self.store.take_served_pops());
+        }
+        let mut wait = match pop {
+            Ok(BlockingPop::Blocked(wait)) => wait,
+            pop => {
+                let outcome = respond(pop.map(BlockingPop::ready), reply);
+                return self.commit(log, outcome).await;
+            }
+        };
+        // A value handed over while waiting is logged by the push that
+        // served it, so the log is not held while waiting.
+        drop(log);
+        let delivered = select! {
+            result = wait.recv() => Some(result),
+            _ = sleep_for(timeout) => None,
+            _ = self.shutdown_token.cancelled() => None,
+        };
+        respond(delivered.or_else(|| wait.cancel()).transpose(), reply)
+    }
+
+    /// Removes each of `names` with `unsubscribe`, replying once per name, or
+    /// once with a nil name if there are none.
+    fn unsubscribe_all(
+        &mut self,
+        kind: &str,
+        names: Vec<Bytes>,
+        unsubscribe: fn(&mut Subscriber, &[u8]) -> usize,
+    ) -> ProcessOutcome {
+        if names.is_empty() {
+            return ProcessOutcome::Respond(subscription_reply(
+                kind,
+                None,
+                self.subscriber.count(),
+            ));
+        }
+        ProcessOutcome::RespondAll(
+            names
+                .into_iter()
+                .map(|name| {
+                    let count = unsubscribe(&mut self.subscriber, &name);
+                    subscription_reply(kind, Some(name), count)
+                })
+                .collect(),
+        )
+    }
+
+    /// Runs the queued transaction, unless a watched key changed since
+    /// `WATCH`, in which case it replies with a nil array.
+    async fn exec(&mut self) -> ProcessOutcome {
+        let Some(transaction) = self.transaction.take() else {
+            return reject(Error::ExecWithoutMulti);
+        };
+        let watch = self.watch.take();
+        if transaction.failed {
+            return reject(Error::ExecAborted);
+        }
+        let mut log = self.lock_aof().await;
+        let guard = self.store.transaction_guard().await;
+        if watch.is_some_and(|watch| watch.is_dirty()) {
+            return ProcessOutcome::Respond(Frame::Array(None));
+        }
+        let mut replies = Vec::with_capacity(transaction.queued.len());
+        for command in transaction.queued {
+            let args = log.as_ref().and_then(|_| command.write_args());
+            let outcome = self.execute(command).await;
+            if let Some(log) = &mut log {
+                self.record(log, args, &outcome);
+            }
+            if let ProcessOutcome::Respond(reply) = outcome {
+                replies.push(reply);
+            }
+        }
+        drop(guard);
+        if let Some(log) = &mut log {
+            log.wrap_in_transaction();
+        }
+        self.commit(log, ProcessOutcome::Respond(Frame::Array(Some(replies))))
+            .await
+    }
+
+    async fn execute(&mut self, command: Command) -> ProcessOutcome {
         match command {
             Command::NOOP => ProcessOutcome::Noop,
             Command::QUIT => ProcessOutcome::Quit,
             Command::PING => ProcessOutcome::Respond(Frame::SimpleString("PONG".into())),
-            Command::SET { key, value } => {
-                self.store.set(key, value).await;
+            Command::SET {
+                key,
+                value,
+                options,
+            } => respond(
+                self.store.set(key.into(), value.into(), options).await,
+                |SetOutcome { written, previous }| match (options.get, written) {
+                    (true, _) => Frame::Bulk(previous.map(Bytes::from)),
+                    (false, true) => Frame::SimpleString("OK".into()),
+                    (false, false) => Frame::Bulk(None),
+                },
+            ),
+            Command::GET { key } => respond(self.store.get(&key).await, |value| {
+                Frame::Bulk(value.map(Bytes::from))
+            }),
+            Command::DEL { keys } | Command::UNLINK { keys } => {
+                ProcessOutcome::Respond(Frame::Integer(self.store.del_many(&keys).await as i64))
+            }
+            Command::EXISTS { keys } => {
+                ProcessOutcome::Respond(Frame::Integer(self.store.exists(&keys).await as i64))
+            }
+            Command::TYPE { key } => {
+                let type_name = self.store.key_type(&key).await.unwrap_or("none");
+                ProcessOutcome::Respond(Frame::SimpleString(type_name.into()))
+            }
+            Command::RENAME { key, new_key } => {
+                respond(self.store.rename(&key, new_key.into()).await, |()| {
+                    Frame::SimpleString("OK".into())
+                })
+            }
+            Command::RENAMENX { key, new_key } => {
+                respond(self.store.renamenx(&key, new_key.into()).await, |renamed| {
+                    Frame::Integer(renamed as i64)
+                })
+            }
+            Command::COPY {
+                source,
+                destination,
+                replace,
+            } => {
+                let copied = self.store.copy(&source, destination.into(), replace).await;
+                ProcessOutcome::Respond(Frame::Integer(copied as i64))
+            }
+            Command::RANDOMKEY => {
+                ProcessOutcome::Respond(Frame::Bulk(self.store.random_key().await.map(Bytes::from)))
+            }
+            Command::DBSIZE => {
+                ProcessOutcome::Respond(Frame::Integer(self.store.dbsize().await as i64))
+            }
+            Command::SCAN {
+                cursor,
+                count,
+                filter,
+            } => {
+                let count = count.unwrap_or(DEFAULT_SCAN_COUNT) as usize;
+                let (cursor, keys) = self.store.scan(cursor, count, &filter).await;
+                ProcessOutcome::Respond(Frame::Array(Some(vec![
+                    Frame::Bulk(Some(cursor.to_string().into())),
+                    bulk_array(keys),
+                ])))
+            }
+            Command::KEYS { pattern } => {
+                ProcessOutcome::Respond(bulk_array(self.store.keys(&pattern).await))
+            }
+            Command::MGET { keys } => {
+                let values = self.store.mget(&keys).await;
+                ProcessOutcome::Respond(Frame::Array(Some(
+                    values
+                        .into_iter()
+                        .map(|value| Frame::Bulk(value.map(Bytes::from)))
+                        .collect(),
+                )))
+            }
+            Command::MSET { pairs } => {
+                self.store.mset(owned_pairs(pairs)).await;
                 ProcessOutcome::Respond(Frame::SimpleString("OK".into()))
             }
-            Command::GET { key } => {
-                ProcessOutcome::Respond(Frame::Bulk(self.store.get(&key).await))
+            Command::MSETNX { pairs } => ProcessOutcome::Respond(Frame::Integer(
+                self.store.msetnx(owned_pairs(pairs)).await as i64,
+            )),
+            Command::EXPIRE {
+                key,
+                value,
+                conditions,
+            } => {
+                let expiration = Expiration::After(Duration::from_secs(value));
+                let changed = self
+                    .store
+                    .set_expiration(key.into(), expiration, &conditions)
+                    .await;
+                ProcessOutcome::Respond(Frame::Integer(changed as i64))
+            }
+            Command::PEXPIRE {
+                key,
+                value,
+                conditions,
+            } => {
+                let expiration = Expiration::After(Duration::from_millis(value));
+                let changed = self
+                    .store
+                    .set_expiration(key.into(), expiration, &conditions)
+                    .await;
+                ProcessOutcome::Respond(Frame::Integer(changed as i64))
+            }
+            Command::EXPIREAT {
+                key,
+                timestamp,
+                conditions,
+            } => {
+                let expiration = Expiration::AtUnixMillis(timestamp * 1000);
+                let changed = self
+                    .store
+                    .set_expiration(key.into(), expiration, &conditions)
+                    .await;
+                ProcessOutcome::Respond(Frame::Integer(changed as i64))
+            }
+            Command::PEXPIREAT {
+                key,
+                timestamp,
+                conditions,
+            } => {
+                let expiration = Expiration::AtUnixMillis(timestamp);
+                let changed = self
+                    .store
+                    .set_expiration(key.into(), expiration, &conditions)
+                    .await;
+                ProcessOutcome::Respond(Frame::Integer(changed as i64))
+            }
+            Command::PTTL { key } => {
+                ProcessOutcome::Respond(Frame::Integer(self.store.pttl(&key).await))
+            }
+            Command::EXPIRETIME { key } => {
+                let expire_time = match self.store.expire_time(&key).await {
+                    millis if millis < 0 => millis,
+                    millis => millis / 1000,
+                };
+                ProcessOutcome::Respond(Frame::Integer(expire_time))
             }
-            Command::DEL { key } => {
-                let deleted = self.store.del(&key).await.map(|_| 1).unwrap_or(0);
-                ProcessOutcome::Respond(Frame::Integer(deleted.into()))
+            Command::PEXPIRETIME { key } => {
+                ProcessOutcome::Respond(Frame::Integer(self.store.expire_time(&key).await))
             }
-            Command::EXPIRE { key, value } => {
-                ProcessOutcome::Respond(Frame::Integer(self.store.expire(key, value).await as i64))
+            Command::PERSIST { key } => {
+                ProcessOutcome::Respond(Frame::Integer(self.store.persist(&key).await as i64))
             }
             Command::TTL { key } => {
-                ProcessOutcome::Respond(Frame::Integer(self.store.ttl(key).await))
+                ProcessOutcome::Respond(Frame::Integer(self.store.ttl(key.into()).await))
+            }
+            Command::INCR { key } => {
+                respond(self.store.incr_by(key.into(), 1).await, Frame::Integer)
+            }
+            Command::DECR { key } => {
+                respond(self.store.incr_by(key.into(), -1).await, Frame::Integer)
+            }
+            Command::INCRBY { key, increment } => respond(
+                self.store.incr_by(key.into(), increment).await,
+                Frame::Integer,
+            ),
+            Command::DECRBY { key, decrement } => {
+                let result = match decrement.checked_neg() {
+                    Some(increment) => self.store.incr_by(key.into(), increment).await,
+                    None => Err(StoreError::Overflow),
+                };
+                respond(result, Frame::Integer)
+            }
+            Command::INCRBYFLOAT { key, increment } => {
+                respond(self.store.incr_by_float(key.into(), increment).await, |n| {
+                    Frame::Bulk(Some(n.to_string().into()))
+                })
+            }
+            Command::LPUSH { key, values } => respond(
+                self.store
+                    .push(key.into(), owned(values), ListEnd::Left)
+                    .await,
+                |len| Frame::Integer(len as i64),
+            ),
+            Command::RPUSH { key, values } => respond(
+                self.store
+                    .push(key.into(), owned(values), ListEnd::Right)
+                    .await,
+                |len| Frame::Integer(len as i64),
+            ),
+            Command::LPOP { key, count } => {
+                let n = count.map_or(1, |c| c as usize);
+                respond(self.store.pop(&key, ListEnd::Left, n).await, |popped| {
+                    pop_reply(popped, count)
+                })
+            }
+            Command::RPOP { key, count } => {
+                let n = count.map_or(1, |c| c as usize);
+                respond(self.store.pop(&key, ListEnd::Right, n).await, |popped| {
+                    pop_reply(popped, count)
+                })
+            }
+            // Inside a transaction blocking pops never wait, like in Redis.
+            Command::BLPOP { keys, .. } => respond(
+                self.store
+                    .pop_or_block(&keys, ListEnd::Left, None)
+                    .await
+                    .map(BlockingPop::ready),
+                key_value_reply,
+            ),
+            Command::BRPOP { keys, .. } => respond(
+                self.store
+                    .pop_or_block(&keys, ListEnd::Right, None)
+                    .await
+                    .map(BlockingPop::ready),
+                key_value_reply,
+            ),
+            Command::BLMOVE {
+                source,
+                destination,
+                from,
+                to,
+                ..
+            } => respond(
+                self.store
+                    .pop_or_block(&[source], from, Some((destination.into(), to)))
+                    .await
+                    .map(BlockingPop::ready),
+                |popped| Frame::Bulk(popped.map(|(_, value)| value.into())),
+            ),
+            Command::LRANGE { key, start, stop } => {
+                respond(self.store.lrange(&key, start, stop).await, bulk_array)
+            }
+            Command::LLEN { key } => respond(self.store.llen(&key).await, |len| {
+                Frame::Integer(len as i64)
+            }),
+            Command::HSET { key, fields } => respond(
+                self.store.hset(key.into(), owned_pairs(fields)).await,
+                |added| Frame::Integer(added as i64),
+            ),
+            Command::HGET { key, field } => respond(self.store.hget(&key, &field).await, |value| {
+                Frame::Bulk(value.map(Bytes::from))
+            }),
+            Command::HDEL { key, fields } => respond(self.store.hdel(&key, &fields).await, |n| {
+                Frame::Integer(n as i64)
+            }),
+            Command::HGETALL { key } => respond(self.store.hgetall(&key).await, |pairs| {
+                Frame::Map(
+                    pairs
+                        .into_iter()
+                        .map(|(field, value)| {
+                            (
+                                Frame::Bulk(Some(field.into())),
+                                Frame::Bulk(Some(value.into())),
+                            )
+                        })
+                        .collect(),
+                )
+            }),
+            Command::HINCRBY {
+                key,
+                field,
+                increment,
+            } => respond(
+                self.store
+                    .hincrby(key.into(), field.into(), increment)
+                    .await,
+                Frame::Integer,
+            ),
+            Command::HLEN { key } => respond(self.store.hlen(&key).await, |len| {
+                Frame::Integer(len as i64)
+            }),
+            Command::HEXISTS { key, field } => {
+                respond(self.store.hexists(&key, &field).await, |exists| {
+                    Frame::Integer(exists.into())
+                })
+            }
+            Command::SADD { key, members } => {
+                respond(self.store.sadd(key.into(), owned(members)).await, |n| {
+                    Frame::Integer(n as i64)
+                })
+            }
+            Command::SREM { key, members } => respond(self.store.srem(&key, &members).await, |n| {
+                Frame::Integer(n as i64)
+            }),
+            Command::SMEMBERS { key } => respond(self.store.smembers(&key).await, bulk_set),
+            Command::SISMEMBER { key, member } => {
+                respond(self.store.sismember(&key, &member).await, |found| {
+                    Frame::Integer(found.into())
+                })
+            }
+            Command::SCARD { key } => {
+                respond(self.store.scard(&key).await, |n| Frame::Integer(n as i64))
+            }
+            Command::SINTER { keys } => respond(
+                self.store
+                    .set_operation(SetOperation::Intersection, &keys)
+                    .await,
+                bulk_set,
+            ),
+            Command::SUNION { keys } => respond(
+                self.store.set_operation(SetOperation::Union, &keys).await,
+                bulk_set,
+            ),
+            Command::SDIFF { keys } => respond(
+                self.store
+                    .set_operation(SetOperation::Difference, &keys)
+                    .await,
+                bulk_set,
+            ),
+            Command::SINTERSTORE { destination, keys } => respond(
+                self.store
+                    .set_operation_store(SetOperation::Intersection, destination.into(), &keys)
+                    .await,
+                |n| Frame::Integer(n as i64),
+            ),
+            Command::SUNIONSTORE { destination, keys } => respond(
+                self.store
+                    .set_operation_store(SetOperation::Union, destination.into(), &keys)
+                    .await,
+                |n| Frame::Integer(n as i64),
+            ),
+            Command::SDIFFSTORE { destination, keys } => respond(
+                self.store
+                    .set_operation_store(SetOperation::Difference, destination.into(), &keys)
+                    .await,
+                |n| Frame::Integer(n as i64),
+            ),
+            Command::ZADD {
+                key,
+                options,
+                members,
+            } => respond(
+                self.store
+                    .zadd(
+                        key.into(),
+                        members
+                            .into_iter()
+                            .map(|(score, member)| (score, member.into()))
+                            .collect(),
+                        options,
+                    )
+                    .await,
+                |n| Frame::Integer(n as i64),
+            ),
+            Command::ZRANGE {
+                key,
+                start,
+                stop,
+                with_scores,
+            } => respond(self.store.zrange(&key, start, stop).await, |members| {
+                scored_array(members, with_scores)
+            }),
+            Command::ZRANGEBYSCORE {
+                key,
+                min,
+                max,
+                with_scores,
+                limit,
+            } => respond(
+                self.store.zrangebyscore(&key, min, max, limit).await,
+                |members| scored_array(members, with_scores),
+            ),
+            Command::ZRANK { key, member } => {
+                respond(self.store.zrank(&key, &member).await, |rank| match rank {
+                    Some(rank) => Frame::Integer(rank as i64),
+                    None => Frame::Bulk(None),
+                })
+            }
+            Command::ZINCRBY {
+                key,
+                increment,
+                member,
+            } => respond(
+                self.store
+                    .zincrby(key.into(), increment, member.into())
+                    .await,
+                Frame::Double,
+            ),
+            Command::ZREM { key, members } => respond(self.store.zrem(&key, &members).await, |n| {
+                Frame::Integer(n as i64)
+            }),
+            Command::ZSCORE { key, member } => {
+                respond(self.store.zscore(&key, &member).await, |score| {
+                    score.map_or(Frame::Bulk(None), Frame::Double)
+                })
+            }
+            Command::ZCARD { key } => {
+                respond(self.store.zcard(&key).await, |n| Frame::Integer(n as i64))
             }
+            // Only reachable from a transaction, whose watch `EXEC` has
+            // already released.
+            Command::UNWATCH => ProcessOutcome::Respond(Frame::SimpleString("OK".into())),
+            Command::PUBLISH { channel, message } => {
+                ProcessOutcome::Respond(Frame::Integer(self.hub.publish(&channel, &message) as i64))
+            }
+            Command::HELLO { protocol } => {
+                if let Some(protocol) = protocol {
+                    self.protocol = protocol;
+                }
+                ProcessOutcome::Respond(hello_reply(self.protocol))
+            }
+            Command::INFO { sections } => {
+                ProcessOutcome::Respond(info_reply(&self.snapshots, &sections))
+            }
+            Command::SAVE | Command::BGSAVE if !self.snapshots.is_enabled() => {
+                reject(Error::ArchiveDisabled)
+            }
+            Command::SAVE if self.snapshots.is_saving() => reject(Error::SaveInProgress),
+            Command::SAVE => match self.snapshots.save().await {
+                Ok(()) => ProcessOutcome::Respond(Frame::SimpleString("OK".into())),
+                Err(e) => reject(Error::Save(e)),
+            },
+            Command::BGSAVE => match self.snapshots.start_save() {
+                Some(_) => {
+                    ProcessOutcome::Respond(Frame::SimpleString("Background saving started".into()))
+                }
+                None => reject(Error::SaveInProgress),
+            },
+            Command::LASTSAVE => {
+                ProcessOutcome::Respond(Frame::Integer(self.snapshots.last_save() as i64))
+            }
+            Command::SHUTDOWN { save } => {
+                if !save {
+                    self.snapshots.skip_save_on_shutdown();
+                }
+                self.shutdown_token.cancel();
+                ProcessOutcome::Quit
+            }
+            Command::BGREWRITEAOF => match &self.aof {
+                None => reject(Error::AofDisabled),
+                Some(aof) => match aof.start_rewrite() {
+                    Some(_) => ProcessOutcome::Respond(Frame::SimpleString(
+                        "Background append only file rewriting started".into(),
+                    )),
+                    None => reject(Error::RewriteInProgress),
+                },
+            },
+            Command::MULTI
+            | Command::EXEC
+            | Command::DISCARD
+            | Command::WATCH { .. }
+            | Command::SUBSCRIBE { .. }
+            | Command::UNSUBSCRIBE { .. }
+            | Command::PSUBSCRIBE { .. }
+            | Command::PUNSUBSCRIBE { .. } => reject(Error::NotAllowedInTransaction),
         }
     }
 
     async fn send_response(&mut self, response: Frame) -> Result<(), Error> {
-        self.writer
-            .write_all(response.to_bytes().as_slice())
-            .await?;
+        self.queue_response(response).await?;
+        self.flush_responses().await
+    }
+
+    /// Encodes `response` behind any other pending replies, writing them out
+    /// only once too much output has built up.
+    async fn queue_response(&mut self, response: Frame) -> Result<(), Error> {
+        response
+            .for_protocol(self.protocol)
+            .write_to(&mut self.output);
+        if self.output.len() >= MAX_PENDING_OUTPUT {
+            self.flush_responses().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush_responses(&mut self) -> Result<(), Error> {
+        if !self.output.is_empty() {
+            self.writer.write_all(&self.output).await?;
+            self.output.clear();
+        }
         self.writer.flush().await?;
         Ok(())
     }
 
     pub async fn run(&mut self) -> Result<(), Error> {
-        let mut p = Parser::new();
-        let mut buf = Vec::<u8>::new();
+        let mut p = Parser::with_limits(self.parser_limits);
         loop {
-            buf.clear();
+            p.buffer_mut().reserve(READ_CHUNK_SIZE);
             select! {
-                read_result = self.reader.read_buf(&mut buf) => {read_result?;},
+                read_result = self.reader.read_buf(p.buffer_mut()) => {
+                    if read_result? == 0 {
+                        return Ok(());
+                    }
+                },
+                message = self.subscriber.recv(), if self.subscriber.count() > 0 => {
+                    let Some(message) = message else {
+                        return self
+                            .send_response(Frame::SimpleError(Error::SubscriberOverflow.to_string()))
+                            .await;
+                    };
+                    self.send_response(message_frame(message)).await?;
+                    continue;
+                },
                 _ = self.shutdown_token.cancelled() => {break;}
             }
-            if buf.is_empty() {
-                return Ok(());
-            }
 
-            let (frames, halting_error) = match p.parse(&buf) {
-                ParseResult::Complete(f) => (f, None),
-                ParseResult::Partial(f, e) => (f, Some(e)),
-            };
+            // A recoverable error leaves the rest of the input buffered, so
+            // keep parsing until it is consumed.
+            loop {
+                let (frames, halting_error) = match p.parse_buffered() {
+                    ParseResult::Complete(f) => (f, None),
+                    ParseResult::Partial(f, e) => (f, Some(e)),
+                };
 
-            for f in frames {
-                let outcome: ProcessOutcome = match Command::try_from(f) {
-                    Ok(cmd) => self.process_command(cmd).await,
-                    Err(Error::UnknownCommand) => {
-                        ProcessOutcome::Respond(Frame::SimpleError("Unknown Command".into()))
+                for f in frames {
+                    let command = Command::try_from(f);
+                    if command.is_err()
+                        && let Some(transaction) = &mut self.transaction
+                    {
+                        transaction.failed = true;
                     }
-                    Err(Error::WrongArity {
-                        command: _,
-                        given: _,
-                        expected: _,
-                    }) => ProcessOutcome::Respond(Frame::SimpleError(
-                        "Wrong number of arguments".into(),
-                    )),
-                    Err(Error::WrongArgumentType) => {
-                        ProcessOutcome::Respond(Frame::SimpleError("Wrong Argument Type".into()))
+                    let outcome: ProcessOutcome = match command {
+                        Ok(cmd) => {
+                            // Replies to earlier commands must not wait on a
+                            // command that can block.
+                            if may_block(&cmd) {
+                                self.flush_responses().await?;
+                            }
+                            self.process_command(cmd).await
+                        }
+                        Err(Error::Io(_)) | Err(Error::InvalidCommandFrame) => {
+                            return self.flush_responses().await;
+                        }
+                        Err(e) => reject(e),
+                    };
+                    match outcome {
+                        ProcessOutcome::Noop => continue,
+                        ProcessOutcome::Quit => {
+                            return self.flush_responses().await;
+                        }
+                        ProcessOutcome::Respond(r) => self.queue_response(r).await?,
+                        ProcessOutcome::RespondAll(responses) => {
+                            for r in responses {
+                                self.queue_response(r).await?;
+                            }
+                        }
                     }
-                    Err(Error::Io(_e)) => return Ok(()),
-                    Err(Error::InvalidCommandFrame) => return Ok(()),
-                };
-                match outcome {
-                    ProcessOutcome::Noop => continue,
-                    ProcessOutcome::Quit => {
-                        return Ok(());
+                }
+                match halting_error {
+                    None => {
+                        self.flush_responses().await?;
+                        break;
+                    }
+                    Some(ParseError::UnbalancedQuotes) => {
+                        if let Some(transaction) = &mut self.transaction {
+                            transaction.failed = true;
+                        }
+                        let error = Error::Protocol("unbalanced quotes in request");
+                        self.queue_response(Frame::SimpleError(error.to_string()))
+                            .await?;
+                    }
+                    Some(e) => {
+                        if let Some(error) = limit_exceeded_error(&e) {
+                            self.queue_response(Frame::SimpleError(error.to_string()))
+                                .await?;
+                        }
+                        return self.flush_responses().await;
                     }
-                    ProcessOutcome::Respond(r) => self.send_response(r).await?,
                 }
             }
-            if halting_error.is_some() {
-                return Ok(());
-            }
         }
         Ok(())
     }
@@ -127,6 +1148,8 @@ mod tests {
     use tokio::io::{AsyncReadExt, AsyncWriteExt, Sink, sink, split};
 
     use super::*;
+    use crate::aof::AppendFsync;
+    use crate::store::{ExpireCondition, ScanFilter, SetCondition, SetExpiration, SetOptions};
 
     fn dummy_shutdown_token() -> CancellationToken {
         CancellationToken::new()
@@ -134,7 +1157,13 @@ mod tests {
 
     fn setup_dummy_connection() -> Connection<tokio::io::Empty, Sink> {
         let store: Store = Store::new();
-        Connection::new(tokio::io::empty(), sink(), store, dummy_shutdown_token())
+        Connection::new(
+            tokio::io::empty(),
+            sink(),
+            store,
+            Hub::new(),
+            dummy_shutdown_token(),
+        )
     }
 
     #[tokio::test]
@@ -161,6 +1190,7 @@ mod tests {
             .process_command(Command::SET {
                 key: "mykey".into(),
                 value: "myvalue".into(),
+                options: SetOptions::default(),
             })
             .await;
         assert_eq!(
@@ -176,6 +1206,7 @@ mod tests {
             .process_command(Command::SET {
                 key: "mykey".into(),
                 value: "myvalue".into(),
+                options: SetOptions::default(),
             })
             .await;
         assert_eq!(
@@ -211,11 +1242,12 @@ mod tests {
             .process_command(Command::SET {
                 key: "mykey".into(),
                 value: "myvalue".into(),
+                options: SetOptions::default(),
             })
             .await;
         let response = conn
             .process_command(Command::DEL {
-                key: "mykey".into(),
+                keys: vec!["mykey".into()],
             })
             .await;
         assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(1)))
@@ -226,7 +1258,7 @@ mod tests {
         let mut conn = setup_dummy_connection();
         let response = conn
             .process_command(Command::DEL {
-                key: "mykey".into(),
+                keys: vec!["mykey".into()],
             })
             .await;
         assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(0)))
@@ -239,12 +1271,14 @@ mod tests {
             .process_command(Command::SET {
                 key: "mykey".into(),
                 value: "myvalue".into(),
+                options: SetOptions::default(),
             })
             .await;
         let response = conn
             .process_command(Command::EXPIRE {
                 key: "mykey".into(),
                 value: 60,
+                conditions: vec![],
             })
             .await;
         assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(1)))
@@ -257,6 +1291,7 @@ mod tests {
             .process_command(Command::EXPIRE {
                 key: "mykey".into(),
                 value: 60,
+                conditions: vec![],
             })
             .await;
         assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(0)))
@@ -269,12 +1304,14 @@ mod tests {
             .process_command(Command::SET {
                 key: "mykey".into(),
                 value: "myvalue".into(),
+                options: SetOptions::default(),
             })
             .await;
         let _ = conn
             .process_command(Command::EXPIRE {
                 key: "mykey".into(),
                 value: 0,
+                conditions: vec![],
             })
             .await;
         tokio::time::sleep(tokio::time::Duration::from_millis(1)).await;
@@ -282,6 +1319,7 @@ mod tests {
             .process_command(Command::EXPIRE {
                 key: "mykey".into(),
                 value: 60,
+                conditions: vec![],
             })
             .await;
         assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(0)))
@@ -305,6 +1343,7 @@ mod tests {
             .process_command(Command::SET {
                 key: "mykey".into(),
                 value: "myvalue".into(),
+                options: SetOptions::default(),
             })
             .await;
         let response = conn
@@ -322,12 +1361,14 @@ mod tests {
             .process_command(Command::SET {
                 key: "mykey".into(),
                 value: "myvalue".into(),
+                options: SetOptions::default(),
             })
             .await;
         let _ = conn
             .process_command(Command::EXPIRE {
                 key: "mykey".into(),
                 value: 60,
+                conditions: vec![],
             })
             .await;
         let response = conn
@@ -348,12 +1389,14 @@ mod tests {
             .process_command(Command::SET {
                 key: "mykey".into(),
                 value: "myvalue".into(),
+                options: SetOptions::default(),
             })
             .await;
         let _ = conn
             .process_command(Command::EXPIRE {
                 key: "mykey".into(),
                 value: 0,
+                conditions: vec![],
             })
             .await;
         tokio::time::sleep(tokio::time::Duration::from_millis(1)).await;
@@ -366,58 +1409,1375 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn send_response() {
-        let (client, server) = tokio::io::duplex(64);
-        let mut client_reader = BufReader::new(client);
-        let store = Store::new();
-        let mut conn = Connection::new(tokio::io::empty(), server, store, dummy_shutdown_token());
-        conn.send_response(Frame::SimpleString("OK".into()))
-            .await
-            .unwrap();
-        let mut buf = [0; 5];
-        client_reader.read_exact(&mut buf).await.unwrap();
-        assert_eq!(&buf, b"+OK\r\n");
-        conn.send_response(Frame::SimpleError("Test".into()))
-            .await
-            .unwrap();
-        let mut buf = [0; 7];
-        client_reader.read_exact(&mut buf).await.unwrap();
-        assert_eq!(&buf, b"-Test\r\n")
+    async fn list_push_pop_and_range() {
+        let mut conn = setup_dummy_connection();
+        let response = conn
+            .process_command(Command::RPUSH {
+                key: "mylist".into(),
+                values: vec!["a".into(), "b".into(), "c".into()],
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(3)));
+
+        let response = conn
+            .process_command(Command::LPOP {
+                key: "mylist".into(),
+                count: None,
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::Bulk(Some("a".into())))
+        );
+
+        let response = conn
+            .process_command(Command::LRANGE {
+                key: "mylist".into(),
+                start: 0,
+                stop: -1,
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::Array(Some(vec![
+                Frame::Bulk(Some("b".into())),
+                Frame::Bulk(Some("c".into())),
+            ])))
+        );
+
+        let response = conn
+            .process_command(Command::LLEN {
+                key: "mylist".into(),
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(2)));
     }
 
-    struct TestCase<'a> {
-        call: &'a [u8],
-        response: &'a [u8],
-        expected: &'a str,
+    #[tokio::test]
+    async fn pop_missing_key_returns_nil_matching_count_form() {
+        let mut conn = setup_dummy_connection();
+        let response = conn
+            .process_command(Command::RPOP {
+                key: "mylist".into(),
+                count: None,
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Bulk(None)));
+
+        let response = conn
+            .process_command(Command::RPOP {
+                key: "mylist".into(),
+                count: Some(2),
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Array(None)));
     }
 
     #[tokio::test]
-    async fn e2e_run() {
-        let test_cases = vec![
-            TestCase {
-                call: b"*1\r\n$4\r\nPING\r\n",
-                response: b"+PONG\r\n",
-                expected: "Should respond to PING with PONG",
-            },
-            TestCase {
-                call: b"*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$7\r\nmyvalue\r\n",
-                response: b"+OK\r\n",
-                expected: "Should respond to SET with OK",
-            },
-            TestCase {
-                call: b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n",
-                response: b"$7\r\nmyvalue\r\n",
-                expected: "Should retrieve value of mykey: myvalue",
-            },
-            TestCase {
-                call: b"*2\r\n$3\r\nGET\r\n$8\r\notherkey\r\n",
-                response: b"$-1\r\n",
-                expected: "Missing keys return null bulk strings",
-            },
-            TestCase {
-                call: b"*2\r\n$3\r\nDEL\r\n$5\r\nmykey\r\n",
-                response: b":1\r\n",
-                expected: "Should return 1 if key is successfully deleted",
+    async fn get_on_list_returns_wrongtype_error() {
+        let mut conn = setup_dummy_connection();
+        let _ = conn
+            .process_command(Command::LPUSH {
+                key: "mylist".into(),
+                values: vec!["a".into()],
+            })
+            .await;
+        let response = conn
+            .process_command(Command::GET {
+                key: "mylist".into(),
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::SimpleError(
+                "WRONGTYPE Operation against a key holding the wrong kind of value".into()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn hash_set_get_and_getall() {
+        let mut conn = setup_dummy_connection();
+        let response = conn
+            .process_command(Command::HSET {
+                key: "user".into(),
+                fields: vec![("name".into(), "ada".into())],
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(1)));
+
+        let response = conn
+            .process_command(Command::HGET {
+                key: "user".into(),
+                field: "name".into(),
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::Bulk(Some("ada".into())))
+        );
+
+        let response = conn
+            .process_command(Command::HGETALL { key: "user".into() })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::Map(vec![(
+                Frame::Bulk(Some("name".into())),
+                Frame::Bulk(Some("ada".into())),
+            )]))
+        );
+
+        let response = conn
+            .process_command(Command::HEXISTS {
+                key: "user".into(),
+                field: "age".into(),
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(0)));
+    }
+
+    #[tokio::test]
+    async fn hincrby_on_non_integer_field_returns_error() {
+        let mut conn = setup_dummy_connection();
+        let _ = conn
+            .process_command(Command::HSET {
+                key: "user".into(),
+                fields: vec![("name".into(), "ada".into())],
+            })
+            .await;
+        let response = conn
+            .process_command(Command::HINCRBY {
+                key: "user".into(),
+                field: "name".into(),
+                increment: 1,
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::SimpleError(
+                "ERR value is not an integer or out of range".into()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn set_membership_and_store_variant() {
+        let mut conn = setup_dummy_connection();
+        let response = conn
+            .process_command(Command::SADD {
+                key: "a".into(),
+                members: vec!["x".into(), "y".into()],
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(2)));
+        let _ = conn
+            .process_command(Command::SADD {
+                key: "b".into(),
+                members: vec!["y".into()],
+            })
+            .await;
+
+        let response = conn
+            .process_command(Command::SINTER {
+                keys: vec!["a".into(), "b".into()],
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::Set(vec![Frame::Bulk(Some("y".into()))]))
+        );
+
+        let response = conn
+            .process_command(Command::SDIFFSTORE {
+                destination: "c".into(),
+                keys: vec!["a".into(), "b".into()],
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(1)));
+
+        let response = conn
+            .process_command(Command::SISMEMBER {
+                key: "c".into(),
+                member: "x".into(),
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(1)));
+    }
+
+    #[tokio::test]
+    async fn sorted_set_range_with_scores_and_rank() {
+        let mut conn = setup_dummy_connection();
+        let response = conn
+            .process_command(Command::ZADD {
+                key: "board".into(),
+                options: Default::default(),
+                members: vec![(2.0, "bob".into()), (1.5, "alice".into())],
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(2)));
+
+        let response = conn
+            .process_command(Command::ZRANGE {
+                key: "board".into(),
+                start: 0,
+                stop: -1,
+                with_scores: true,
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::Array(Some(vec![
+                Frame::Bulk(Some("alice".into())),
+                Frame::Bulk(Some("1.5".into())),
+                Frame::Bulk(Some("bob".into())),
+                Frame::Bulk(Some("2".into())),
+            ])))
+        );
+
+        let response = conn
+            .process_command(Command::ZRANK {
+                key: "board".into(),
+                member: "bob".into(),
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(1)));
+
+        let response = conn
+            .process_command(Command::ZRANK {
+                key: "board".into(),
+                member: "carol".into(),
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Bulk(None)));
+
+        let response = conn
+            .process_command(Command::ZINCRBY {
+                key: "board".into(),
+                increment: 1.0,
+                member: "alice".into(),
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Double(2.5)));
+    }
+
+    #[tokio::test]
+    async fn counter_commands_reply_with_new_values() {
+        let mut conn = setup_dummy_connection();
+        let response = conn
+            .process_command(Command::INCR {
+                key: "counter".into(),
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(1)));
+
+        let response = conn
+            .process_command(Command::DECRBY {
+                key: "counter".into(),
+                decrement: 3,
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(-2)));
+
+        let response = conn
+            .process_command(Command::INCRBYFLOAT {
+                key: "counter".into(),
+                increment: 0.5,
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::Bulk(Some("-1.5".into())))
+        );
+
+        let response = conn
+            .process_command(Command::INCR {
+                key: "counter".into(),
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::SimpleError(
+                "ERR value is not an integer or out of range".into()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn decrby_of_minimum_integer_reports_overflow() {
+        let mut conn = setup_dummy_connection();
+        let response = conn
+            .process_command(Command::DECRBY {
+                key: "counter".into(),
+                decrement: i64::MIN,
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::SimpleError(
+                "ERR increment or decrement would overflow".into()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn set_with_nx_replies_nil_when_key_exists() {
+        let mut conn = setup_dummy_connection();
+        let nx = SetOptions {
+            condition: Some(SetCondition::IfMissing),
+            expiration: Some(SetExpiration::After(Duration::from_secs(30))),
+            get: false,
+        };
+        let response = conn
+            .process_command(Command::SET {
+                key: "lock".into(),
+                value: "first".into(),
+                options: nx,
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::SimpleString("OK".into()))
+        );
+
+        let response = conn
+            .process_command(Command::SET {
+                key: "lock".into(),
+                value: "second".into(),
+                options: nx,
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Bulk(None)));
+    }
+
+    #[tokio::test]
+    async fn set_with_get_replies_previous_value() {
+        let mut conn = setup_dummy_connection();
+        let get = SetOptions {
+            get: true,
+            ..Default::default()
+        };
+        let response = conn
+            .process_command(Command::SET {
+                key: "key".into(),
+                value: "first".into(),
+                options: get,
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Bulk(None)));
+
+        let response = conn
+            .process_command(Command::SET {
+                key: "key".into(),
+                value: "second".into(),
+                options: get,
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::Bulk(Some("first".into())))
+        );
+    }
+
+    #[tokio::test]
+    async fn mset_then_mget_and_variadic_del() {
+        let mut conn = setup_dummy_connection();
+        let response = conn
+            .process_command(Command::MSET {
+                pairs: vec![("a".into(), "1".into()), ("b".into(), "2".into())],
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::SimpleString("OK".into()))
+        );
+
+        let response = conn
+            .process_command(Command::MGET {
+                keys: vec!["a".into(), "missing".into(), "b".into()],
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::Array(Some(vec![
+                Frame::Bulk(Some("1".into())),
+                Frame::Bulk(None),
+                Frame::Bulk(Some("2".into())),
+            ])))
+        );
+
+        let response = conn
+            .process_command(Command::MSETNX {
+                pairs: vec![("b".into(), "3".into()), ("c".into(), "4".into())],
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(0)));
+
+        let response = conn
+            .process_command(Command::UNLINK {
+                keys: vec!["a".into(), "b".into(), "c".into()],
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(2)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pexpire_with_flags_then_pttl_and_persist() {
+        let mut conn = setup_dummy_connection();
+        let _ = conn
+            .process_command(Command::SET {
+                key: "mykey".into(),
+                value: "myvalue".into(),
+                options: SetOptions::default(),
+            })
+            .await;
+
+        let response = conn
+            .process_command(Command::PEXPIRE {
+                key: "mykey".into(),
+                value: 2500,
+                conditions: vec![ExpireCondition::IfSome],
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(0)));
+
+        let response = conn
+            .process_command(Command::PEXPIRE {
+                key: "mykey".into(),
+                value: 2500,
+                conditions: vec![ExpireCondition::IfNone],
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(1)));
+
+        let response = conn
+            .process_command(Command::PTTL {
+                key: "mykey".into(),
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(2500)));
+
+        let response = conn
+            .process_command(Command::PERSIST {
+                key: "mykey".into(),
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(1)));
+
+        let response = conn
+            .process_command(Command::EXPIRETIME {
+                key: "mykey".into(),
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(-1)));
+    }
+
+    #[tokio::test]
+    async fn type_rename_and_copy_replies() {
+        let mut conn = setup_dummy_connection();
+        let response = conn
+            .process_command(Command::TYPE {
+                key: "mykey".into(),
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::SimpleString("none".into()))
+        );
+
+        let response = conn
+            .process_command(Command::RENAME {
+                key: "mykey".into(),
+                new_key: "other".into(),
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::SimpleError("ERR no such key".into()))
+        );
+
+        let _ = conn
+            .process_command(Command::RPUSH {
+                key: "mykey".into(),
+                values: vec!["a".into()],
+            })
+            .await;
+        let response = conn
+            .process_command(Command::RENAME {
+                key: "mykey".into(),
+                new_key: "other".into(),
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::SimpleString("OK".into()))
+        );
+
+        let response = conn
+            .process_command(Command::COPY {
+                source: "other".into(),
+                destination: "copied".into(),
+                replace: false,
+            })
+            .await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(1)));
+
+        let response = conn
+            .process_command(Command::TYPE {
+                key: "copied".into(),
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::SimpleString("list".into()))
+        );
+
+        let response = conn.process_command(Command::DBSIZE).await;
+        assert_eq!(response, ProcessOutcome::Respond(Frame::Integer(2)));
+    }
+
+    #[tokio::test]
+    async fn scan_replies_with_cursor_and_keys() {
+        let mut conn = setup_dummy_connection();
+        let _ = conn
+            .process_command(Command::MSET {
+                pairs: vec![("a".into(), "1".into()), ("b".into(), "2".into())],
+            })
+            .await;
+
+        let response = conn
+            .process_command(Command::SCAN {
+                cursor: 0,
+                count: None,
+                filter: ScanFilter {
+                    pattern: Some("a".into()),
+                    type_name: None,
+                },
+            })
+            .await;
+        assert_eq!(
+            response,
+            ProcessOutcome::Respond(Frame::Array(Some(vec![
+                Frame::Bulk(Some("0".into())),
+                Frame::Array(Some(vec![Frame::Bulk(Some("a".into()))])),
+            ])))
+        );
+    }
+
+    #[tokio::test]
+    async fn multi_queues_commands_until_exec() {
+        let mut conn = setup_dummy_connection();
+        let ok = ProcessOutcome::Respond(Frame::SimpleString("OK".into()));
+        let queued = ProcessOutcome::Respond(Frame::SimpleString("QUEUED".into()));
+
+        assert_eq!(conn.process_command(Command::MULTI).await, ok);
+        assert_eq!(
+            conn.process_command(Command::INCR {
+                key: Bytes::from_static(b"n")
+            })
+            .await,
+            queued
+        );
+        assert_eq!(
+            conn.process_command(Command::GET {
+                key: Bytes::from_static(b"n")
+            })
+            .await,
+            queued
+        );
+        assert_eq!(
+            conn.store.get(b"n").await.unwrap(),
+            None,
+            "queued commands should not run before EXEC"
+        );
+        assert_eq!(
+            conn.process_command(Command::EXEC).await,
+            ProcessOutcome::Respond(Frame::Array(Some(vec![
+                Frame::Integer(1),
+                Frame::Bulk(Some(Bytes::from_static(b"1"))),
+            ])))
+        );
+
+        assert_eq!(conn.process_command(Command::MULTI).await, ok);
+        assert_eq!(
+            conn.process_command(Command::INCR {
+                key: Bytes::from_static(b"n")
+            })
+            .await,
+            queued
+        );
+        assert_eq!(conn.process_command(Command::DISCARD).await, ok);
+        assert_eq!(conn.store.get(b"n").await.unwrap(), Some(b"1".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn transaction_commands_out_of_place_reply_with_errors() {
+        let mut conn = setup_dummy_connection();
+        let error = |message: &str| ProcessOutcome::Respond(Frame::SimpleError(message.into()));
+
+        assert_eq!(
+            conn.process_command(Command::EXEC).await,
+            error("ERR EXEC without MULTI")
+        );
+        assert_eq!(
+            conn.process_command(Command::DISCARD).await,
+            error("ERR DISCARD without MULTI")
+        );
+        conn.process_command(Command::MULTI).await;
+        assert_eq!(
+            conn.process_command(Command::MULTI).await,
+            error("ERR MULTI calls can not be nested")
+        );
+        assert_eq!(
+            conn.process_command(Command::WATCH {
+                keys: vec![Bytes::from_static(b"k")]
+            })
+            .await,
+            error("ERR WATCH inside MULTI is not allowed")
+        );
+        assert_eq!(
+            conn.process_command(Command::EXEC).await,
+            ProcessOutcome::Respond(Frame::Array(Some(vec![])))
+        );
+    }
+
+    #[tokio::test]
+    async fn exec_aborts_when_watched_key_changes() {
+        let mut conn = setup_dummy_connection();
+        let mut other = Connection::new(
+            tokio::io::empty(),
+            sink(),
+            conn.store.clone(),
+            conn.hub.clone(),
+            dummy_shutdown_token(),
+        );
+        let watch = Command::WATCH {
+            keys: vec![Bytes::from_static(b"k")],
+        };
+        let set = |value: &[u8]| Command::SET {
+            key: Bytes::from_static(b"k"),
+            value: Bytes::copy_from_slice(value),
+            options: SetOptions::default(),
+        };
+
+        conn.process_command(watch).await;
+        other.process_command(set(b"theirs")).await;
+        conn.process_command(Command::MULTI).await;
+        conn.process_command(set(b"mine")).await;
+        assert_eq!(
+            conn.process_command(Command::EXEC).await,
+            ProcessOutcome::Respond(Frame::Array(None))
+        );
+        assert_eq!(
+            conn.store.get(b"k").await.unwrap(),
+            Some(b"theirs".to_vec())
+        );
+
+        // EXEC released the watch, so the next transaction goes through.
+        other.process_command(set(b"theirs again")).await;
+        conn.process_command(Command::MULTI).await;
+        conn.process_command(set(b"mine")).await;
+        assert_eq!(
+            conn.process_command(Command::EXEC).await,
+            ProcessOutcome::Respond(Frame::Array(Some(vec![Frame::SimpleString("OK".into())])))
+        );
+    }
+
+    #[tokio::test]
+    async fn subscribe_and_unsubscribe_reply_per_channel() {
+        let mut conn = setup_dummy_connection();
+        let reply = |kind: &[u8], name: Option<&[u8]>, count: i64| {
+            Frame::Push(vec![
+                Frame::Bulk(Some(Bytes::copy_from_slice(kind))),
+                Frame::Bulk(name.map(Bytes::copy_from_slice)),
+                Frame::Integer(count),
+            ])
+        };
+
+        assert_eq!(
+            conn.process_command(Command::UNSUBSCRIBE { channels: vec![] })
+                .await,
+            ProcessOutcome::Respond(reply(b"unsubscribe", None, 0))
+        );
+        assert_eq!(
+            conn.process_command(Command::SUBSCRIBE {
+                channels: vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")]
+            })
+            .await,
+            ProcessOutcome::RespondAll(vec![
+                reply(b"subscribe", Some(b"a"), 1),
+                reply(b"subscribe", Some(b"b"), 2),
+            ])
+        );
+        assert_eq!(
+            conn.process_command(Command::GET {
+                key: Bytes::from_static(b"k")
+            })
+            .await,
+            ProcessOutcome::Respond(Frame::SimpleError(
+                "ERR only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT are allowed in this context"
+                    .into()
+            ))
+        );
+        assert_eq!(
+            conn.process_command(Command::UNSUBSCRIBE { channels: vec![] })
+                .await,
+            ProcessOutcome::RespondAll(vec![
+                reply(b"unsubscribe", Some(b"a"), 1),
+                reply(b"unsubscribe", Some(b"b"), 0),
+            ])
+        );
+        assert_eq!(
+            conn.process_command(Command::GET {
+                key: Bytes::from_static(b"k")
+            })
+            .await,
+            ProcessOutcome::Respond(Frame::Bulk(None))
+        );
+    }
+
+    #[tokio::test]
+    async fn publish_replies_with_receiver_count() {
+        let mut conn = setup_dummy_connection();
+        let mut subscriber = conn.hub.subscriber();
+        subscriber.psubscribe(b"news.*".to_vec());
+        let publish = |channel: &[u8]| Command::PUBLISH {
+            channel: Bytes::copy_from_slice(channel),
+            message: Bytes::from_static(b"hi"),
+        };
+
+        assert_eq!(
+            conn.process_command(publish(b"news.tech")).await,
+            ProcessOutcome::Respond(Frame::Integer(1))
+        );
+        assert_eq!(
+            conn.process_command(publish(b"weather")).await,
+            ProcessOutcome::Respond(Frame::Integer(0))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn blpop_times_out_with_nil_array() {
+        let mut conn = setup_dummy_connection();
+        let waiting = tokio::spawn(async move {
+            conn.process_command(Command::BLPOP {
+                keys: vec![Bytes::from_static(b"jobs")],
+                timeout: Some(Duration::from_secs(5)),
+            })
+            .await
+        });
+
+        tokio::time::sleep(Duration::from_millis(4900)).await;
+        assert!(!waiting.is_finished());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            waiting.await.unwrap(),
+            ProcessOutcome::Respond(Frame::Array(None))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn blpop_is_woken_by_a_push_from_another_connection() {
+        let mut conn = setup_dummy_connection();
+        let mut other = Connection::new(
+            tokio::io::empty(),
+            sink(),
+            conn.store.clone(),
+            conn.hub.clone(),
+            dummy_shutdown_token(),
+        );
+        let waiting = tokio::spawn(async move {
+            conn.process_command(Command::BRPOP {
+                keys: vec![Bytes::from_static(b"jobs")],
+                timeout: None,
+            })
+            .await
+        });
+        tokio::time::sleep(Duration::from_secs(60)).await;
+
+        other
+            .process_command(Command::LPUSH {
+                key: Bytes::from_static(b"jobs"),
+                values: vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")],
+            })
+            .await;
+        assert_eq!(
+            waiting.await.unwrap(),
+            ProcessOutcome::Respond(bulk_array(vec![b"jobs".to_vec(), b"a".to_vec()]))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn blmove_ends_without_value_on_shutdown() {
+        let shutdown_token = dummy_shutdown_token();
+        let mut conn = Connection::new(
+            tokio::io::empty(),
+            sink(),
+            Store::new(),
+            Hub::new(),
+            shutdown_token.clone(),
+        );
+        let waiting = tokio::spawn(async move {
+            conn.process_command(Command::BLMOVE {
+                source: Bytes::from_static(b"src"),
+                destination: Bytes::from_static(b"dst"),
+                from: ListEnd::Left,
+                to: ListEnd::Right,
+                timeout: None,
+            })
+            .await
+        });
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        shutdown_token.cancel();
+        assert_eq!(
+            waiting.await.unwrap(),
+            ProcessOutcome::Respond(Frame::Bulk(None))
+        );
+    }
+
+    #[tokio::test]
+    async fn blocking_pops_do_not_wait_inside_transactions() {
+        let mut conn = setup_dummy_connection();
+        conn.process_command(Command::MULTI).await;
+        conn.process_command(Command::BLPOP {
+            keys: vec![Bytes::from_static(b"jobs")],
+            timeout: None,
+        })
+        .await;
+        assert_eq!(
+            conn.process_command(Command::EXEC).await,
+            ProcessOutcome::Respond(Frame::Array(Some(vec![Frame::Array(None)])))
+        );
+    }
+
+    #[tokio::test]
+    async fn hello_switches_the_protocol_used_for_replies() {
+        let (client, server) = tokio::io::duplex(256);
+        let mut client_reader = BufReader::new(client);
+        let mut conn = Connection::new(
+            tokio::io::empty(),
+            server,
+            Store::new(),
+            Hub::new(),
+            dummy_shutdown_token(),
+        );
+
+        let ProcessOutcome::Respond(reply) = conn
+            .process_command(Command::HELLO {
+                protocol: Some(Protocol::Resp3),
+            })
+            .await
+        else {
+            panic!("expected a single HELLO reply");
+        };
+        let Frame::Map(fields) = reply else {
+            panic!("expected HELLO to reply with a map");
+        };
+        assert!(fields.contains(&(
+            Frame::Bulk(Some(Bytes::from_static(b"proto"))),
+            Frame::Integer(3)
+        )));
+
+        conn.send_response(Frame::Bulk(None)).await.unwrap();
+        conn.send_response(Frame::Map(vec![(Frame::Integer(1), Frame::Integer(2))]))
+            .await
+            .unwrap();
+        let mut buf = [0; 15];
+        client_reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"_\r\n%1\r\n:1\r\n:2\r\n");
+
+        conn.process_command(Command::HELLO {
+            protocol: Some(Protocol::Resp2),
+        })
+        .await;
+        conn.send_response(Frame::Map(vec![(Frame::Integer(1), Frame::Integer(2))]))
+            .await
+            .unwrap();
+        let mut buf = [0; 12];
+        client_reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"*2\r\n:1\r\n:2\r\n");
+    }
+
+    #[tokio::test]
+    async fn resp3_subscribers_may_run_other_commands() {
+        let mut conn = setup_dummy_connection();
+        conn.process_command(Command::HELLO {
+            protocol: Some(Protocol::Resp3),
+        })
+        .await;
+        conn.process_command(Command::SUBSCRIBE {
+            channels: vec![Bytes::from_static(b"a")],
+        })
+        .await;
+
+        assert_eq!(
+            conn.process_command(Command::GET {
+                key: Bytes::from_static(b"k")
+            })
+            .await,
+            ProcessOutcome::Respond(Frame::Bulk(None))
+        );
+        assert_eq!(
+            conn.process_command(Command::PING).await,
+            ProcessOutcome::Respond(Frame::SimpleString("PONG".into()))
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn write_that_cannot_be_logged_stays_applied() {
+        let store = Store::new();
+        // Every write to /dev/full fails with ENOSPC.
+        let aof = AppendOnlyFile::open(
+            std::path::Path::new("/dev/full"),
+            AppendFsync::No,
+            store.clone(),
+        )
+        .await
+        .unwrap();
+        let mut conn = Connection::new(
+            tokio::io::empty(),
+            sink(),
+            store.clone(),
+            Hub::new(),
+            dummy_shutdown_token(),
+        )
+        .with_append_only_file(aof);
+
+        let outcome = conn
+            .process_command(Command::INCR {
+                key: Bytes::from_static(b"n"),
+            })
+            .await;
+        let ProcessOutcome::Respond(Frame::SimpleError(message)) = outcome else {
+            panic!("expected an error, got {outcome:?}");
+        };
+        assert!(message.starts_with(
+            "ERR Errors writing to the AOF file, the write was applied but not logged"
+        ));
+        assert_eq!(store.get(b"n").await.unwrap(), Some(b"1".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn writes_are_logged_in_the_append_only_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("appendonly.aof");
+        let store = Store::new();
+        store.record_served_pops();
+        let aof = AppendOnlyFile::open(&path, AppendFsync::Always, store.clone())
+            .await
+            .unwrap();
+        let connection = || {
+            Connection::new(
+                tokio::io::empty(),
+                sink(),
+                store.clone(),
+                Hub::new(),
+                dummy_shutdown_token(),
+            )
+            .with_append_only_file(aof.clone())
+        };
+        let mut conn = connection();
+        let mut waiting = connection();
+        let waiting = tokio::spawn(async move {
+            waiting
+                .process_command(Command::BRPOP {
+                    keys: vec![Bytes::from_static(b"jobs")],
+                    timeout: None,
+                })
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let parse = |args: &[&[u8]]| {
+            Command::try_from(Frame::Array(Some(
+                args.iter()
+                    .map(|a| Frame::Bulk(Some(Bytes::copy_from_slice(a))))
+                    .collect(),
+            )))
+            .unwrap()
+        };
+        for args in [
+            &[&b"SET"[..], b"a", b"1"][..],
+            &[b"GET", b"a"],
+            &[b"HSET", b"h", b"f", b"v"],
+            &[b"INCR", b"h"],
+            &[b"MULTI"],
+            &[b"INCR", b"a"],
+            &[b"GET", b"a"],
+            &[b"EXEC"],
+            &[b"LPUSH", b"jobs", b"x", b"y"],
+        ] {
+            conn.process_command(parse(args)).await;
+        }
+        assert_eq!(
+            waiting.await.unwrap(),
+            ProcessOutcome::Respond(bulk_array(vec![b"jobs".to_vec(), b"x".to_vec()]))
+        );
+
+        let contents = tokio::fs::read(&path).await.unwrap();
+        let ParseResult::Complete(frames) = Parser::new().parse(&contents) else {
+            panic!("log should parse");
+        };
+        let logged: Vec<Command> = frames
+            .into_iter()
+            .map(|frame| Command::try_from(frame).unwrap())
+            .collect();
+        let expected: Vec<Command> = [
+            &[&b"SET"[..], b"a", b"1"][..],
+            &[b"HSET", b"h", b"f", b"v"],
+            &[b"MULTI"],
+            &[b"INCR", b"a"],
+            &[b"EXEC"],
+            &[b"LPUSH", b"jobs", b"x", b"y"],
+            &[b"RPOP", b"jobs"],
+        ]
+        .into_iter()
+        .map(parse)
+        .collect();
+        assert_eq!(logged, expected);
+    }
+
+    #[tokio::test]
+    async fn reads_do_not_wait_for_the_append_only_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new();
+        let aof = AppendOnlyFile::open(
+            &dir.path().join("appendonly.aof"),
+            AppendFsync::No,
+            store.clone(),
+        )
+        .await
+        .unwrap();
+        let mut conn = Connection::new(
+            tokio::io::empty(),
+            sink(),
+            store,
+            Hub::new(),
+            dummy_shutdown_token(),
+        )
+        .with_append_only_file(aof.clone());
+
+        let log = aof.lock().await;
+        let read = conn.process_command(Command::GET {
+            key: Bytes::from_static(b"a"),
+        });
+        assert_eq!(
+            tokio::time::timeout(Duration::from_secs(1), read)
+                .await
+                .expect("GET waited for the append-only file"),
+            ProcessOutcome::Respond(Frame::Bulk(None))
+        );
+        drop(log);
+    }
+
+    #[tokio::test]
+    async fn info_reports_persistence_status() {
+        let mut conn = Connection::new(
+            tokio::io::empty(),
+            sink(),
+            Store::new(),
+            Hub::new(),
+            dummy_shutdown_token(),
+        );
+        conn.process_command(Command::SET {
+            key: Bytes::from_static(b"a"),
+            value: Bytes::from_static(b"1"),
+            options: SetOptions::default(),
+        })
+        .await;
+
+        let ProcessOutcome::Respond(Frame::Bulk(Some(info))) = conn
+            .process_command(Command::INFO { sections: vec![] })
+            .await
+        else {
+            panic!("expected INFO to reply with a bulk string");
+        };
+        let info = String::from_utf8(info.to_vec()).unwrap();
+        assert!(info.starts_with("# Persistence\r\n"));
+        assert!(info.contains("rdb_changes_since_last_save:1\r\n"));
+        assert!(info.contains("rdb_bgsave_in_progress:0\r\n"));
+        assert!(info.contains("rdb_last_bgsave_status:ok\r\n"));
+
+        assert_eq!(
+            conn.process_command(Command::INFO {
+                sections: vec![Bytes::from_static(b"server")]
+            })
+            .await,
+            ProcessOutcome::Respond(Frame::Bulk(Some(Bytes::new())))
+        );
+    }
+
+    #[tokio::test]
+    async fn save_commands_write_the_archive() {
+        let mut conn = setup_dummy_connection();
+        for command in [Command::SAVE, Command::BGSAVE] {
+            assert_eq!(
+                conn.process_command(command).await,
+                ProcessOutcome::Respond(Frame::SimpleError(
+                    "ERR archive persistence is not enabled".into()
+                ))
+            );
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("archive");
+        let store = Store::new();
+        let snapshots = Snapshots::new(store.clone(), Some(path.clone()));
+        let mut conn = Connection::new(
+            tokio::io::empty(),
+            sink(),
+            store,
+            Hub::new(),
+            dummy_shutdown_token(),
+        )
+        .with_snapshots(snapshots.clone());
+        conn.process_command(Command::SET {
+            key: Bytes::from_static(b"a"),
+            value: Bytes::from_static(b"1"),
+            options: SetOptions::default(),
+        })
+        .await;
+
+        assert_eq!(
+            conn.process_command(Command::SAVE).await,
+            ProcessOutcome::Respond(Frame::SimpleString("OK".into()))
+        );
+        assert!(path.exists());
+        assert_eq!(
+            conn.process_command(Command::LASTSAVE).await,
+            ProcessOutcome::Respond(Frame::Integer(snapshots.last_save() as i64))
+        );
+
+        let running = snapshots.start_save().unwrap();
+        for command in [Command::SAVE, Command::BGSAVE] {
+            assert_eq!(
+                conn.process_command(command).await,
+                ProcessOutcome::Respond(Frame::SimpleError(
+                    "ERR Background save already in progress".into()
+                ))
+            );
+        }
+        running.await.unwrap().unwrap();
+        assert_eq!(
+            conn.process_command(Command::BGSAVE).await,
+            ProcessOutcome::Respond(Frame::SimpleString("Background saving started".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn shutdown_cancels_the_server() {
+        let mut conn = setup_dummy_connection();
+        assert_eq!(
+            conn.process_command(Command::SHUTDOWN { save: false })
+                .await,
+            ProcessOutcome::Quit
+        );
+        assert!(conn.shutdown_token.is_cancelled());
+        assert!(!conn.snapshots.saves_on_shutdown());
+    }
+
+    #[tokio::test]
+    async fn bgrewriteaof_needs_the_append_only_file() {
+        let mut conn = Connection::new(
+            tokio::io::empty(),
+            sink(),
+            Store::new(),
+            Hub::new(),
+            dummy_shutdown_token(),
+        );
+        assert_eq!(
+            conn.process_command(Command::BGREWRITEAOF).await,
+            ProcessOutcome::Respond(Frame::SimpleError(
+                "ERR append only file is not enabled".into()
+            ))
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new();
+        let aof = AppendOnlyFile::open(
+            &dir.path().join("appendonly.aof"),
+            AppendFsync::No,
+            store.clone(),
+        )
+        .await
+        .unwrap();
+        let mut conn = Connection::new(
+            tokio::io::empty(),
+            sink(),
+            store,
+            Hub::new(),
+            dummy_shutdown_token(),
+        )
+        .with_append_only_file(aof);
+        assert_eq!(
+            conn.process_command(Command::BGREWRITEAOF).await,
+            ProcessOutcome::Respond(Frame::SimpleString(
+                "Background append only file rewriting started".into()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn queued_responses_wait_for_flush() {
+        let (client, server) = tokio::io::duplex(64);
+        let (reader, writer) = split(server);
+        let mut conn = Connection::new(
+            reader,
+            writer,
+            Store::new(),
+            Hub::new(),
+            dummy_shutdown_token(),
+        );
+        let (client_reader, _client_writer) = split(client);
+        let mut client_reader = BufReader::new(client_reader);
+
+        conn.queue_response(Frame::SimpleString("OK".into()))
+            .await
+            .unwrap();
+        conn.queue_response(Frame::Integer(1)).await.unwrap();
+        let mut buf = [0; 1];
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), client_reader.read(&mut buf))
+                .await
+                .is_err(),
+            "queued responses should not be written before a flush"
+        );
+
+        conn.flush_responses().await.unwrap();
+        let mut buf = [0; 9];
+        client_reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"+OK\r\n:1\r\n");
+    }
+
+    #[tokio::test]
+    async fn pipelined_replies_are_flushed_before_blocking() {
+        let (client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = split(server);
+        let mut conn = Connection::new(
+            reader,
+            writer,
+            Store::new(),
+            Hub::new(),
+            dummy_shutdown_token(),
+        );
+        let (client_reader, mut client_writer) = split(client);
+        let mut client_reader = BufReader::new(client_reader);
+        let handle = tokio::spawn(async move { conn.run().await });
+
+        client_writer
+            .write_all(
+                b"*1\r\n$4\r\nPING\r\n*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n\
+                  *3\r\n$5\r\nBLPOP\r\n$4\r\nlist\r\n$1\r\n0\r\n",
+            )
+            .await
+            .unwrap();
+        let mut buf = [0; 12];
+        tokio::time::timeout(
+            Duration::from_millis(250),
+            client_reader.read_exact(&mut buf),
+        )
+        .await
+        .expect("replies before BLPOP should not wait on it")
+        .unwrap();
+        assert_eq!(&buf, b"+PONG\r\n+OK\r\n");
+
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn subscriber_that_falls_behind_is_told_before_disconnecting() {
+        let (client, server) = tokio::io::duplex(64);
+        let (reader, writer) = split(server);
+        let hub = Hub::with_buffer_len(2);
+        let mut conn = Connection::new(
+            reader,
+            writer,
+            Store::new(),
+            hub.clone(),
+            dummy_shutdown_token(),
+        );
+        let (client_reader, mut client_writer) = split(client);
+        let mut client_reader = BufReader::new(client_reader);
+        let handle = tokio::spawn(async move { conn.run().await });
+
+        client_writer
+            .write_all(b"*2\r\n$9\r\nSUBSCRIBE\r\n$1\r\na\r\n")
+            .await
+            .unwrap();
+        let mut buf = [0; 30];
+        client_reader.read_exact(&mut buf).await.unwrap();
+        while hub.publish(b"a", b"payload") > 0 {}
+
+        let mut rest = Vec::new();
+        client_reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(
+            rest,
+            b"-ERR subscriber fell too far behind, closing the connection\r\n"
+        );
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn send_response() {
+        let (client, server) = tokio::io::duplex(64);
+        let mut client_reader = BufReader::new(client);
+        let store = Store::new();
+        let mut conn = Connection::new(
+            tokio::io::empty(),
+            server,
+            store,
+            Hub::new(),
+            dummy_shutdown_token(),
+        );
+        conn.send_response(Frame::SimpleString("OK".into()))
+            .await
+            .unwrap();
+        let mut buf = [0; 5];
+        client_reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"+OK\r\n");
+        conn.send_response(Frame::SimpleError("Test".into()))
+            .await
+            .unwrap();
+        let mut buf = [0; 7];
+        client_reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"-Test\r\n")
+    }
+
+    struct TestCase<'a> {
+        call: &'a [u8],
+        response: &'a [u8],
+        expected: &'a str,
+    }
+
+    #[tokio::test]
+    async fn e2e_run() {
+        let test_cases = vec![
+            TestCase {
+                call: b"*1\r\n$4\r\nPING\r\n",
+                response: b"+PONG\r\n",
+                expected: "Should respond to PING with PONG",
+            },
+            TestCase {
+                call: b"*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$7\r\nmyvalue\r\n",
+                response: b"+OK\r\n",
+                expected: "Should respond to SET with OK",
+            },
+            TestCase {
+                call: b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n",
+                response: b"$7\r\nmyvalue\r\n",
+                expected: "Should retrieve value of mykey: myvalue",
+            },
+            TestCase {
+                call: b"*2\r\n$3\r\nGET\r\n$8\r\notherkey\r\n",
+                response: b"$-1\r\n",
+                expected: "Missing keys return null bulk strings",
+            },
+            TestCase {
+                call: b"*2\r\n$3\r\nDEL\r\n$5\r\nmykey\r\n",
+                response: b":1\r\n",
+                expected: "Should return 1 if key is successfully deleted",
             },
             TestCase {
                 call: b"*2\r\n$3\r\nDEL\r\n$5\r\nmykey\r\n",
@@ -496,17 +2856,22 @@ mod tests {
             },
             TestCase {
                 call: b"*1\r\n$3\r\nFOO\r\n",
-                response: b"-Unknown Command\r\n",
+                response: b"-ERR unknown command 'FOO', with args beginning with: \r\n",
                 expected: "Unknown command gives error",
             },
             TestCase {
                 call: b"*4\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$7\r\nmyvalue\r\n$8\r\ntoo many\r\n",
-                response: b"-Wrong number of arguments\r\n",
+                response: b"-ERR syntax error\r\n",
+                expected: "Unknown SET option gives syntax error",
+            },
+            TestCase {
+                call: b"*2\r\n$3\r\nSET\r\n$5\r\nmykey\r\n",
+                response: b"-ERR wrong number of arguments for 'set' command\r\n",
                 expected: "Wrong number of arguments gives error",
             },
             TestCase {
                 call: b"*1\r\n$3\r\nGET\r\n",
-                response: b"-Wrong number of arguments\r\n",
+                response: b"-ERR wrong number of arguments for 'get' command\r\n",
                 expected: "Wrong number of arguments gives error",
             },
         ];
@@ -514,7 +2879,7 @@ mod tests {
         let (client, server) = tokio::io::duplex(128);
         let (reader, writer) = split(server);
         let store = Store::new();
-        let mut conn = Connection::new(reader, writer, store, dummy_shutdown_token());
+        let mut conn = Connection::new(reader, writer, store, Hub::new(), dummy_shutdown_token());
 
         let (reader, writer) = split(client);
 
@@ -1,5 +1,37 @@
+use std::fmt;
+
+use crate::archive::ArchiveError;
 use crate::parser::ParseError;
 
+/// How many bytes of an unknown command's name and arguments are echoed back.
+const UNKNOWN_COMMAND_ECHO_LEN: usize = 128;
+
+/// The leading word of an error reply, which clients use to classify it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    Err,
+    WrongType,
+    ExecAbort,
+    NoProto,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::Err => "ERR",
+            ErrorCode::WrongType => "WRONGTYPE",
+            ErrorCode::ExecAbort => "EXECABORT",
+            ErrorCode::NoProto => "NOPROTO",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     Io(tokio::io::Error),
@@ -8,10 +40,148 @@ pub enum Error {
         given: usize,
         expected: usize,
     },
-    UnknownCommand,
+    UnknownCommand {
+        command: String,
+        args: Vec<String>,
+    },
     InvalidCommandFrame,
-    WrongArgumentType,
+    WrongArgumentType(&'static str),
+    SyntaxError,
+    IncompatibleOptions(&'static str),
+    InvalidExpireTime(&'static str),
+    InvalidTimeout(&'static str),
+    UnsupportedProtocol,
+    /// A request broke the protocol in a way the parser can describe.
+    Protocol(&'static str),
+    NotAllowedWhileSubscribed,
+    /// A subscriber left too many messages unread and is being disconnected.
+    SubscriberOverflow,
+    NotAllowedInTransaction,
+    NestedMulti,
+    ExecWithoutMulti,
+    DiscardWithoutMulti,
+    WatchInsideMulti,
+    /// `EXEC` after a command failed to queue.
+    ExecAborted,
+    /// Logging a write failed after the store had already applied it.
+    AofWrite(std::io::Error),
+    AofDisabled,
+    RewriteInProgress,
+    Save(ArchiveError),
+    ArchiveDisabled,
+    SaveInProgress,
+}
+
+impl Error {
+    /// Builds [`Error::UnknownCommand`] from the raw command name and arguments.
+    pub fn unknown_command(command: &[u8], args: &[&[u8]]) -> Error {
+        Error::UnknownCommand {
+            command: String::from_utf8_lossy(command).into_owned(),
+            args: args
+                .iter()
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect(),
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::UnsupportedProtocol => ErrorCode::NoProto,
+            Error::ExecAborted => ErrorCode::ExecAbort,
+            _ => ErrorCode::Err,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.code())?;
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::WrongArity { command, .. } => write!(
+                f,
+                "wrong number of arguments for '{}' command",
+                command.to_ascii_lowercase()
+            ),
+            Error::UnknownCommand { command, args } => {
+                write!(
+                    f,
+                    "unknown command '{}', with args beginning with: ",
+                    single_line(truncate(command, UNKNOWN_COMMAND_ECHO_LEN))
+                )?;
+                let mut echoed = 0;
+                for arg in args {
+                    if echoed >= UNKNOWN_COMMAND_ECHO_LEN {
+                        break;
+                    }
+                    let arg = truncate(arg, UNKNOWN_COMMAND_ECHO_LEN - echoed);
+                    echoed += arg.len() + 3;
+                    write!(f, "'{}' ", single_line(arg))?;
+                }
+                Ok(())
+            }
+            Error::InvalidCommandFrame => write!(f, "Protocol error: invalid command frame"),
+            Error::WrongArgumentType(message)
+            | Error::IncompatibleOptions(message)
+            | Error::InvalidTimeout(message) => write!(f, "{message}"),
+            Error::SyntaxError => write!(f, "syntax error"),
+            Error::InvalidExpireTime(command) => {
+                write!(f, "invalid expire time in '{command}' command")
+            }
+            Error::UnsupportedProtocol => write!(f, "unsupported protocol version"),
+            Error::Protocol(message) => write!(f, "Protocol error: {message}"),
+            Error::NotAllowedWhileSubscribed => write!(
+                f,
+                "only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT are allowed in this context"
+            ),
+            Error::SubscriberOverflow => {
+                write!(f, "subscriber fell too far behind, closing the connection")
+            }
+            Error::NotAllowedInTransaction => write!(f, "Command not allowed inside a transaction"),
+            Error::NestedMulti => write!(f, "MULTI calls can not be nested"),
+            Error::ExecWithoutMulti => write!(f, "EXEC without MULTI"),
+            Error::DiscardWithoutMulti => write!(f, "DISCARD without MULTI"),
+            Error::WatchInsideMulti => write!(f, "WATCH inside MULTI is not allowed"),
+            Error::ExecAborted => {
+                write!(f, "Transaction discarded because of previous errors.")
+            }
+            Error::AofWrite(e) => write!(
+                f,
+                "Errors writing to the AOF file, the write was applied but not logged: {e}"
+            ),
+            Error::AofDisabled => write!(f, "append only file is not enabled"),
+            Error::RewriteInProgress => {
+                write!(
+                    f,
+                    "Background append only file rewriting already in progress"
+                )
+            }
+            Error::Save(e) => write!(f, "{e}"),
+            Error::ArchiveDisabled => write!(f, "archive persistence is not enabled"),
+            Error::SaveInProgress => write!(f, "Background save already in progress"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Cuts `value` to at most `max` bytes without splitting a character.
+fn truncate(value: &str, max: usize) -> &str {
+    if value.len() <= max {
+        return value;
+    }
+    let mut end = max;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    &value[..end]
+}
+
+/// Replaces line breaks so client input cannot end a simple error early.
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
 }
+
 impl From<tokio::io::Error> for Error {
     fn from(value: tokio::io::Error) -> crate::error::Error {
         Error::Io(value)
@@ -23,3 +193,70 @@ impl From<ParseError> for Error {
         Error::InvalidCommandFrame
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrong_arity_names_the_command() {
+        let error = Error::WrongArity {
+            command: "GET".into(),
+            given: 0,
+            expected: 1,
+        };
+        assert_eq!(
+            error.to_string(),
+            "ERR wrong number of arguments for 'get' command"
+        );
+    }
+
+    #[test]
+    fn unknown_command_echoes_its_arguments() {
+        let error = Error::unknown_command(b"foo", &[b"bar", b"baz"]);
+        assert_eq!(
+            error.to_string(),
+            "ERR unknown command 'foo', with args beginning with: 'bar' 'baz' "
+        );
+
+        let error = Error::unknown_command(b"foo", &[]);
+        assert_eq!(
+            error.to_string(),
+            "ERR unknown command 'foo', with args beginning with: "
+        );
+    }
+
+    #[test]
+    fn unknown_command_limits_echoed_arguments() {
+        let long = vec![b'a'; 200];
+        let error = Error::unknown_command(&long, &[&long, b"never shown"]);
+        let message = error.to_string();
+
+        assert!(message.contains(&format!("'{}'", "a".repeat(128))));
+        assert!(!message.contains(&"a".repeat(129)));
+        assert!(!message.contains("never shown"));
+    }
+
+    #[test]
+    fn unknown_command_strips_line_breaks() {
+        let error = Error::unknown_command(b"fo\r\no", &[b"x\ny"]);
+        assert_eq!(
+            error.to_string(),
+            "ERR unknown command 'fo  o', with args beginning with: 'x y' "
+        );
+    }
+
+    #[test]
+    fn errors_carry_their_code() {
+        assert_eq!(Error::SyntaxError.code(), ErrorCode::Err);
+        assert_eq!(Error::UnsupportedProtocol.code(), ErrorCode::NoProto);
+        assert_eq!(
+            Error::UnsupportedProtocol.to_string(),
+            "NOPROTO unsupported protocol version"
+        );
+        assert_eq!(
+            Error::ExecAborted.to_string(),
+            "EXECABORT Transaction discarded because of previous errors."
+        );
+    }
+}
@@ -1,14 +1,71 @@
-#[derive(Debug, PartialEq, Clone, Eq)]
+use crate::sorted_set::format_score;
+use bytes::Bytes;
+
+/// The protocol version a connection speaks, negotiated with `HELLO`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Frame {
     SimpleString(String),
     SimpleError(String),
-    Bulk(Option<Vec<u8>>),
+    Bulk(Option<Bytes>),
     Integer(i64),
     Array(Option<Vec<Frame>>),
+    Null,
+    Boolean(bool),
+    Double(f64),
+    BigNumber(String),
+    Verbatim { format: String, text: Bytes },
+    Map(Vec<(Frame, Frame)>),
+    Set(Vec<Frame>),
+    Push(Vec<Frame>),
+    Attribute(Vec<(Frame, Frame)>),
+    BlobError(Bytes),
+}
+
+fn write_line(buf: &mut Vec<u8>, prefix: u8, line: &[u8]) {
+    buf.push(prefix);
+    buf.extend_from_slice(line);
+    buf.extend_from_slice(b"\r\n");
+}
+
+fn write_blob(buf: &mut Vec<u8>, prefix: u8, parts: &[&[u8]]) {
+    let len: usize = parts.iter().map(|part| part.len()).sum();
+    write_line(buf, prefix, len.to_string().as_bytes());
+    for part in parts {
+        buf.extend_from_slice(part);
+    }
+    buf.extend_from_slice(b"\r\n");
+}
+
+fn write_aggregate<'a>(
+    buf: &mut Vec<u8>,
+    prefix: u8,
+    len: usize,
+    elements: impl Iterator<Item = &'a Frame>,
+) {
+    write_line(buf, prefix, len.to_string().as_bytes());
+    for element in elements {
+        element.write_to(buf);
+    }
+}
+
+fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        "nan".to_string()
+    } else {
+        format_score(value)
+    }
 }
 
 impl Frame {
-    fn write_to(&self, buf: &mut Vec<u8>) {
+    /// Appends the RESP encoding of this frame to `buf`.
+    pub fn write_to(&self, buf: &mut Vec<u8>) {
         match self {
             Self::SimpleString(inner) => {
                 buf.extend_from_slice(b"+");
@@ -46,6 +103,88 @@ impl Frame {
             Self::Array(None) => {
                 buf.extend_from_slice(b"*-1\r\n");
             }
+            Self::Null => buf.extend_from_slice(b"_\r\n"),
+            Self::Boolean(true) => buf.extend_from_slice(b"#t\r\n"),
+            Self::Boolean(false) => buf.extend_from_slice(b"#f\r\n"),
+            Self::Double(value) => write_line(buf, b',', format_double(*value).as_bytes()),
+            Self::BigNumber(digits) => write_line(buf, b'(', digits.as_bytes()),
+            Self::Verbatim { format, text } => {
+                write_blob(buf, b'=', &[format.as_bytes(), b":", text]);
+            }
+            Self::Map(pairs) => write_aggregate(
+                buf,
+                b'%',
+                pairs.len(),
+                pairs.iter().flat_map(|(key, value)| [key, value]),
+            ),
+            Self::Set(elements) => write_aggregate(buf, b'~', elements.len(), elements.iter()),
+            Self::Push(elements) => write_aggregate(buf, b'>', elements.len(), elements.iter()),
+            Self::Attribute(pairs) => write_aggregate(
+                buf,
+                b'|',
+                pairs.len(),
+                pairs.iter().flat_map(|(key, value)| [key, value]),
+            ),
+            Self::BlobError(message) => write_blob(buf, b'!', &[message]),
+        }
+    }
+
+    /// Rewrites a reply for the client's protocol. Replies are built with
+    /// RESP3 types where they carry meaning (maps, sets, pushes, doubles);
+    /// RESP2 clients get their flattened equivalents, and RESP3 clients get
+    /// `_` in place of the RESP2 nil bulk string and nil array.
+    pub fn for_protocol(self, protocol: Protocol) -> Frame {
+        match protocol {
+            Protocol::Resp2 => self.into_resp2(),
+            Protocol::Resp3 => self.into_resp3(),
+        }
+    }
+
+    fn into_resp2(self) -> Frame {
+        fn flatten(pairs: Vec<(Frame, Frame)>) -> Frame {
+            Frame::Array(Some(
+                pairs
+                    .into_iter()
+                    .flat_map(|(key, value)| [key.into_resp2(), value.into_resp2()])
+                    .collect(),
+            ))
+        }
+        match self {
+            Self::Array(Some(elements)) | Self::Set(elements) | Self::Push(elements) => {
+                Self::Array(Some(elements.into_iter().map(Self::into_resp2).collect()))
+            }
+            Self::Map(pairs) | Self::Attribute(pairs) => flatten(pairs),
+            Self::Null => Self::Bulk(None),
+            Self::Boolean(value) => Self::Integer(value.into()),
+            Self::Double(value) => Self::Bulk(Some(format_double(value).into())),
+            Self::BigNumber(digits) => Self::Bulk(Some(digits.into())),
+            Self::Verbatim { text, .. } => Self::Bulk(Some(text)),
+            // A simple error ends at the first line break.
+            Self::BlobError(message) => {
+                Self::SimpleError(String::from_utf8_lossy(&message).replace(['\r', '\n'], " "))
+            }
+            other => other,
+        }
+    }
+
+    fn into_resp3(self) -> Frame {
+        fn convert(elements: Vec<Frame>) -> Vec<Frame> {
+            elements.into_iter().map(Frame::into_resp3).collect()
+        }
+        fn convert_pairs(pairs: Vec<(Frame, Frame)>) -> Vec<(Frame, Frame)> {
+            pairs
+                .into_iter()
+                .map(|(key, value)| (key.into_resp3(), value.into_resp3()))
+                .collect()
+        }
+        match self {
+            Self::Bulk(None) | Self::Array(None) => Self::Null,
+            Self::Array(Some(elements)) => Self::Array(Some(convert(elements))),
+            Self::Set(elements) => Self::Set(convert(elements)),
+            Self::Push(elements) => Self::Push(convert(elements)),
+            Self::Map(pairs) => Self::Map(convert_pairs(pairs)),
+            Self::Attribute(pairs) => Self::Attribute(convert_pairs(pairs)),
+            other => other,
         }
     }
 
@@ -78,7 +217,7 @@ mod test {
     #[test]
     fn bulk_some() {
         assert_eq!(
-            Frame::Bulk(Some("test".as_bytes().to_vec())).to_bytes(),
+            Frame::Bulk(Some(Bytes::from_static(b"test"))).to_bytes(),
             b"$4\r\ntest\r\n".to_vec()
         )
     }
@@ -112,11 +251,101 @@ mod test {
     fn some_array() {
         assert_eq!(
             Frame::Array(Some(vec![
-                Frame::Bulk(Some(b"hello".to_vec())),
+                Frame::Bulk(Some(Bytes::from_static(b"hello"))),
                 Frame::Integer(12345)
             ]))
             .to_bytes(),
             b"*2\r\n$5\r\nhello\r\n:12345\r\n".to_vec()
         )
     }
+
+    #[test]
+    fn resp3_scalars() {
+        assert_eq!(Frame::Null.to_bytes(), b"_\r\n".to_vec());
+        assert_eq!(Frame::Boolean(true).to_bytes(), b"#t\r\n".to_vec());
+        assert_eq!(Frame::Boolean(false).to_bytes(), b"#f\r\n".to_vec());
+        assert_eq!(Frame::Double(1.5).to_bytes(), b",1.5\r\n".to_vec());
+        assert_eq!(
+            Frame::Double(f64::NEG_INFINITY).to_bytes(),
+            b",-inf\r\n".to_vec()
+        );
+        assert_eq!(
+            Frame::BigNumber("3492890328409238509324850943850943825024385".into()).to_bytes(),
+            b"(3492890328409238509324850943850943825024385\r\n".to_vec()
+        );
+        assert_eq!(
+            Frame::BlobError(Bytes::from_static(b"SYNTAX invalid")).to_bytes(),
+            b"!14\r\nSYNTAX invalid\r\n".to_vec()
+        );
+        assert_eq!(
+            Frame::Verbatim {
+                format: "txt".into(),
+                text: Bytes::from_static(b"Some string")
+            }
+            .to_bytes(),
+            b"=15\r\ntxt:Some string\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn resp3_aggregates() {
+        let pairs = vec![(Frame::SimpleString("first".into()), Frame::Integer(1))];
+        assert_eq!(
+            Frame::Map(pairs.clone()).to_bytes(),
+            b"%1\r\n+first\r\n:1\r\n".to_vec()
+        );
+        assert_eq!(
+            Frame::Attribute(pairs).to_bytes(),
+            b"|1\r\n+first\r\n:1\r\n".to_vec()
+        );
+        assert_eq!(
+            Frame::Set(vec![Frame::Integer(1)]).to_bytes(),
+            b"~1\r\n:1\r\n".to_vec()
+        );
+        assert_eq!(
+            Frame::Push(vec![Frame::Integer(1)]).to_bytes(),
+            b">1\r\n:1\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn resp2_flattens_resp3_types() {
+        let reply = Frame::Map(vec![(
+            Frame::Bulk(Some(Bytes::from_static(b"score"))),
+            Frame::Set(vec![Frame::Double(2.5), Frame::Null, Frame::Boolean(true)]),
+        )]);
+        assert_eq!(
+            reply.for_protocol(Protocol::Resp2),
+            Frame::Array(Some(vec![
+                Frame::Bulk(Some(Bytes::from_static(b"score"))),
+                Frame::Array(Some(vec![
+                    Frame::Bulk(Some(Bytes::from_static(b"2.5"))),
+                    Frame::Bulk(None),
+                    Frame::Integer(1),
+                ])),
+            ]))
+        );
+    }
+
+    #[test]
+    fn resp2_blob_errors_stay_on_one_line() {
+        let error = Frame::BlobError(Bytes::from_static(b"ERR first\r\nsecond\nthird"));
+        assert_eq!(
+            error.for_protocol(Protocol::Resp2).to_bytes(),
+            b"-ERR first  second third\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn resp3_replaces_nil_replies_with_null() {
+        let reply = Frame::Array(Some(vec![Frame::Bulk(None), Frame::Array(None)]));
+        assert_eq!(
+            reply.for_protocol(Protocol::Resp3),
+            Frame::Array(Some(vec![Frame::Null, Frame::Null]))
+        );
+        assert_eq!(
+            Frame::Bulk(Some(Bytes::from_static(b"a"))).for_protocol(Protocol::Resp3),
+            Frame::Bulk(Some(Bytes::from_static(b"a")))
+        );
+    }
 }
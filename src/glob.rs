@@ -0,0 +1,107 @@
+/// Matches `text` against a Redis-style glob `pattern`.
+///
+/// Supports `*` (any run of bytes), `?` (any single byte), `[abc]`, `[^abc]`
+/// and `[a-z]` classes, and `\` to escape the next byte.
+pub fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let mut p = 0;
+    let mut t = 0;
+    // Where to resume after the most recent `*`: the pattern position just
+    // past it, and the next text position it should try to absorb.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            p += 1;
+            backtrack = Some((p, t + 1));
+            continue;
+        }
+        if let Some(next) = match_token(pattern, p, text[t]) {
+            p = next;
+            t += 1;
+            continue;
+        }
+        match backtrack {
+            Some((star_p, star_t)) => {
+                p = star_p;
+                t = star_t;
+                backtrack = Some((star_p, star_t + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+/// Matches a single non-`*` token at `pattern[p..]` against `byte`, returning
+/// the position after the token if it matches.
+fn match_token(pattern: &[u8], p: usize, byte: u8) -> Option<usize> {
+    match *pattern.get(p)? {
+        b'?' => Some(p + 1),
+        b'[' => match_class(pattern, p + 1, byte),
+        b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == byte).then_some(p + 2),
+        literal => (literal == byte).then_some(p + 1),
+    }
+}
+
+/// Matches the class starting after its `[` at `pattern[p..]`. An unclosed
+/// class runs to the end of the pattern, as it does in Redis.
+fn match_class(pattern: &[u8], mut p: usize, byte: u8) -> Option<usize> {
+    let negated = pattern.get(p) == Some(&b'^');
+    if negated {
+        p += 1;
+    }
+    let mut matched = false;
+    while p < pattern.len() && pattern[p] != b']' {
+        if pattern[p] == b'\\' && p + 1 < pattern.len() {
+            matched |= pattern[p + 1] == byte;
+            p += 2;
+        } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+            let (low, high) = (pattern[p], pattern[p + 2]);
+            let (low, high) = (low.min(high), low.max(high));
+            matched |= (low..=high).contains(&byte);
+            p += 3;
+        } else {
+            matched |= pattern[p] == byte;
+            p += 1;
+        }
+    }
+    let end = (p + 1).min(pattern.len());
+    (matched != negated).then_some(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literals_and_wildcards() {
+        assert!(glob_match(b"user:1", b"user:1"));
+        assert!(!glob_match(b"user:1", b"user:12"));
+        assert!(glob_match(b"user:*", b"user:"));
+        assert!(glob_match(b"user:*", b"user:123"));
+        assert!(glob_match(b"*:1*", b"session:user:1:token"));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(!glob_match(b"h?llo", b"hllo"));
+        assert!(glob_match(b"*", b""));
+        assert!(!glob_match(b"a*b", b"acbd"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[ae]llo", b"hillo"));
+        assert!(glob_match(b"h[^e]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"key[0-9]", b"key7"));
+        assert!(glob_match(b"key[9-0]", b"key7"));
+        assert!(!glob_match(b"key[0-9]", b"keyx"));
+    }
+
+    #[test]
+    fn escapes_match_literally() {
+        assert!(glob_match(b"what\\?", b"what?"));
+        assert!(!glob_match(b"what\\?", b"whats"));
+        assert!(glob_match(b"star\\*", b"star*"));
+        assert!(glob_match(b"[\\]]", b"]"));
+    }
+}
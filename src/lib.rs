@@ -1,9 +1,14 @@
+pub mod aof;
 pub mod archive;
+pub mod archive_format;
 pub mod command;
 pub mod config;
 pub mod connection;
 pub mod error;
 pub mod frame;
+pub mod glob;
 pub mod parser;
+pub mod pubsub;
 pub mod server;
+pub mod sorted_set;
 pub mod store;
@@ -9,7 +9,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let shutdown_token = CancellationToken::new();
     let (_address, handle) = run_server(&config, shutdown_token.clone()).await?;
 
-    wait_for_shutdown_signal().await?;
+    // A SHUTDOWN command cancels the token from inside the server
+    tokio::select! {
+        signal = wait_for_shutdown_signal() => signal?,
+        _ = shutdown_token.cancelled() => {}
+    }
     shutdown_token.cancel();
     handle.await.map_err(std::io::Error::other)??;
 
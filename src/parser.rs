@@ -1,11 +1,118 @@
 use crate::frame::Frame;
-use memchr::memchr;
+use bytes::{Buf, BytesMut};
+use memchr::{memchr, memmem};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum ParseError {
     UnreadableUtf,
     InvalidLength,
     UnreadableBulkString,
+    InvalidValue,
+    /// An inline command had an unterminated quote, or a closing quote
+    /// followed by something other than whitespace. Only the offending line
+    /// is discarded, so parsing can resume with the next one.
+    UnbalancedQuotes,
+    /// A bulk string declared a length above `ParserLimits::max_bulk_len`.
+    BulkTooLong,
+    /// An aggregate declared more entries than
+    /// `ParserLimits::max_multibulk_len`.
+    MultibulkTooLong,
+    /// Aggregates nested deeper than `ParserLimits::max_nesting_depth`.
+    NestingTooDeep,
+    /// An inline command, or any other CRLF-terminated line, ran past
+    /// `ParserLimits::max_inline_len` bytes.
+    InlineTooLong,
+}
+
+/// Redis' default `proto-max-bulk-len`.
+pub const DEFAULT_MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+pub const DEFAULT_MAX_MULTIBULK_LEN: usize = 1024 * 1024;
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 32;
+/// Redis' limit on the size of an inline request.
+pub const DEFAULT_MAX_INLINE_LEN: usize = 64 * 1024;
+
+/// Caps the space reserved up front for a declared length, so a client
+/// cannot make the server allocate memory by announcing data it never sends.
+const MAX_PREALLOCATION: usize = 1024;
+const MAX_BULK_PREALLOCATION: usize = 1024 * 1024;
+
+/// Bounds on how much a single request can make the parser buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserLimits {
+    /// The longest bulk string accepted, in bytes.
+    pub max_bulk_len: usize,
+    /// The most entries accepted in one aggregate.
+    pub max_multibulk_len: usize,
+    /// How many aggregates may be open inside one another.
+    pub max_nesting_depth: usize,
+    /// The longest inline command or protocol line accepted, in bytes.
+    pub max_inline_len: usize,
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        ParserLimits {
+            max_bulk_len: DEFAULT_MAX_BULK_LEN,
+            max_multibulk_len: DEFAULT_MAX_MULTIBULK_LEN,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            max_inline_len: DEFAULT_MAX_INLINE_LEN,
+        }
+    }
+}
+
+/// The kinds of length-prefixed string a frame can carry.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Blob {
+    String,
+    Error,
+    Verbatim,
+}
+
+/// The kinds of frame that contain other frames.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Aggregate {
+    Array,
+    Map,
+    Set,
+    Push,
+    Attribute,
+}
+
+impl Aggregate {
+    /// How many frames an aggregate announcing `len` entries holds; maps and
+    /// attributes count key-value pairs.
+    fn element_count(self, len: usize) -> Option<usize> {
+        match self {
+            Self::Map | Self::Attribute => len.checked_mul(2),
+            Self::Array | Self::Set | Self::Push => Some(len),
+        }
+    }
+
+    fn build(self, elements: Vec<Frame>) -> Frame {
+        fn pairs(elements: Vec<Frame>) -> Vec<(Frame, Frame)> {
+            let mut elements = elements.into_iter();
+            let mut pairs = Vec::with_capacity(elements.len() / 2);
+            while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+                pairs.push((key, value));
+            }
+            pairs
+        }
+        match self {
+            Self::Array => Frame::Array(Some(elements)),
+            Self::Map => Frame::Map(pairs(elements)),
+            Self::Set => Frame::Set(elements),
+            Self::Push => Frame::Push(elements),
+            Self::Attribute => Frame::Attribute(pairs(elements)),
+        }
+    }
+}
+
+/// An aggregate whose elements are still being read.
+#[derive(Debug, PartialEq)]
+struct PendingAggregate {
+    kind: Aggregate,
+    expected: usize,
+    elements: Vec<Frame>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -13,19 +120,24 @@ enum State {
     Start,
     ReadingSimpleString,
     ReadingSimpleError,
-    ReadingBulkLength,
-    ReadingBulkString(usize),
+    ReadingBulkLength(Blob),
+    ReadingBulkString(Blob, usize),
     ReadingInteger,
     ReadingInline,
-    ReadingArrayLength,
+    ReadingAggregateLength(Aggregate),
+    ReadingNull,
+    ReadingBoolean,
+    ReadingDouble,
+    ReadingBigNumber,
     Error(ParseError),
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Parser {
     state: State,
-    buf: Vec<u8>,
-    stack: Vec<Vec<Frame>>,
+    buf: BytesMut,
+    stack: Vec<PendingAggregate>,
+    limits: ParserLimits,
 }
 
 #[derive(Debug, PartialEq)]
@@ -40,12 +152,96 @@ impl Default for Parser {
     }
 }
 
+fn is_inline_space(c: u8) -> bool {
+    matches!(c, b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c)
+}
+
+fn hex_value(c: u8) -> u8 {
+    (c as char).to_digit(16).unwrap_or(0) as u8
+}
+
+/// Splits an inline command into arguments the way `redis-cli` does.
+/// Arguments are separated by whitespace and may be quoted: double quotes
+/// understand `\n`, `\r`, `\t`, `\b`, `\a` and `\xHH` escapes (any other
+/// escaped character stands for itself), while single quotes only unescape
+/// `\'`. Arguments are raw bytes, so they need not be valid UTF-8.
+fn split_inline(mut rest: &[u8]) -> Result<Vec<Frame>, ParseError> {
+    let mut args = Vec::new();
+    loop {
+        while let [c, tail @ ..] = rest
+            && is_inline_space(*c)
+        {
+            rest = tail;
+        }
+        if rest.is_empty() {
+            return Ok(args);
+        }
+        let mut arg = Vec::new();
+        let mut quote = None;
+        loop {
+            match (quote, rest) {
+                (None, []) => break,
+                (None, [c, ..]) if is_inline_space(*c) => break,
+                (None, [q @ (b'"' | b'\''), tail @ ..]) => {
+                    quote = Some(*q);
+                    rest = tail;
+                }
+                (Some(_), []) => return Err(ParseError::UnbalancedQuotes),
+                (Some(b'"'), [b'\\', b'x', high, low, tail @ ..])
+                    if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() =>
+                {
+                    arg.push(hex_value(*high) << 4 | hex_value(*low));
+                    rest = tail;
+                }
+                (Some(b'"'), [b'\\', c, tail @ ..]) => {
+                    arg.push(match c {
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'b' => 0x08,
+                        b'a' => 0x07,
+                        other => *other,
+                    });
+                    rest = tail;
+                }
+                (Some(b'\''), [b'\\', b'\'', tail @ ..]) => {
+                    arg.push(b'\'');
+                    rest = tail;
+                }
+                (Some(q), [c, tail @ ..]) if *c == q => {
+                    if tail.first().is_some_and(|c| !is_inline_space(*c)) {
+                        return Err(ParseError::UnbalancedQuotes);
+                    }
+                    rest = tail;
+                    break;
+                }
+                (_, [c, tail @ ..]) => {
+                    arg.push(*c);
+                    rest = tail;
+                }
+            }
+        }
+        args.push(Frame::Bulk(Some(arg.into())));
+    }
+}
+
+/// Checks the digits of a RESP3 big number, which may carry a sign.
+fn is_big_number(line: &[u8]) -> bool {
+    let digits = line.strip_prefix(b"-").unwrap_or(line);
+    !digits.is_empty() && digits.iter().all(u8::is_ascii_digit)
+}
+
 impl Parser {
     pub fn new() -> Self {
+        Parser::with_limits(ParserLimits::default())
+    }
+
+    pub fn with_limits(limits: ParserLimits) -> Self {
         Parser {
             state: State::Start,
-            buf: Vec::new(),
+            buf: BytesMut::new(),
             stack: Vec::new(),
+            limits,
         }
     }
 
@@ -54,48 +250,76 @@ impl Parser {
         err
     }
 
+    /// Buffers `input` and parses every frame it completes.
     pub fn parse(&mut self, input: &[u8]) -> ParseResult {
         self.buf.extend_from_slice(input);
+        self.parse_buffered()
+    }
+
+    /// The parser's input buffer, so callers can read from a socket straight
+    /// into it and then call `parse_buffered` without an intermediate copy.
+    pub fn buffer_mut(&mut self) -> &mut BytesMut {
+        &mut self.buf
+    }
+
+    /// Parses every frame completed by the input buffered so far. Bulk
+    /// payloads are sliced out of the buffer rather than copied.
+    pub fn parse_buffered(&mut self) -> ParseResult {
         let mut output = Vec::<Frame>::new();
+        loop {
+            match self.next_frame() {
+                Ok(Some(frame)) => output.push(frame),
+                Ok(None) => return ParseResult::Complete(output),
+                Err(e) => return ParseResult::Partial(output, e),
+            }
+        }
+    }
+
+    /// Parses the next frame completed by the input buffered so far, if there
+    /// is one. Once it returns a frame, [`Parser::buffered_len`] is the input
+    /// left after that frame.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, ParseError> {
         loop {
             match self.try_parse_one_frame() {
-                Ok(Some(f)) => {
-                    if !self.stack.is_empty() {
-                        self.stack.last_mut().unwrap().push(f);
-                        while self.stack.last().unwrap().capacity()
-                            - self.stack.last().unwrap().len()
-                            == 0
-                        {
-                            let new_array_frame: Frame =
-                                Frame::Array(Some(self.stack.pop().unwrap()));
-                            if self.stack.is_empty() {
-                                output.push(new_array_frame);
-                                break;
-                            } else {
-                                self.stack.last_mut().unwrap().push(new_array_frame);
-                            }
-                        }
-                    } else {
-                        output.push(f);
+                Ok(Some(mut frame)) => loop {
+                    let Some(pending) = self.stack.last_mut() else {
+                        return Ok(Some(frame));
+                    };
+                    pending.elements.push(frame);
+                    if pending.elements.len() < pending.expected {
+                        break;
                     }
-
-                    continue;
-                }
-                Ok(None) => return ParseResult::Complete(output),
-                Err(e) => {
-                    self.set_error(e.clone());
-                    return ParseResult::Partial(output, e);
-                }
+                    let done = self.stack.pop().unwrap();
+                    frame = done.kind.build(done.elements);
+                },
+                Ok(None) => return Ok(None),
+                Err(ParseError::UnbalancedQuotes) => return Err(ParseError::UnbalancedQuotes),
+                Err(e) => return Err(self.set_error(e)),
             }
         }
     }
 
+    /// How many bytes of input are buffered but not yet parsed.
+    pub fn buffered_len(&self) -> usize {
+        self.buf.len()
+    }
+
     pub fn is_empty(self) -> bool {
         self.buf.is_empty()
     }
 
+    /// Finds the end of the current line, failing once the buffered part of
+    /// an unterminated line is already over the limit.
+    fn find_crlf(&self) -> Result<Option<usize>, ParseError> {
+        match memmem::find(&self.buf, b"\r\n") {
+            Some(pos) if pos > self.limits.max_inline_len => Err(ParseError::InlineTooLong),
+            None if self.buf.len() > self.limits.max_inline_len => Err(ParseError::InlineTooLong),
+            pos => Ok(pos),
+        }
+    }
+
     fn read_length(&mut self) -> Result<Option<i64>, ParseError> {
-        let pos = match self.buf.windows(2).position(|w| w == b"\r\n") {
+        let pos = match self.find_crlf()? {
             Some(pos) => pos,
             None => return Ok(None),
         };
@@ -104,10 +328,20 @@ impl Parser {
             .map_err(|_| ParseError::InvalidLength)?
             .parse::<i64>()
             .map_err(|_| ParseError::InvalidLength)?;
-        self.buf.drain(..pos + 2);
+        self.buf.advance(pos + 2);
         Ok(Some(value))
     }
 
+    /// Takes the next CRLF-terminated line, without its terminator.
+    fn take_line(&mut self) -> Result<Option<BytesMut>, ParseError> {
+        let Some(pos) = self.find_crlf()? else {
+            return Ok(None);
+        };
+        let line = self.buf.split_to(pos);
+        self.buf.advance(2);
+        Ok(Some(line))
+    }
+
     fn try_parse_one_frame(&mut self) -> Result<Option<Frame>, ParseError> {
         if let State::Error(ref e) = self.state {
             return Err(e.clone());
@@ -115,66 +349,56 @@ impl Parser {
 
         loop {
             match &mut self.state {
-                State::Start => match self.buf.first() {
-                    Some(b'+') => {
-                        self.buf.drain(..1);
-                        self.state = State::ReadingSimpleString;
-                        continue;
-                    }
-                    Some(b'$') => {
-                        self.buf.drain(..1);
-                        self.state = State::ReadingBulkLength;
-                        continue;
-                    }
-                    Some(b'*') => {
-                        self.buf.drain(..1);
-                        self.state = State::ReadingArrayLength;
-                        continue;
-                    }
-                    Some(b'-') => {
-                        self.buf.drain(..1);
-                        self.state = State::ReadingSimpleError;
-                        continue;
-                    }
-                    Some(b':') => {
-                        self.buf.drain(..1);
-                        self.state = State::ReadingInteger;
-                        continue;
-                    }
-                    Some(_) => {
-                        self.state = State::ReadingInline;
-                        continue;
-                    }
-                    None => return Ok(None),
-                },
+                State::Start => {
+                    let next = match self.buf.first() {
+                        Some(b'+') => State::ReadingSimpleString,
+                        Some(b'-') => State::ReadingSimpleError,
+                        Some(b':') => State::ReadingInteger,
+                        Some(b'$') => State::ReadingBulkLength(Blob::String),
+                        Some(b'!') => State::ReadingBulkLength(Blob::Error),
+                        Some(b'=') => State::ReadingBulkLength(Blob::Verbatim),
+                        Some(b'*') => State::ReadingAggregateLength(Aggregate::Array),
+                        Some(b'%') => State::ReadingAggregateLength(Aggregate::Map),
+                        Some(b'~') => State::ReadingAggregateLength(Aggregate::Set),
+                        Some(b'>') => State::ReadingAggregateLength(Aggregate::Push),
+                        Some(b'|') => State::ReadingAggregateLength(Aggregate::Attribute),
+                        Some(b'_') => State::ReadingNull,
+                        Some(b'#') => State::ReadingBoolean,
+                        Some(b',') => State::ReadingDouble,
+                        Some(b'(') => State::ReadingBigNumber,
+                        Some(_) => {
+                            self.state = State::ReadingInline;
+                            continue;
+                        }
+                        None => return Ok(None),
+                    };
+                    self.buf.advance(1);
+                    self.state = next;
+                    continue;
+                }
 
                 State::ReadingSimpleString => {
-                    let pos = match self.buf.windows(2).position(|w| w == b"\r\n") {
-                        Some(pos) => pos,
-                        None => return Ok(None),
+                    let Some(line) = self.take_line()? else {
+                        return Ok(None);
                     };
-                    let bytes: Vec<u8> = self.buf.drain(..pos).collect();
-                    let payload =
-                        String::from_utf8(bytes).map_err(|_| ParseError::UnreadableUtf)?;
-                    self.buf.drain(..2);
+                    let payload = std::str::from_utf8(&line)
+                        .map_err(|_| ParseError::UnreadableUtf)?
+                        .to_string();
                     self.state = State::Start;
                     return Ok(Some(Frame::SimpleString(payload)));
                 }
 
                 State::ReadingInline => {
-                    let mut array_inner = Vec::<Frame>::new();
                     let pos = match memchr(b'\n', &self.buf) {
-                        Some(pos) => pos,
-                        None => return Ok(None),
+                        Some(pos) if pos <= self.limits.max_inline_len => pos,
+                        None if self.buf.len() <= self.limits.max_inline_len => {
+                            return Ok(None);
+                        }
+                        _ => return Err(ParseError::InlineTooLong),
                     };
-                    for p in String::from_utf8(self.buf.drain(..pos).collect())
-                        .map_err(|_| ParseError::UnreadableUtf)?
-                        .split_whitespace()
-                    {
-                        array_inner.push(Frame::Bulk(Some(p.as_bytes().to_vec())));
-                    }
-                    self.buf.drain(..1);
+                    let line = self.buf.split_to(pos + 1);
                     self.state = State::Start;
+                    let array_inner = split_inline(&line)?;
                     if array_inner.is_empty() {
                         continue;
                     };
@@ -182,47 +406,63 @@ impl Parser {
                 }
 
                 State::ReadingSimpleError => {
-                    let pos = match self.buf.windows(2).position(|w| w == b"\r\n") {
-                        Some(pos) => pos,
-                        None => return Ok(None),
+                    let Some(line) = self.take_line()? else {
+                        return Ok(None);
                     };
-                    let bytes: Vec<u8> = self.buf.drain(..pos).collect();
-                    let payload =
-                        String::from_utf8(bytes).map_err(|_| ParseError::UnreadableUtf)?;
-                    self.buf.drain(..2);
+                    let payload = std::str::from_utf8(&line)
+                        .map_err(|_| ParseError::UnreadableUtf)?
+                        .to_string();
                     self.state = State::Start;
                     return Ok(Some(Frame::SimpleError(payload)));
                 }
 
-                State::ReadingBulkLength => {
+                State::ReadingBulkLength(kind) => {
+                    let kind = *kind;
                     let length = match self.read_length()? {
                         Some(l) => l,
                         None => return Ok(None),
                     };
-                    if length == -1 {
+                    if length == -1 && kind == Blob::String {
                         self.state = State::Start;
                         return Ok(Some(Frame::Bulk(None)));
                     }
-                    if length < -1 {
-                        return Err(ParseError::InvalidLength);
-                    }
                     let length = usize::try_from(length).map_err(|_| ParseError::InvalidLength)?;
-                    self.state = State::ReadingBulkString(length);
+                    if length > self.limits.max_bulk_len {
+                        return Err(ParseError::BulkTooLong);
+                    }
+                    self.state = State::ReadingBulkString(kind, length);
                     continue;
                 }
 
-                State::ReadingBulkString(length) => {
-                    let len = *length;
+                State::ReadingBulkString(kind, length) => {
+                    let (kind, len) = (*kind, *length);
                     if len + 2 > self.buf.len() {
+                        // Make room for more of the payload up front so a large
+                        // value is not reallocated on every read.
+                        self.buf
+                            .reserve((len + 2 - self.buf.len()).min(MAX_BULK_PREALLOCATION));
                         return Ok(None);
                     }
                     if self.buf[len] != b'\r' || self.buf[len + 1] != b'\n' {
                         return Err(ParseError::UnreadableBulkString);
                     }
-                    let payload = self.buf.drain(..len).collect();
-                    self.buf.drain(..2);
+                    let payload = self.buf.split_to(len).freeze();
+                    self.buf.advance(2);
                     self.state = State::Start;
-                    return Ok(Some(Frame::Bulk(Some(payload))));
+                    return match kind {
+                        Blob::String => Ok(Some(Frame::Bulk(Some(payload)))),
+                        Blob::Error => Ok(Some(Frame::BlobError(payload))),
+                        Blob::Verbatim => {
+                            if payload.get(3) != Some(&b':') {
+                                return Err(ParseError::InvalidValue);
+                            }
+                            let format = std::str::from_utf8(&payload[..3])
+                                .map_err(|_| ParseError::UnreadableUtf)?
+                                .to_string();
+                            let text = payload.slice(4..);
+                            Ok(Some(Frame::Verbatim { format, text }))
+                        }
+                    };
                 }
 
                 State::ReadingInteger => {
@@ -235,26 +475,84 @@ impl Parser {
                     };
                 }
 
-                State::ReadingArrayLength => {
+                State::ReadingAggregateLength(kind) => {
+                    let kind = *kind;
                     let length = match self.read_length()? {
                         Some(l) => l,
                         None => return Ok(None),
                     };
-                    if length == -1 {
-                        self.state = State::Start;
+                    self.state = State::Start;
+                    if length == -1 && kind == Aggregate::Array {
                         return Ok(Some(Frame::Array(None)));
                     }
-                    if length == 0 {
-                        self.state = State::Start;
-                        return Ok(Some(Frame::Array(Some(Vec::new()))));
+                    let length = usize::try_from(length).map_err(|_| ParseError::InvalidLength)?;
+                    if length > self.limits.max_multibulk_len {
+                        return Err(ParseError::MultibulkTooLong);
                     }
-                    if length < -1 {
-                        return Err(ParseError::InvalidLength);
+                    if self.stack.len() >= self.limits.max_nesting_depth {
+                        return Err(ParseError::NestingTooDeep);
                     }
-                    let length = usize::try_from(length).map_err(|_| ParseError::InvalidLength)?;
-                    self.stack.push(Vec::<Frame>::with_capacity(length));
+                    let expected = kind
+                        .element_count(length)
+                        .ok_or(ParseError::InvalidLength)?;
+                    if expected == 0 {
+                        return Ok(Some(kind.build(Vec::new())));
+                    }
+                    self.stack.push(PendingAggregate {
+                        kind,
+                        expected,
+                        elements: Vec::with_capacity(expected.min(MAX_PREALLOCATION)),
+                    });
+                }
+
+                State::ReadingNull => {
+                    let Some(line) = self.take_line()? else {
+                        return Ok(None);
+                    };
+                    if !line.is_empty() {
+                        return Err(ParseError::InvalidValue);
+                    }
+                    self.state = State::Start;
+                    return Ok(Some(Frame::Null));
+                }
 
+                State::ReadingBoolean => {
+                    let Some(line) = self.take_line()? else {
+                        return Ok(None);
+                    };
+                    let value = match &line[..] {
+                        b"t" => true,
+                        b"f" => false,
+                        _ => return Err(ParseError::InvalidValue),
+                    };
                     self.state = State::Start;
+                    return Ok(Some(Frame::Boolean(value)));
+                }
+
+                State::ReadingDouble => {
+                    let Some(line) = self.take_line()? else {
+                        return Ok(None);
+                    };
+                    let value = std::str::from_utf8(&line)
+                        .map_err(|_| ParseError::UnreadableUtf)?
+                        .parse::<f64>()
+                        .map_err(|_| ParseError::InvalidValue)?;
+                    self.state = State::Start;
+                    return Ok(Some(Frame::Double(value)));
+                }
+
+                State::ReadingBigNumber => {
+                    let Some(line) = self.take_line()? else {
+                        return Ok(None);
+                    };
+                    if !is_big_number(&line) {
+                        return Err(ParseError::InvalidValue);
+                    }
+                    let digits = std::str::from_utf8(&line)
+                        .map_err(|_| ParseError::UnreadableUtf)?
+                        .to_string();
+                    self.state = State::Start;
+                    return Ok(Some(Frame::BigNumber(digits)));
                 }
 
                 State::Error(e) => {
@@ -268,6 +566,7 @@ impl Parser {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bytes::Bytes;
 
     fn complete(frames: Vec<Frame>) -> ParseResult {
         ParseResult::Complete(frames)
@@ -418,7 +717,23 @@ mod tests {
         fn zero_length_bulk_string() {
             let mut p = Parser::new();
             let buf = b"$0\r\n\r\n";
-            assert_eq!(p.parse(buf), complete(vec![Frame::Bulk(Some(vec![]))]));
+            assert_eq!(
+                p.parse(buf),
+                complete(vec![Frame::Bulk(Some(Bytes::new()))])
+            );
+        }
+
+        #[test]
+        fn payload_is_sliced_from_the_read_buffer() {
+            let mut p = Parser::new();
+            p.buffer_mut().extend_from_slice(b"$5\r\nhello\r\n");
+            let payload_start = p.buf[4..].as_ptr();
+            let frames = expect_complete(p.parse_buffered());
+            let [Frame::Bulk(Some(payload))] = frames.as_slice() else {
+                panic!("expected a single bulk string, got {frames:?}");
+            };
+            assert_eq!(payload, &Bytes::from_static(b"hello"));
+            assert_eq!(payload.as_ptr(), payload_start);
         }
 
         #[test]
@@ -449,9 +764,9 @@ mod tests {
             let buf = b"$5\r\nhello\r\nleftovers";
             assert_eq!(
                 p.parse(buf),
-                complete(vec![Frame::Bulk(Some(b"hello".to_vec()))])
+                complete(vec![Frame::Bulk(Some(Bytes::from_static(b"hello")))])
             );
-            assert_eq!(p.buf, b"leftovers")
+            assert_eq!(&p.buf[..], b"leftovers")
         }
 
         #[test]
@@ -466,9 +781,9 @@ mod tests {
                 assert_eq!(
                     builder,
                     vec![
-                        Frame::Bulk(Some(b"hello".to_vec())),
-                        Frame::Bulk(Some(b"another".to_vec())),
-                        Frame::Bulk(Some(b"bulk".to_vec())),
+                        Frame::Bulk(Some(Bytes::from_static(b"hello"))),
+                        Frame::Bulk(Some(Bytes::from_static(b"another"))),
+                        Frame::Bulk(Some(Bytes::from_static(b"bulk"))),
                     ]
                 )
             }
@@ -527,9 +842,9 @@ mod tests {
             assert_eq!(
                 p.parse(buf),
                 complete(vec![Frame::Array(Some(vec![
-                    Frame::Bulk(Some(b"hello".to_vec())),
-                    Frame::Bulk(Some(b"bye".to_vec())),
-                    Frame::Bulk(Some(b"more".to_vec()))
+                    Frame::Bulk(Some(Bytes::from_static(b"hello"))),
+                    Frame::Bulk(Some(Bytes::from_static(b"bye"))),
+                    Frame::Bulk(Some(Bytes::from_static(b"more")))
                 ]))])
             )
         }
@@ -546,9 +861,9 @@ mod tests {
                 assert_eq!(
                     result,
                     vec![Frame::Array(Some(vec![
-                        Frame::Bulk(Some(b"hello".to_vec())),
-                        Frame::Bulk(Some(b"bye".to_vec())),
-                        Frame::Bulk(Some(b"more".to_vec()))
+                        Frame::Bulk(Some(Bytes::from_static(b"hello"))),
+                        Frame::Bulk(Some(Bytes::from_static(b"bye"))),
+                        Frame::Bulk(Some(Bytes::from_static(b"more")))
                     ]))]
                 )
             }
@@ -562,7 +877,7 @@ mod tests {
                 p.parse(buf),
                 complete(vec![Frame::Array(Some(vec![Frame::Array(Some(vec![
                     Frame::Array(Some(vec![
-                        Frame::Bulk(Some(b"a".to_vec())),
+                        Frame::Bulk(Some(Bytes::from_static(b"a"))),
                         Frame::Array(None)
                     ]))
                 ]))]))])
@@ -582,7 +897,7 @@ mod tests {
                     result,
                     vec![Frame::Array(Some(vec![Frame::Array(Some(vec![
                         Frame::Array(Some(vec![
-                            Frame::Bulk(Some(b"a".to_vec())),
+                            Frame::Bulk(Some(Bytes::from_static(b"a"))),
                             Frame::Array(None)
                         ]))
                     ]))]))]
@@ -611,7 +926,7 @@ mod tests {
             let mut p = Parser::new();
             let buf = b":12345\r\nleftovers";
             assert_eq!(p.parse(buf), complete(vec![Frame::Integer(12345)]));
-            assert_eq!(p.buf, b"leftovers")
+            assert_eq!(&p.buf[..], b"leftovers")
         }
 
         #[test]
@@ -678,7 +993,7 @@ mod tests {
             assert_eq!(
                 p.parse(buf),
                 complete(vec![Frame::Array(Some(vec![Frame::Bulk(Some(
-                    b"hello".to_vec()
+                    Bytes::from_static(b"hello")
                 )),])),])
             );
         }
@@ -696,12 +1011,12 @@ mod tests {
                     result,
                     vec![
                         Frame::Array(Some(vec![
-                            Frame::Bulk(Some(b"hello".to_vec())),
-                            Frame::Bulk(Some(b"there".to_vec())),
+                            Frame::Bulk(Some(Bytes::from_static(b"hello"))),
+                            Frame::Bulk(Some(Bytes::from_static(b"there"))),
                         ])),
                         Frame::Array(Some(vec![
-                            Frame::Bulk(Some(b"another".to_vec())),
-                            Frame::Bulk(Some(b"line".to_vec())),
+                            Frame::Bulk(Some(Bytes::from_static(b"another"))),
+                            Frame::Bulk(Some(Bytes::from_static(b"line"))),
                         ]))
                     ]
                 )
@@ -716,7 +1031,7 @@ mod tests {
                 p.parse(buf),
                 partial(
                     vec![Frame::Array(Some(vec![Frame::Bulk(Some(
-                        b"PING".to_vec()
+                        Bytes::from_static(b"PING")
                     ))]))],
                     ParseError::InvalidLength
                 )
@@ -724,4 +1039,284 @@ mod tests {
             assert_eq!(p.state, State::Error(ParseError::InvalidLength));
         }
     }
+
+    mod inline_quoting_tests {
+        use super::*;
+
+        fn args(values: &[&[u8]]) -> Frame {
+            Frame::Array(Some(
+                values
+                    .iter()
+                    .map(|v| Frame::Bulk(Some(Bytes::copy_from_slice(v))))
+                    .collect(),
+            ))
+        }
+
+        #[test]
+        fn double_quotes_group_words_and_unescape() {
+            let mut p = Parser::new();
+            let buf = b"SET greeting \"hello world\\n\" \"\\x00\\xffok\\\"\" \"\"\r\n";
+            assert_eq!(
+                p.parse(buf),
+                complete(vec![args(&[
+                    b"SET",
+                    b"greeting",
+                    b"hello world\n",
+                    b"\x00\xffok\"",
+                    b"",
+                ])])
+            );
+        }
+
+        #[test]
+        fn single_quotes_only_unescape_single_quotes() {
+            let mut p = Parser::new();
+            let buf = b"SET 'it\\'s' 'a\\nb \"c\"'\n";
+            assert_eq!(
+                p.parse(buf),
+                complete(vec![args(&[b"SET", b"it's", b"a\\nb \"c\""])])
+            );
+        }
+
+        #[test]
+        fn quotes_may_start_inside_an_argument() {
+            let mut p = Parser::new();
+            let buf = b"GET key\" with space\"\n";
+            assert_eq!(
+                p.parse(buf),
+                complete(vec![args(&[b"GET", b"key with space"])])
+            );
+        }
+
+        #[test]
+        fn non_utf8_bytes_pass_through() {
+            let mut p = Parser::new();
+            let buf = b"SET k \xc3\x28\n";
+            assert_eq!(
+                p.parse(buf),
+                complete(vec![args(&[b"SET", b"k", b"\xc3\x28"])])
+            );
+        }
+
+        #[test]
+        fn unbalanced_quotes_discard_only_their_line() {
+            for buf in [
+                &b"SET k \"unterminated\nPING\n"[..],
+                b"SET k 'open\nPING\n",
+                b"SET k \"closed\"trailing\nPING\n",
+            ] {
+                let mut p = Parser::new();
+                assert_eq!(
+                    p.parse(buf),
+                    partial(Vec::new(), ParseError::UnbalancedQuotes)
+                );
+                assert_eq!(p.state, State::Start);
+                assert_eq!(p.parse(&[]), complete(vec![args(&[b"PING"])]));
+            }
+        }
+
+        #[test]
+        fn divide_quoted_inline_at_different_locations() {
+            let buf = b"SET \"a b\" 'c d'\r\n";
+            for (i, _) in buf.iter().enumerate() {
+                let mut result = Vec::<Frame>::new();
+                let mut p = Parser::new();
+                let (left, right) = buf.split_at(i);
+                result.extend(expect_complete(p.parse(left)));
+                result.extend(expect_complete(p.parse(right)));
+                assert_eq!(result, vec![args(&[b"SET", b"a b", b"c d"])]);
+            }
+        }
+    }
+
+    mod resp3_tests {
+        use super::*;
+
+        #[test]
+        fn parses_scalar_types() {
+            let mut p = Parser::new();
+            let buf = b"_\r\n#t\r\n#f\r\n,1.5\r\n,-inf\r\n(-12345678901234567890\r\n";
+            assert_eq!(
+                p.parse(buf),
+                complete(vec![
+                    Frame::Null,
+                    Frame::Boolean(true),
+                    Frame::Boolean(false),
+                    Frame::Double(1.5),
+                    Frame::Double(f64::NEG_INFINITY),
+                    Frame::BigNumber("-12345678901234567890".into()),
+                ])
+            );
+        }
+
+        #[test]
+        fn parses_blob_errors_and_verbatim_strings() {
+            let mut p = Parser::new();
+            let buf = b"!10\r\nERR failed\r\n=9\r\ntxt:hello\r\n";
+            assert_eq!(
+                p.parse(buf),
+                complete(vec![
+                    Frame::BlobError(Bytes::from_static(b"ERR failed")),
+                    Frame::Verbatim {
+                        format: "txt".into(),
+                        text: Bytes::from_static(b"hello")
+                    },
+                ])
+            );
+        }
+
+        #[test]
+        fn divide_nested_aggregates_at_different_locations() {
+            let buf = b"%2\r\n+a\r\n~2\r\n:1\r\n_\r\n+b\r\n%0\r\n>2\r\n|1\r\n+ttl\r\n:3\r\n#t\r\n";
+            for (i, _) in buf.iter().enumerate() {
+                let mut result = Vec::<Frame>::new();
+                let mut p = Parser::new();
+                let (left, right) = buf.split_at(i);
+                result.extend(expect_complete(p.parse(left)));
+                result.extend(expect_complete(p.parse(right)));
+                assert_eq!(
+                    result,
+                    vec![
+                        Frame::Map(vec![
+                            (
+                                Frame::SimpleString("a".into()),
+                                Frame::Set(vec![Frame::Integer(1), Frame::Null])
+                            ),
+                            (Frame::SimpleString("b".into()), Frame::Map(vec![])),
+                        ]),
+                        Frame::Push(vec![
+                            Frame::Attribute(vec![(
+                                Frame::SimpleString("ttl".into()),
+                                Frame::Integer(3)
+                            )]),
+                            Frame::Boolean(true),
+                        ]),
+                    ]
+                )
+            }
+        }
+
+        #[test]
+        fn malformed_values_return_error() {
+            for buf in [
+                &b"#x\r\n"[..],
+                b"_1\r\n",
+                b",one\r\n",
+                b"(12a\r\n",
+                b"=5\r\nhello\r\n",
+            ] {
+                let mut p = Parser::new();
+                assert_eq!(p.parse(buf), partial(Vec::new(), ParseError::InvalidValue));
+            }
+        }
+
+        #[test]
+        fn only_arrays_may_be_nil() {
+            for buf in [&b"%-1\r\n"[..], b"~-1\r\n", b">-1\r\n", b"!-1\r\n"] {
+                let mut p = Parser::new();
+                assert_eq!(p.parse(buf), partial(Vec::new(), ParseError::InvalidLength));
+            }
+        }
+    }
+
+    mod limit_tests {
+        use super::*;
+
+        fn limited() -> Parser {
+            Parser::with_limits(ParserLimits {
+                max_bulk_len: 5,
+                max_multibulk_len: 3,
+                max_nesting_depth: 2,
+                max_inline_len: 16,
+            })
+        }
+
+        #[test]
+        fn bulk_strings_up_to_the_limit_are_accepted() {
+            let mut p = limited();
+            assert_eq!(
+                p.parse(b"$5\r\nhello\r\n"),
+                complete(vec![Frame::Bulk(Some(Bytes::from_static(b"hello")))])
+            );
+            let mut p = limited();
+            assert_eq!(
+                p.parse(b"$6\r\n"),
+                partial(Vec::new(), ParseError::BulkTooLong)
+            );
+            assert_eq!(p.state, State::Error(ParseError::BulkTooLong));
+        }
+
+        #[test]
+        fn huge_declared_lengths_are_rejected_before_allocating() {
+            let mut p = Parser::new();
+            assert_eq!(
+                p.parse(b"*2147483647\r\n"),
+                partial(Vec::new(), ParseError::MultibulkTooLong)
+            );
+            let mut p = Parser::new();
+            assert_eq!(
+                p.parse(b"$2147483647\r\n"),
+                partial(Vec::new(), ParseError::BulkTooLong)
+            );
+        }
+
+        #[test]
+        fn aggregates_over_the_limit_are_rejected() {
+            let mut p = limited();
+            assert_eq!(
+                p.parse(b"*3\r\n:1\r\n:2\r\n:3\r\n*4\r\n"),
+                partial(
+                    vec![Frame::Array(Some(vec![
+                        Frame::Integer(1),
+                        Frame::Integer(2),
+                        Frame::Integer(3),
+                    ]))],
+                    ParseError::MultibulkTooLong
+                )
+            );
+        }
+
+        #[test]
+        fn nesting_past_the_limit_is_rejected() {
+            let mut p = limited();
+            assert_eq!(
+                p.parse(b"*1\r\n*1\r\n:1\r\n*1\r\n*1\r\n*0\r\n"),
+                partial(
+                    vec![Frame::Array(Some(vec![Frame::Array(Some(vec![
+                        Frame::Integer(1)
+                    ]))]))],
+                    ParseError::NestingTooDeep
+                )
+            );
+        }
+
+        #[test]
+        fn long_inline_commands_are_rejected_even_unterminated() {
+            let mut p = limited();
+            assert_eq!(p.parse(b"GET 0123456789ab"), complete(Vec::new()));
+            assert_eq!(
+                p.parse(b"c"),
+                partial(Vec::new(), ParseError::InlineTooLong)
+            );
+            let mut p = limited();
+            assert_eq!(
+                p.parse(b"GET 0123456789abcdef\n"),
+                partial(Vec::new(), ParseError::InlineTooLong)
+            );
+        }
+
+        #[test]
+        fn long_protocol_lines_are_rejected() {
+            let mut p = limited();
+            assert_eq!(
+                p.parse(b"+01234567890123456"),
+                partial(Vec::new(), ParseError::InlineTooLong)
+            );
+            let mut p = limited();
+            assert_eq!(
+                p.parse(b"$00000000000000005\r\n"),
+                partial(Vec::new(), ParseError::InlineTooLong)
+            );
+        }
+    }
 }
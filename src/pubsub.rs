@@ -0,0 +1,484 @@
+use crate::glob::glob_match;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+
+/// How many published messages may wait for one subscriber before it is
+/// disconnected.
+pub const DEFAULT_PUBSUB_BUFFER_LEN: usize = 8192;
+
+type SubscriberId = u64;
+type Subscribers = HashMap<SubscriberId, Mailbox>;
+
+/// A message delivered to a [`Subscriber`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// Published to a channel the subscriber subscribed to by name.
+    Message { channel: Vec<u8>, payload: Vec<u8> },
+    /// Published to a channel matching a pattern the subscriber subscribed to.
+    PMessage {
+        pattern: Vec<u8>,
+        channel: Vec<u8>,
+        payload: Vec<u8>,
+    },
+}
+
+#[derive(Default)]
+struct Subscriptions {
+    channels: HashMap<Vec<u8>, Subscribers>,
+    patterns: HashMap<Vec<u8>, Subscribers>,
+    next_id: SubscriberId,
+}
+
+/// Where published messages for one subscriber are sent.
+#[derive(Clone)]
+struct Mailbox {
+    sender: Sender<Message>,
+    /// Set once a message did not fit, after which the subscriber is
+    /// disconnected rather than left to miss messages.
+    overflowed: Arc<AtomicBool>,
+}
+
+impl Mailbox {
+    /// Queues `message`, returning whether it was queued.
+    fn deliver(&self, message: Message) -> bool {
+        match self.sender.try_send(message) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.overflowed.store(true, Ordering::Release);
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+}
+
+/// Routes published messages to subscribers. Clones share the same
+/// subscriptions.
+#[derive(Clone)]
+pub struct Hub {
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    buffer_len: usize,
+}
+
+impl Hub {
+    pub fn new() -> Hub {
+        Hub::with_buffer_len(DEFAULT_PUBSUB_BUFFER_LEN)
+    }
+
+    /// Creates a hub that disconnects a subscriber once `buffer_len`
+    /// messages are waiting for it.
+    pub fn with_buffer_len(buffer_len: usize) -> Hub {
+        Hub {
+            subscriptions: Arc::default(),
+            buffer_len: buffer_len.max(1),
+        }
+    }
+
+    /// Creates a subscriber with no subscriptions.
+    pub fn subscriber(&self) -> Subscriber {
+        let id = {
+            let mut subscriptions = self.subscriptions.lock().unwrap();
+            subscriptions.next_id += 1;
+            subscriptions.next_id
+        };
+        let (sender, receiver) = channel(self.buffer_len);
+        Subscriber {
+            id,
+            hub: self.clone(),
+            mailbox: Mailbox {
+                sender,
+                overflowed: Arc::new(AtomicBool::new(false)),
+            },
+            receiver,
+            channels: Vec::new(),
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Sends `payload` to every subscriber of `channel` and of each pattern
+    /// matching it, returning how many messages were delivered.
+    ///
+    /// A subscriber to both the channel and a matching pattern, or to several
+    /// matching patterns, receives and counts once for each.
+    pub fn publish(&self, channel: &[u8], payload: &[u8]) -> usize {
+        let subscriptions = self.subscriptions.lock().unwrap();
+        let mut delivered = 0;
+        for mailbox in subscriptions
+            .channels
+            .get(channel)
+            .into_iter()
+            .flat_map(|s| s.values())
+        {
+            let message = Message::Message {
+                channel: channel.to_vec(),
+                payload: payload.to_vec(),
+            };
+            delivered += mailbox.deliver(message) as usize;
+        }
+        for (pattern, subscribers) in &subscriptions.patterns {
+            if !glob_match(pattern, channel) {
+                continue;
+            }
+            for mailbox in subscribers.values() {
+                let message = Message::PMessage {
+                    pattern: pattern.clone(),
+                    channel: channel.to_vec(),
+                    payload: payload.to_vec(),
+                };
+                delivered += mailbox.deliver(message) as usize;
+            }
+        }
+        delivered
+    }
+}
+
+impl Default for Hub {
+    fn default() -> Self {
+        Hub::new()
+    }
+}
+
+/// One connection's subscriptions, created by [`Hub::subscriber`].
+///
+/// Dropping the subscriber removes all of its subscriptions.
+pub struct Subscriber {
+    id: SubscriberId,
+    hub: Hub,
+    mailbox: Mailbox,
+    receiver: Receiver<Message>,
+    channels: Vec<Vec<u8>>,
+    patterns: Vec<Vec<u8>>,
+}
+
+impl Subscriber {
+    /// Subscribes to `channel` and returns the number of subscriptions held.
+    pub fn subscribe(&mut self, channel: Vec<u8>) -> usize {
+        if !self.channels.contains(&channel) {
+            let mut subscriptions = self.hub.subscriptions.lock().unwrap();
+            subscriptions
+                .channels
+                .entry(channel.clone())
+                .or_default()
+                .insert(self.id, self.mailbox.clone());
+            self.channels.push(channel);
+        }
+        self.count()
+    }
+
+    /// Subscribes to every channel matching the glob `pattern` and returns
+    /// the number of subscriptions held.
+    pub fn psubscribe(&mut self, pattern: Vec<u8>) -> usize {
+        if !self.patterns.contains(&pattern) {
+            let mut subscriptions = self.hub.subscriptions.lock().unwrap();
+            subscriptions
+                .patterns
+                .entry(pattern.clone())
+                .or_default()
+                .insert(self.id, self.mailbox.clone());
+            self.patterns.push(pattern);
+        }
+        self.count()
+    }
+
+    /// Unsubscribes from `channel` and returns the number of subscriptions
+    /// still held.
+    pub fn unsubscribe(&mut self, channel: &[u8]) -> usize {
+        if let Some(index) = self.channels.iter().position(|c| c == channel) {
+            self.channels.remove(index);
+            let mut subscriptions = self.hub.subscriptions.lock().unwrap();
+            remove_subscriber(&mut subscriptions.channels, channel, self.id);
+        }
+        self.discard_if_unsubscribed();
+        self.count()
+    }
+
+    /// Unsubscribes from `pattern` and returns the number of subscriptions
+    /// still held.
+    pub fn punsubscribe(&mut self, pattern: &[u8]) -> usize {
+        if let Some(index) = self.patterns.iter().position(|p| p == pattern) {
+            self.patterns.remove(index);
+            let mut subscriptions = self.hub.subscriptions.lock().unwrap();
+            remove_subscriber(&mut subscriptions.patterns, pattern, self.id);
+        }
+        self.discard_if_unsubscribed();
+        self.count()
+    }
+
+    /// Returns the subscribed channels in the order they were subscribed.
+    pub fn channels(&self) -> &[Vec<u8>] {
+        &self.channels
+    }
+
+    /// Returns the subscribed patterns in the order they were subscribed.
+    pub fn patterns(&self) -> &[Vec<u8>] {
+        &self.patterns
+    }
+
+    /// Returns the number of channels and patterns subscribed to.
+    pub fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    /// Waits for the next message, or returns `None` once messages were
+    /// dropped because too many were waiting, after which the connection
+    /// should be closed.
+    pub async fn recv(&mut self) -> Option<Message> {
+        if self.mailbox.overflowed.load(Ordering::Acquire) {
+            return None;
+        }
+        let message = self
+            .receiver
+            .recv()
+            .await
+            .expect("the subscriber holds a sender, so the channel stays open");
+        (!self.mailbox.overflowed.load(Ordering::Acquire)).then_some(message)
+    }
+
+    /// Drops messages that arrived before the last subscription was removed,
+    /// so they are not delivered after a later subscribe.
+    fn discard_if_unsubscribed(&mut self) {
+        if self.count() == 0 {
+            while self.receiver.try_recv().is_ok() {}
+        }
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        let mut subscriptions = self.hub.subscriptions.lock().unwrap();
+        for channel in &self.channels {
+            remove_subscriber(&mut subscriptions.channels, channel, self.id);
+        }
+        for pattern in &self.patterns {
+            remove_subscriber(&mut subscriptions.patterns, pattern, self.id);
+        }
+    }
+}
+
+/// The kind of change a keyspace notification reports, used to choose which
+/// notifications are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventClass {
+    Generic,
+    String,
+    List,
+    Set,
+    Hash,
+    SortedSet,
+    Expired,
+}
+
+impl EventClass {
+    fn flag(self) -> u8 {
+        match self {
+            EventClass::Generic => 1,
+            EventClass::String => 1 << 1,
+            EventClass::List => 1 << 2,
+            EventClass::Set => 1 << 3,
+            EventClass::Hash => 1 << 4,
+            EventClass::SortedSet => 1 << 5,
+            EventClass::Expired => 1 << 6,
+        }
+    }
+}
+
+/// Which keyspace notifications to send, parsed from a Redis
+/// `notify-keyspace-events` string such as `"Ex"` or `"KA"`.
+///
+/// `K` and `E` enable the `__keyspace@0__` and `__keyevent@0__` channels, and
+/// `g`, `$`, `l`, `s`, `h`, `z` and `x` choose event classes, with `A` as an
+/// alias for all of them. `e` is accepted for compatibility, but no events
+/// are ever evicted. Nothing is sent unless a channel and a class are both
+/// chosen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyspaceEvents {
+    keyspace: bool,
+    keyevent: bool,
+    classes: u8,
+}
+
+impl KeyspaceEvents {
+    /// Returns whether any notifications would be sent.
+    pub fn is_enabled(&self) -> bool {
+        (self.keyspace || self.keyevent) && self.classes != 0
+    }
+
+    fn includes(&self, class: EventClass) -> bool {
+        self.classes & class.flag() != 0
+    }
+}
+
+impl FromStr for KeyspaceEvents {
+    type Err = String;
+
+    fn from_str(flags: &str) -> Result<Self, Self::Err> {
+        let mut events = KeyspaceEvents::default();
+        for flag in flags.chars() {
+            match flag {
+                'K' => events.keyspace = true,
+                'E' => events.keyevent = true,
+                'g' => events.classes |= EventClass::Generic.flag(),
+                '$' => events.classes |= EventClass::String.flag(),
+                'l' => events.classes |= EventClass::List.flag(),
+                's' => events.classes |= EventClass::Set.flag(),
+                'h' => events.classes |= EventClass::Hash.flag(),
+                'z' => events.classes |= EventClass::SortedSet.flag(),
+                'x' => events.classes |= EventClass::Expired.flag(),
+                'e' => {}
+                'A' => events.classes = u8::MAX,
+                other => return Err(format!("unknown keyspace event flag '{other}'")),
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// Publishes keyspace notifications to a [`Hub`].
+pub struct KeyspaceNotifier {
+    hub: Hub,
+    events: KeyspaceEvents,
+}
+
+impl KeyspaceNotifier {
+    pub fn new(hub: Hub, events: KeyspaceEvents) -> KeyspaceNotifier {
+        KeyspaceNotifier { hub, events }
+    }
+
+    /// Publishes `event` on `key` to `__keyspace@0__:<key>` and `key` to
+    /// `__keyevent@0__:<event>`, as far as the configured events allow.
+    pub fn notify(&self, class: EventClass, event: &str, key: &[u8]) {
+        if !self.events.includes(class) {
+            return;
+        }
+        if self.events.keyspace {
+            let channel = [b"__keyspace@0__:".as_slice(), key].concat();
+            self.hub.publish(&channel, event.as_bytes());
+        }
+        if self.events.keyevent {
+            let channel = [b"__keyevent@0__:".as_slice(), event.as_bytes()].concat();
+            self.hub.publish(&channel, key);
+        }
+    }
+}
+
+fn remove_subscriber(map: &mut HashMap<Vec<u8>, Subscribers>, name: &[u8], id: SubscriberId) {
+    if let Some(subscribers) = map.get_mut(name) {
+        subscribers.remove(&id);
+        if subscribers.is_empty() {
+            map.remove(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn publish_reaches_channel_and_pattern_subscribers() {
+        let hub = Hub::new();
+        let mut by_name = hub.subscriber();
+        let mut by_pattern = hub.subscriber();
+        by_name.subscribe(b"cache:users".to_vec());
+        by_pattern.psubscribe(b"cache:*".to_vec());
+
+        assert_eq!(hub.publish(b"cache:users", b"42"), 2);
+        assert_eq!(
+            by_name.recv().await,
+            Some(Message::Message {
+                channel: b"cache:users".to_vec(),
+                payload: b"42".to_vec()
+            })
+        );
+        assert_eq!(
+            by_pattern.recv().await,
+            Some(Message::PMessage {
+                pattern: b"cache:*".to_vec(),
+                channel: b"cache:users".to_vec(),
+                payload: b"42".to_vec()
+            })
+        );
+        assert_eq!(hub.publish(b"other", b"x"), 0);
+    }
+
+    #[tokio::test]
+    async fn subscriber_that_falls_behind_is_disconnected() {
+        let hub = Hub::with_buffer_len(2);
+        let mut subscriber = hub.subscriber();
+        subscriber.subscribe(b"a".to_vec());
+
+        assert_eq!(hub.publish(b"a", b"1"), 1);
+        assert_eq!(hub.publish(b"a", b"2"), 1);
+        assert_eq!(hub.publish(b"a", b"3"), 0);
+        assert_eq!(subscriber.recv().await, None);
+    }
+
+    #[test]
+    fn subscription_counts_ignore_repeats() {
+        let hub = Hub::new();
+        let mut subscriber = hub.subscriber();
+        assert_eq!(subscriber.subscribe(b"a".to_vec()), 1);
+        assert_eq!(subscriber.subscribe(b"a".to_vec()), 1);
+        assert_eq!(subscriber.psubscribe(b"a*".to_vec()), 2);
+        assert_eq!(subscriber.unsubscribe(b"missing"), 2);
+        assert_eq!(subscriber.unsubscribe(b"a"), 1);
+        assert_eq!(subscriber.punsubscribe(b"a*"), 0);
+        assert_eq!(hub.publish(b"a", b"x"), 0);
+    }
+
+    #[test]
+    fn dropping_a_subscriber_removes_its_subscriptions() {
+        let hub = Hub::new();
+        let mut subscriber = hub.subscriber();
+        subscriber.subscribe(b"a".to_vec());
+        subscriber.psubscribe(b"*".to_vec());
+        assert_eq!(hub.publish(b"a", b"x"), 2);
+
+        drop(subscriber);
+        assert_eq!(hub.publish(b"a", b"x"), 0);
+        let subscriptions = hub.subscriptions.lock().unwrap();
+        assert!(subscriptions.channels.is_empty());
+        assert!(subscriptions.patterns.is_empty());
+    }
+
+    #[test]
+    fn keyspace_events_parse_redis_flags() {
+        let events: KeyspaceEvents = "Ex".parse().unwrap();
+        assert!(events.is_enabled());
+        assert!(events.includes(EventClass::Expired));
+        assert!(!events.includes(EventClass::Generic));
+
+        let all: KeyspaceEvents = "KEA".parse().unwrap();
+        assert!(all.keyspace && all.keyevent);
+        assert!(all.includes(EventClass::SortedSet));
+
+        assert!(!"".parse::<KeyspaceEvents>().unwrap().is_enabled());
+        assert!(!"g$".parse::<KeyspaceEvents>().unwrap().is_enabled());
+        assert!(!"K".parse::<KeyspaceEvents>().unwrap().is_enabled());
+        assert!("Kq".parse::<KeyspaceEvents>().is_err());
+    }
+
+    #[tokio::test]
+    async fn notifier_publishes_only_enabled_channels_and_classes() {
+        let hub = Hub::new();
+        let mut subscriber = hub.subscriber();
+        subscriber.psubscribe(b"__key*__:*".to_vec());
+        let notifier = KeyspaceNotifier::new(hub.clone(), "Kx".parse().unwrap());
+
+        notifier.notify(EventClass::Generic, "del", b"ignored");
+        notifier.notify(EventClass::Expired, "expired", b"session:1");
+        assert_eq!(
+            subscriber.recv().await,
+            Some(Message::PMessage {
+                pattern: b"__key*__:*".to_vec(),
+                channel: b"__keyspace@0__:session:1".to_vec(),
+                payload: b"expired".to_vec()
+            })
+        );
+        assert!(subscriber.receiver.try_recv().is_err());
+    }
+}
@@ -1,23 +1,25 @@
 use std::fmt;
 use std::net::SocketAddr;
-use std::path::PathBuf;
 use std::time::Duration;
 
-use crate::archive::save;
-use crate::archive::{ArchiveError, load};
+use crate::aof::{AofError, AppendOnlyFile, replay};
+use crate::archive::{ArchiveError, Snapshots, load};
 use crate::config::Config;
 use crate::connection::Connection;
+use crate::parser::ParserLimits;
+use crate::pubsub::Hub;
 use crate::store::Store;
 use tokio::net::TcpListener;
 use tokio::select;
 use tokio::task::{JoinHandle, JoinSet};
-use tokio::time::timeout;
+use tokio::time::{interval, timeout};
 use tokio_util::sync::CancellationToken;
 
 #[derive(Debug)]
 pub enum ServerError {
     Io(std::io::Error),
     Archive(ArchiveError),
+    AppendOnly(AofError),
 }
 
 type ServerResult<T> = std::result::Result<T, ServerError>;
@@ -27,6 +29,7 @@ impl fmt::Display for ServerError {
         match self {
             ServerError::Io(e) => write!(f, "Server IO Error {}", e),
             ServerError::Archive(e) => write!(f, "Archive Error {}", e),
+            ServerError::AppendOnly(e) => write!(f, "Append-Only File Error {}", e),
         }
     }
 }
@@ -43,15 +46,47 @@ impl From<ArchiveError> for ServerError {
     }
 }
 
+impl From<AofError> for ServerError {
+    fn from(value: AofError) -> Self {
+        ServerError::AppendOnly(value)
+    }
+}
+
 impl std::error::Error for ServerError {}
 
+/// How often the save rules are checked.
+const SAVE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Saves the archive whenever one of its save rules is met, until shutdown.
+async fn save_on_schedule(snapshots: Snapshots, shutdown_token: CancellationToken) {
+    if !snapshots.is_enabled() {
+        return;
+    }
+    let mut ticks = interval(SAVE_CHECK_INTERVAL);
+    loop {
+        select! {
+            _ = ticks.tick() => {},
+            _ = shutdown_token.cancelled() => return,
+        }
+        if snapshots.is_due()
+            && let Err(e) = snapshots.save().await
+        {
+            println!("scheduled save failed: {:?}", e);
+        }
+    }
+}
+
 pub async fn server_from_listener(
     listener: TcpListener,
     store: Store,
-    archive_path: Option<PathBuf>,
+    snapshots: Snapshots,
+    aof: Option<AppendOnlyFile>,
+    hub: Hub,
+    parser_limits: ParserLimits,
     shutdown_token: CancellationToken,
 ) -> ServerResult<()> {
     let mut open_connections = JoinSet::new();
+    let scheduled_saves = tokio::spawn(save_on_schedule(snapshots.clone(), shutdown_token.clone()));
 
     loop {
         select! {
@@ -59,6 +94,9 @@ pub async fn server_from_listener(
                 match connection_result {
                     Ok((mut socket, _addr)) => {
                         let store = store.clone();
+                        let hub = hub.clone();
+                        let snapshots = snapshots.clone();
+                        let aof = aof.clone();
                         let connection_shutdown = shutdown_token.clone();
                         open_connections.spawn(async move {
                             let (read_half, write_half) = socket.split();
@@ -66,8 +104,14 @@ pub async fn server_from_listener(
                                 read_half,
                                 write_half,
                                 store,
+                                hub,
                                 connection_shutdown,
-                            );
+                            )
+                            .with_parser_limits(parser_limits)
+                            .with_snapshots(snapshots);
+                            if let Some(aof) = aof {
+                                conn = conn.with_append_only_file(aof);
+                            }
                             if let Err(e) = conn.run().await {
                                 println!("connection failed: {:?}", e)
                             }
@@ -107,8 +151,15 @@ pub async fn server_from_listener(
         }
     }
 
-    if let Some(p) = archive_path {
-        save(p, store).await?;
+    if let Some(aof) = aof {
+        aof.sync().await?;
+    }
+
+    if let Err(err) = scheduled_saves.await {
+        println!("save scheduler failed: {:?}", err);
+    }
+    if snapshots.saves_on_shutdown() {
+        snapshots.save().await?;
     }
 
     Ok(())
@@ -121,14 +172,43 @@ pub async fn run_server(
     let addr = format!("{}:{}", config.address, config.port);
     let listener = TcpListener::bind(addr).await?;
     let addr: SocketAddr = listener.local_addr()?;
-    let store: Store = match config.archive_path.clone() {
-        Some(path) => load(path).await.map_err(ServerError::Archive)?,
-        None => Store::new(),
+    // An append-only file is only started from the archive when it does not
+    // exist yet, as when it is first turned on for existing data.
+    let seed_aof = match &config.aof_path {
+        Some(path) => !tokio::fs::try_exists(path).await?,
+        None => false,
     };
+    let store: Store = match (&config.aof_path, config.archive_path.clone()) {
+        (Some(_), Some(path)) if seed_aof => load(path).await.map_err(ServerError::Archive)?,
+        (Some(_), _) => Store::new(),
+        (None, Some(path)) => load(path).await.map_err(ServerError::Archive)?,
+        (None, None) => Store::new(),
+    };
+    let aof = match &config.aof_path {
+        Some(path) => {
+            replay(path.clone(), &store).await?;
+            store.record_served_pops();
+            let aof = AppendOnlyFile::open(path, config.appendfsync, store.clone())
+                .await
+                .map_err(AofError::WriteFile)?;
+            if seed_aof && let Some(rewrite) = aof.start_rewrite() {
+                rewrite.await.map_err(std::io::Error::other)??;
+            }
+            Some(aof.with_auto_rewrite(config.auto_rewrite()))
+        }
+        None => None,
+    };
+    let hub = Hub::with_buffer_len(config.pubsub_buffer_len);
+    if config.notify_keyspace_events.is_enabled() {
+        store.notify_keyspace_events(hub.clone(), config.notify_keyspace_events);
+    }
     let handle = tokio::spawn(server_from_listener(
         listener,
-        store,
-        config.archive_path.clone(),
+        store.clone(),
+        Snapshots::new(store, config.archive_path.clone()).with_save_rules(config.save.clone()),
+        aof,
+        hub,
+        config.parser_limits(),
         shutdown_token.clone(),
     ));
     Ok((addr, handle))
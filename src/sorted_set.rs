@@ -0,0 +1,345 @@
+mod tree;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use tree::RankedTree;
+
+/// A set of unique members ordered by score, then by member bytes.
+///
+/// Members are indexed by a hash map for score lookups and kept in a tree
+/// ordered by score that counts the members under each node, so inserts,
+/// removals, ranks and the start of a range all take logarithmic time.
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    scores: HashMap<Vec<u8>, f64>,
+    ordered: RankedTree<(Score, Vec<u8>)>,
+}
+
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &Self) -> bool {
+        // The tree's shape depends on the order of changes, not its contents.
+        self.scores == other.scores
+    }
+}
+
+/// A score that sorts with [`f64::total_cmp`], so it can key the tree.
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// One end of a score range, as written in `ZRANGEBYSCORE key min max`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+/// Whether `ZADD` may create new members or update existing ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZAddCondition {
+    OnlyNew,
+    OnlyExisting,
+}
+
+/// Restricts `ZADD` updates to scores that move in one direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZAddComparison {
+    GreaterThan,
+    LessThan,
+}
+
+/// The `NX`/`XX`, `GT`/`LT` and `CH` flags of `ZADD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ZAddOptions {
+    pub condition: Option<ZAddCondition>,
+    pub comparison: Option<ZAddComparison>,
+    pub changed: bool,
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ordered.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Sets `member` to `score`, returning the previous score if it existed.
+    pub fn insert(&mut self, member: Vec<u8>, score: f64) -> Option<f64> {
+        // Normalise -0.0 so it sorts together with 0.0.
+        let score = score + 0.0;
+        let previous = self.remove(&member);
+        self.ordered.insert((Score(score), member.clone()));
+        self.scores.insert(member, score);
+        previous
+    }
+
+    /// Removes `member`, returning its score if it existed.
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+        self.ordered.remove(entry_of(score, member));
+        Some(score)
+    }
+
+    /// Returns the zero-based position of `member` in score order.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        self.ordered.rank(entry_of(score, member))
+    }
+
+    /// Returns the members at positions `first..=last`.
+    pub fn range_by_rank(&self, first: usize, last: usize) -> impl Iterator<Item = (f64, &[u8])> {
+        self.ordered
+            .iter_from_rank(first)
+            .take(last + 1 - first)
+            .map(|(score, member)| (score.0, member.as_slice()))
+    }
+
+    /// Returns the members whose scores fall between `min` and `max`.
+    pub fn range_by_score(
+        &self,
+        min: ScoreBound,
+        max: ScoreBound,
+    ) -> impl Iterator<Item = (f64, &[u8])> {
+        self.ordered
+            .iter_from(|(score, _)| match min {
+                ScoreBound::Inclusive(min) => score.0 < min,
+                ScoreBound::Exclusive(min) => score.0 <= min,
+            })
+            .map(|(score, member)| (score.0, member.as_slice()))
+            .take_while(move |(score, _)| match max {
+                ScoreBound::Inclusive(max) => *score <= max,
+                ScoreBound::Exclusive(max) => *score < max,
+            })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (f64, &[u8])> {
+        self.ordered
+            .iter()
+            .map(|(score, member)| (score.0, member.as_slice()))
+    }
+}
+
+impl FromIterator<(Vec<u8>, f64)> for SortedSet {
+    fn from_iter<I: IntoIterator<Item = (Vec<u8>, f64)>>(iter: I) -> Self {
+        // Later duplicates win, as they would with repeated inserts.
+        let scores: HashMap<Vec<u8>, f64> = iter
+            .into_iter()
+            .map(|(member, score)| (member, score + 0.0))
+            .collect();
+        let mut entries: Vec<_> = scores
+            .iter()
+            .map(|(member, score)| (Score(*score), member.clone()))
+            .collect();
+        entries.sort_unstable();
+        SortedSet {
+            scores,
+            ordered: RankedTree::from_sorted(entries),
+        }
+    }
+}
+
+/// Compares the entry for `member` at `score` with another entry, to find
+/// it in the tree.
+fn entry_of(score: f64, member: &[u8]) -> impl Fn(&(Score, Vec<u8>)) -> Ordering {
+    move |(other_score, other_member)| {
+        Score(score)
+            .cmp(other_score)
+            .then_with(|| member.cmp(other_member.as_slice()))
+    }
+}
+
+/// Formats a score the way replies and archives spell it, e.g. `1`, `2.5`,
+/// `inf`.
+pub fn format_score(score: f64) -> String {
+    if score == f64::INFINITY {
+        "inf".to_string()
+    } else if score == f64::NEG_INFINITY {
+        "-inf".to_string()
+    } else {
+        score.to_string()
+    }
+}
+
+/// Parses a score such as `1.5`, `-3`, `+inf` or `-inf`. NaN is rejected.
+pub fn parse_score(value: &[u8]) -> Option<f64> {
+    let score = std::str::from_utf8(value).ok()?.parse::<f64>().ok()?;
+    if score.is_nan() {
+        return None;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members<'a>(entries: impl Iterator<Item = (f64, &'a [u8])>) -> Vec<&'a [u8]> {
+        entries.map(|(_, m)| m).collect()
+    }
+
+    #[test]
+    fn orders_by_score_then_member() {
+        let set: SortedSet = [
+            (b"b".to_vec(), 1.0),
+            (b"a".to_vec(), 1.0),
+            (b"c".to_vec(), 0.5),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            members(set.range_by_rank(0, 2)),
+            vec![b"c".as_slice(), b"a", b"b"]
+        );
+        assert_eq!(set.rank(b"a"), Some(1));
+        assert_eq!(set.rank(b"missing"), None);
+    }
+
+    #[test]
+    fn reinserting_moves_member_to_new_position() {
+        let mut set = SortedSet::new();
+        set.insert(b"a".to_vec(), 1.0);
+        set.insert(b"b".to_vec(), 2.0);
+
+        assert_eq!(set.insert(b"a".to_vec(), 3.0), Some(1.0));
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.rank(b"a"), Some(1));
+        assert_eq!(set.score(b"a"), Some(3.0));
+    }
+
+    #[test]
+    fn remove_drops_member_from_both_indexes() {
+        let mut set = SortedSet::new();
+        set.insert(b"a".to_vec(), 1.0);
+
+        assert_eq!(set.remove(b"a"), Some(1.0));
+        assert_eq!(set.remove(b"a"), None);
+        assert!(set.is_empty());
+        assert_eq!(set.rank(b"a"), None);
+    }
+
+    #[test]
+    fn collecting_keeps_the_last_score_of_a_repeated_member() {
+        let set: SortedSet = [(b"a".to_vec(), 1.0), (b"a".to_vec(), 2.0)]
+            .into_iter()
+            .collect();
+
+        assert_eq!(set.len(), 1);
+        assert_eq!(set.score(b"a"), Some(2.0));
+    }
+
+    #[test]
+    fn ranks_follow_interleaved_inserts_and_removals() {
+        let mut set = SortedSet::new();
+        let mut expected: Vec<(f64, Vec<u8>)> = Vec::new();
+        for i in 0..200u32 {
+            let member = format!("m{}", i % 50).into_bytes();
+            let score = f64::from((i * 37) % 23);
+            if i % 3 == 2 {
+                set.remove(&member);
+                expected.retain(|(_, m)| *m != member);
+            } else {
+                set.insert(member.clone(), score);
+                expected.retain(|(_, m)| *m != member);
+                expected.push((score, member));
+            }
+            expected.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+
+            assert_eq!(set.len(), expected.len());
+            for (rank, (_, member)) in expected.iter().enumerate() {
+                assert_eq!(set.rank(member), Some(rank));
+            }
+        }
+        let last = expected.len() - 1;
+        assert_eq!(
+            members(set.range_by_rank(1, last)),
+            expected[1..]
+                .iter()
+                .map(|(_, m)| m.as_slice())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn negative_zero_sorts_with_zero() {
+        let mut set = SortedSet::new();
+        set.insert(b"b".to_vec(), 0.0);
+        set.insert(b"a".to_vec(), -0.0);
+
+        assert_eq!(set.rank(b"a"), Some(0));
+        assert_eq!(set.rank(b"b"), Some(1));
+    }
+
+    #[test]
+    fn range_by_score_respects_bound_types() {
+        let set: SortedSet = [
+            (b"a".to_vec(), 1.0),
+            (b"b".to_vec(), 2.0),
+            (b"c".to_vec(), 3.0),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            members(set.range_by_score(ScoreBound::Inclusive(1.0), ScoreBound::Inclusive(2.0))),
+            vec![b"a".as_slice(), b"b"]
+        );
+        assert_eq!(
+            members(set.range_by_score(ScoreBound::Exclusive(1.0), ScoreBound::Exclusive(3.0))),
+            vec![b"b".as_slice()]
+        );
+        assert_eq!(
+            members(set.range_by_score(
+                ScoreBound::Inclusive(f64::NEG_INFINITY),
+                ScoreBound::Inclusive(f64::INFINITY)
+            )),
+            vec![b"a".as_slice(), b"b", b"c"]
+        );
+        assert_eq!(
+            set.range_by_score(ScoreBound::Inclusive(3.0), ScoreBound::Inclusive(1.0))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn scores_format_and_parse() {
+        assert_eq!(format_score(1.0), "1");
+        assert_eq!(format_score(2.5), "2.5");
+        assert_eq!(format_score(f64::INFINITY), "inf");
+        assert_eq!(format_score(f64::NEG_INFINITY), "-inf");
+        assert_eq!(parse_score(b"+inf"), Some(f64::INFINITY));
+        assert_eq!(parse_score(b"-3"), Some(-3.0));
+        assert_eq!(parse_score(b"nan"), None);
+        assert_eq!(parse_score(b"abc"), None);
+    }
+}
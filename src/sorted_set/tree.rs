@@ -0,0 +1,344 @@
+use std::cmp::Ordering;
+
+/// A balanced search tree that counts the keys under each node, so that
+/// finding a key's rank, or the key at a rank, takes logarithmic time.
+///
+/// Lookups take a closure comparing the wanted key with a key in the tree,
+/// so callers can search without building an owned key.
+#[derive(Debug, Clone)]
+pub(super) struct RankedTree<K> {
+    root: Link<K>,
+}
+
+type Link<K> = Option<Box<Node<K>>>;
+
+#[derive(Debug, Clone)]
+struct Node<K> {
+    key: K,
+    height: u8,
+    /// The number of keys in this subtree, this one included.
+    size: usize,
+    left: Link<K>,
+    right: Link<K>,
+}
+
+impl<K> Default for RankedTree<K> {
+    fn default() -> Self {
+        RankedTree { root: None }
+    }
+}
+
+impl<K: Ord> RankedTree<K> {
+    /// Builds a tree from keys already in ascending order without repeats.
+    pub(super) fn from_sorted(keys: Vec<K>) -> Self {
+        let len = keys.len();
+        RankedTree {
+            root: build(&mut keys.into_iter(), len),
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    /// Adds `key`, returning `false` if it was already present.
+    pub(super) fn insert(&mut self, key: K) -> bool {
+        let (root, inserted) = insert(self.root.take(), key);
+        self.root = Some(root);
+        inserted
+    }
+
+    /// Removes the key for which `target` returns [`Ordering::Equal`],
+    /// returning whether there was one.
+    pub(super) fn remove(&mut self, target: impl Fn(&K) -> Ordering) -> bool {
+        let (root, removed) = remove(self.root.take(), &target);
+        self.root = root;
+        removed
+    }
+
+    /// Returns how many keys sort before the one for which `target` returns
+    /// [`Ordering::Equal`], or `None` if there is no such key.
+    ///
+    /// `target` says how the wanted key compares with the key it is given.
+    pub(super) fn rank(&self, target: impl Fn(&K) -> Ordering) -> Option<usize> {
+        let mut link = &self.root;
+        let mut rank = 0;
+        while let Some(node) = link {
+            match target(&node.key) {
+                Ordering::Less => link = &node.left,
+                Ordering::Greater => {
+                    rank += size(&node.left) + 1;
+                    link = &node.right;
+                }
+                Ordering::Equal => return Some(rank + size(&node.left)),
+            }
+        }
+        None
+    }
+}
+
+impl<K> RankedTree<K> {
+    /// Iterates over every key in ascending order.
+    pub(super) fn iter(&self) -> Iter<'_, K> {
+        self.iter_from_rank(0)
+    }
+
+    /// Iterates in ascending order, starting with the key at `rank`.
+    pub(super) fn iter_from_rank(&self, mut rank: usize) -> Iter<'_, K> {
+        let mut stack = Vec::new();
+        let mut link = &self.root;
+        while let Some(node) = link {
+            let left = size(&node.left);
+            if rank <= left {
+                stack.push(node.as_ref());
+                if rank == left {
+                    break;
+                }
+                link = &node.left;
+            } else {
+                rank -= left + 1;
+                link = &node.right;
+            }
+        }
+        Iter { stack }
+    }
+
+    /// Iterates in ascending order, starting with the first key for which
+    /// `below` returns `false`. The keys for which it returns `true` must
+    /// all sort before the others.
+    pub(super) fn iter_from(&self, below: impl Fn(&K) -> bool) -> Iter<'_, K> {
+        let mut stack = Vec::new();
+        let mut link = &self.root;
+        while let Some(node) = link {
+            if below(&node.key) {
+                link = &node.right;
+            } else {
+                stack.push(node.as_ref());
+                link = &node.left;
+            }
+        }
+        Iter { stack }
+    }
+}
+
+/// An in-order walk over a [`RankedTree`].
+pub(super) struct Iter<'a, K> {
+    /// Nodes still to visit, each before its right subtree.
+    stack: Vec<&'a Node<K>>,
+}
+
+impl<'a, K> Iterator for Iter<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        let mut link = &node.right;
+        while let Some(child) = link {
+            self.stack.push(child);
+            link = &child.left;
+        }
+        Some(&node.key)
+    }
+}
+
+fn height<K>(link: &Link<K>) -> u8 {
+    link.as_ref().map_or(0, |node| node.height)
+}
+
+fn size<K>(link: &Link<K>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+impl<K> Node<K> {
+    fn leaf(key: K) -> Box<Node<K>> {
+        Box::new(Node {
+            key,
+            height: 1,
+            size: 1,
+            left: None,
+            right: None,
+        })
+    }
+
+    fn update(&mut self) {
+        self.height = 1 + height(&self.left).max(height(&self.right));
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+}
+
+fn build<K>(keys: &mut impl Iterator<Item = K>, len: usize) -> Link<K> {
+    if len == 0 {
+        return None;
+    }
+    let left = build(keys, len / 2);
+    let mut node = Node::leaf(keys.next().expect("len keys remain"));
+    node.left = left;
+    node.right = build(keys, len - len / 2 - 1);
+    node.update();
+    Some(node)
+}
+
+fn rotate_right<K>(mut node: Box<Node<K>>) -> Box<Node<K>> {
+    let mut left = node.left.take().expect("rotated node has a left child");
+    node.left = left.right.take();
+    node.update();
+    left.right = Some(node);
+    left.update();
+    left
+}
+
+fn rotate_left<K>(mut node: Box<Node<K>>) -> Box<Node<K>> {
+    let mut right = node.right.take().expect("rotated node has a right child");
+    node.right = right.left.take();
+    node.update();
+    right.left = Some(node);
+    right.update();
+    right
+}
+
+/// Restores the height and size of `node` after one of its subtrees
+/// changed, rotating if the subtrees' heights differ by more than one.
+fn balance<K>(mut node: Box<Node<K>>) -> Box<Node<K>> {
+    node.update();
+    let (left, right) = (height(&node.left), height(&node.right));
+    if left > right + 1 {
+        let child = node.left.take().expect("taller side exists");
+        node.left = Some(if height(&child.left) < height(&child.right) {
+            rotate_left(child)
+        } else {
+            child
+        });
+        return rotate_right(node);
+    }
+    if right > left + 1 {
+        let child = node.right.take().expect("taller side exists");
+        node.right = Some(if height(&child.right) < height(&child.left) {
+            rotate_right(child)
+        } else {
+            child
+        });
+        return rotate_left(node);
+    }
+    node
+}
+
+fn insert<K: Ord>(link: Link<K>, key: K) -> (Box<Node<K>>, bool) {
+    let Some(mut node) = link else {
+        return (Node::leaf(key), true);
+    };
+    let inserted = match key.cmp(&node.key) {
+        Ordering::Less => {
+            let (child, inserted) = insert(node.left.take(), key);
+            node.left = Some(child);
+            inserted
+        }
+        Ordering::Greater => {
+            let (child, inserted) = insert(node.right.take(), key);
+            node.right = Some(child);
+            inserted
+        }
+        Ordering::Equal => return (node, false),
+    };
+    (balance(node), inserted)
+}
+
+fn remove<K>(link: Link<K>, target: &impl Fn(&K) -> Ordering) -> (Link<K>, bool) {
+    let Some(mut node) = link else {
+        return (None, false);
+    };
+    let removed = match target(&node.key) {
+        Ordering::Less => {
+            let (child, removed) = remove(node.left.take(), target);
+            node.left = child;
+            removed
+        }
+        Ordering::Greater => {
+            let (child, removed) = remove(node.right.take(), target);
+            node.right = child;
+            removed
+        }
+        Ordering::Equal => {
+            let replacement = match (node.left.take(), node.right.take()) {
+                (None, right) => right,
+                (left, None) => left,
+                (left, Some(right)) => {
+                    let (right, mut successor) = remove_first(right);
+                    successor.left = left;
+                    successor.right = right;
+                    Some(balance(successor))
+                }
+            };
+            return (replacement, true);
+        }
+    };
+    (Some(balance(node)), removed)
+}
+
+/// Detaches the smallest node of the subtree at `node`, returning what is
+/// left of the subtree and the detached node.
+fn remove_first<K>(mut node: Box<Node<K>>) -> (Link<K>, Box<Node<K>>) {
+    match node.left.take() {
+        None => (node.right.take(), node),
+        Some(left) => {
+            let (left, first) = remove_first(left);
+            node.left = left;
+            (Some(balance(node)), first)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks the ordering, heights, sizes and balance of every node.
+    fn check<K: Ord>(link: &Link<K>) {
+        let Some(node) = link else {
+            return;
+        };
+        check(&node.left);
+        check(&node.right);
+        assert!(node.left.as_ref().is_none_or(|left| left.key < node.key));
+        assert!(node.right.as_ref().is_none_or(|right| right.key > node.key));
+        assert_eq!(node.size, 1 + size(&node.left) + size(&node.right));
+        assert_eq!(node.height, 1 + height(&node.left).max(height(&node.right)));
+        assert!(height(&node.left).abs_diff(height(&node.right)) <= 1);
+    }
+
+    #[test]
+    fn stays_balanced_through_inserts_and_removals() {
+        let mut tree = RankedTree::default();
+        for key in 0..1000 {
+            assert!(tree.insert(key));
+        }
+        assert!(!tree.insert(500));
+        check(&tree.root);
+        assert!(height(&tree.root) <= 15);
+
+        for key in (0..1000).step_by(3) {
+            assert!(tree.remove(|other| key.cmp(other)));
+        }
+        assert!(!tree.remove(|other| 0.cmp(other)));
+        check(&tree.root);
+        assert_eq!(tree.len(), 666);
+    }
+
+    #[test]
+    fn walks_from_a_rank_or_a_bound() {
+        let tree = RankedTree::from_sorted((0..100).collect());
+        check(&tree.root);
+
+        assert_eq!(tree.rank(|other| 42.cmp(other)), Some(42));
+        assert_eq!(tree.rank(|other| 100.cmp(other)), None);
+        assert_eq!(
+            tree.iter_from_rank(97).copied().collect::<Vec<_>>(),
+            vec![97, 98, 99]
+        );
+        assert_eq!(tree.iter_from_rank(100).next(), None);
+        assert_eq!(
+            tree.iter_from(|key| *key < 98).copied().collect::<Vec<_>>(),
+            vec![98, 99]
+        );
+        assert_eq!(tree.iter().count(), 100);
+    }
+}
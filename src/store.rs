@@ -1,34 +1,66 @@
+use crate::error::ErrorCode;
+use crate::glob::glob_match;
+use crate::pubsub::{EventClass, Hub, KeyspaceEvents, KeyspaceNotifier};
+use crate::sorted_set::{
+    ScoreBound, SortedSet, ZAddComparison, ZAddCondition, ZAddOptions, format_score, parse_score,
+};
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
 use std::fmt;
-use std::sync::Arc;
+use std::hash::{BuildHasher, DefaultHasher, Hash, Hasher};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock as StdRwLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::spawn;
-use tokio::sync::{Notify, RwLock};
+use tokio::sync::{Notify, OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock, oneshot};
 use tokio::time::{Duration, Instant, sleep_until};
 
 type Key = Vec<u8>;
 type ExpirationEntry = Reverse<(Instant, Key)>;
 type ExpirationHeap = BinaryHeap<ExpirationEntry>;
+type Watchers = HashMap<Key, Vec<Arc<AtomicBool>>>;
+type ListWaiters = HashMap<Key, VecDeque<ListWaiter>>;
+/// Where a blocked pop's result is sent. Whoever takes the sender out first,
+/// a push serving the waiter or the waiter giving up, decides the outcome.
+type WaiterSlot = Arc<Mutex<Option<oneshot::Sender<PopResult>>>>;
+type PopResult = Result<(Key, Vec<u8>), StoreError>;
+/// Pops made for blocking commands, or `None` when they are not recorded.
+type ServedPops = Option<Vec<ServedPop>>;
 
 #[derive(Clone)]
 pub struct Store {
-    hashmap: Arc<RwLock<HashMap<Vec<u8>, StoreValue>>>,
+    hashmap: Arc<RwLock<Keyspace>>,
     expiration_heap: Arc<RwLock<ExpirationHeap>>,
     wakeup: Arc<Notify>,
+    transaction_lock: Arc<RwLock<()>>,
+    watchers: Arc<Mutex<Watchers>>,
+    notifier: Arc<StdRwLock<Option<KeyspaceNotifier>>>,
+    list_waiters: Arc<Mutex<ListWaiters>>,
+    served_pops: Arc<Mutex<ServedPops>>,
+    /// Writes made since the store was last saved.
+    dirty: Arc<AtomicU64>,
 }
 
 impl Store {
     pub fn new() -> Store {
-        Self::from_parts(HashMap::new(), ExpirationHeap::new())
+        Self::from_parts(Keyspace::default(), ExpirationHeap::new())
     }
 
-    fn from_parts(hashmap: HashMap<Vec<u8>, StoreValue>, expiration_heap: ExpirationHeap) -> Store {
+    fn from_parts(hashmap: Keyspace, expiration_heap: ExpirationHeap) -> Store {
         let new_store = Store {
             hashmap: Arc::new(RwLock::new(hashmap)),
             expiration_heap: Arc::new(RwLock::new(expiration_heap)),
             wakeup: Arc::new(Notify::new()),
+            transaction_lock: Arc::new(RwLock::new(())),
+            watchers: Arc::new(Mutex::new(HashMap::new())),
+            notifier: Arc::new(StdRwLock::new(None)),
+            list_waiters: Arc::new(Mutex::new(HashMap::new())),
+            served_pops: Arc::new(Mutex::new(None)),
+            dirty: Arc::new(AtomicU64::new(0)),
         };
         let sweep_store = new_store.clone();
         spawn(async move {
@@ -73,50 +105,203 @@ impl Store {
             if let Some(v) = map.get(&key)
                 && Store::is_expired(v, now)
             {
-                map.remove_entry(&key);
+                map.remove(&key);
+                self.modified(&key, EventClass::Expired, "expired");
             }
         }
         drop(map);
     }
 
+    /// Waits until no transaction is executing and returns a guard that keeps
+    /// transactions out until it is dropped.
+    ///
+    /// Connections hold this while running a single command, so that a
+    /// transaction never observes a command half way through.
+    pub async fn command_guard(&self) -> OwnedRwLockReadGuard<()> {
+        self.transaction_lock.clone().read_owned().await
+    }
+
+    /// Waits for running commands to finish and returns a guard under which
+    /// a transaction's commands run without interleaving with other
+    /// connections.
+    pub async fn transaction_guard(&self) -> OwnedRwLockWriteGuard<()> {
+        self.transaction_lock.clone().write_owned().await
+    }
+
+    /// Starts watching no keys. Add keys with [`Watch::add`].
+    pub fn watch(&self) -> Watch {
+        Watch {
+            store: self.clone(),
+            keys: Vec::new(),
+            dirty: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Publishes keyspace notifications about `key` to `hub` for the events
+    /// enabled in `events`.
+    pub fn notify_keyspace_events(&self, hub: Hub, events: KeyspaceEvents) {
+        *self.notifier.write().unwrap() = Some(KeyspaceNotifier::new(hub, events));
+    }
+
+    /// Starts recording the pops made for blocking commands, so they can be
+    /// logged as plain pops. Collect them with [`Store::take_served_pops`].
+    pub fn record_served_pops(&self) {
+        self.served_pops
+            .lock()
+            .unwrap()
+            .get_or_insert_with(Vec::new);
+    }
+
+    /// Returns the pops made for blocking commands since the last call, in
+    /// the order they happened. Always empty unless recording was started.
+    pub fn take_served_pops(&self) -> Vec<ServedPop> {
+        self.served_pops
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of writes made since the store was created or
+    /// last saved.
+    pub fn dirty(&self) -> u64 {
+        self.dirty.load(Ordering::Acquire)
+    }
+
+    /// Marks `saved` writes as saved, where `saved` is what [`Store::dirty`]
+    /// returned when the save began. Writes made during the save still count.
+    pub fn mark_saved(&self, saved: u64) {
+        self.dirty.fetch_sub(saved, Ordering::AcqRel);
+    }
+
+    fn served(&self, key: &[u8], end: ListEnd, destination: Option<&(Key, ListEnd)>, value: &[u8]) {
+        if let Some(pops) = self.served_pops.lock().unwrap().as_mut() {
+            pops.push(ServedPop {
+                key: key.to_vec(),
+                end,
+                destination: destination.cloned(),
+                value: value.to_vec(),
+            });
+        }
+    }
+
+    /// Records that `event` changed `key`: counts the write towards
+    /// [`Store::dirty`], marks every [`Watch`] on it as dirty and sends any
+    /// enabled keyspace notifications.
+    fn modified(&self, key: &[u8], class: EventClass, event: &str) {
+        self.dirty.fetch_add(1, Ordering::AcqRel);
+        let watchers = self.watchers.lock().unwrap();
+        for dirty in watchers.get(key).into_iter().flatten() {
+            dirty.store(true, Ordering::Release);
+        }
+        drop(watchers);
+        if let Some(notifier) = self.notifier.read().unwrap().as_ref() {
+            notifier.notify(class, event, key);
+        }
+    }
+
+    /// Removes `key` after a write emptied its collection, since the store
+    /// never holds an empty list, hash, set or sorted set.
+    fn remove_emptied(&self, map: &mut Keyspace, key: &[u8]) {
+        map.remove(key);
+        self.modified(key, EventClass::Generic, "del");
+    }
+
     /// Returns the value for `key`, or `None` if the key is missing or expired.
-    pub async fn get(&self, key: &Key) -> Option<Vec<u8>> {
+    ///
+    /// Returns [`StoreError::WrongType`] if the key holds a non-string value.
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
         let map = self.hashmap.read().await;
         let now = Instant::now();
-        match map.get(key) {
-            None => None,
-            Some(v) if Store::is_expired(v, now) => None,
+        match Store::live_entry(&map, key, now) {
+            None => Ok(None),
             Some(StoreValue {
-                value: v,
-                expiration_time: _,
-            }) => Some(v.to_vec()),
+                value: Value::String(v),
+                ..
+            }) => Ok(Some(v.to_vec())),
+            Some(_) => Err(StoreError::WrongType),
         }
     }
 
-    /// Sets `key` to `value`, returning the previous value if one existed.
+    /// Sets `key` to `value` according to `options`.
     ///
-    /// Any existing expiration on the key is cleared.
-    pub async fn set(&self, key: Key, value: Vec<u8>) -> Option<Vec<u8>> {
+    /// Values of any type are replaced. Unless `options` asks to keep it, any
+    /// existing expiration on the key is cleared. The returned [`SetOutcome`]
+    /// reports whether the write happened and the previous string value.
+    ///
+    /// Returns [`StoreError::WrongType`] without writing if `options.get` is
+    /// set and the key holds a non-string value.
+    pub async fn set(
+        &self,
+        key: Key,
+        value: Vec<u8>,
+        options: SetOptions,
+    ) -> Result<SetOutcome, StoreError> {
+        let now = Instant::now();
         let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, &key, now);
+        let existing = map.get(&key);
+        let previous = match existing {
+            Some(StoreValue {
+                value: Value::String(v),
+                ..
+            }) => Some(v.clone()),
+            Some(_) if options.get => return Err(StoreError::WrongType),
+            _ => None,
+        };
+        let allowed = match options.condition {
+            None => true,
+            Some(SetCondition::IfMissing) => existing.is_none(),
+            Some(SetCondition::IfExists) => existing.is_some(),
+        };
+        if !allowed {
+            return Ok(SetOutcome {
+                written: false,
+                previous,
+            });
+        }
+
+        let (expiration_time, needs_heap_entry) = match options.expiration {
+            None => (None, false),
+            Some(SetExpiration::Keep) => (existing.and_then(|v| v.expiration_time), false),
+            Some(SetExpiration::After(duration)) => {
+                (Some(Expiration::After(duration).deadline(now)), true)
+            }
+            Some(SetExpiration::AtUnixMillis(millis)) => {
+                (Some(Expiration::AtUnixMillis(millis).deadline(now)), true)
+            }
+        };
         map.insert(
-            key,
+            key.clone(),
             StoreValue {
-                value: value.clone(),
-                expiration_time: None,
+                value: Value::String(value),
+                expiration_time,
             },
-        )
-        .map(
-            |StoreValue {
-                 value: v,
-                 expiration_time: _,
-             }| v.to_vec(),
-        )
+        );
+        self.modified(&key, EventClass::String, "set");
+        // The heap lock is always taken before the map lock elsewhere, so the
+        // map must be released before registering the new expiration.
+        drop(map);
+        if let Some(expires) = expiration_time
+            && needs_heap_entry
+        {
+            self.expiration_heap
+                .write()
+                .await
+                .push(Reverse((expires, key)));
+            self.wakeup.notify_one();
+        }
+        Ok(SetOutcome {
+            written: true,
+            previous,
+        })
     }
 
     /// Deletes `key`, returning the stored value if it existed and was not expired.
     ///
     /// Expired keys are treated as absent.
-    pub async fn del(&self, key: &Key) -> Option<Vec<u8>> {
+    pub async fn del(&self, key: &[u8]) -> Option<Value> {
         let now = Instant::now();
         let mut map = self.hashmap.write().await;
         match map.remove(key) {
@@ -124,324 +309,3813 @@ impl Store {
 
             None => None,
 
-            Some(StoreValue { value, .. }) => Some(value.to_vec()),
+            Some(StoreValue { value, .. }) => {
+                self.modified(key, EventClass::Generic, "del");
+                Some(value)
+            }
         }
     }
 
-    /// Sets a timeout in seconds on `key`.
+    /// Deletes every key in `keys` under a single write lock and returns how
+    /// many of them existed.
     ///
-    /// Returns `1` if the timeout was set, or `0` if the key does not exist
-    /// or is already expired.
-    pub async fn expire(&self, key: Key, ttl: u64) -> u64 {
-        let mut heap = self.expiration_heap.write().await;
-        let mut map = self.hashmap.write().await;
+    /// Expired keys are treated as absent.
+    pub async fn del_many(&self, keys: &[impl AsRef<[u8]>]) -> usize {
         let now = Instant::now();
-        let ttl_duration = Duration::new(ttl, 0);
-        match map.remove_entry(&key) {
-            Some(v) if Store::is_expired(&v.1, now) => 0,
-
-            None => 0,
-
-            Some((k, store_value)) => {
-                let expires = now + ttl_duration;
-                map.insert(
-                    k.clone(),
-                    StoreValue {
-                        expiration_time: Some(expires),
-                        ..store_value
-                    },
-                );
-                heap.push(Reverse((expires, k)));
-                self.wakeup.notify_one();
-                1
-            }
-        }
+        let mut map = self.hashmap.write().await;
+        keys.iter()
+            .filter(|key| {
+                let removed = map
+                    .remove(key.as_ref())
+                    .is_some_and(|v| !Store::is_expired(&v, now));
+                if removed {
+                    self.modified(key.as_ref(), EventClass::Generic, "del");
+                }
+                removed
+            })
+            .count()
     }
 
-    /// Returns the remaining time to live for `key` in whole seconds.
-    ///
-    /// Returns:
-    /// - `-2` if the key does not exist or is expired
-    /// - `-1` if the key exists but has no expiration
-    /// - a non-negative number for the remaining TTL
-    pub async fn ttl(&self, key: Key) -> i64 {
+    /// Returns how many of `keys` exist. Keys named more than once are
+    /// counted each time.
+    pub async fn exists(&self, keys: &[impl AsRef<[u8]>]) -> usize {
         let map = self.hashmap.read().await;
         let now = Instant::now();
-        match map.get(key.as_slice()) {
-            None => -2,
-            Some(v) if Store::is_expired(v, now) => -2,
-            Some(StoreValue {
-                value: _,
-                expiration_time: None,
-            }) => -1,
-            Some(StoreValue {
-                value: _,
-                expiration_time: Some(expires_on),
-            }) => expires_on.duration_since(now).as_secs() as i64,
-        }
-    }
-
-    fn is_expired(value: &StoreValue, now: Instant) -> bool {
-        matches!(value.expiration_time, Some(t) if t <= now)
+        keys.iter()
+            .filter(|key| Store::live_entry(&map, key.as_ref(), now).is_some())
+            .count()
     }
 
-    async fn to_snapshot(&self) -> Snapshot {
+    /// Returns the string value of each key in `keys`, in order.
+    ///
+    /// Missing keys and keys holding other types yield `None`.
+    pub async fn mget(&self, keys: &[impl AsRef<[u8]>]) -> Vec<Option<Vec<u8>>> {
+        let map = self.hashmap.read().await;
         let now = Instant::now();
-        Snapshot {
-            entries: self
-                .hashmap
-                .read()
-                .await
-                .iter()
-                .filter(|(_, v)| !Store::is_expired(v, now))
-                .map(|(key, value)| SnapshotEntry {
-                    key: key.clone(),
-                    value: value.into(),
-                })
-                .collect(),
-        }
+        keys.iter()
+            .map(|key| match Store::live_entry(&map, key.as_ref(), now) {
+                Some(StoreValue {
+                    value: Value::String(v),
+                    ..
+                }) => Some(v.clone()),
+                _ => None,
+            })
+            .collect()
     }
 
-    async fn from_snapshot(snapshot: Snapshot) -> Result<Store, SnapshotError> {
-        let now_unix_millis = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("System Time is set before Unix Epoch")
-            .as_millis();
-
-        let mut unique_keys: HashSet<Vec<u8>> = HashSet::new();
-        for entry in snapshot.entries.iter() {
-            if !unique_keys.insert(entry.key.clone()) {
-                return Err(SnapshotError::DuplicateKey);
-            }
+    /// Sets every key in `pairs` under a single write lock, so other
+    /// connections see either none or all of the writes.
+    ///
+    /// Like [`Store::set`], values of any type are replaced and expirations
+    /// are cleared. Later pairs win when a key is repeated.
+    pub async fn mset(&self, pairs: Vec<(Key, Vec<u8>)>) {
+        let mut map = self.hashmap.write().await;
+        for (key, value) in pairs {
+            self.modified(&key, EventClass::String, "set");
+            map.insert(
+                key,
+                StoreValue {
+                    value: Value::String(value),
+                    expiration_time: None,
+                },
+            );
         }
+    }
 
-        let hashmap: HashMap<Vec<u8>, StoreValue> = snapshot
-            .entries
-            .into_iter()
-            .filter(|snapshot_entry| {
-                snapshot_entry.value.expiration_time_unix.is_none()
-                    || snapshot_entry.value.expiration_time_unix.unwrap() > now_unix_millis
-            })
-            .map(
-                |SnapshotEntry { key, value }| -> Result<(Vec<u8>, StoreValue), SnapshotError> {
-                    Ok((key, value.try_into()?))
-                },
-            )
-            .collect::<Result<_, _>>()?;
-        let expiration_heap: ExpirationHeap = hashmap
+    /// Sets every key in `pairs` only if none of them exist, returning whether
+    /// the write happened.
+    pub async fn msetnx(&self, pairs: Vec<(Key, Vec<u8>)>) -> bool {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        if pairs
             .iter()
-            .filter_map(|(key, store_value)| match store_value {
+            .any(|(key, _)| Store::live_entry(&map, key, now).is_some())
+        {
+            return false;
+        }
+        for (key, value) in pairs {
+            self.modified(&key, EventClass::String, "set");
+            map.insert(
+                key,
                 StoreValue {
+                    value: Value::String(value),
                     expiration_time: None,
-                    ..
-                } => None,
-                StoreValue {
-                    expiration_time: Some(expiration_instant),
-                    ..
-                } => Some(Reverse((*expiration_instant, key.clone()))),
-            })
-            .collect();
-        Ok(Store::from_parts(hashmap, expiration_heap))
+                },
+            );
+        }
+        true
     }
 
-    pub async fn dump(&self) -> Result<Vec<u8>, serde_json::Error> {
-        serde_json::to_vec(&self.to_snapshot().await)
+    /// Returns the type name of the value at `key`, or `None` if it is missing.
+    pub async fn key_type(&self, key: &[u8]) -> Option<&'static str> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        Store::live_entry(&map, key, now).map(|v| v.value.type_name())
     }
 
-    pub async fn restore(bytes: &[u8]) -> Result<Store, RestoreError> {
-        let snapshot: Snapshot = serde_json::from_slice(bytes)?;
-        Store::from_snapshot(snapshot).await.map_err(Into::into)
+    /// Moves the value at `key` to `new_key`, replacing anything stored there.
+    ///
+    /// The expiration moves with the value. Returns [`StoreError::NoSuchKey`]
+    /// if `key` is missing or expired.
+    pub async fn rename(&self, key: &[u8], new_key: Key) -> Result<(), StoreError> {
+        self.rename_if(key, new_key, false).await.map(|_| ())
     }
-}
 
-impl Default for Store {
-    fn default() -> Self {
-        Store::new()
+    /// Like [`Store::rename`], but only moves the value if `new_key` does not
+    /// exist, returning whether it did.
+    pub async fn renamenx(&self, key: &[u8], new_key: Key) -> Result<bool, StoreError> {
+        self.rename_if(key, new_key, true).await
     }
-}
 
-#[derive(Debug, Clone)]
-struct StoreValue {
-    value: Vec<u8>,
-    expiration_time: Option<Instant>,
-}
+    async fn rename_if(
+        &self,
+        key: &[u8],
+        new_key: Key,
+        only_if_missing: bool,
+    ) -> Result<bool, StoreError> {
+        let mut heap = self.expiration_heap.write().await;
+        let mut map = self.hashmap.write().await;
+        let now = Instant::now();
+        Store::purge_if_expired(&mut map, key, now);
+        Store::purge_if_expired(&mut map, &new_key, now);
+        if !map.contains_key(key) {
+            return Err(StoreError::NoSuchKey);
+        }
+        if key == new_key {
+            return Ok(!only_if_missing);
+        }
+        if only_if_missing && map.contains_key(&new_key) {
+            return Ok(false);
+        }
+        let value = map.remove(key).expect("key checked above");
+        self.modified(key, EventClass::Generic, "rename_from");
+        self.modified(&new_key, EventClass::Generic, "rename_to");
+        if let Some(expires) = value.expiration_time {
+            heap.push(Reverse((expires, new_key.clone())));
+            self.wakeup.notify_one();
+        }
+        map.insert(new_key.clone(), value);
+        self.serve_list_waiters(&mut map, new_key, now);
+        Ok(true)
+    }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-#[serde(deny_unknown_fields)]
-struct SnapshotValue {
-    #[serde(with = "serde_bytes")]
-    value: Vec<u8>,
-    expiration_time_unix: Option<u128>,
-}
+    /// Copies the value and expiration at `source` to `destination`,
+    /// returning whether the copy happened.
+    ///
+    /// Nothing is copied if `source` is missing, or if `destination` exists
+    /// and `replace` is false.
+    pub async fn copy(&self, source: &[u8], destination: Key, replace: bool) -> bool {
+        let mut heap = self.expiration_heap.write().await;
+        let mut map = self.hashmap.write().await;
+        let now = Instant::now();
+        Store::purge_if_expired(&mut map, &destination, now);
+        let Some(value) = Store::live_entry(&map, source, now).cloned() else {
+            return false;
+        };
+        if source == destination || (!replace && map.contains_key(&destination)) {
+            return false;
+        }
+        if let Some(expires) = value.expiration_time {
+            heap.push(Reverse((expires, destination.clone())));
+            self.wakeup.notify_one();
+        }
+        self.modified(&destination, EventClass::Generic, "copy_to");
+        map.insert(destination.clone(), value);
+        self.serve_list_waiters(&mut map, destination, now);
+        true
+    }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-#[serde(deny_unknown_fields)]
-struct SnapshotEntry {
-    #[serde(with = "serde_bytes")]
-    key: Vec<u8>,
-    value: SnapshotValue,
-}
+    /// Returns a key chosen at random, or `None` if the store is empty.
+    pub async fn random_key(&self) -> Option<Key> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        let live = map.iter().filter(|(_, v)| !Store::is_expired(v, now));
+        let count = live.clone().count();
+        if count == 0 {
+            return None;
+        }
+        // Each `RandomState` is seeded differently, which is all the
+        // randomness needed to pick an index.
+        let seed = RandomState::new().build_hasher().finish();
+        live.map(|(k, _)| k)
+            .nth((seed % count as u64) as usize)
+            .cloned()
+    }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-#[serde(deny_unknown_fields)]
-struct Snapshot {
-    entries: Vec<SnapshotEntry>,
-}
+    /// Returns the number of keys that have not expired.
+    pub async fn dbsize(&self) -> usize {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        map.values().filter(|v| !Store::is_expired(v, now)).count()
+    }
 
-#[derive(Debug)]
-pub enum SnapshotError {
-    DurationOverflow,
-    DuplicateKey,
-}
+    /// Returns every live key matching the glob `pattern`.
+    pub async fn keys(&self, pattern: &[u8]) -> Vec<Key> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        map.iter()
+            .filter(|(k, v)| !Store::is_expired(v, now) && glob_match(pattern, k))
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
 
-impl fmt::Display for SnapshotError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            SnapshotError::DurationOverflow => {
-                write!(f, "snapshot expiration exceeds supported duration")
+    /// Returns the next batch of keys for a `SCAN` starting at `cursor`, and
+    /// the cursor to continue from, which is `0` once the scan is complete.
+    ///
+    /// Keys are visited in order of a fixed hash of the key bytes, which the
+    /// store keeps an index of, and the cursor is the next hash to visit. Because that order does not depend on
+    /// the map's layout, every key present for the whole scan is returned
+    /// at least once no matter how the map grows or shrinks in between calls.
+    /// Each call looks at about `count` keys before applying `filter`, and
+    /// never splits keys that share a hash across two batches.
+    pub async fn scan(&self, cursor: u64, count: usize, filter: &ScanFilter) -> (u64, Vec<Key>) {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        let count = count.max(1);
+        let mut visited = 0;
+        let mut last_hash = None;
+        let mut keys = Vec::new();
+        for (hash, key) in map.scan_from(cursor) {
+            if visited >= count && last_hash != Some(hash) {
+                break;
             }
-            SnapshotError::DuplicateKey => {
-                write!(f, "snapshot contains duplicate keys")
+            let value = &map[key];
+            if Store::is_expired(value, now) {
+                continue;
+            }
+            visited += 1;
+            last_hash = Some(hash);
+            if filter.matches(key, &value.value) {
+                keys.push(key.clone());
             }
         }
-    }
-}
-
-impl std::error::Error for SnapshotError {}
-
-#[derive(Debug)]
-pub enum RestoreError {
-    InvalidSnapshot(serde_json::Error),
-    InvalidData(SnapshotError),
-}
 
-impl fmt::Display for RestoreError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            RestoreError::InvalidSnapshot(err) => write!(f, "{err}"),
-            RestoreError::InvalidData(err) => write!(f, "{err}"),
-        }
+        let next_cursor = match last_hash {
+            Some(last_hash) if visited >= count => last_hash.wrapping_add(1),
+            _ => 0,
+        };
+        (next_cursor, keys)
     }
-}
 
-impl std::error::Error for RestoreError {}
+    /// Adds `increment` to the integer stored at `key` and returns the result.
+    ///
+    /// Missing keys start at `0`. The read, update and write happen under a
+    /// single write lock, and any expiration on the key is kept.
+    pub async fn incr_by(&self, key: Key, increment: i64) -> Result<i64, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, &key, now);
+        let entry = map.get_or_insert_with(key.clone(), || StoreValue {
+            value: Value::String(b"0".to_vec()),
+            expiration_time: None,
+        });
+        let Value::String(value) = &mut entry.value else {
+            return Err(StoreError::WrongType);
+        };
+        let current = parse_integer(value).ok_or(StoreError::NotInteger)?;
+        let updated = current.checked_add(increment).ok_or(StoreError::Overflow)?;
+        *value = updated.to_string().into_bytes();
+        self.modified(&key, EventClass::String, "incrby");
+        Ok(updated)
+    }
 
-impl From<serde_json::Error> for RestoreError {
-    fn from(value: serde_json::Error) -> Self {
-        RestoreError::InvalidSnapshot(value)
+    /// Adds `increment` to the floating point number stored at `key` and
+    /// returns the result.
+    ///
+    /// Behaves like [`Store::incr_by`], except that results which are not
+    /// finite are rejected and leave the value unchanged.
+    pub async fn incr_by_float(&self, key: Key, increment: f64) -> Result<f64, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, &key, now);
+        let entry = map.get_or_insert_with(key.clone(), || StoreValue {
+            value: Value::String(b"0".to_vec()),
+            expiration_time: None,
+        });
+        let Value::String(value) = &mut entry.value else {
+            return Err(StoreError::WrongType);
+        };
+        let current = parse_float(value).ok_or(StoreError::NotFloat)?;
+        let updated = current + increment;
+        if !updated.is_finite() {
+            return Err(StoreError::NonFiniteResult);
+        }
+        *value = updated.to_string().into_bytes();
+        self.modified(&key, EventClass::String, "incrbyfloat");
+        Ok(updated)
     }
-}
+
+    /// Pushes `values` onto one end of the list at `key`, creating the list if
+    /// the key is missing.
+    ///
+    /// Values are pushed one at a time, so `LPUSH k a b` leaves `b` at the head.
+    /// Returns the length of the list after the push.
+    pub async fn push(
+        &self,
+        key: Key,
+        values: Vec<Vec<u8>>,
+        end: ListEnd,
+    ) -> Result<usize, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, &key, now);
+        let entry = map.get_or_insert_with(key.clone(), || StoreValue {
+            value: Value::List(VecDeque::new()),
+            expiration_time: None,
+        });
+        let Value::List(list) = &mut entry.value else {
+            return Err(StoreError::WrongType);
+        };
+        for value in values {
+            match end {
+                ListEnd::Left => list.push_front(value),
+                ListEnd::Right => list.push_back(value),
+            }
+        }
+        let event = match end {
+            ListEnd::Left => "lpush",
+            ListEnd::Right => "rpush",
+        };
+        self.modified(&key, EventClass::List, event);
+        let len = list.len();
+        self.serve_list_waiters(&mut map, key, now);
+        Ok(len)
+    }
+
+    /// Removes and returns up to `count` values from one end of the list at `key`.
+    ///
+    /// Returns `None` if the key is missing or expired. The key is removed once
+    /// its list is empty.
+    pub async fn pop(
+        &self,
+        key: &[u8],
+        end: ListEnd,
+        count: usize,
+    ) -> Result<Option<Vec<Vec<u8>>>, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, key, now);
+        let Some(entry) = map.get_mut(key) else {
+            return Ok(None);
+        };
+        let Value::List(list) = &mut entry.value else {
+            return Err(StoreError::WrongType);
+        };
+        let count = count.min(list.len());
+        if count == 0 {
+            return Ok(Some(Vec::new()));
+        }
+        let popped = match end {
+            ListEnd::Left => list.drain(..count).collect(),
+            ListEnd::Right => list.drain(list.len() - count..).rev().collect(),
+        };
+        let event = match end {
+            ListEnd::Left => "lpop",
+            ListEnd::Right => "rpop",
+        };
+        self.modified(key, EventClass::List, event);
+        if list.is_empty() {
+            self.remove_emptied(&mut map, key);
+        }
+        Ok(Some(popped))
+    }
+
+    /// Pops one value from the first non-empty list among `keys`, or
+    /// registers to receive one as soon as any of them gets data.
+    ///
+    /// With a `destination`, the popped value is pushed onto that end of the
+    /// destination list in the same step, as `LMOVE` does. Waiters are served
+    /// one value each, in the order they blocked.
+    ///
+    /// Returns [`StoreError::WrongType`] if a key, or the destination, holds
+    /// a non-list value.
+    pub async fn pop_or_block(
+        &self,
+        keys: &[impl AsRef<[u8]>],
+        end: ListEnd,
+        destination: Option<(Key, ListEnd)>,
+    ) -> Result<BlockingPop, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        for key in keys {
+            let key = key.as_ref();
+            if let Some(value) = self.pop_one(&mut map, key, end, destination.as_ref(), now)? {
+                self.served(key, end, destination.as_ref(), &value);
+                if let Some((destination, _)) = destination {
+                    self.serve_list_waiters(&mut map, destination, now);
+                }
+                return Ok(BlockingPop::Ready(key.to_vec(), value));
+            }
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        let slot: WaiterSlot = Arc::new(Mutex::new(Some(sender)));
+        let mut waiting_keys: Vec<Key> = Vec::new();
+        let mut waiters = self.list_waiters.lock().unwrap();
+        for key in keys {
+            let key = key.as_ref();
+            if waiting_keys.iter().any(|waiting| waiting == key) {
+                continue;
+            }
+            waiters
+                .entry(key.to_vec())
+                .or_default()
+                .push_back(ListWaiter {
+                    end,
+                    destination: destination.clone(),
+                    slot: slot.clone(),
+                });
+            waiting_keys.push(key.to_vec());
+        }
+        Ok(BlockingPop::Blocked(ListWait {
+            store: self.clone(),
+            keys: waiting_keys,
+            slot,
+            receiver,
+        }))
+    }
+
+    /// Pops one value from the list at `key` and pushes it to `destination`
+    /// if given, under the caller's write lock.
+    ///
+    /// Returns `None` without changing anything if `key` is missing.
+    fn pop_one(
+        &self,
+        map: &mut Keyspace,
+        key: &[u8],
+        end: ListEnd,
+        destination: Option<&(Key, ListEnd)>,
+        now: Instant,
+    ) -> Result<Option<Vec<u8>>, StoreError> {
+        Store::purge_if_expired(map, key, now);
+        match map.get(key) {
+            None => return Ok(None),
+            Some(StoreValue {
+                value: Value::List(_),
+                ..
+            }) => {}
+            Some(_) => return Err(StoreError::WrongType),
+        }
+        if let Some((destination, _)) = destination {
+            Store::purge_if_expired(map, destination, now);
+            if let Some(v) = map.get(destination)
+                && !matches!(v.value, Value::List(_))
+            {
+                return Err(StoreError::WrongType);
+            }
+        }
+
+        let Some(StoreValue {
+            value: Value::List(list),
+            ..
+        }) = map.get_mut(key)
+        else {
+            unreachable!("key checked above");
+        };
+        let (value, event) = match end {
+            ListEnd::Left => (list.pop_front(), "lpop"),
+            ListEnd::Right => (list.pop_back(), "rpop"),
+        };
+        let value = value.expect("empty lists are removed");
+        let emptied = list.is_empty();
+        self.modified(key, EventClass::List, event);
+        if emptied {
+            self.remove_emptied(map, key);
+        }
+
+        if let Some((destination, to)) = destination {
+            let entry = map.get_or_insert_with(destination.clone(), || StoreValue {
+                value: Value::List(VecDeque::new()),
+                expiration_time: None,
+            });
+            let Value::List(list) = &mut entry.value else {
+                unreachable!("destination checked above");
+            };
+            let event = match to {
+                ListEnd::Left => {
+                    list.push_front(value.clone());
+                    "lpush"
+                }
+                ListEnd::Right => {
+                    list.push_back(value.clone());
+                    "rpush"
+                }
+            };
+            self.modified(destination, EventClass::List, event);
+        }
+        Ok(Some(value))
+    }
+
+    /// Hands values from the list at `key` to blocked waiters, oldest first,
+    /// until the list or the waiters run out. Values moved onto another list
+    /// serve that list's waiters in turn.
+    fn serve_list_waiters(&self, map: &mut Keyspace, key: Key, now: Instant) {
+        let mut ready = vec![key];
+        while let Some(key) = ready.pop() {
+            while Store::live_entry(map, &key, now)
+                .is_some_and(|v| matches!(v.value, Value::List(_)))
+            {
+                let waiter = {
+                    let mut waiters = self.list_waiters.lock().unwrap();
+                    let Some(queue) = waiters.get_mut(&key) else {
+                        break;
+                    };
+                    let waiter = queue.pop_front();
+                    if queue.is_empty() {
+                        waiters.remove(&key);
+                    }
+                    match waiter {
+                        Some(waiter) => waiter,
+                        None => break,
+                    }
+                };
+                // Holding the slot until the result is sent means a waiter
+                // that gives up either sees nothing or finds the result in
+                // its channel.
+                let mut slot = waiter.slot.lock().unwrap();
+                let Some(sender) = slot.take() else {
+                    continue;
+                };
+                let result = self.pop_one(map, &key, waiter.end, waiter.destination.as_ref(), now);
+                if let Ok(Some(value)) = &result {
+                    self.served(&key, waiter.end, waiter.destination.as_ref(), value);
+                    if let Some((destination, _)) = &waiter.destination {
+                        ready.push(destination.clone());
+                    }
+                }
+                let result = result.map(|value| (key.clone(), value.expect("list checked above")));
+                let _ = sender.send(result);
+            }
+        }
+    }
+
+    /// Returns the elements of the list at `key` between `start` and `stop`,
+    /// inclusive.
+    ///
+    /// Negative indexes count back from the tail, and out of range indexes are
+    /// clamped to the list. Missing keys behave like empty lists.
+    pub async fn lrange(
+        &self,
+        key: &[u8],
+        start: i64,
+        stop: i64,
+    ) -> Result<Vec<Vec<u8>>, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => Ok(Vec::new()),
+            Some(StoreValue {
+                value: Value::List(list),
+                ..
+            }) => Ok(match normalize_range(start, stop, list.len()) {
+                Some((first, last)) => list.range(first..=last).cloned().collect(),
+                None => Vec::new(),
+            }),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Returns the length of the list at `key`, or `0` if the key is missing.
+    pub async fn llen(&self, key: &[u8]) -> Result<usize, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => Ok(0),
+            Some(StoreValue {
+                value: Value::List(list),
+                ..
+            }) => Ok(list.len()),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Sets each `(field, value)` pair in the hash at `key`, creating the hash
+    /// if the key is missing.
+    ///
+    /// Returns the number of fields that were newly added.
+    pub async fn hset(
+        &self,
+        key: Key,
+        fields: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<usize, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, &key, now);
+        let entry = map.get_or_insert_with(key.clone(), || StoreValue {
+            value: Value::Hash(HashMap::new()),
+            expiration_time: None,
+        });
+        let Value::Hash(hash) = &mut entry.value else {
+            return Err(StoreError::WrongType);
+        };
+        let mut added = 0;
+        for (field, value) in fields {
+            if hash.insert(field, value).is_none() {
+                added += 1;
+            }
+        }
+        self.modified(&key, EventClass::Hash, "hset");
+        Ok(added)
+    }
+
+    /// Returns the value of `field` in the hash at `key`.
+    pub async fn hget(&self, key: &[u8], field: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => Ok(None),
+            Some(StoreValue {
+                value: Value::Hash(hash),
+                ..
+            }) => Ok(hash.get(field).cloned()),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Removes `fields` from the hash at `key`, returning how many existed.
+    ///
+    /// The key is removed once its hash is empty.
+    pub async fn hdel(&self, key: &[u8], fields: &[impl AsRef<[u8]>]) -> Result<usize, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, key, now);
+        let Some(entry) = map.get_mut(key) else {
+            return Ok(0);
+        };
+        let Value::Hash(hash) = &mut entry.value else {
+            return Err(StoreError::WrongType);
+        };
+        let removed = fields
+            .iter()
+            .filter(|field| hash.remove(field.as_ref()).is_some())
+            .count();
+        if removed > 0 {
+            self.modified(key, EventClass::Hash, "hdel");
+        }
+        if hash.is_empty() {
+            self.remove_emptied(&mut map, key);
+        }
+        Ok(removed)
+    }
+
+    /// Returns every `(field, value)` pair in the hash at `key`.
+    pub async fn hgetall(&self, key: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => Ok(Vec::new()),
+            Some(StoreValue {
+                value: Value::Hash(hash),
+                ..
+            }) => Ok(hash
+                .iter()
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect()),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Adds `increment` to the integer stored in `field` of the hash at `key`.
+    ///
+    /// Missing keys and fields start at `0`. Returns the new value.
+    pub async fn hincrby(
+        &self,
+        key: Key,
+        field: Vec<u8>,
+        increment: i64,
+    ) -> Result<i64, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, &key, now);
+        let entry = map.get_or_insert_with(key.clone(), || StoreValue {
+            value: Value::Hash(HashMap::new()),
+            expiration_time: None,
+        });
+        let Value::Hash(hash) = &mut entry.value else {
+            return Err(StoreError::WrongType);
+        };
+        let current = match hash.get(&field) {
+            None => 0,
+            Some(v) => parse_integer(v).ok_or(StoreError::NotInteger)?,
+        };
+        let updated = current.checked_add(increment).ok_or(StoreError::Overflow)?;
+        hash.insert(field, updated.to_string().into_bytes());
+        self.modified(&key, EventClass::Hash, "hincrby");
+        Ok(updated)
+    }
+
+    /// Returns the number of fields in the hash at `key`.
+    pub async fn hlen(&self, key: &[u8]) -> Result<usize, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => Ok(0),
+            Some(StoreValue {
+                value: Value::Hash(hash),
+                ..
+            }) => Ok(hash.len()),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Returns whether `field` exists in the hash at `key`.
+    pub async fn hexists(&self, key: &[u8], field: &[u8]) -> Result<bool, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => Ok(false),
+            Some(StoreValue {
+                value: Value::Hash(hash),
+                ..
+            }) => Ok(hash.contains_key(field)),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Adds `members` to the set at `key`, creating the set if the key is
+    /// missing.
+    ///
+    /// Returns the number of members that were not already present.
+    pub async fn sadd(&self, key: Key, members: Vec<Vec<u8>>) -> Result<usize, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, &key, now);
+        let entry = map.get_or_insert_with(key.clone(), || StoreValue {
+            value: Value::Set(HashSet::new()),
+            expiration_time: None,
+        });
+        let Value::Set(set) = &mut entry.value else {
+            return Err(StoreError::WrongType);
+        };
+        let mut added = 0;
+        for member in members {
+            if set.insert(member) {
+                added += 1;
+            }
+        }
+        if added > 0 {
+            self.modified(&key, EventClass::Set, "sadd");
+        }
+        Ok(added)
+    }
+
+    /// Removes `members` from the set at `key`, returning how many existed.
+    ///
+    /// The key is removed once its set is empty.
+    pub async fn srem(
+        &self,
+        key: &[u8],
+        members: &[impl AsRef<[u8]>],
+    ) -> Result<usize, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, key, now);
+        let Some(entry) = map.get_mut(key) else {
+            return Ok(0);
+        };
+        let Value::Set(set) = &mut entry.value else {
+            return Err(StoreError::WrongType);
+        };
+        let removed = members
+            .iter()
+            .filter(|member| set.remove(member.as_ref()))
+            .count();
+        if removed > 0 {
+            self.modified(key, EventClass::Set, "srem");
+        }
+        if set.is_empty() {
+            self.remove_emptied(&mut map, key);
+        }
+        Ok(removed)
+    }
+
+    /// Returns every member of the set at `key`.
+    pub async fn smembers(&self, key: &[u8]) -> Result<Vec<Vec<u8>>, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => Ok(Vec::new()),
+            Some(StoreValue {
+                value: Value::Set(set),
+                ..
+            }) => Ok(set.iter().cloned().collect()),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Returns whether `member` belongs to the set at `key`.
+    pub async fn sismember(&self, key: &[u8], member: &[u8]) -> Result<bool, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => Ok(false),
+            Some(StoreValue {
+                value: Value::Set(set),
+                ..
+            }) => Ok(set.contains(member)),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Returns the number of members in the set at `key`.
+    pub async fn scard(&self, key: &[u8]) -> Result<usize, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => Ok(0),
+            Some(StoreValue {
+                value: Value::Set(set),
+                ..
+            }) => Ok(set.len()),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Applies `operation` across the sets at `keys`, in order.
+    ///
+    /// Missing keys behave like empty sets.
+    pub async fn set_operation(
+        &self,
+        operation: SetOperation,
+        keys: &[impl AsRef<[u8]>],
+    ) -> Result<Vec<Vec<u8>>, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        Ok(Store::combine_sets(&map, operation, keys, now)?
+            .into_iter()
+            .collect())
+    }
+
+    /// Applies `operation` across the sets at `keys` and stores the result at
+    /// `destination`, replacing any existing value and expiration.
+    ///
+    /// An empty result deletes `destination`. Returns the size of the result.
+    pub async fn set_operation_store(
+        &self,
+        operation: SetOperation,
+        destination: Key,
+        keys: &[impl AsRef<[u8]>],
+    ) -> Result<usize, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, &destination, now);
+        let result = Store::combine_sets(&map, operation, keys, now)?;
+        let len = result.len();
+        if result.is_empty() {
+            if map.remove(&destination).is_some() {
+                self.modified(&destination, EventClass::Generic, "del");
+            }
+        } else {
+            let event = match operation {
+                SetOperation::Intersection => "sinterstore",
+                SetOperation::Union => "sunionstore",
+                SetOperation::Difference => "sdiffstore",
+            };
+            self.modified(&destination, EventClass::Set, event);
+            map.insert(
+                destination,
+                StoreValue {
+                    value: Value::Set(result),
+                    expiration_time: None,
+                },
+            );
+        }
+        Ok(len)
+    }
+
+    fn combine_sets(
+        map: &Keyspace,
+        operation: SetOperation,
+        keys: &[impl AsRef<[u8]>],
+        now: Instant,
+    ) -> Result<HashSet<Vec<u8>>, StoreError> {
+        let empty = HashSet::new();
+        let sets = keys
+            .iter()
+            .map(|key| match Store::live_entry(map, key.as_ref(), now) {
+                None => Ok(&empty),
+                Some(StoreValue {
+                    value: Value::Set(set),
+                    ..
+                }) => Ok(set),
+                Some(_) => Err(StoreError::WrongType),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let Some((first, rest)) = sets.split_first() else {
+            return Ok(HashSet::new());
+        };
+        Ok(match operation {
+            SetOperation::Union => sets.iter().flat_map(|set| set.iter()).cloned().collect(),
+            SetOperation::Intersection => first
+                .iter()
+                .filter(|member| rest.iter().all(|set| set.contains(*member)))
+                .cloned()
+                .collect(),
+            SetOperation::Difference => first
+                .iter()
+                .filter(|member| !rest.iter().any(|set| set.contains(*member)))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// Adds or updates `(score, member)` pairs in the sorted set at `key`,
+    /// subject to the `ZADD` flags in `options`.
+    ///
+    /// Returns the number of members added, or with `changed` set, the number
+    /// added or whose score changed.
+    pub async fn zadd(
+        &self,
+        key: Key,
+        members: Vec<(f64, Vec<u8>)>,
+        options: ZAddOptions,
+    ) -> Result<usize, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, &key, now);
+        // Only updates are allowed, so a missing key stays missing rather
+        // than being created empty.
+        if options.condition == Some(ZAddCondition::OnlyExisting) && !map.contains_key(&key) {
+            return Ok(0);
+        }
+        let entry = map.get_or_insert_with(key.clone(), || StoreValue {
+            value: Value::SortedSet(SortedSet::new()),
+            expiration_time: None,
+        });
+        let Value::SortedSet(set) = &mut entry.value else {
+            return Err(StoreError::WrongType);
+        };
+        let mut counted = 0;
+        let mut written = false;
+        for (score, member) in members {
+            let previous = set.score(&member);
+            let allowed = match (previous, options.condition, options.comparison) {
+                (None, Some(ZAddCondition::OnlyExisting), _) => false,
+                (None, _, _) => true,
+                (Some(_), Some(ZAddCondition::OnlyNew), _) => false,
+                (Some(old), _, Some(ZAddComparison::GreaterThan)) => score > old,
+                (Some(old), _, Some(ZAddComparison::LessThan)) => score < old,
+                (Some(_), _, None) => true,
+            };
+            if !allowed {
+                continue;
+            }
+            set.insert(member, score);
+            written = true;
+            match previous {
+                None => counted += 1,
+                Some(old) if options.changed && old != score => counted += 1,
+                Some(_) => {}
+            }
+        }
+        if written {
+            self.modified(&key, EventClass::SortedSet, "zadd");
+        }
+        if set.is_empty() {
+            self.remove_emptied(&mut map, &key);
+        }
+        Ok(counted)
+    }
+
+    /// Adds `increment` to the score of `member` in the sorted set at `key`,
+    /// returning the new score. Missing members start at `0`.
+    pub async fn zincrby(
+        &self,
+        key: Key,
+        increment: f64,
+        member: Vec<u8>,
+    ) -> Result<f64, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, &key, now);
+        let entry = map.get_or_insert_with(key.clone(), || StoreValue {
+            value: Value::SortedSet(SortedSet::new()),
+            expiration_time: None,
+        });
+        let Value::SortedSet(set) = &mut entry.value else {
+            return Err(StoreError::WrongType);
+        };
+        let updated = set.score(&member).unwrap_or(0.0) + increment;
+        if updated.is_nan() {
+            if set.is_empty() {
+                map.remove(&key);
+            }
+            return Err(StoreError::NotANumber);
+        }
+        set.insert(member, updated);
+        self.modified(&key, EventClass::SortedSet, "zincr");
+        Ok(updated)
+    }
+
+    /// Removes `members` from the sorted set at `key`, returning how many
+    /// existed.
+    ///
+    /// The key is removed once its sorted set is empty.
+    pub async fn zrem(
+        &self,
+        key: &[u8],
+        members: &[impl AsRef<[u8]>],
+    ) -> Result<usize, StoreError> {
+        let now = Instant::now();
+        let mut map = self.hashmap.write().await;
+        Store::purge_if_expired(&mut map, key, now);
+        let Some(entry) = map.get_mut(key) else {
+            return Ok(0);
+        };
+        let Value::SortedSet(set) = &mut entry.value else {
+            return Err(StoreError::WrongType);
+        };
+        let removed = members
+            .iter()
+            .filter(|member| set.remove(member.as_ref()).is_some())
+            .count();
+        if removed > 0 {
+            self.modified(key, EventClass::SortedSet, "zrem");
+        }
+        if set.is_empty() {
+            self.remove_emptied(&mut map, key);
+        }
+        Ok(removed)
+    }
+
+    /// Returns the `(member, score)` pairs between ranks `start` and `stop`,
+    /// inclusive, using the same index rules as [`Store::lrange`].
+    pub async fn zrange(
+        &self,
+        key: &[u8],
+        start: i64,
+        stop: i64,
+    ) -> Result<Vec<(Vec<u8>, f64)>, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => Ok(Vec::new()),
+            Some(StoreValue {
+                value: Value::SortedSet(set),
+                ..
+            }) => Ok(match normalize_range(start, stop, set.len()) {
+                Some((first, last)) => set
+                    .range_by_rank(first, last)
+                    .map(|(score, member)| (member.to_vec(), score))
+                    .collect(),
+                None => Vec::new(),
+            }),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Returns the `(member, score)` pairs with scores between `min` and
+    /// `max`, optionally skipping `offset` matches and returning at most
+    /// `count`. A negative `count` returns every remaining match.
+    pub async fn zrangebyscore(
+        &self,
+        key: &[u8],
+        min: ScoreBound,
+        max: ScoreBound,
+        limit: Option<(i64, i64)>,
+    ) -> Result<Vec<(Vec<u8>, f64)>, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        let set = match Store::live_entry(&map, key, now) {
+            None => return Ok(Vec::new()),
+            Some(StoreValue {
+                value: Value::SortedSet(set),
+                ..
+            }) => set,
+            Some(_) => return Err(StoreError::WrongType),
+        };
+        let matches = set.range_by_score(min, max);
+        let (offset, count) = match limit {
+            None => (0, usize::MAX),
+            Some((offset, _)) if offset < 0 => return Ok(Vec::new()),
+            Some((offset, count)) if count < 0 => (offset as usize, usize::MAX),
+            Some((offset, count)) => (offset as usize, count as usize),
+        };
+        Ok(matches
+            .skip(offset)
+            .take(count)
+            .map(|(score, member)| (member.to_vec(), score))
+            .collect())
+    }
+
+    /// Returns the zero-based rank of `member` in the sorted set at `key`.
+    pub async fn zrank(&self, key: &[u8], member: &[u8]) -> Result<Option<usize>, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => Ok(None),
+            Some(StoreValue {
+                value: Value::SortedSet(set),
+                ..
+            }) => Ok(set.rank(member)),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Returns the score of `member` in the sorted set at `key`.
+    pub async fn zscore(&self, key: &[u8], member: &[u8]) -> Result<Option<f64>, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => Ok(None),
+            Some(StoreValue {
+                value: Value::SortedSet(set),
+                ..
+            }) => Ok(set.score(member)),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Returns the number of members in the sorted set at `key`.
+    pub async fn zcard(&self, key: &[u8]) -> Result<usize, StoreError> {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => Ok(0),
+            Some(StoreValue {
+                value: Value::SortedSet(set),
+                ..
+            }) => Ok(set.len()),
+            Some(_) => Err(StoreError::WrongType),
+        }
+    }
+
+    /// Sets a timeout in seconds on `key`.
+    ///
+    /// Returns `1` if the timeout was set, or `0` if the key does not exist
+    /// or is already expired.
+    pub async fn expire(&self, key: Key, ttl: u64) -> u64 {
+        let expiration = Expiration::After(Duration::from_secs(ttl));
+        self.set_expiration(key, expiration, &[]).await as u64
+    }
+
+    /// Sets the expiration of `key`, returning whether it was changed.
+    ///
+    /// Returns `false` if the key is missing or expired, or if any of
+    /// `conditions` rejects the change. Keys without an expiration count as
+    /// never expiring when compared by [`ExpireCondition::IfGreater`] and
+    /// [`ExpireCondition::IfLess`].
+    pub async fn set_expiration(
+        &self,
+        key: Key,
+        expiration: Expiration,
+        conditions: &[ExpireCondition],
+    ) -> bool {
+        let mut heap = self.expiration_heap.write().await;
+        let mut map = self.hashmap.write().await;
+        let now = Instant::now();
+        let Some(entry) = map.get_mut(&key).filter(|v| !Store::is_expired(v, now)) else {
+            return false;
+        };
+        let expires = expiration.deadline(now);
+        let current = entry.expiration_time;
+        let allowed = conditions.iter().all(|condition| match condition {
+            ExpireCondition::IfNone => current.is_none(),
+            ExpireCondition::IfSome => current.is_some(),
+            ExpireCondition::IfGreater => current.is_some_and(|t| expires > t),
+            ExpireCondition::IfLess => current.is_none_or(|t| expires < t),
+        });
+        if !allowed {
+            return false;
+        }
+        entry.expiration_time = Some(expires);
+        self.modified(&key, EventClass::Generic, "expire");
+        heap.push(Reverse((expires, key)));
+        self.wakeup.notify_one();
+        true
+    }
+
+    /// Removes the expiration of `key`, returning whether it had one.
+    pub async fn persist(&self, key: &[u8]) -> bool {
+        let mut map = self.hashmap.write().await;
+        let now = Instant::now();
+        match map.get_mut(key) {
+            Some(entry) if !Store::is_expired(entry, now) => {
+                let persisted = entry.expiration_time.take().is_some();
+                if persisted {
+                    self.modified(key, EventClass::Generic, "persist");
+                }
+                persisted
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the remaining time to live for `key` in whole seconds.
+    ///
+    /// Returns:
+    /// - `-2` if the key does not exist or is expired
+    /// - `-1` if the key exists but has no expiration
+    /// - a non-negative number for the remaining TTL
+    pub async fn ttl(&self, key: Key) -> i64 {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match map.get(key.as_slice()) {
+            None => -2,
+            Some(v) if Store::is_expired(v, now) => -2,
+            Some(StoreValue {
+                expiration_time: None,
+                ..
+            }) => -1,
+            Some(StoreValue {
+                expiration_time: Some(expires_on),
+                ..
+            }) => expires_on.duration_since(now).as_secs() as i64,
+        }
+    }
+
+    /// Returns the remaining time to live for `key` in milliseconds, using
+    /// the same `-2` and `-1` replies as [`Store::ttl`].
+    pub async fn pttl(&self, key: &[u8]) -> i64 {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => -2,
+            Some(StoreValue {
+                expiration_time: None,
+                ..
+            }) => -1,
+            Some(StoreValue {
+                expiration_time: Some(expires_on),
+                ..
+            }) => expires_on.duration_since(now).as_millis() as i64,
+        }
+    }
+
+    /// Returns the Unix time in milliseconds at which `key` expires, using
+    /// the same `-2` and `-1` replies as [`Store::ttl`].
+    pub async fn expire_time(&self, key: &[u8]) -> i64 {
+        let map = self.hashmap.read().await;
+        let now = Instant::now();
+        match Store::live_entry(&map, key, now) {
+            None => -2,
+            Some(StoreValue {
+                expiration_time: None,
+                ..
+            }) => -1,
+            Some(StoreValue {
+                expiration_time: Some(expires_on),
+                ..
+            }) => {
+                let unix_now_millis = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Time before UNIX epoch")
+                    .as_millis();
+                (unix_now_millis + expires_on.duration_since(now).as_millis()) as i64
+            }
+        }
+    }
+
+    fn is_expired(value: &StoreValue, now: Instant) -> bool {
+        matches!(value.expiration_time, Some(t) if t <= now)
+    }
+
+    fn live_entry<'a>(map: &'a Keyspace, key: &[u8], now: Instant) -> Option<&'a StoreValue> {
+        map.get(key).filter(|v| !Store::is_expired(v, now))
+    }
+
+    /// Drops `key` if it has expired but not been swept yet, so that write
+    /// paths can treat it as missing.
+    fn purge_if_expired(map: &mut Keyspace, key: &[u8], now: Instant) {
+        if map.get(key).is_some_and(|v| Store::is_expired(v, now)) {
+            map.remove(key);
+        }
+    }
+
+    /// Returns a copy of every live key, taken at one point in time.
+    pub async fn entries(&self) -> Vec<StoreEntry> {
+        self.entries_once_locked(|| {}).await
+    }
+
+    /// Like [`Store::entries`], but calls `locked` as soon as writes are held
+    /// off, before the copy is taken.
+    pub async fn entries_once_locked(&self, locked: impl FnOnce()) -> Vec<StoreEntry> {
+        let map = self.hashmap.read().await;
+        locked();
+        let now = Instant::now();
+        let unix_now_millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time before UNIX epoch")
+            .as_millis();
+        map.iter()
+            .filter(|(_, v)| !Store::is_expired(v, now))
+            .map(|(key, v)| StoreEntry {
+                key: key.clone(),
+                value: v.value.clone(),
+                expiration_time_unix: v
+                    .expiration_time
+                    .map(|t| t.saturating_duration_since(now).as_millis() + unix_now_millis),
+            })
+            .collect()
+    }
+
+    async fn to_snapshot(&self) -> Snapshot {
+        let now = Instant::now();
+        Snapshot {
+            entries: self
+                .hashmap
+                .read()
+                .await
+                .iter()
+                .filter(|(_, v)| !Store::is_expired(v, now))
+                .map(|(key, value)| SnapshotEntry {
+                    key: key.clone(),
+                    value: value.into(),
+                })
+                .collect(),
+        }
+    }
+
+    async fn from_snapshot(snapshot: Snapshot) -> Result<Store, SnapshotError> {
+        let entries = snapshot
+            .entries
+            .into_iter()
+            .map(|SnapshotEntry { key, value }| {
+                Ok(StoreEntry {
+                    key,
+                    value: value.value.try_into()?,
+                    expiration_time_unix: value.expiration_time_unix,
+                })
+            })
+            .collect::<Result<_, SnapshotError>>()?;
+        Store::from_entries(entries).await
+    }
+
+    /// Builds a store holding `entries`, as returned by [`Store::entries`],
+    /// leaving out any that have already expired.
+    pub async fn from_entries(entries: Vec<StoreEntry>) -> Result<Store, SnapshotError> {
+        let now_unix_millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System Time is set before Unix Epoch")
+            .as_millis();
+        let now = Instant::now();
+
+        let mut unique_keys: HashSet<&[u8]> = HashSet::new();
+        for entry in entries.iter() {
+            if !unique_keys.insert(&entry.key) {
+                return Err(SnapshotError::DuplicateKey);
+            }
+            if entry.value.is_empty_collection() {
+                return Err(SnapshotError::EmptyCollection);
+            }
+        }
+
+        let hashmap: Keyspace = entries
+            .into_iter()
+            .filter(|entry| {
+                entry
+                    .expiration_time_unix
+                    .is_none_or(|expiration| expiration > now_unix_millis)
+            })
+            .map(
+                |StoreEntry {
+                     key,
+                     value,
+                     expiration_time_unix,
+                 }|
+                 -> Result<(Vec<u8>, StoreValue), SnapshotError> {
+                    let expiration_time = expiration_time_unix
+                        .map(|expiration| expiration_instant(expiration, now_unix_millis, now))
+                        .transpose()?;
+                    Ok((
+                        key,
+                        StoreValue {
+                            value,
+                            expiration_time,
+                        },
+                    ))
+                },
+            )
+            .collect::<Result<_, _>>()?;
+        let expiration_heap: ExpirationHeap = hashmap
+            .iter()
+            .filter_map(|(key, store_value)| match store_value {
+                StoreValue {
+                    expiration_time: None,
+                    ..
+                } => None,
+                StoreValue {
+                    expiration_time: Some(expiration_instant),
+                    ..
+                } => Some(Reverse((*expiration_instant, key.clone()))),
+            })
+            .collect();
+        Ok(Store::from_parts(hashmap, expiration_heap))
+    }
+
+    pub async fn dump(&self) -> Result<Vec<u8>, serde_json::Error> {
+        serde_json::to_vec(&self.to_snapshot().await)
+    }
+
+    pub async fn restore(bytes: &[u8]) -> Result<Store, RestoreError> {
+        let snapshot: Snapshot = serde_json::from_slice(bytes)?;
+        Store::from_snapshot(snapshot).await.map_err(Into::into)
+    }
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Store::new()
+    }
+}
+
+/// A key and its value, as returned by [`Store::entries`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreEntry {
+    pub key: Vec<u8>,
+    pub value: Value,
+    /// The Unix time in milliseconds at which the key expires.
+    pub expiration_time_unix: Option<u128>,
+}
+
+/// A pop made for a blocking command, recorded by [`Store::record_served_pops`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServedPop {
+    pub key: Vec<u8>,
+    pub end: ListEnd,
+    /// Where the value was pushed, for `BLMOVE`.
+    pub destination: Option<(Vec<u8>, ListEnd)>,
+    pub value: Vec<u8>,
+}
+
+/// The result of [`Store::pop_or_block`].
+pub enum BlockingPop {
+    /// A value was available, and was popped from the given key.
+    Ready(Vec<u8>, Vec<u8>),
+    /// Every list was empty, so the caller should wait on the [`ListWait`].
+    Blocked(ListWait),
+}
+
+impl BlockingPop {
+    /// Returns the popped key and value, giving up if the pop would block.
+    pub fn ready(self) -> Option<(Vec<u8>, Vec<u8>)> {
+        match self {
+            BlockingPop::Ready(key, value) => Some((key, value)),
+            BlockingPop::Blocked(_) => None,
+        }
+    }
+}
+
+struct ListWaiter {
+    end: ListEnd,
+    destination: Option<(Key, ListEnd)>,
+    slot: WaiterSlot,
+}
+
+/// A pop blocked on empty lists, created by [`Store::pop_or_block`].
+///
+/// Dropping the wait gives up on it. Use [`ListWait::cancel`] instead to
+/// collect a value that arrived just before giving up.
+pub struct ListWait {
+    store: Store,
+    keys: Vec<Key>,
+    slot: WaiterSlot,
+    receiver: oneshot::Receiver<PopResult>,
+}
+
+impl ListWait {
+    /// Waits for a value, returning it with the key it was popped from.
+    pub async fn recv(&mut self) -> PopResult {
+        (&mut self.receiver)
+            .await
+            .expect("the sender is only dropped after sending")
+    }
+
+    /// Stops waiting. Returns the result if one was delivered before the
+    /// wait was cancelled.
+    pub fn cancel(&mut self) -> Option<PopResult> {
+        self.slot.lock().unwrap().take();
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Drop for ListWait {
+    fn drop(&mut self) {
+        self.slot.lock().unwrap().take();
+        let mut waiters = self.store.list_waiters.lock().unwrap();
+        for key in &self.keys {
+            if let Some(queue) = waiters.get_mut(key) {
+                queue.retain(|waiter| !Arc::ptr_eq(&waiter.slot, &self.slot));
+                if queue.is_empty() {
+                    waiters.remove(key);
+                }
+            }
+        }
+    }
+}
+
+/// A set of keys watched for changes, created by [`Store::watch`].
+///
+/// Any write to a watched key, including expiry, marks the watch as dirty.
+/// Dropping the watch stops watching its keys.
+pub struct Watch {
+    store: Store,
+    keys: Vec<Key>,
+    dirty: Arc<AtomicBool>,
+}
+
+impl Watch {
+    /// Starts watching `keys` in addition to any already watched.
+    pub fn add(&mut self, keys: Vec<Key>) {
+        let mut watchers = self.store.watchers.lock().unwrap();
+        for key in keys {
+            if self.keys.contains(&key) {
+                continue;
+            }
+            watchers
+                .entry(key.clone())
+                .or_default()
+                .push(self.dirty.clone());
+            self.keys.push(key);
+        }
+    }
+
+    /// Returns whether any watched key has been written since it was added.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        let mut watchers = self.store.watchers.lock().unwrap();
+        for key in &self.keys {
+            if let Some(flags) = watchers.get_mut(key) {
+                flags.retain(|flag| !Arc::ptr_eq(flag, &self.dirty));
+                if flags.is_empty() {
+                    watchers.remove(key);
+                }
+            }
+        }
+    }
+}
+
+/// The position of `key` in `SCAN` order.
+///
+/// `DefaultHasher::new` always uses the same keys, so the order is stable for
+/// the life of the process.
+fn scan_hash(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Converts an inclusive `start..=stop` range with Redis-style negative
+/// indexes into bounds within a collection of length `len`.
+///
+/// Returns `None` if the range selects nothing.
+fn normalize_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = i64::try_from(len).ok()?;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+/// Parses a stored value as a base-10 signed 64 bit integer.
+fn parse_integer(value: &[u8]) -> Option<i64> {
+    std::str::from_utf8(value).ok()?.parse::<i64>().ok()
+}
+
+/// Parses a stored value as a finite 64 bit float.
+pub(crate) fn parse_float(value: &[u8]) -> Option<f64> {
+    let number = std::str::from_utf8(value).ok()?.parse::<f64>().ok()?;
+    number.is_finite().then_some(number)
+}
+
+/// The `MATCH` and `TYPE` filters of `SCAN`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanFilter {
+    pub pattern: Option<Bytes>,
+    pub type_name: Option<Bytes>,
+}
+
+impl ScanFilter {
+    fn matches(&self, key: &[u8], value: &Value) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| glob_match(pattern, key))
+            && self
+                .type_name
+                .as_ref()
+                .is_none_or(|t| t.eq_ignore_ascii_case(value.type_name().as_bytes()))
+    }
+}
+
+/// When a key should expire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiration {
+    /// Expire after a relative duration.
+    After(Duration),
+    /// Expire at a Unix time in milliseconds.
+    AtUnixMillis(u64),
+}
+
+impl Expiration {
+    /// Converts the expiration into a deadline on the store's clock.
+    ///
+    /// Unix times that have already passed map to `now`, so the key is
+    /// expired at once.
+    fn deadline(self, now: Instant) -> Instant {
+        match self {
+            Expiration::After(duration) => now + duration,
+            Expiration::AtUnixMillis(unix_millis) => {
+                let unix_now_millis = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Time before UNIX epoch")
+                    .as_millis();
+                let remaining = u128::from(unix_millis).saturating_sub(unix_now_millis);
+                now + Duration::from_millis(remaining as u64)
+            }
+        }
+    }
+}
+
+/// The `NX`/`XX`/`GT`/`LT` flags of the `EXPIRE` family of commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpireCondition {
+    /// Only set an expiration on keys that have none.
+    IfNone,
+    /// Only replace an existing expiration.
+    IfSome,
+    /// Only move the expiration later.
+    IfGreater,
+    /// Only move the expiration earlier.
+    IfLess,
+}
+
+/// Restricts [`Store::set`] to keys that are missing (`NX`) or present (`XX`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetCondition {
+    IfMissing,
+    IfExists,
+}
+
+/// The expiration [`Store::set`] gives the key it writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetExpiration {
+    /// Expire after a relative duration, as with `EX` and `PX`.
+    After(Duration),
+    /// Expire at a Unix time in milliseconds, as with `EXAT` and `PXAT`.
+    AtUnixMillis(u64),
+    /// Keep the key's current expiration, as with `KEEPTTL`.
+    Keep,
+}
+
+/// The `NX`/`XX`, expiration and `GET` options of `SET`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SetOptions {
+    pub condition: Option<SetCondition>,
+    pub expiration: Option<SetExpiration>,
+    pub get: bool,
+}
+
+/// The result of [`Store::set`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetOutcome {
+    /// Whether the value was written, which is false when the condition failed.
+    pub written: bool,
+    /// The previous value, if the key held a string.
+    pub previous: Option<Vec<u8>>,
+}
+
+/// Which end of a list an operation applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+/// How [`Store::set_operation`] combines its input sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOperation {
+    Intersection,
+    Union,
+    Difference,
+}
+
+/// The typed data held under a key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(Vec<u8>),
+    List(VecDeque<Vec<u8>>),
+    Hash(HashMap<Vec<u8>, Vec<u8>>),
+    Set(HashSet<Vec<u8>>),
+    SortedSet(SortedSet),
+}
+
+impl Value {
+    /// Returns the name `TYPE` reports for this value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+        }
+    }
+
+    /// Returns whether this is a collection with no elements, which the
+    /// store never holds: a key is removed along with its last element.
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            Value::String(_) => false,
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::SortedSet(zset) => zset.is_empty(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StoreValue {
+    value: Value,
+    expiration_time: Option<Instant>,
+}
+
+/// The store's keys and values, along with an index of the keys in `SCAN`
+/// order so that a scan only has to visit the keys it returns.
+///
+/// Reads go through the map directly. Changes to which keys exist go through
+/// the methods here to keep the index in step.
+#[derive(Default)]
+struct Keyspace {
+    map: HashMap<Key, StoreValue>,
+    scan_order: BTreeSet<(u64, Key)>,
+}
+
+impl Keyspace {
+    fn get_mut(&mut self, key: &[u8]) -> Option<&mut StoreValue> {
+        self.map.get_mut(key)
+    }
+
+    fn get_or_insert_with(
+        &mut self,
+        key: Key,
+        default: impl FnOnce() -> StoreValue,
+    ) -> &mut StoreValue {
+        if !self.map.contains_key(&key) {
+            self.scan_order.insert((scan_hash(&key), key.clone()));
+        }
+        self.map.entry(key).or_insert_with(default)
+    }
+
+    fn insert(&mut self, key: Key, value: StoreValue) -> Option<StoreValue> {
+        if !self.map.contains_key(&key) {
+            self.scan_order.insert((scan_hash(&key), key.clone()));
+        }
+        self.map.insert(key, value)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<StoreValue> {
+        let value = self.map.remove(key)?;
+        self.scan_order.remove(&(scan_hash(key), key.to_vec()));
+        Some(value)
+    }
+
+    /// Keys from `cursor` onwards in `SCAN` order.
+    fn scan_from(&self, cursor: u64) -> impl Iterator<Item = (u64, &Key)> {
+        self.scan_order
+            .range((cursor, Vec::new())..)
+            .map(|(hash, key)| (*hash, key))
+    }
+}
+
+impl Deref for Keyspace {
+    type Target = HashMap<Key, StoreValue>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.map
+    }
+}
+
+impl FromIterator<(Key, StoreValue)> for Keyspace {
+    fn from_iter<I: IntoIterator<Item = (Key, StoreValue)>>(iter: I) -> Self {
+        let map: HashMap<Key, StoreValue> = iter.into_iter().collect();
+        let scan_order = map
+            .keys()
+            .map(|key| (scan_hash(key), key.clone()))
+            .collect();
+        Keyspace { map, scan_order }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StoreError {
+    WrongType,
+    NotInteger,
+    Overflow,
+    NotANumber,
+    NotFloat,
+    NonFiniteResult,
+    NoSuchKey,
+}
+
+impl StoreError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            StoreError::WrongType => ErrorCode::WrongType,
+            _ => ErrorCode::Err,
+        }
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.code())?;
+        match self {
+            StoreError::WrongType => {
+                write!(f, "Operation against a key holding the wrong kind of value")
+            }
+            StoreError::NotInteger => write!(f, "value is not an integer or out of range"),
+            StoreError::Overflow => write!(f, "increment or decrement would overflow"),
+            StoreError::NotANumber => write!(f, "resulting score is not a number (NaN)"),
+            StoreError::NotFloat => write!(f, "value is not a valid float"),
+            StoreError::NonFiniteResult => write!(f, "increment would produce NaN or Infinity"),
+            StoreError::NoSuchKey => write!(f, "no such key"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+/// Archived form of a [`Value`].
+///
+/// Strings keep the original bare byte-array encoding so older archives still
+/// load; other types are wrapped in an object naming the type.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+enum SnapshotData {
+    String(#[serde(with = "serde_bytes")] Vec<u8>),
+    List { list: Vec<Vec<u8>> },
+    Hash { hash: Vec<(Vec<u8>, Vec<u8>)> },
+    Set { set: Vec<Vec<u8>> },
+    SortedSet { zset: Vec<(Vec<u8>, String)> },
+}
+
+impl From<Value> for SnapshotData {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::String(v) => SnapshotData::String(v),
+            Value::List(list) => SnapshotData::List {
+                list: list.into_iter().collect(),
+            },
+            Value::Hash(hash) => SnapshotData::Hash {
+                hash: hash.into_iter().collect(),
+            },
+            Value::Set(set) => SnapshotData::Set {
+                set: set.into_iter().collect(),
+            },
+            // Scores are archived as strings since JSON cannot hold infinities.
+            Value::SortedSet(zset) => SnapshotData::SortedSet {
+                zset: zset
+                    .iter()
+                    .map(|(score, member)| (member.to_vec(), format_score(score)))
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl TryFrom<SnapshotData> for Value {
+    type Error = SnapshotError;
+
+    fn try_from(data: SnapshotData) -> Result<Self, Self::Error> {
+        Ok(match data {
+            SnapshotData::String(v) => Value::String(v),
+            SnapshotData::List { list } => Value::List(list.into()),
+            SnapshotData::Hash { hash } => Value::Hash(hash.into_iter().collect()),
+            SnapshotData::Set { set } => Value::Set(set.into_iter().collect()),
+            SnapshotData::SortedSet { zset } => Value::SortedSet(
+                zset.into_iter()
+                    .map(|(member, score)| {
+                        parse_score(score.as_bytes())
+                            .map(|score| (member, score))
+                            .ok_or(SnapshotError::InvalidScore)
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct SnapshotValue {
+    value: SnapshotData,
+    expiration_time_unix: Option<u128>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct SnapshotEntry {
+    #[serde(with = "serde_bytes")]
+    key: Vec<u8>,
+    value: SnapshotValue,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct Snapshot {
+    entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    DurationOverflow,
+    DuplicateKey,
+    InvalidScore,
+    EmptyCollection,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::DurationOverflow => {
+                write!(f, "snapshot expiration exceeds supported duration")
+            }
+            SnapshotError::DuplicateKey => {
+                write!(f, "snapshot contains duplicate keys")
+            }
+            SnapshotError::InvalidScore => {
+                write!(f, "snapshot contains an invalid sorted set score")
+            }
+            SnapshotError::EmptyCollection => {
+                write!(f, "snapshot contains an empty collection")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+#[derive(Debug)]
+pub enum RestoreError {
+    InvalidSnapshot(serde_json::Error),
+    InvalidData(SnapshotError),
+}
+
+impl fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestoreError::InvalidSnapshot(err) => write!(f, "{err}"),
+            RestoreError::InvalidData(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for RestoreError {}
+
+impl From<serde_json::Error> for RestoreError {
+    fn from(value: serde_json::Error) -> Self {
+        RestoreError::InvalidSnapshot(value)
+    }
+}
 
 impl From<SnapshotError> for RestoreError {
     fn from(value: SnapshotError) -> Self {
         RestoreError::InvalidData(value)
     }
-}
+}
+
+impl From<StoreValue> for SnapshotValue {
+    fn from(store_value: StoreValue) -> Self {
+        let StoreValue {
+            value,
+            expiration_time,
+        } = store_value;
+        let unix_now_millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System Time is set before Unix Epoch")
+            .as_millis();
+        let store_now = Instant::now();
+        Self {
+            value: value.into(),
+            expiration_time_unix: expiration_time
+                .map(|t| t.saturating_duration_since(store_now).as_millis() + unix_now_millis),
+        }
+    }
+}
+
+impl From<&StoreValue> for SnapshotValue {
+    fn from(store_value: &StoreValue) -> Self {
+        let StoreValue {
+            value,
+            expiration_time,
+        } = store_value.clone();
+        let unix_now_millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time before UNIX epoch")
+            .as_millis();
+        let store_now = Instant::now();
+        Self {
+            value: value.into(),
+            expiration_time_unix: expiration_time
+                .map(|t| t.saturating_duration_since(store_now).as_millis() + unix_now_millis),
+        }
+    }
+}
+
+impl TryFrom<SnapshotValue> for StoreValue {
+    type Error = SnapshotError;
+
+    fn try_from(snapshot_value: SnapshotValue) -> Result<Self, Self::Error> {
+        let SnapshotValue {
+            value,
+            expiration_time_unix,
+        } = snapshot_value;
+        let unix_now_millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time before UNIX epoch")
+            .as_millis();
+        let store_now = Instant::now();
+        Ok(Self {
+            value: value.try_into()?,
+            expiration_time: expiration_time_unix
+                .map(|t| expiration_instant(t, unix_now_millis, store_now))
+                .transpose()?,
+        })
+    }
+}
+
+/// Converts an expiration at `unix_millis` into an [`Instant`], given the
+/// current time in both clocks. Past expirations become `store_now`.
+fn expiration_instant(
+    unix_millis: u128,
+    unix_now_millis: u128,
+    store_now: Instant,
+) -> Result<Instant, SnapshotError> {
+    let remaining = unix_millis.saturating_sub(unix_now_millis);
+    let remaining_millis = u64::try_from(remaining).map_err(|_| SnapshotError::DurationOverflow)?;
+    store_now
+        .checked_add(Duration::from_millis(remaining_millis))
+        .ok_or(SnapshotError::DurationOverflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pubsub::Message;
+    use tokio::sync::Barrier;
+    use tokio::time::{self, sleep};
+
+    #[tokio::test]
+    async fn set_then_get() {
+        let store = Store::new();
+        store
+            .set(
+                "newkey".as_bytes().to_vec(),
+                "newvalue".as_bytes().to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            Some("newvalue".as_bytes().to_vec()),
+            store.get("newkey".as_bytes()).await.unwrap()
+        )
+    }
+
+    #[tokio::test]
+    async fn get_nonexistent_key() {
+        let store = Store::new();
+        assert_eq!(None, store.get("newkey".as_bytes()).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn delete_existing_key() {
+        let store = Store::new();
+        store
+            .set(
+                "newkey".as_bytes().to_vec(),
+                "newvalue".as_bytes().to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert!(store.del("newkey".as_bytes()).await.is_some())
+    }
+
+    #[tokio::test]
+    async fn delete_nonexistent_key() {
+        let store = Store::new();
+        assert!(store.del("newkey".as_bytes()).await.is_none())
+    }
+
+    #[tokio::test]
+    async fn push_builds_list_in_command_order() {
+        let store = Store::new();
+        let key = b"list-key".to_vec();
+
+        assert_eq!(
+            Ok(2),
+            store
+                .push(
+                    key.clone(),
+                    vec![b"a".to_vec(), b"b".to_vec()],
+                    ListEnd::Left
+                )
+                .await
+        );
+        assert_eq!(
+            Ok(3),
+            store
+                .push(key.clone(), vec![b"c".to_vec()], ListEnd::Right)
+                .await
+        );
+
+        assert_eq!(
+            Ok(vec![b"b".to_vec(), b"a".to_vec(), b"c".to_vec()]),
+            store.lrange(&key, 0, -1).await
+        );
+    }
+
+    #[tokio::test]
+    async fn pop_removes_from_either_end_and_deletes_empty_list() {
+        let store = Store::new();
+        let key = b"list-key".to_vec();
+        let values = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        store
+            .push(key.clone(), values, ListEnd::Right)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Ok(Some(vec![b"a".to_vec()])),
+            store.pop(&key, ListEnd::Left, 1).await
+        );
+        assert_eq!(
+            Ok(Some(vec![b"c".to_vec(), b"b".to_vec()])),
+            store.pop(&key, ListEnd::Right, 5).await
+        );
+        assert_eq!(Ok(None), store.pop(&key, ListEnd::Left, 1).await);
+        assert!(!store.hashmap.read().await.contains_key(&key));
+    }
+
+    #[tokio::test]
+    async fn popping_nothing_is_not_a_change() {
+        let store = Store::new();
+        let key = b"list-key".to_vec();
+        store
+            .push(key.clone(), vec![b"a".to_vec()], ListEnd::Right)
+            .await
+            .unwrap();
+        let mut watch = store.watch();
+        watch.add(vec![key.clone()]);
+        let dirty = store.dirty();
+
+        assert_eq!(
+            Ok(Some(Vec::new())),
+            store.pop(&key, ListEnd::Left, 0).await
+        );
+        assert_eq!(dirty, store.dirty());
+        assert!(!watch.is_dirty());
+    }
+
+    #[tokio::test]
+    async fn lrange_clamps_and_handles_negative_indexes() {
+        let store = Store::new();
+        let key = b"list-key".to_vec();
+        let values = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        store
+            .push(key.clone(), values, ListEnd::Right)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Ok(vec![b"b".to_vec(), b"c".to_vec()]),
+            store.lrange(&key, -2, 100).await
+        );
+        assert_eq!(Ok(vec![b"a".to_vec()]), store.lrange(&key, -100, 0).await);
+        assert_eq!(Ok(Vec::<Vec<u8>>::new()), store.lrange(&key, 2, 1).await);
+        assert_eq!(Ok(Vec::<Vec<u8>>::new()), store.lrange(&key, 3, 5).await);
+        assert_eq!(
+            Ok(Vec::<Vec<u8>>::new()),
+            store.lrange(b"missing", 0, -1).await
+        );
+    }
+
+    #[tokio::test]
+    async fn list_and_string_operations_reject_the_other_type() {
+        let store = Store::new();
+        let string_key = b"string-key".to_vec();
+        let list_key = b"list-key".to_vec();
+        store
+            .set(string_key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        store
+            .push(list_key.clone(), vec![b"a".to_vec()], ListEnd::Left)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Err(StoreError::WrongType),
+            store
+                .push(string_key.clone(), vec![b"a".to_vec()], ListEnd::Left)
+                .await
+        );
+        assert_eq!(
+            Err(StoreError::WrongType),
+            store.pop(&string_key, ListEnd::Left, 1).await
+        );
+        assert_eq!(Err(StoreError::WrongType), store.llen(&string_key).await);
+        assert_eq!(Err(StoreError::WrongType), store.get(&list_key).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn push_onto_expired_list_starts_a_new_list() {
+        let store = Store::new();
+        let key = b"list-key".to_vec();
+        store
+            .push(key.clone(), vec![b"old".to_vec()], ListEnd::Left)
+            .await
+            .unwrap();
+        assert_eq!(1, store.expire(key.clone(), 1).await);
+
+        time::advance(Duration::from_secs(1)).await;
+
+        assert_eq!(
+            Ok(1),
+            store
+                .push(key.clone(), vec![b"new".to_vec()], ListEnd::Left)
+                .await
+        );
+        assert_eq!(-1, store.ttl(key).await);
+    }
+
+    #[tokio::test]
+    async fn empty_set_operation_over_expired_destination_is_not_a_change() {
+        let store = Store::new();
+        // Left out of the expiration heap so the sweeper cannot remove it.
+        store.hashmap.write().await.insert(
+            b"dest".to_vec(),
+            StoreValue {
+                value: Value::Set(HashSet::from([b"m".to_vec()])),
+                expiration_time: Some(Instant::now()),
+            },
+        );
+        let dirty = store.dirty();
+
+        assert_eq!(
+            Ok(0),
+            store
+                .set_operation_store(SetOperation::Union, b"dest".to_vec(), &[b"missing"])
+                .await
+        );
+        assert_eq!(dirty, store.dirty());
+        assert!(!store.hashmap.read().await.contains_key(b"dest".as_slice()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn list_round_trips_through_dump_with_ttl() {
+        let s = Store::new();
+        let key = b"list-key".to_vec();
+        let values = vec![b"a".to_vec(), b"\xF4\xFF".to_vec()];
+        s.push(key.clone(), values.clone(), ListEnd::Right)
+            .await
+            .unwrap();
+        s.expire(key.clone(), 5).await;
+
+        let bytes = s.dump().await.unwrap();
+        let s = Store::restore(&bytes).await.unwrap();
+
+        assert_eq!(Ok(values), s.lrange(&key, 0, -1).await);
+        assert!(s.ttl(key.clone()).await > 0);
+        time::advance(Duration::from_secs(5)).await;
+        assert_eq!(Ok(0), s.llen(&key).await);
+    }
+
+    #[tokio::test]
+    async fn hset_counts_only_new_fields() {
+        let store = Store::new();
+        let key = b"hash-key".to_vec();
+
+        assert_eq!(
+            Ok(2),
+            store
+                .hset(
+                    key.clone(),
+                    vec![
+                        (b"name".to_vec(), b"ada".to_vec()),
+                        (b"lang".to_vec(), b"rust".to_vec()),
+                    ]
+                )
+                .await
+        );
+        assert_eq!(
+            Ok(0),
+            store
+                .hset(key.clone(), vec![(b"name".to_vec(), b"grace".to_vec())])
+                .await
+        );
+
+        assert_eq!(Ok(Some(b"grace".to_vec())), store.hget(&key, b"name").await);
+        assert_eq!(Ok(2), store.hlen(&key).await);
+        assert_eq!(Ok(true), store.hexists(&key, b"lang").await);
+        assert_eq!(Ok(false), store.hexists(&key, b"missing").await);
+    }
+
+    #[tokio::test]
+    async fn hdel_removes_fields_and_deletes_empty_hash() {
+        let store = Store::new();
+        let key = b"hash-key".to_vec();
+        store
+            .hset(
+                key.clone(),
+                vec![
+                    (b"a".to_vec(), b"1".to_vec()),
+                    (b"b".to_vec(), b"2".to_vec()),
+                ],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Ok(1),
+            store
+                .hdel(&key, &[b"a".to_vec(), b"missing".to_vec()])
+                .await
+        );
+        assert_eq!(
+            Ok(vec![(b"b".to_vec(), b"2".to_vec())]),
+            store.hgetall(&key).await
+        );
+        assert_eq!(Ok(1), store.hdel(&key, &[b"b".to_vec()]).await);
+        assert!(!store.hashmap.read().await.contains_key(&key));
+    }
+
+    #[tokio::test]
+    async fn del_many_counts_only_live_keys() {
+        let store = Store::new();
+        store
+            .mset(vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"b".to_vec(), b"2".to_vec()),
+            ])
+            .await;
+
+        let keys = vec![
+            b"a".to_vec(),
+            b"missing".to_vec(),
+            b"b".to_vec(),
+            b"a".to_vec(),
+        ];
+        assert_eq!(2, store.del_many(&keys).await);
+        assert_eq!(0, store.exists(&keys).await);
+    }
+
+    #[tokio::test]
+    async fn exists_counts_repeated_keys_each_time() {
+        let store = Store::new();
+        store
+            .sadd(b"set".to_vec(), vec![b"member".to_vec()])
+            .await
+            .unwrap();
+
+        let keys = vec![b"set".to_vec(), b"set".to_vec(), b"missing".to_vec()];
+        assert_eq!(2, store.exists(&keys).await);
+    }
+
+    #[tokio::test]
+    async fn mget_returns_none_for_missing_and_non_string_keys() {
+        let store = Store::new();
+        store.mset(vec![(b"a".to_vec(), b"1".to_vec())]).await;
+        store
+            .sadd(b"set".to_vec(), vec![b"member".to_vec()])
+            .await
+            .unwrap();
+
+        let keys = vec![b"a".to_vec(), b"set".to_vec(), b"missing".to_vec()];
+        assert_eq!(
+            vec![Some(b"1".to_vec()), None, None],
+            store.mget(&keys).await
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn mset_clears_existing_expirations() {
+        let store = Store::new();
+        let key = b"a".to_vec();
+        store
+            .set(key.clone(), b"old".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        store.expire(key.clone(), 5).await;
+
+        store.mset(vec![(key.clone(), b"new".to_vec())]).await;
+        assert_eq!(-1, store.ttl(key).await);
+    }
+
+    #[tokio::test]
+    async fn msetnx_writes_nothing_if_any_key_exists() {
+        let store = Store::new();
+        store
+            .set(b"b".to_vec(), b"existing".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+
+        let pairs = vec![
+            (b"a".to_vec(), b"1".to_vec()),
+            (b"b".to_vec(), b"2".to_vec()),
+        ];
+        assert!(!store.msetnx(pairs).await);
+        assert_eq!(Ok(None), store.get(b"a").await);
+        assert_eq!(Ok(Some(b"existing".to_vec())), store.get(b"b").await);
+
+        assert!(store.msetnx(vec![(b"a".to_vec(), b"1".to_vec())]).await);
+        assert_eq!(Ok(Some(b"1".to_vec())), store.get(b"a").await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn set_expiration_accepts_millisecond_durations() {
+        let store = Store::new();
+        let key = b"key".to_vec();
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+
+        let expiration = Expiration::After(Duration::from_millis(1500));
+        assert!(store.set_expiration(key.clone(), expiration, &[]).await);
+        assert_eq!(1500, store.pttl(&key).await);
+        assert_eq!(1, store.ttl(key.clone()).await);
+
+        time::advance(Duration::from_millis(1500)).await;
+        assert_eq!(-2, store.pttl(&key).await);
+    }
+
+    #[tokio::test]
+    async fn set_expiration_at_unix_time_round_trips_through_expire_time() {
+        let store = Store::new();
+        let key = b"key".to_vec();
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(-1, store.expire_time(&key).await);
+
+        let unix_now_millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let deadline = unix_now_millis + 60_000;
+        let expiration = Expiration::AtUnixMillis(deadline);
+        assert!(store.set_expiration(key.clone(), expiration, &[]).await);
+
+        let expire_time = store.expire_time(&key).await as u64;
+        assert!(expire_time.abs_diff(deadline) <= 5);
+        assert_eq!(-2, store.expire_time(b"missing").await);
+    }
+
+    #[tokio::test]
+    async fn set_expiration_in_the_past_expires_key() {
+        let store = Store::new();
+        let key = b"key".to_vec();
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+
+        assert!(
+            store
+                .set_expiration(key.clone(), Expiration::AtUnixMillis(1), &[])
+                .await
+        );
+        assert_eq!(Ok(None), store.get(&key).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn set_expiration_honours_conditions() {
+        let store = Store::new();
+        let key = b"key".to_vec();
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        let after = |secs| Expiration::After(Duration::from_secs(secs));
+
+        use ExpireCondition::*;
+        assert!(
+            !store
+                .set_expiration(key.clone(), after(10), &[IfSome])
+                .await
+        );
+        assert!(
+            !store
+                .set_expiration(key.clone(), after(10), &[IfGreater])
+                .await
+        );
+        assert!(
+            store
+                .set_expiration(key.clone(), after(10), &[IfNone])
+                .await
+        );
+        assert!(
+            !store
+                .set_expiration(key.clone(), after(20), &[IfNone])
+                .await
+        );
+        assert!(
+            !store
+                .set_expiration(key.clone(), after(5), &[IfGreater])
+                .await
+        );
+        assert!(
+            store
+                .set_expiration(key.clone(), after(20), &[IfGreater])
+                .await
+        );
+        assert!(
+            !store
+                .set_expiration(key.clone(), after(30), &[IfLess])
+                .await
+        );
+        assert!(
+            store
+                .set_expiration(key.clone(), after(15), &[IfSome, IfLess])
+                .await
+        );
+        assert_eq!(15, store.ttl(key.clone()).await);
+
+        assert!(store.persist(&key).await);
+        assert!(
+            store
+                .set_expiration(key.clone(), after(30), &[IfLess])
+                .await
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn persist_removes_expiration() {
+        let store = Store::new();
+        let key = b"key".to_vec();
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+
+        assert!(!store.persist(&key).await);
+        store.expire(key.clone(), 5).await;
+        assert!(store.persist(&key).await);
+        assert_eq!(-1, store.ttl(key.clone()).await);
+
+        time::advance(Duration::from_secs(5)).await;
+        store.sweep_expired_once().await;
+        assert_eq!(Ok(Some(b"value".to_vec())), store.get(&key).await);
+        assert!(!store.persist(b"missing").await);
+    }
+
+    #[tokio::test]
+    async fn key_type_names_each_value_type() {
+        let store = Store::new();
+        store
+            .set(b"string".to_vec(), b"v".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        store
+            .push(b"list".to_vec(), vec![b"a".to_vec()], ListEnd::Left)
+            .await
+            .unwrap();
+        store
+            .hset(b"hash".to_vec(), vec![(b"f".to_vec(), b"v".to_vec())])
+            .await
+            .unwrap();
+        store
+            .sadd(b"set".to_vec(), vec![b"m".to_vec()])
+            .await
+            .unwrap();
+        store
+            .zadd(
+                b"zset".to_vec(),
+                vec![(1.0, b"m".to_vec())],
+                Default::default(),
+            )
+            .await
+            .unwrap();
+
+        for name in ["string", "list", "hash", "set", "zset"] {
+            assert_eq!(Some(name), store.key_type(name.as_bytes()).await);
+        }
+        assert_eq!(None, store.key_type(b"missing").await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rename_carries_expiration_to_new_key() {
+        let store = Store::new();
+        let key = b"old".to_vec();
+        let new_key = b"new".to_vec();
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        store.expire(key.clone(), 5).await;
+
+        assert_eq!(Ok(()), store.rename(&key, new_key.clone()).await);
+        assert_eq!(-2, store.ttl(key.clone()).await);
+        assert_eq!(5, store.ttl(new_key.clone()).await);
+
+        time::advance(Duration::from_secs(5)).await;
+        store.sweep_expired_once().await;
+        assert!(!store.hashmap.read().await.contains_key(&new_key));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rename_onto_expiring_key_drops_its_expiration() {
+        let store = Store::new();
+        store
+            .set(b"a".to_vec(), b"a".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        store
+            .set(b"b".to_vec(), b"b".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        store.expire(b"b".to_vec(), 5).await;
+
+        store.rename(b"a", b"b".to_vec()).await.unwrap();
+        time::advance(Duration::from_secs(5)).await;
+        store.sweep_expired_once().await;
+        assert_eq!(Ok(Some(b"a".to_vec())), store.get(b"b").await);
+    }
+
+    #[tokio::test]
+    async fn rename_of_missing_key_is_an_error() {
+        let store = Store::new();
+
+        assert_eq!(
+            Err(StoreError::NoSuchKey),
+            store.rename(b"missing", b"new".to_vec()).await
+        );
+        assert_eq!(
+            Err(StoreError::NoSuchKey),
+            store.renamenx(b"missing", b"new".to_vec()).await
+        );
+    }
+
+    #[tokio::test]
+    async fn renamenx_only_moves_to_missing_keys() {
+        let store = Store::new();
+        store
+            .mset(vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"b".to_vec(), b"2".to_vec()),
+            ])
+            .await;
+
+        assert_eq!(Ok(false), store.renamenx(b"a", b"b".to_vec()).await);
+        assert_eq!(Ok(true), store.renamenx(b"a", b"c".to_vec()).await);
+        assert_eq!(
+            vec![None, Some(b"2".to_vec()), Some(b"1".to_vec())],
+            store
+                .mget(&[b"a".to_vec(), b"b".to_vec(), b"c".to_vec()])
+                .await
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn copy_respects_replace_and_copies_expiration() {
+        let store = Store::new();
+        let source = b"source".to_vec();
+        store
+            .sadd(source.clone(), vec![b"m".to_vec()])
+            .await
+            .unwrap();
+        store.expire(source.clone(), 5).await;
+        store
+            .set(b"taken".to_vec(), b"v".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+
+        assert!(!store.copy(&source, b"taken".to_vec(), false).await);
+        assert!(store.copy(&source, b"taken".to_vec(), true).await);
+        assert!(!store.copy(b"missing", b"other".to_vec(), true).await);
+        assert_eq!(Ok(vec![b"m".to_vec()]), store.smembers(b"taken").await);
+        assert_eq!(5, store.ttl(b"taken".to_vec()).await);
+
+        store
+            .sadd(source.clone(), vec![b"n".to_vec()])
+            .await
+            .unwrap();
+        assert_eq!(Ok(1), store.scard(b"taken").await);
+    }
+
+    #[tokio::test]
+    async fn random_key_and_dbsize_ignore_expired_keys() {
+        let store = Store::new();
+        assert_eq!(None, store.random_key().await);
+        assert_eq!(0, store.dbsize().await);
+
+        store
+            .mset(vec![
+                (b"live".to_vec(), b"1".to_vec()),
+                (b"expired".to_vec(), b"2".to_vec()),
+            ])
+            .await;
+        store.expire(b"expired".to_vec(), 0).await;
+
+        assert_eq!(1, store.dbsize().await);
+        for _ in 0..10 {
+            assert_eq!(Some(b"live".to_vec()), store.random_key().await);
+        }
+    }
+
+    async fn full_scan(store: &Store, count: usize, filter: &ScanFilter) -> Vec<Key> {
+        let mut cursor = 0;
+        let mut keys = Vec::new();
+        loop {
+            let (next, batch) = store.scan(cursor, count, filter).await;
+            keys.extend(batch);
+            if next == 0 {
+                return keys;
+            }
+            assert!(next > cursor, "cursor must move forward");
+            cursor = next;
+        }
+    }
+
+    #[tokio::test]
+    async fn scan_visits_every_key_exactly_once_when_unchanged() {
+        let store = Store::new();
+        let pairs: Vec<_> = (0..100)
+            .map(|i| (format!("key:{i}").into_bytes(), b"v".to_vec()))
+            .collect();
+        store.mset(pairs.clone()).await;
+
+        let mut keys = full_scan(&store, 7, &ScanFilter::default()).await;
+        keys.sort();
+        let mut expected: Vec<_> = pairs.into_iter().map(|(k, _)| k).collect();
+        expected.sort();
+        assert_eq!(expected, keys);
+    }
+
+    #[tokio::test]
+    async fn scan_returns_stable_keys_while_map_grows_and_shrinks() {
+        let store = Store::new();
+        let stable: Vec<_> = (0..50)
+            .map(|i| (format!("stable:{i}").into_bytes(), b"v".to_vec()))
+            .collect();
+        store.mset(stable.clone()).await;
+
+        let mut cursor = 0;
+        let mut seen = HashSet::new();
+        let mut churn_keys = Vec::new();
+        for round in 0.. {
+            let (next, batch) = store.scan(cursor, 5, &ScanFilter::default()).await;
+            seen.extend(batch);
+            // Force rehashing between calls by replacing a large batch of
+            // unrelated keys, growing the map on even rounds.
+            store.del_many(&churn_keys).await;
+            let size = if round % 2 == 0 { 400 } else { 20 };
+            let churn: Vec<_> = (0..size)
+                .map(|i| (format!("churn:{round}:{i}").into_bytes(), b"v".to_vec()))
+                .collect();
+            churn_keys = churn.iter().map(|(k, _)| k.clone()).collect();
+            store.mset(churn).await;
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        for (key, _) in stable {
+            assert!(seen.contains(&key), "missing {key:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn scan_follows_renamed_and_deleted_keys() {
+        let store = Store::new();
+        store
+            .mset(vec![
+                (b"a".to_vec(), b"v".to_vec()),
+                (b"b".to_vec(), b"v".to_vec()),
+                (b"c".to_vec(), b"v".to_vec()),
+            ])
+            .await;
+        store.rename(b"a", b"renamed".to_vec()).await.unwrap();
+        store.del_many(&[b"b"]).await;
+
+        let mut keys = full_scan(&store, 1, &ScanFilter::default()).await;
+        keys.sort();
+        assert_eq!(vec![b"c".to_vec(), b"renamed".to_vec()], keys);
+    }
+
+    #[tokio::test]
+    async fn scan_applies_match_and_type_filters() {
+        let store = Store::new();
+        store
+            .mset(vec![
+                (b"user:1".to_vec(), b"v".to_vec()),
+                (b"user:2".to_vec(), b"v".to_vec()),
+                (b"order:1".to_vec(), b"v".to_vec()),
+            ])
+            .await;
+        store
+            .sadd(b"user:set".to_vec(), vec![b"m".to_vec()])
+            .await
+            .unwrap();
+
+        let filter = ScanFilter {
+            pattern: Some(Bytes::from_static(b"user:*")),
+            type_name: Some(Bytes::from_static(b"STRING")),
+        };
+        let mut keys = full_scan(&store, 10, &filter).await;
+        keys.sort();
+        assert_eq!(vec![b"user:1".to_vec(), b"user:2".to_vec()], keys);
+    }
+
+    #[tokio::test]
+    async fn keys_returns_live_matching_keys() {
+        let store = Store::new();
+        store
+            .mset(vec![
+                (b"user:1".to_vec(), b"v".to_vec()),
+                (b"user:2".to_vec(), b"v".to_vec()),
+                (b"order:1".to_vec(), b"v".to_vec()),
+            ])
+            .await;
+        store.expire(b"user:2".to_vec(), 0).await;
+
+        assert_eq!(vec![b"user:1".to_vec()], store.keys(b"user:*").await);
+    }
+
+    #[tokio::test]
+    async fn watch_is_dirtied_only_by_writes_to_watched_keys() {
+        let store = Store::new();
+        let watched = b"watched".to_vec();
+        let mut watch = store.watch();
+        watch.add(vec![watched.clone()]);
+
+        store
+            .set(b"other".to_vec(), b"v".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        store.get(&watched).await.unwrap();
+        assert_eq!(store.del(&watched).await, None);
+        assert!(!watch.is_dirty());
+
+        store.incr_by(watched.clone(), 1).await.unwrap();
+        assert!(watch.is_dirty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn watch_is_dirtied_by_expiry() {
+        let store = Store::new();
+        let key = b"key".to_vec();
+        store
+            .set(key.clone(), b"v".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        store.expire(key.clone(), 1).await;
+        let mut watch = store.watch();
+        watch.add(vec![key]);
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        store.sweep_expired_once().await;
+        assert!(watch.is_dirty());
+    }
+
+    #[tokio::test]
+    async fn writes_count_as_dirty_until_saved() {
+        let store = Store::new();
+        store
+            .set(b"a".to_vec(), b"1".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        store.get(b"a").await.unwrap();
+        store.incr_by(b"a".to_vec(), 1).await.unwrap();
+        assert_eq!(store.dirty(), 2);
+
+        let saving = store.dirty();
+        store.incr_by(b"a".to_vec(), 1).await.unwrap();
+        store.mark_saved(saving);
+        assert_eq!(store.dirty(), 1);
+    }
+
+    #[tokio::test]
+    async fn dropping_a_watch_unregisters_its_keys() {
+        let store = Store::new();
+        let key = b"key".to_vec();
+        let mut first = store.watch();
+        first.add(vec![key.clone(), key.clone()]);
+        let mut second = store.watch();
+        second.add(vec![key.clone()]);
+        drop(first);
+        assert_eq!(store.watchers.lock().unwrap()[&key].len(), 1);
+
+        drop(second);
+        assert!(store.watchers.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keyspace_notifications_follow_enabled_events() {
+        let store = Store::new();
+        let hub = Hub::new();
+        let mut subscriber = hub.subscriber();
+        subscriber.psubscribe(b"__keyevent@0__:*".to_vec());
+        store.notify_keyspace_events(hub, "Eg$x".parse().unwrap());
+        let key = b"session".to_vec();
+        let event = |name: &[u8]| Message::PMessage {
+            pattern: b"__keyevent@0__:*".to_vec(),
+            channel: [b"__keyevent@0__:".as_slice(), name].concat(),
+            payload: b"session".to_vec(),
+        };
+
+        store
+            .set(key.clone(), b"v".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(subscriber.recv().await, Some(event(b"set")));
+        store.expire(key.clone(), 1).await;
+        assert_eq!(subscriber.recv().await, Some(event(b"expire")));
+        store
+            .push(b"list".to_vec(), vec![b"a".to_vec()], ListEnd::Left)
+            .await
+            .unwrap();
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        store.sweep_expired_once().await;
+        assert_eq!(subscriber.recv().await, Some(event(b"expired")));
+
+        store
+            .set(key.clone(), b"v".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(subscriber.recv().await, Some(event(b"set")));
+        store.del(&key).await;
+        assert_eq!(subscriber.recv().await, Some(event(b"del")));
+    }
+
+    fn blocked(pop: BlockingPop) -> ListWait {
+        match pop {
+            BlockingPop::Blocked(wait) => wait,
+            BlockingPop::Ready(..) => panic!("expected the pop to block"),
+        }
+    }
+
+    #[tokio::test]
+    async fn pop_or_block_serves_waiters_one_value_each_in_order() {
+        let store = Store::new();
+        let key = b"jobs".to_vec();
+        let keys = [b"other".to_vec(), key.clone()];
+        let mut first = blocked(
+            store
+                .pop_or_block(&keys, ListEnd::Left, None)
+                .await
+                .unwrap(),
+        );
+        let mut second = blocked(
+            store
+                .pop_or_block(&keys, ListEnd::Left, None)
+                .await
+                .unwrap(),
+        );
+
+        store
+            .push(key.clone(), vec![b"a".to_vec()], ListEnd::Right)
+            .await
+            .unwrap();
+        assert_eq!(first.recv().await.unwrap(), (key.clone(), b"a".to_vec()));
+        assert!(second.cancel().is_none(), "only one waiter should be woken");
+
+        let mut third = blocked(
+            store
+                .pop_or_block(&keys, ListEnd::Left, None)
+                .await
+                .unwrap(),
+        );
+        store
+            .push(
+                key.clone(),
+                vec![b"b".to_vec(), b"c".to_vec()],
+                ListEnd::Right,
+            )
+            .await
+            .unwrap();
+        assert_eq!(third.recv().await.unwrap(), (key.clone(), b"b".to_vec()));
+        assert_eq!(
+            store.lrange(&key, 0, -1).await.unwrap(),
+            vec![b"c".to_vec()]
+        );
+
+        assert!(matches!(
+            store.pop_or_block(&keys, ListEnd::Left, None).await,
+            Ok(BlockingPop::Ready(k, v)) if k == key && v == b"c"
+        ));
+    }
+
+    #[tokio::test]
+    async fn served_pops_are_recorded_once_started() {
+        let store = Store::new();
+        let source = b"jobs".to_vec();
+        let destination = (b"done".to_vec(), ListEnd::Left);
+        store
+            .push(source.clone(), vec![b"a".to_vec()], ListEnd::Right)
+            .await
+            .unwrap();
+        store
+            .pop_or_block(std::slice::from_ref(&source), ListEnd::Left, None)
+            .await
+            .unwrap();
+        assert!(store.take_served_pops().is_empty());
+
+        store.record_served_pops();
+        let mut waiting = blocked(
+            store
+                .pop_or_block(
+                    std::slice::from_ref(&source),
+                    ListEnd::Right,
+                    Some(destination.clone()),
+                )
+                .await
+                .unwrap(),
+        );
+        store
+            .push(source.clone(), vec![b"b".to_vec()], ListEnd::Right)
+            .await
+            .unwrap();
+        waiting.recv().await.unwrap();
+        store
+            .pop_or_block(std::slice::from_ref(&destination.0), ListEnd::Left, None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.take_served_pops(),
+            vec![
+                ServedPop {
+                    key: source,
+                    end: ListEnd::Right,
+                    destination: Some(destination.clone()),
+                    value: b"b".to_vec(),
+                },
+                ServedPop {
+                    key: destination.0,
+                    end: ListEnd::Left,
+                    destination: None,
+                    value: b"b".to_vec(),
+                },
+            ]
+        );
+        assert!(store.take_served_pops().is_empty());
+    }
 
-impl From<StoreValue> for SnapshotValue {
-    fn from(store_value: StoreValue) -> Self {
-        let StoreValue {
-            value,
-            expiration_time,
-        } = store_value;
-        let unix_now_millis = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("System Time is set before Unix Epoch")
-            .as_millis();
-        let store_now = Instant::now();
-        Self {
-            value,
-            expiration_time_unix: expiration_time
-                .map(|t| t.saturating_duration_since(store_now).as_millis() + unix_now_millis),
+    #[tokio::test]
+    async fn cancelled_and_dropped_waits_leave_values_in_the_list() {
+        let store = Store::new();
+        let key = b"jobs".to_vec();
+        let mut cancelled = blocked(
+            store
+                .pop_or_block(std::slice::from_ref(&key), ListEnd::Left, None)
+                .await
+                .unwrap(),
+        );
+        let dropped = blocked(
+            store
+                .pop_or_block(std::slice::from_ref(&key), ListEnd::Left, None)
+                .await
+                .unwrap(),
+        );
+        assert!(cancelled.cancel().is_none());
+        drop(dropped);
+        assert!(
+            store
+                .list_waiters
+                .lock()
+                .unwrap()
+                .get(&key)
+                .is_none_or(|q| q.iter().all(|w| w.slot.lock().unwrap().is_none()))
+        );
+
+        store
+            .push(key.clone(), vec![b"a".to_vec()], ListEnd::Left)
+            .await
+            .unwrap();
+        assert_eq!(store.llen(&key).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancel_returns_a_value_delivered_before_it() {
+        let store = Store::new();
+        let key = b"jobs".to_vec();
+        let mut wait = blocked(
+            store
+                .pop_or_block(std::slice::from_ref(&key), ListEnd::Right, None)
+                .await
+                .unwrap(),
+        );
+        store
+            .push(key.clone(), vec![b"a".to_vec()], ListEnd::Left)
+            .await
+            .unwrap();
+        assert_eq!(wait.cancel(), Some(Ok((key.clone(), b"a".to_vec()))));
+        assert_eq!(store.llen(&key).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn blocked_move_pushes_to_destination_and_serves_its_waiters() {
+        let store = Store::new();
+        let source = b"source".to_vec();
+        let destination = b"destination".to_vec();
+        let mut mover = blocked(
+            store
+                .pop_or_block(
+                    std::slice::from_ref(&source),
+                    ListEnd::Left,
+                    Some((destination.clone(), ListEnd::Right)),
+                )
+                .await
+                .unwrap(),
+        );
+        let mut consumer = blocked(
+            store
+                .pop_or_block(std::slice::from_ref(&destination), ListEnd::Left, None)
+                .await
+                .unwrap(),
+        );
+
+        store
+            .push(source.clone(), vec![b"a".to_vec()], ListEnd::Left)
+            .await
+            .unwrap();
+        assert_eq!(mover.recv().await.unwrap(), (source.clone(), b"a".to_vec()));
+        assert_eq!(
+            consumer.recv().await.unwrap(),
+            (destination.clone(), b"a".to_vec())
+        );
+        assert_eq!(store.llen(&source).await.unwrap(), 0);
+        assert_eq!(store.llen(&destination).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn pop_or_block_rejects_non_list_keys_and_destinations() {
+        let store = Store::new();
+        store
+            .set(b"string".to_vec(), b"v".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        store
+            .push(b"list".to_vec(), vec![b"a".to_vec()], ListEnd::Left)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            store
+                .pop_or_block(&[b"string".to_vec()], ListEnd::Left, None)
+                .await,
+            Err(StoreError::WrongType)
+        ));
+        assert!(matches!(
+            store
+                .pop_or_block(
+                    &[b"list".to_vec()],
+                    ListEnd::Left,
+                    Some((b"string".to_vec(), ListEnd::Left))
+                )
+                .await,
+            Err(StoreError::WrongType)
+        ));
+        assert_eq!(store.llen(b"list").await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn incr_by_starts_missing_keys_at_zero() {
+        let store = Store::new();
+        let key = b"counter".to_vec();
+
+        assert_eq!(Ok(1), store.incr_by(key.clone(), 1).await);
+        assert_eq!(Ok(-4), store.incr_by(key.clone(), -5).await);
+        assert_eq!(Ok(Some(b"-4".to_vec())), store.get(&key).await);
+    }
+
+    #[tokio::test]
+    async fn incr_by_reports_errors_and_leaves_value_unchanged() {
+        let store = Store::new();
+        let key = b"counter".to_vec();
+
+        store
+            .set(key.clone(), b"abc".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            Err(StoreError::NotInteger),
+            store.incr_by(key.clone(), 1).await
+        );
+
+        store
+            .set(
+                key.clone(),
+                i64::MAX.to_string().into_bytes(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            Err(StoreError::Overflow),
+            store.incr_by(key.clone(), 1).await
+        );
+        assert_eq!(
+            Ok(Some(i64::MAX.to_string().into_bytes())),
+            store.get(&key).await
+        );
+
+        store
+            .push(b"list".to_vec(), vec![b"a".to_vec()], ListEnd::Left)
+            .await
+            .unwrap();
+        assert_eq!(
+            Err(StoreError::WrongType),
+            store.incr_by(b"list".to_vec(), 1).await
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn incr_by_preserves_expiration() {
+        let store = Store::new();
+        let key = b"counter".to_vec();
+        store
+            .set(key.clone(), b"10".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        store.expire(key.clone(), 5).await;
+
+        assert_eq!(Ok(11), store.incr_by(key.clone(), 1).await);
+        assert_eq!(Ok(11.5), store.incr_by_float(key.clone(), 0.5).await);
+        assert_eq!(5, store.ttl(key.clone()).await);
+
+        time::advance(Duration::from_secs(5)).await;
+        assert_eq!(Ok(None), store.get(&key).await);
+    }
+
+    #[tokio::test]
+    async fn concurrent_incr_by_loses_no_updates() {
+        let store = Store::new();
+        let key = b"counter".to_vec();
+
+        let tasks: Vec<_> = (0..50)
+            .map(|_| {
+                let store = store.clone();
+                let key = key.clone();
+                tokio::spawn(async move { store.incr_by(key, 1).await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
         }
+
+        assert_eq!(Ok(Some(b"50".to_vec())), store.get(&key).await);
+    }
+
+    #[tokio::test]
+    async fn incr_by_float_formats_result_and_reports_errors() {
+        let store = Store::new();
+        let key = b"float".to_vec();
+
+        store
+            .set(key.clone(), b"10.5".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(Ok(10.6), store.incr_by_float(key.clone(), 0.1).await);
+        assert_eq!(Ok(Some(b"10.6".to_vec())), store.get(&key).await);
+
+        store
+            .set(key.clone(), b"5.0e3".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(Ok(5200.0), store.incr_by_float(key.clone(), 200.0).await);
+        assert_eq!(Ok(Some(b"5200".to_vec())), store.get(&key).await);
+
+        store
+            .set(key.clone(), b"1.7e308".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            Err(StoreError::NonFiniteResult),
+            store.incr_by_float(key.clone(), 1.7e308).await
+        );
+        assert_eq!(Ok(Some(b"1.7e308".to_vec())), store.get(&key).await);
+
+        store
+            .set(key.clone(), b"abc".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            Err(StoreError::NotFloat),
+            store.incr_by_float(key.clone(), 1.0).await
+        );
+    }
+
+    #[tokio::test]
+    async fn hincrby_parses_existing_value_and_reports_errors() {
+        let store = Store::new();
+        let key = b"hash-key".to_vec();
+
+        assert_eq!(Ok(5), store.hincrby(key.clone(), b"n".to_vec(), 5).await);
+        assert_eq!(Ok(2), store.hincrby(key.clone(), b"n".to_vec(), -3).await);
+
+        store
+            .hset(key.clone(), vec![(b"text".to_vec(), b"abc".to_vec())])
+            .await
+            .unwrap();
+        assert_eq!(
+            Err(StoreError::NotInteger),
+            store.hincrby(key.clone(), b"text".to_vec(), 1).await
+        );
+
+        store
+            .hset(
+                key.clone(),
+                vec![(b"big".to_vec(), i64::MAX.to_string().into_bytes())],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            Err(StoreError::Overflow),
+            store.hincrby(key.clone(), b"big".to_vec(), 1).await
+        );
+    }
+
+    #[tokio::test]
+    async fn hash_operations_reject_string_keys() {
+        let store = Store::new();
+        let key = b"string-key".to_vec();
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Err(StoreError::WrongType),
+            store
+                .hset(key.clone(), vec![(b"f".to_vec(), b"v".to_vec())])
+                .await
+        );
+        assert_eq!(Err(StoreError::WrongType), store.hget(&key, b"f").await);
+        assert_eq!(Err(StoreError::WrongType), store.hgetall(&key).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hash_round_trips_through_dump_with_ttl() {
+        let s = Store::new();
+        let key = b"hash-key".to_vec();
+        s.hset(key.clone(), vec![(b"\xF4\xFF".to_vec(), b"value".to_vec())])
+            .await
+            .unwrap();
+        s.expire(key.clone(), 5).await;
+
+        let bytes = s.dump().await.unwrap();
+        let s = Store::restore(&bytes).await.unwrap();
+
+        assert_eq!(Ok(Some(b"value".to_vec())), s.hget(&key, b"\xF4\xFF").await);
+        assert!(s.ttl(key.clone()).await > 0);
+        time::advance(Duration::from_secs(5)).await;
+        assert_eq!(Ok(0), s.hlen(&key).await);
+    }
+
+    fn sorted(mut members: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+        members.sort();
+        members
+    }
+
+    #[tokio::test]
+    async fn sadd_and_srem_track_membership() {
+        let store = Store::new();
+        let key = b"set-key".to_vec();
+
+        assert_eq!(
+            Ok(2),
+            store
+                .sadd(
+                    key.clone(),
+                    vec![b"a".to_vec(), b"b".to_vec(), b"a".to_vec()]
+                )
+                .await
+        );
+        assert_eq!(Ok(0), store.sadd(key.clone(), vec![b"b".to_vec()]).await);
+        assert_eq!(Ok(true), store.sismember(&key, b"a").await);
+        assert_eq!(Ok(2), store.scard(&key).await);
+        assert_eq!(
+            vec![b"a".to_vec(), b"b".to_vec()],
+            sorted(store.smembers(&key).await.unwrap())
+        );
+
+        assert_eq!(
+            Ok(2),
+            store
+                .srem(&key, &[b"a".to_vec(), b"b".to_vec(), b"c".to_vec()])
+                .await
+        );
+        assert!(!store.hashmap.read().await.contains_key(&key));
+    }
+
+    #[tokio::test]
+    async fn set_operations_combine_sets_and_treat_missing_keys_as_empty() {
+        let store = Store::new();
+        let a = b"a".to_vec();
+        let b = b"b".to_vec();
+        let missing = b"missing".to_vec();
+        store
+            .sadd(a.clone(), vec![b"1".to_vec(), b"2".to_vec(), b"3".to_vec()])
+            .await
+            .unwrap();
+        store
+            .sadd(b.clone(), vec![b"2".to_vec(), b"3".to_vec(), b"4".to_vec()])
+            .await
+            .unwrap();
+
+        let inter = store
+            .set_operation(SetOperation::Intersection, &[a.clone(), b.clone()])
+            .await
+            .unwrap();
+        let union = store
+            .set_operation(
+                SetOperation::Union,
+                &[a.clone(), b.clone(), missing.clone()],
+            )
+            .await
+            .unwrap();
+        let diff = store
+            .set_operation(SetOperation::Difference, &[a.clone(), b.clone()])
+            .await
+            .unwrap();
+        let inter_missing = store
+            .set_operation(SetOperation::Intersection, &[a.clone(), missing])
+            .await
+            .unwrap();
+
+        assert_eq!(vec![b"2".to_vec(), b"3".to_vec()], sorted(inter));
+        assert_eq!(
+            vec![b"1".to_vec(), b"2".to_vec(), b"3".to_vec(), b"4".to_vec()],
+            sorted(union)
+        );
+        assert_eq!(vec![b"1".to_vec()], diff);
+        assert!(inter_missing.is_empty());
+    }
+
+    #[tokio::test]
+    async fn set_operation_store_replaces_destination_and_deletes_on_empty() {
+        let store = Store::new();
+        let a = b"a".to_vec();
+        let b = b"b".to_vec();
+        let dest = b"dest".to_vec();
+        store.sadd(a.clone(), vec![b"1".to_vec()]).await.unwrap();
+        store.sadd(b.clone(), vec![b"2".to_vec()]).await.unwrap();
+        store
+            .set(dest.clone(), b"string".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        store.expire(dest.clone(), 60).await;
+
+        assert_eq!(
+            Ok(2),
+            store
+                .set_operation_store(SetOperation::Union, dest.clone(), &[a.clone(), b.clone()])
+                .await
+        );
+        assert_eq!(Ok(2), store.scard(&dest).await);
+        assert_eq!(-1, store.ttl(dest.clone()).await);
+
+        assert_eq!(
+            Ok(0),
+            store
+                .set_operation_store(SetOperation::Intersection, dest.clone(), &[a, b])
+                .await
+        );
+        assert_eq!(-2, store.ttl(dest).await);
+    }
+
+    #[tokio::test]
+    async fn set_operations_reject_non_set_keys() {
+        let store = Store::new();
+        let set_key = b"set-key".to_vec();
+        let string_key = b"string-key".to_vec();
+        store
+            .sadd(set_key.clone(), vec![b"1".to_vec()])
+            .await
+            .unwrap();
+        store
+            .set(string_key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Err(StoreError::WrongType),
+            store
+                .set_operation(SetOperation::Union, &[set_key, string_key.clone()])
+                .await
+        );
+        assert_eq!(
+            Err(StoreError::WrongType),
+            store.sadd(string_key, vec![b"1".to_vec()]).await
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn set_round_trips_through_dump_with_ttl() {
+        let s = Store::new();
+        let key = b"set-key".to_vec();
+        s.sadd(key.clone(), vec![b"a".to_vec(), b"\xF4\xFF".to_vec()])
+            .await
+            .unwrap();
+        s.expire(key.clone(), 5).await;
+
+        let bytes = s.dump().await.unwrap();
+        let s = Store::restore(&bytes).await.unwrap();
+
+        assert_eq!(
+            vec![b"a".to_vec(), b"\xF4\xFF".to_vec()],
+            sorted(s.smembers(&key).await.unwrap())
+        );
+        assert!(s.ttl(key.clone()).await > 0);
+        time::advance(Duration::from_secs(5)).await;
+        assert_eq!(Ok(0), s.scard(&key).await);
+    }
+
+    fn scored(pairs: &[(&[u8], f64)]) -> Vec<(Vec<u8>, f64)> {
+        pairs.iter().map(|(m, s)| (m.to_vec(), *s)).collect()
+    }
+
+    #[tokio::test]
+    async fn zadd_orders_members_and_reports_rank() {
+        let store = Store::new();
+        let key = b"zset-key".to_vec();
+
+        assert_eq!(
+            Ok(3),
+            store
+                .zadd(
+                    key.clone(),
+                    vec![
+                        (2.0, b"b".to_vec()),
+                        (1.0, b"a".to_vec()),
+                        (3.0, b"c".to_vec())
+                    ],
+                    ZAddOptions::default(),
+                )
+                .await
+        );
+        assert_eq!(
+            Ok(scored(&[(b"a", 1.0), (b"b", 2.0), (b"c", 3.0)])),
+            store.zrange(&key, 0, -1).await
+        );
+        assert_eq!(Ok(Some(2)), store.zrank(&key, b"c").await);
+        assert_eq!(Ok(None), store.zrank(&key, b"missing").await);
+        assert_eq!(Ok(3), store.zcard(&key).await);
     }
-}
 
-impl From<&StoreValue> for SnapshotValue {
-    fn from(store_value: &StoreValue) -> Self {
-        let StoreValue {
-            value,
-            expiration_time,
-        } = store_value.clone();
-        let unix_now_millis = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time before UNIX epoch")
-            .as_millis();
-        let store_now = Instant::now();
-        Self {
-            value,
-            expiration_time_unix: expiration_time
-                .map(|t| t.saturating_duration_since(store_now).as_millis() + unix_now_millis),
-        }
-    }
-}
+    #[tokio::test]
+    async fn zadd_flags_control_inserts_and_updates() {
+        let store = Store::new();
+        let key = b"zset-key".to_vec();
+        store
+            .zadd(
+                key.clone(),
+                vec![(5.0, b"a".to_vec())],
+                ZAddOptions::default(),
+            )
+            .await
+            .unwrap();
 
-impl TryFrom<SnapshotValue> for StoreValue {
-    type Error = SnapshotError;
+        let only_existing = ZAddOptions {
+            condition: Some(ZAddCondition::OnlyExisting),
+            changed: true,
+            ..ZAddOptions::default()
+        };
+        assert_eq!(
+            Ok(1),
+            store
+                .zadd(
+                    key.clone(),
+                    vec![(6.0, b"a".to_vec()), (1.0, b"new".to_vec())],
+                    only_existing
+                )
+                .await
+        );
+        assert_eq!(Ok(None), store.zscore(&key, b"new").await);
 
-    fn try_from(snapshot_value: SnapshotValue) -> Result<Self, Self::Error> {
-        let SnapshotValue {
-            value,
-            expiration_time_unix,
-        } = snapshot_value;
-        let unix_now_millis = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time before UNIX epoch")
-            .as_millis();
-        let store_now = Instant::now();
-        Ok(Self {
-            value,
-            expiration_time: expiration_time_unix
-                .map(|t| -> Result<Instant, SnapshotError> {
-                    let remaining = t.saturating_sub(unix_now_millis);
-                    let remaining_millis =
-                        u64::try_from(remaining).map_err(|_| SnapshotError::DurationOverflow)?;
-                    store_now
-                        .checked_add(Duration::from_millis(remaining_millis))
-                        .ok_or(SnapshotError::DurationOverflow)
-                })
-                .transpose()?,
-        })
+        let greater_than = ZAddOptions {
+            comparison: Some(ZAddComparison::GreaterThan),
+            ..ZAddOptions::default()
+        };
+        store
+            .zadd(key.clone(), vec![(1.0, b"a".to_vec())], greater_than)
+            .await
+            .unwrap();
+        assert_eq!(Ok(Some(6.0)), store.zscore(&key, b"a").await);
+
+        let only_new = ZAddOptions {
+            condition: Some(ZAddCondition::OnlyNew),
+            ..ZAddOptions::default()
+        };
+        assert_eq!(
+            Ok(0),
+            store
+                .zadd(key.clone(), vec![(9.0, b"a".to_vec())], only_new)
+                .await
+        );
+        assert_eq!(Ok(Some(6.0)), store.zscore(&key, b"a").await);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tokio::sync::Barrier;
-    use tokio::time::{self, sleep};
+    #[tokio::test]
+    async fn zadd_of_only_existing_members_leaves_a_missing_key_alone() {
+        let store = Store::new();
+        let dirty = store.dirty();
+        let only_existing = ZAddOptions {
+            condition: Some(ZAddCondition::OnlyExisting),
+            ..ZAddOptions::default()
+        };
+
+        assert_eq!(
+            Ok(0),
+            store
+                .zadd(
+                    b"zset-key".to_vec(),
+                    vec![(1.0, b"a".to_vec())],
+                    only_existing
+                )
+                .await
+        );
+        assert_eq!(dirty, store.dirty());
+        assert_eq!(Ok(0), store.zcard(b"zset-key").await);
+    }
 
     #[tokio::test]
-    async fn set_then_get() {
+    async fn zrangebyscore_applies_bounds_and_limit() {
         let store = Store::new();
+        let key = b"zset-key".to_vec();
         store
-            .set("newkey".as_bytes().to_vec(), "newvalue".as_bytes().to_vec())
-            .await;
+            .zadd(
+                key.clone(),
+                vec![
+                    (1.0, b"a".to_vec()),
+                    (2.0, b"b".to_vec()),
+                    (3.0, b"c".to_vec()),
+                    (4.0, b"d".to_vec()),
+                ],
+                ZAddOptions::default(),
+            )
+            .await
+            .unwrap();
+
         assert_eq!(
-            Some("newvalue".as_bytes().to_vec()),
-            store.get(&"newkey".as_bytes().to_vec()).await
-        )
+            Ok(scored(&[(b"b", 2.0), (b"c", 3.0)])),
+            store
+                .zrangebyscore(
+                    &key,
+                    ScoreBound::Exclusive(1.0),
+                    ScoreBound::Inclusive(3.0),
+                    None
+                )
+                .await
+        );
+        assert_eq!(
+            Ok(scored(&[(b"c", 3.0)])),
+            store
+                .zrangebyscore(
+                    &key,
+                    ScoreBound::Inclusive(f64::NEG_INFINITY),
+                    ScoreBound::Inclusive(f64::INFINITY),
+                    Some((2, 1))
+                )
+                .await
+        );
+        assert_eq!(
+            Ok(scored(&[(b"b", 2.0), (b"c", 3.0), (b"d", 4.0)])),
+            store
+                .zrangebyscore(
+                    &key,
+                    ScoreBound::Inclusive(f64::NEG_INFINITY),
+                    ScoreBound::Inclusive(f64::INFINITY),
+                    Some((1, -1))
+                )
+                .await
+        );
     }
 
     #[tokio::test]
-    async fn get_nonexistent_key() {
+    async fn zincrby_updates_score_and_rejects_nan() {
         let store = Store::new();
-        assert_eq!(None, store.get(&"newkey".as_bytes().to_vec()).await)
+        let key = b"zset-key".to_vec();
+
+        assert_eq!(
+            Ok(2.5),
+            store.zincrby(key.clone(), 2.5, b"a".to_vec()).await
+        );
+        assert_eq!(
+            Ok(1.0),
+            store.zincrby(key.clone(), -1.5, b"a".to_vec()).await
+        );
+        store
+            .zincrby(key.clone(), f64::INFINITY, b"b".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(
+            Err(StoreError::NotANumber),
+            store
+                .zincrby(key.clone(), f64::NEG_INFINITY, b"b".to_vec())
+                .await
+        );
+        assert_eq!(Ok(Some(f64::INFINITY)), store.zscore(&key, b"b").await);
     }
 
     #[tokio::test]
-    async fn delete_existing_key() {
+    async fn zrem_deletes_empty_sorted_set() {
         let store = Store::new();
+        let key = b"zset-key".to_vec();
         store
-            .set("newkey".as_bytes().to_vec(), "newvalue".as_bytes().to_vec())
-            .await;
-        assert!(store.del(&"newkey".as_bytes().to_vec()).await.is_some())
+            .zadd(
+                key.clone(),
+                vec![(1.0, b"a".to_vec())],
+                ZAddOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Ok(1),
+            store.zrem(&key, &[b"a".to_vec(), b"b".to_vec()]).await
+        );
+        assert!(!store.hashmap.read().await.contains_key(&key));
     }
 
     #[tokio::test]
-    async fn delete_nonexistent_key() {
+    async fn sorted_set_operations_reject_other_types() {
         let store = Store::new();
-        assert!(store.del(&"newkey".as_bytes().to_vec()).await.is_none())
+        let key = b"string-key".to_vec();
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Err(StoreError::WrongType),
+            store
+                .zadd(
+                    key.clone(),
+                    vec![(1.0, b"a".to_vec())],
+                    ZAddOptions::default()
+                )
+                .await
+        );
+        assert_eq!(
+            Err(StoreError::WrongType),
+            store.zincrby(key.clone(), 1.0, b"a".to_vec()).await
+        );
+        assert_eq!(Err(StoreError::WrongType), store.zrank(&key, b"a").await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sorted_set_round_trips_through_dump_with_float_scores() {
+        let s = Store::new();
+        let key = b"zset-key".to_vec();
+        let members = vec![
+            (f64::NEG_INFINITY, b"low".to_vec()),
+            (0.1, b"tenth".to_vec()),
+            (1e300, b"huge".to_vec()),
+            (f64::INFINITY, b"high".to_vec()),
+        ];
+        s.zadd(key.clone(), members, ZAddOptions::default())
+            .await
+            .unwrap();
+        s.expire(key.clone(), 5).await;
+
+        let bytes = s.dump().await.unwrap();
+        let s = Store::restore(&bytes).await.unwrap();
+
+        assert_eq!(
+            Ok(scored(&[
+                (b"low", f64::NEG_INFINITY),
+                (b"tenth", 0.1),
+                (b"huge", 1e300),
+                (b"high", f64::INFINITY),
+            ])),
+            s.zrange(&key, 0, -1).await
+        );
+        assert!(s.ttl(key.clone()).await > 0);
+        time::advance(Duration::from_secs(5)).await;
+        assert_eq!(Ok(0), s.zcard(&key).await);
+    }
+
+    #[tokio::test]
+    async fn restore_rejects_invalid_sorted_set_score() {
+        let archive = br#"{"entries":[{"key":[0],"value":{"value":{"zset":[[[97],"not-a-score"]]},"expiration_time_unix":null}}]}"#;
+        assert!(matches!(
+            Store::restore(archive).await,
+            Err(RestoreError::InvalidData(SnapshotError::InvalidScore))
+        ));
     }
 
     #[tokio::test]
@@ -450,12 +4124,15 @@ mod tests {
         let key = b"expiring-key".to_vec();
         let value = b"value".to_vec();
 
-        store.set(key.clone(), value).await;
+        store
+            .set(key.clone(), value, SetOptions::default())
+            .await
+            .unwrap();
         assert_eq!(store.expire(key.clone(), 0).await, 1);
 
         sleep(Duration::from_millis(1)).await;
 
-        assert_eq!(None, store.get(&key).await);
+        assert_eq!(None, store.get(&key).await.unwrap());
     }
 
     #[tokio::test]
@@ -464,7 +4141,10 @@ mod tests {
         let key = b"expiring-key".to_vec();
         let value = b"value".to_vec();
 
-        store.set(key.clone(), value).await;
+        store
+            .set(key.clone(), value, SetOptions::default())
+            .await
+            .unwrap();
         assert_eq!(store.expire(key.clone(), 0).await, 1);
 
         sleep(Duration::from_millis(1)).await;
@@ -477,7 +4157,10 @@ mod tests {
         let store = Store::new();
         let key = b"ttl-key".to_vec();
 
-        store.set(key.clone(), b"value".to_vec()).await;
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
 
         assert_eq!(1, store.expire(key, 60).await);
     }
@@ -487,7 +4170,10 @@ mod tests {
         let store = Store::new();
         let key = b"ttl-key".to_vec();
 
-        store.set(key.clone(), b"value".to_vec()).await;
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
         assert_eq!(1, store.expire(key.clone(), 0).await);
 
         sleep(Duration::from_millis(1)).await;
@@ -508,7 +4194,10 @@ mod tests {
         let store = Store::new();
         let key = b"ttl-key".to_vec();
 
-        store.set(key.clone(), b"value".to_vec()).await;
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
 
         store.expire(key.clone(), 0).await;
 
@@ -523,7 +4212,10 @@ mod tests {
         let key = b"ttl-key".to_vec();
         let value = b"ttl-value".to_vec();
 
-        store.set(key.clone(), value).await;
+        store
+            .set(key.clone(), value, SetOptions::default())
+            .await
+            .unwrap();
 
         assert_eq!(-1, store.ttl(key).await);
     }
@@ -533,7 +4225,10 @@ mod tests {
         let store = Store::new();
         let key = b"ttl-key".to_vec();
 
-        store.set(key.clone(), b"value".to_vec()).await;
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
 
         store.expire(key.clone(), 100).await;
 
@@ -547,7 +4242,10 @@ mod tests {
         let store = Store::new();
         let key = b"ttl-key".to_vec();
 
-        store.set(key.clone(), b"value".to_vec()).await;
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
 
         store.expire(key.clone(), 100).await;
 
@@ -560,23 +4258,147 @@ mod tests {
         assert!(tick1 > tick2);
     }
 
+    #[tokio::test]
+    async fn set_with_conditions_only_writes_when_allowed() {
+        let store = Store::new();
+        let key = b"lock".to_vec();
+        let nx = SetOptions {
+            condition: Some(SetCondition::IfMissing),
+            ..Default::default()
+        };
+        let xx = SetOptions {
+            condition: Some(SetCondition::IfExists),
+            ..Default::default()
+        };
+
+        let outcome = store.set(key.clone(), b"a".to_vec(), xx).await.unwrap();
+        assert!(!outcome.written);
+        assert_eq!(Ok(None), store.get(&key).await);
+
+        let outcome = store.set(key.clone(), b"b".to_vec(), nx).await.unwrap();
+        assert!(outcome.written);
+        let outcome = store.set(key.clone(), b"c".to_vec(), nx).await.unwrap();
+        assert_eq!(
+            SetOutcome {
+                written: false,
+                previous: Some(b"b".to_vec()),
+            },
+            outcome
+        );
+
+        let outcome = store.set(key.clone(), b"d".to_vec(), xx).await.unwrap();
+        assert!(outcome.written);
+        assert_eq!(Ok(Some(b"d".to_vec())), store.get(&key).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn set_with_expiration_registers_it_for_sweeping() {
+        let store = Store::new();
+        let key = b"cache".to_vec();
+        let options = SetOptions {
+            expiration: Some(SetExpiration::After(Duration::from_millis(1500))),
+            ..Default::default()
+        };
+
+        store
+            .set(key.clone(), b"v".to_vec(), options)
+            .await
+            .unwrap();
+        assert_eq!(1, store.ttl(key.clone()).await);
+
+        time::advance(Duration::from_millis(1500)).await;
+        store.sweep_expired_once().await;
+        assert!(!store.hashmap.read().await.contains_key(&key));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn set_with_keepttl_retains_existing_expiration() {
+        let store = Store::new();
+        let key = b"cache".to_vec();
+        store
+            .set(key.clone(), b"old".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        store.expire(key.clone(), 30).await;
+
+        let options = SetOptions {
+            expiration: Some(SetExpiration::Keep),
+            ..Default::default()
+        };
+        store
+            .set(key.clone(), b"new".to_vec(), options)
+            .await
+            .unwrap();
+        assert_eq!(30, store.ttl(key.clone()).await);
+        assert_eq!(Ok(Some(b"new".to_vec())), store.get(&key).await);
+    }
+
+    #[tokio::test]
+    async fn set_with_unix_time_in_the_past_expires_immediately() {
+        let store = Store::new();
+        let key = b"cache".to_vec();
+        let options = SetOptions {
+            expiration: Some(SetExpiration::AtUnixMillis(1)),
+            ..Default::default()
+        };
+
+        let outcome = store
+            .set(key.clone(), b"v".to_vec(), options)
+            .await
+            .unwrap();
+        assert!(outcome.written);
+        assert_eq!(Ok(None), store.get(&key).await);
+    }
+
+    #[tokio::test]
+    async fn set_with_get_rejects_non_string_keys_without_writing() {
+        let store = Store::new();
+        let key = b"list".to_vec();
+        store
+            .push(key.clone(), vec![b"a".to_vec()], ListEnd::Left)
+            .await
+            .unwrap();
+        let options = SetOptions {
+            get: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Err(StoreError::WrongType),
+            store.set(key.clone(), b"v".to_vec(), options).await
+        );
+        assert_eq!(Ok(1), store.llen(&key).await);
+    }
+
     #[tokio::test(start_paused = true)]
     async fn setting_an_existing_key_with_ttl_clears_the_ttl() {
         let store = Store::new();
         let key = b"ttl-key".to_vec();
         let replacement_value = b"new-value".to_vec();
 
-        store.set(key.clone(), b"value".to_vec()).await;
+        store
+            .set(key.clone(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
         assert_eq!(1, store.expire(key.clone(), 60).await);
         assert_eq!(
-            Some(b"value".to_vec()),
-            store.set(key.clone(), replacement_value.clone()).await
+            Ok(SetOutcome {
+                written: true,
+                previous: Some(b"value".to_vec()),
+            }),
+            store
+                .set(
+                    key.clone(),
+                    replacement_value.clone(),
+                    SetOptions::default()
+                )
+                .await
         );
 
         tokio::time::advance(Duration::from_secs(60)).await;
         store.sweep_expired_once().await;
 
-        assert_eq!(Some(replacement_value), store.get(&key).await);
+        assert_eq!(Some(replacement_value), store.get(&key).await.unwrap());
         assert_eq!(store.ttl(key).await, -1)
     }
 
@@ -586,20 +4408,23 @@ mod tests {
         let key = b"ttl-key".to_vec();
         let value = b"value".to_vec();
 
-        store.set(key.clone(), value.clone()).await;
+        store
+            .set(key.clone(), value.clone(), SetOptions::default())
+            .await
+            .unwrap();
         assert_eq!(1, store.expire(key.clone(), 5).await);
         assert_eq!(1, store.expire(key.clone(), 10).await);
 
         tokio::time::advance(Duration::from_secs(5)).await;
         store.sweep_expired_once().await;
 
-        assert_eq!(Some(value.clone()), store.get(&key).await);
+        assert_eq!(Some(value.clone()), store.get(&key).await.unwrap());
         assert!(store.ttl(key.clone()).await > 0);
 
         tokio::time::advance(Duration::from_secs(5)).await;
         store.sweep_expired_once().await;
 
-        assert_eq!(None, store.get(&key).await);
+        assert_eq!(None, store.get(&key).await.unwrap());
         assert_eq!(-2, store.ttl(key).await);
     }
 
@@ -608,13 +4433,21 @@ mod tests {
         let store = Store::new();
         for i in 0..10 {
             let key: Vec<u8> = u8::to_le_bytes(i).to_vec();
-            store.set(key.clone(), b"value".to_vec()).await;
+            store
+                .set(key.clone(), b"value".to_vec(), SetOptions::default())
+                .await
+                .unwrap();
             store.expire(key, 0).await;
         }
         let persistent_key = b"persistent_key".to_vec();
         store
-            .set(persistent_key.clone(), b"this key should remain".to_vec())
-            .await;
+            .set(
+                persistent_key.clone(),
+                b"this key should remain".to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
         sleep(Duration::from_millis(1)).await;
         store.sweep_expired_once().await;
         let map = store.hashmap.read().await;
@@ -631,13 +4464,21 @@ mod tests {
         let store = Store::new();
         for i in 0..10 {
             let key: Vec<u8> = u8::to_le_bytes(i).to_vec();
-            store.set(key.clone(), b"value".to_vec()).await;
+            store
+                .set(key.clone(), b"value".to_vec(), SetOptions::default())
+                .await
+                .unwrap();
             store.expire(key, 0).await;
         }
         let persistent_key = b"persistent_key".to_vec();
         store
-            .set(persistent_key.clone(), b"this key should remain".to_vec())
-            .await;
+            .set(
+                persistent_key.clone(),
+                b"this key should remain".to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
         sleep(Duration::from_millis(1)).await;
         let map = store.hashmap.read().await;
         for i in 0..10 {
@@ -655,7 +4496,10 @@ mod tests {
 
         let store = Store::new();
         let key = b"ttl-key".to_vec();
-        store.set(key.clone(), b"my_val".to_vec()).await;
+        store
+            .set(key.clone(), b"my_val".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
 
         let task_a = {
             let store = store.clone();
@@ -687,12 +4531,12 @@ mod tests {
         tokio::time::advance(Duration::from_secs(5)).await;
         store.sweep_expired_once().await;
 
-        let after_five = store.get(&key).await;
+        let after_five = store.get(&key).await.unwrap();
 
         tokio::time::advance(Duration::from_secs(5)).await;
         store.sweep_expired_once().await;
 
-        let after_ten = store.get(&key).await;
+        let after_ten = store.get(&key).await.unwrap();
 
         assert!(after_five.is_none() || after_ten.is_none());
         assert_eq!(None, after_ten)
@@ -704,7 +4548,10 @@ mod tests {
 
         let store = Store::new();
         let key = b"ttl-key".to_vec();
-        store.set(key.clone(), b"my_val".to_vec()).await;
+        store
+            .set(key.clone(), b"my_val".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
 
         let task_a = {
             let store = store.clone();
@@ -732,37 +4579,40 @@ mod tests {
         let b = task_b.await.unwrap();
 
         assert!(a == 0 || a == 1);
-        assert!(b == Some(b"my_val".to_vec()) || b.is_none());
+        assert!(b == Some(Value::String(b"my_val".to_vec())) || b.is_none());
 
         store.sweep_expired_once().await;
 
-        assert_eq!(None, store.get(&key).await);
+        assert_eq!(None, store.get(&key).await.unwrap());
         assert_eq!(-2, store.ttl(key).await);
     }
 
     #[test]
     fn snapshot_from_store_value_preserves_value_and_none_expiration() {
         let store_value = StoreValue {
-            value: b"snapshot-value".to_vec(),
+            value: Value::String(b"snapshot-value".to_vec()),
             expiration_time: None,
         };
 
         let snapshot_value: SnapshotValue = store_value.into();
 
-        assert_eq!(snapshot_value.value, b"snapshot-value".to_vec());
+        assert_eq!(
+            snapshot_value.value,
+            SnapshotData::String(b"snapshot-value".to_vec())
+        );
         assert_eq!(snapshot_value.expiration_time_unix, None);
     }
 
     #[test]
     fn store_value_from_snapshot_preserves_value_and_none_expiration() {
         let snapshot_value = SnapshotValue {
-            value: b"snapshot-value".to_vec(),
+            value: SnapshotData::String(b"snapshot-value".to_vec()),
             expiration_time_unix: None,
         };
 
         let store_value: StoreValue = snapshot_value.try_into().expect("valid snapshot value");
 
-        assert_eq!(store_value.value, b"snapshot-value".to_vec());
+        assert_eq!(store_value.value, Value::String(b"snapshot-value".to_vec()));
         assert_eq!(store_value.expiration_time, None);
     }
 
@@ -773,7 +4623,7 @@ mod tests {
             .expect("Time before UNIX epoch")
             .as_millis();
         let store_value = StoreValue {
-            value: b"snapshot-value".to_vec(),
+            value: Value::String(b"snapshot-value".to_vec()),
             expiration_time: Some(Instant::now() + Duration::from_secs(5)),
         };
 
@@ -797,7 +4647,7 @@ mod tests {
             .expect("Time before UNIX epoch")
             .as_millis();
         let snapshot_value = SnapshotValue {
-            value: b"snapshot-value".to_vec(),
+            value: SnapshotData::String(b"snapshot-value".to_vec()),
             expiration_time_unix: Some(now_unix + 5_000),
         };
         let before = Instant::now();
@@ -809,7 +4659,7 @@ mod tests {
             .expiration_time
             .expect("Expected expiration time");
 
-        assert_eq!(store_value.value, b"snapshot-value".to_vec());
+        assert_eq!(store_value.value, Value::String(b"snapshot-value".to_vec()));
         assert!(expiration_time >= before + Duration::from_secs(4));
         assert!(expiration_time <= after + Duration::from_secs(5));
     }
@@ -825,21 +4675,21 @@ mod tests {
                 SnapshotEntry {
                     key: b"persistent-key".to_vec(),
                     value: SnapshotValue {
-                        value: b"persistent-value".to_vec(),
+                        value: SnapshotData::String(b"persistent-value".to_vec()),
                         expiration_time_unix: None,
                     },
                 },
                 SnapshotEntry {
                     key: b"future-key".to_vec(),
                     value: SnapshotValue {
-                        value: b"future-value".to_vec(),
+                        value: SnapshotData::String(b"future-value".to_vec()),
                         expiration_time_unix: Some(now_unix + 60_000),
                     },
                 },
                 SnapshotEntry {
                     key: b"expired-key".to_vec(),
                     value: SnapshotValue {
-                        value: b"expired-value".to_vec(),
+                        value: SnapshotData::String(b"expired-value".to_vec()),
                         expiration_time_unix: Some(now_unix.saturating_sub(1)),
                     },
                 },
@@ -852,13 +4702,13 @@ mod tests {
 
         assert_eq!(
             Some(b"persistent-value".to_vec()),
-            store.get(&b"persistent-key".to_vec()).await
+            store.get(b"persistent-key").await.unwrap()
         );
         assert_eq!(
             Some(b"future-value".to_vec()),
-            store.get(&b"future-key".to_vec()).await
+            store.get(b"future-key").await.unwrap()
         );
-        assert_eq!(None, store.get(&b"expired-key".to_vec()).await);
+        assert_eq!(None, store.get(b"expired-key").await.unwrap());
         assert_eq!(-1, store.ttl(b"persistent-key".to_vec()).await);
 
         let future_ttl = store.ttl(b"future-key".to_vec()).await;
@@ -874,14 +4724,29 @@ mod tests {
         let expired_key = b"roundtrip-expired".to_vec();
 
         store
-            .set(persistent_key.clone(), b"persistent-value".to_vec())
-            .await;
+            .set(
+                persistent_key.clone(),
+                b"persistent-value".to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
         store
-            .set(expiring_key.clone(), b"future-value".to_vec())
-            .await;
+            .set(
+                expiring_key.clone(),
+                b"future-value".to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
         store
-            .set(expired_key.clone(), b"expired-value".to_vec())
-            .await;
+            .set(
+                expired_key.clone(),
+                b"expired-value".to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
 
         assert_eq!(1, store.expire(expiring_key.clone(), 60).await);
         assert_eq!(1, store.expire(expired_key.clone(), 0).await);
@@ -895,13 +4760,13 @@ mod tests {
 
         assert_eq!(
             Some(b"persistent-value".to_vec()),
-            restored.get(&persistent_key).await
+            restored.get(&persistent_key).await.unwrap()
         );
         assert_eq!(
             Some(b"future-value".to_vec()),
-            restored.get(&expiring_key).await
+            restored.get(&expiring_key).await.unwrap()
         );
-        assert_eq!(None, restored.get(&expired_key).await);
+        assert_eq!(None, restored.get(&expired_key).await.unwrap());
         assert_eq!(-1, restored.ttl(persistent_key).await);
 
         let future_ttl = restored.ttl(expiring_key).await;
@@ -917,7 +4782,7 @@ mod tests {
     #[test]
     fn store_value_from_snapshot_rejects_unrepresentable_duration() {
         let snapshot_value = SnapshotValue {
-            value: b"snapshot-value".to_vec(),
+            value: SnapshotData::String(b"snapshot-value".to_vec()),
             expiration_time_unix: Some(u128::MAX),
         };
 
@@ -931,7 +4796,13 @@ mod tests {
     async fn truncated_archive_is_rejected_by_restore() {
         let s = Store::new();
         for i in 0u8..3 {
-            s.set(i.to_le_bytes().to_vec(), b"my_value".to_vec()).await;
+            s.set(
+                i.to_le_bytes().to_vec(),
+                b"my_value".to_vec(),
+                SetOptions::default(),
+            )
+            .await
+            .unwrap();
         }
         let truncated_dump = s.dump().await.unwrap();
         for i in 1..=5 {
@@ -965,6 +4836,15 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn restore_of_archives_with_empty_collections_fails() {
+        let archive = br#"{"entries":[{"key":[0],"value":{"value":{"list":[]},"expiration_time_unix":null}}]}"#;
+        assert!(matches!(
+            Store::restore(archive).await,
+            Err(RestoreError::InvalidData(SnapshotError::EmptyCollection))
+        ));
+    }
+
     #[tokio::test]
     async fn duplicate_keys_fail_even_if_one_key_is_expired() {
         let archive = br#"{"entries":[{"key":[0],"value":{"value":[109,121,95,118,97,108,117,101],"expiration_time_unix":null}},{"key":[0],"value":{"value":[109,121,95,118,97,108,117,101],"expiration_time_unix":0}},{"key":[2],"value":{"value":[109,121,95,118,97,108,117,101],"expiration_time_unix":null}}]}"#;
@@ -974,26 +4854,60 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn entries_hold_off_writes_before_calling_back() {
+        let s = Store::new();
+        s.set(b"key".to_vec(), b"value".to_vec(), SetOptions::default())
+            .await
+            .unwrap();
+        let mut called = false;
+        let entries = s
+            .entries_once_locked(|| {
+                assert!(s.hashmap.try_write().is_err());
+                called = true;
+            })
+            .await;
+        assert!(called);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key, b"key".to_vec());
+    }
+
     #[tokio::test(start_paused = true)]
     async fn dump_excludes_expired_entries() {
         let s = Store::new();
-        s.set(b"live_key".to_vec(), b"live_value".to_vec()).await;
+        s.set(
+            b"live_key".to_vec(),
+            b"live_value".to_vec(),
+            SetOptions::default(),
+        )
+        .await
+        .unwrap();
         s.expire(b"live_key".to_vec(), 1000).await;
-        s.set(b"expired_key".to_vec(), b"expired_value".to_vec())
-            .await;
+        s.set(
+            b"expired_key".to_vec(),
+            b"expired_value".to_vec(),
+            SetOptions::default(),
+        )
+        .await
+        .unwrap();
         s.expire(b"expired_key".to_vec(), 0).await;
-        s.set(b"persistent_key".to_vec(), b"persistent_value".to_vec())
-            .await;
+        s.set(
+            b"persistent_key".to_vec(),
+            b"persistent_value".to_vec(),
+            SetOptions::default(),
+        )
+        .await
+        .unwrap();
         let bytes = s.dump().await.unwrap();
         let s = Store::restore(&bytes).await.unwrap();
         time::advance(Duration::from_secs(5)).await;
         assert_eq!(
-            s.get(&b"live_key".to_vec()).await.unwrap(),
+            s.get(b"live_key").await.unwrap().unwrap(),
             b"live_value".to_vec()
         );
-        assert!(s.get(&b"expired_key".to_vec()).await.is_none());
+        assert!(s.get(b"expired_key").await.unwrap().is_none());
         assert_eq!(
-            s.get(&b"persistent_key".to_vec()).await.unwrap(),
+            s.get(b"persistent_key").await.unwrap().unwrap(),
             b"persistent_value".to_vec()
         );
     }
@@ -1001,29 +4915,51 @@ mod tests {
     #[tokio::test(start_paused = true)]
     async fn binary_values_preserved_e2e() {
         let s = Store::new();
-        s.set(b"empty_bytes_key".to_vec(), b"".to_vec()).await;
-        s.set(b"non_utf_bytes_key".to_vec(), b"\xF4\xFF".to_vec())
-            .await;
-        s.set(b"embedded_zero_key".to_vec(), b"hello\x00world".to_vec())
-            .await;
-        s.set(b"\xF4\xFF".to_vec(), b"value".to_vec()).await;
+        s.set(
+            b"empty_bytes_key".to_vec(),
+            b"".to_vec(),
+            SetOptions::default(),
+        )
+        .await
+        .unwrap();
+        s.set(
+            b"non_utf_bytes_key".to_vec(),
+            b"\xF4\xFF".to_vec(),
+            SetOptions::default(),
+        )
+        .await
+        .unwrap();
+        s.set(
+            b"embedded_zero_key".to_vec(),
+            b"hello\x00world".to_vec(),
+            SetOptions::default(),
+        )
+        .await
+        .unwrap();
+        s.set(
+            b"\xF4\xFF".to_vec(),
+            b"value".to_vec(),
+            SetOptions::default(),
+        )
+        .await
+        .unwrap();
         let bytes = s.dump().await.unwrap();
         let s = Store::restore(&bytes).await.unwrap();
         time::advance(Duration::from_secs(5)).await;
         assert_eq!(
-            s.get(&b"empty_bytes_key".to_vec()).await.unwrap(),
+            s.get(b"empty_bytes_key").await.unwrap().unwrap(),
             b"".to_vec()
         );
         assert_eq!(
-            s.get(&b"non_utf_bytes_key".to_vec()).await.unwrap(),
+            s.get(b"non_utf_bytes_key").await.unwrap().unwrap(),
             b"\xF4\xFF".to_vec()
         );
         assert_eq!(
-            s.get(&b"embedded_zero_key".to_vec()).await.unwrap(),
+            s.get(b"embedded_zero_key").await.unwrap().unwrap(),
             b"hello\x00world".to_vec()
         );
         assert_eq!(
-            s.get(&b"\xF4\xFF".to_vec()).await.unwrap(),
+            s.get(b"\xF4\xFF").await.unwrap().unwrap(),
             b"value".to_vec()
         );
     }
@@ -1031,16 +4967,22 @@ mod tests {
     #[tokio::test(start_paused = true)]
     async fn expiration_times_properly_preserved_e2e() {
         let s = Store::new();
-        s.set(b"live_key".to_vec(), b"live_value".to_vec()).await;
+        s.set(
+            b"live_key".to_vec(),
+            b"live_value".to_vec(),
+            SetOptions::default(),
+        )
+        .await
+        .unwrap();
         s.expire(b"live_key".to_vec(), 5).await;
         let bytes = s.dump().await.unwrap();
         let s = Store::restore(&bytes).await.unwrap();
         assert_eq!(
-            s.get(&b"live_key".to_vec()).await.unwrap(),
+            s.get(b"live_key").await.unwrap().unwrap(),
             b"live_value".to_vec()
         );
         time::advance(Duration::from_secs(5)).await;
-        assert!(s.get(&b"live_key".to_vec()).await.is_none());
+        assert!(s.get(b"live_key").await.unwrap().is_none());
     }
 
     #[tokio::test]
@@ -1,4 +1,9 @@
 use redlike::config::Config;
+use redlike::parser::{
+    DEFAULT_MAX_BULK_LEN, DEFAULT_MAX_INLINE_LEN, DEFAULT_MAX_MULTIBULK_LEN,
+    DEFAULT_MAX_NESTING_DEPTH,
+};
+use redlike::pubsub::DEFAULT_PUBSUB_BUFFER_LEN;
 use redlike::server::{ServerError, run_server};
 use std::net::SocketAddr;
 use std::path::PathBuf;
@@ -16,18 +21,41 @@ pub async fn setup_test_server_with_archive(
     listener_address: &str,
     archive_path: Option<PathBuf>,
 ) -> Result<(SocketAddr, JoinHandle<io::Result<()>>, CancellationToken), io::Error> {
-    let shutdown_token = CancellationToken::new();
+    let mut config = test_config(listener_address)?;
+    config.archive_path = archive_path;
+    setup_test_server_with_config(config).await
+}
+
+/// Returns the default configuration, listening on `listener_address`.
+pub fn test_config(listener_address: &str) -> io::Result<Config> {
     let socket_addr: SocketAddr = listener_address.parse().map_err(|err| {
         io::Error::new(
             ErrorKind::InvalidInput,
             format!("invalid test listener address: {err}"),
         )
     })?;
-    let config = Config {
+    Ok(Config {
         address: socket_addr.ip(),
         port: socket_addr.port(),
-        archive_path,
-    };
+        archive_path: None,
+        save: Default::default(),
+        aof_path: None,
+        appendfsync: Default::default(),
+        auto_aof_rewrite_percentage: 0,
+        auto_aof_rewrite_min_size: 0,
+        notify_keyspace_events: Default::default(),
+        proto_max_bulk_len: DEFAULT_MAX_BULK_LEN,
+        max_multibulk_len: DEFAULT_MAX_MULTIBULK_LEN,
+        max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+        max_inline_len: DEFAULT_MAX_INLINE_LEN,
+        pubsub_buffer_len: DEFAULT_PUBSUB_BUFFER_LEN,
+    })
+}
+
+pub async fn setup_test_server_with_config(
+    config: Config,
+) -> Result<(SocketAddr, JoinHandle<io::Result<()>>, CancellationToken), io::Error> {
+    let shutdown_token = CancellationToken::new();
     let (addr, handle) = run_server(&config, shutdown_token.clone())
         .await
         .map_err(server_error_to_io)?;
@@ -44,5 +72,6 @@ fn server_error_to_io(err: ServerError) -> io::Error {
     match err {
         ServerError::Io(err) => err,
         ServerError::Archive(err) => io::Error::other(err),
+        ServerError::AppendOnly(err) => io::Error::other(err),
     }
 }
@@ -1,4 +1,5 @@
 mod common;
+use bytes::Bytes;
 use common::setup_test_server::setup_test_server;
 use common::test_case::TestCase;
 use common::test_client::TestClient;
@@ -20,7 +21,7 @@ async fn e2e_sequential() -> tokio::io::Result<()> {
         },
         TestCase {
             call: b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n",
-            response: Frame::Bulk(Some(b"myvalue".to_vec())),
+            response: Frame::Bulk(Some(Bytes::from_static(b"myvalue"))),
             expected: "Should retrieve value of mykey: myvalue",
         },
         TestCase {
@@ -40,17 +41,29 @@ async fn e2e_sequential() -> tokio::io::Result<()> {
         },
         TestCase {
             call: b"*1\r\n$3\r\nFOO\r\n",
-            response: Frame::SimpleError("Unknown Command".into()),
+            response: Frame::SimpleError(
+                "ERR unknown command 'FOO', with args beginning with: ".into(),
+            ),
             expected: "Unknown command gives error",
         },
         TestCase {
             call: b"*4\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$7\r\nmyvalue\r\n$8\r\ntoo many\r\n",
-            response: Frame::SimpleError("Wrong number of arguments".into()),
-            expected: "Wrong number of arguments gives error",
+            response: Frame::SimpleError("ERR syntax error".into()),
+            expected: "Unknown SET option gives syntax error",
+        },
+        TestCase {
+            call: b"*6\r\n$3\r\nSET\r\n$4\r\nlock\r\n$5\r\nowner\r\n$2\r\nNX\r\n$2\r\nPX\r\n$5\r\n30000\r\n",
+            response: Frame::SimpleString("OK".into()),
+            expected: "SET NX PX should acquire a free lock",
+        },
+        TestCase {
+            call: b"*6\r\n$3\r\nSET\r\n$4\r\nlock\r\n$5\r\nother\r\n$2\r\nNX\r\n$2\r\nPX\r\n$5\r\n30000\r\n",
+            response: Frame::Bulk(None),
+            expected: "SET NX PX should not take a held lock",
         },
         TestCase {
             call: b"*1\r\n$3\r\nGET\r\n",
-            response: Frame::SimpleError("Wrong number of arguments".into()),
+            response: Frame::SimpleError("ERR wrong number of arguments for 'get' command".into()),
             expected: "Wrong number of arguments gives error",
         },
     ];
@@ -95,7 +108,7 @@ async fn e2e_inline_terminal_requests() -> tokio::io::Result<()> {
         },
         TestCase {
             call: b"GET mykey\n",
-            response: Frame::Bulk(Some(b"myvalue".to_vec())),
+            response: Frame::Bulk(Some(Bytes::from_static(b"myvalue"))),
             expected: "Inline GET should return the stored value",
         },
         TestCase {
@@ -114,18 +127,20 @@ async fn e2e_inline_terminal_requests() -> tokio::io::Result<()> {
             expected: "Inline DEL should return 0 for a missing key",
         },
         TestCase {
-            call: b"FOO\n",
-            response: Frame::SimpleError("Unknown Command".into()),
+            call: b"FOO bar\n",
+            response: Frame::SimpleError(
+                "ERR unknown command 'FOO', with args beginning with: 'bar' ".into(),
+            ),
             expected: "Inline unknown commands should return an error",
         },
         TestCase {
-            call: b"SET mykey myvalue too many\n",
-            response: Frame::SimpleError("Wrong number of arguments".into()),
+            call: b"SET mykey\n",
+            response: Frame::SimpleError("ERR wrong number of arguments for 'set' command".into()),
             expected: "Inline wrong-arity SET should return an error",
         },
         TestCase {
             call: b"GET\n",
-            response: Frame::SimpleError("Wrong number of arguments".into()),
+            response: Frame::SimpleError("ERR wrong number of arguments for 'get' command".into()),
             expected: "Inline wrong-arity GET should return an error",
         },
     ];
@@ -170,3 +185,176 @@ async fn e2e_blank_line_gets_no_response() -> tokio::io::Result<()> {
     handle.abort();
     Ok(())
 }
+
+#[tokio::test]
+async fn e2e_list_commands() -> tokio::io::Result<()> {
+    let test_case_sequential: Vec<TestCase> = vec![
+        TestCase {
+            call: b"*4\r\n$5\r\nRPUSH\r\n$5\r\nqueue\r\n$3\r\none\r\n$3\r\ntwo\r\n",
+            response: Frame::Integer(2),
+            expected: "RPUSH should return the new list length",
+        },
+        TestCase {
+            call: b"LPUSH queue zero\n",
+            response: Frame::Integer(3),
+            expected: "LPUSH should return the new list length",
+        },
+        TestCase {
+            call: b"LRANGE queue 0 -1\n",
+            response: Frame::Array(Some(vec![
+                Frame::Bulk(Some(Bytes::from_static(b"zero"))),
+                Frame::Bulk(Some(Bytes::from_static(b"one"))),
+                Frame::Bulk(Some(Bytes::from_static(b"two"))),
+            ])),
+            expected: "LRANGE should return the whole list in order",
+        },
+        TestCase {
+            call: b"RPOP queue\n",
+            response: Frame::Bulk(Some(Bytes::from_static(b"two"))),
+            expected: "RPOP should return the tail element",
+        },
+        TestCase {
+            call: b"LPOP queue 5\n",
+            response: Frame::Array(Some(vec![
+                Frame::Bulk(Some(Bytes::from_static(b"zero"))),
+                Frame::Bulk(Some(Bytes::from_static(b"one"))),
+            ])),
+            expected: "LPOP with a count should return up to count elements",
+        },
+        TestCase {
+            call: b"LLEN queue\n",
+            response: Frame::Integer(0),
+            expected: "Emptied lists should be removed",
+        },
+        TestCase {
+            call: b"SET plain value\n",
+            response: Frame::SimpleString("OK".into()),
+            expected: "Should set a string key",
+        },
+        TestCase {
+            call: b"LPUSH plain item\n",
+            response: Frame::SimpleError(
+                "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
+            ),
+            expected: "List commands on string keys should return WRONGTYPE",
+        },
+    ];
+
+    let (addr, handle, _shutdown) = setup_test_server(ADDR).await?;
+    let mut client = TestClient::new(addr).await?;
+
+    for TestCase {
+        call,
+        response,
+        expected,
+    } in test_case_sequential
+    {
+        client.write(call).await?;
+        let received = client.read_frame().await?;
+        assert!(
+            response == received,
+            "{} - Expected {:?}, Received {:?}",
+            expected,
+            response,
+            received
+        );
+    }
+
+    client.send_quit().await?;
+    handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn e2e_transactions() -> tokio::io::Result<()> {
+    let (addr, handle, _shutdown) = setup_test_server(ADDR).await?;
+    let mut client = TestClient::new(addr).await?;
+    let mut other = TestClient::new(addr).await?;
+    let ok = Frame::SimpleString("OK".into());
+    let queued = Frame::SimpleString("QUEUED".into());
+
+    for (call, response) in [
+        (&b"MULTI\n"[..], ok.clone()),
+        (b"SET balance 10\n", queued.clone()),
+        (b"INCRBY balance 5\n", queued.clone()),
+        (
+            b"EXEC\n",
+            Frame::Array(Some(vec![ok.clone(), Frame::Integer(15)])),
+        ),
+        (b"WATCH balance\n", ok.clone()),
+        (b"MULTI\n", ok.clone()),
+        (b"INCRBY balance 1\n", queued.clone()),
+    ] {
+        client.write(call).await?;
+        assert_eq!(client.read_frame().await?, response);
+    }
+
+    other.write(b"SET balance 0\n").await?;
+    assert_eq!(other.read_frame().await?, ok);
+
+    client.write(b"EXEC\n").await?;
+    assert_eq!(
+        client.read_frame().await?,
+        Frame::Array(None),
+        "EXEC should abort when a watched key was modified by another connection"
+    );
+
+    for (call, response) in [
+        (&b"MULTI\n"[..], ok.clone()),
+        (
+            b"GET\n",
+            Frame::SimpleError("ERR wrong number of arguments for 'get' command".into()),
+        ),
+        (
+            b"EXEC\n",
+            Frame::SimpleError(
+                "EXECABORT Transaction discarded because of previous errors.".into(),
+            ),
+        ),
+        (
+            b"GET balance\n",
+            Frame::Bulk(Some(Bytes::from_static(b"0"))),
+        ),
+    ] {
+        client.write(call).await?;
+        assert_eq!(client.read_frame().await?, response);
+    }
+
+    client.send_quit().await?;
+    other.send_quit().await?;
+    handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn e2e_blocking_pop_waits_for_push() -> tokio::io::Result<()> {
+    let (addr, handle, _shutdown) = setup_test_server(ADDR).await?;
+    let mut worker = TestClient::new(addr).await?;
+    let mut producer = TestClient::new(addr).await?;
+
+    worker.write(b"BLPOP jobs 0.05\n").await?;
+    assert_eq!(
+        worker.read_frame().await?,
+        Frame::Array(None),
+        "BLPOP should time out with a null array"
+    );
+
+    worker.write(b"BLPOP other jobs 0\n").await?;
+    producer.write(b"RPUSH jobs first second\n").await?;
+    assert_eq!(producer.read_frame().await?, Frame::Integer(2));
+    assert_eq!(
+        worker.read_frame().await?,
+        Frame::Array(Some(vec![
+            Frame::Bulk(Some(Bytes::from_static(b"jobs"))),
+            Frame::Bulk(Some(Bytes::from_static(b"first"))),
+        ]))
+    );
+
+    producer.write(b"LLEN jobs\n").await?;
+    assert_eq!(producer.read_frame().await?, Frame::Integer(1));
+
+    worker.send_quit().await?;
+    producer.send_quit().await?;
+    handle.abort();
+    Ok(())
+}
@@ -2,6 +2,11 @@ mod common;
 use common::test_client::TestClient;
 use redlike::config::Config;
 use redlike::frame::Frame;
+use redlike::parser::{
+    DEFAULT_MAX_BULK_LEN, DEFAULT_MAX_INLINE_LEN, DEFAULT_MAX_MULTIBULK_LEN,
+    DEFAULT_MAX_NESTING_DEPTH,
+};
+use redlike::pubsub::DEFAULT_PUBSUB_BUFFER_LEN;
 use redlike::server::{ServerError, run_server};
 use tokio::io;
 use tokio::task::JoinSet;
@@ -28,7 +33,7 @@ async fn test_all_commands(addr: std::net::SocketAddr) -> tokio::io::Result<()>
         assert!(
             matches!(
                 get_response,
-                Frame::Bulk(Some(ref bytes)) if bytes == b"myvalue"
+                Frame::Bulk(Some(ref bytes)) if bytes.as_ref() == b"myvalue"
             ) || matches!(get_response, Frame::Bulk(None))
         );
         client.write(b"*2\r\n$3\r\nDEL\r\n$5\r\nmykey\r\n").await?;
@@ -51,6 +56,17 @@ async fn get_set_del_same_record() -> tokio::io::Result<()> {
         address: "127.0.0.1".parse().unwrap(),
         port: 0,
         archive_path: None,
+        save: Default::default(),
+        aof_path: None,
+        appendfsync: Default::default(),
+        auto_aof_rewrite_percentage: 0,
+        auto_aof_rewrite_min_size: 0,
+        notify_keyspace_events: Default::default(),
+        proto_max_bulk_len: DEFAULT_MAX_BULK_LEN,
+        max_multibulk_len: DEFAULT_MAX_MULTIBULK_LEN,
+        max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+        max_inline_len: DEFAULT_MAX_INLINE_LEN,
+        pubsub_buffer_len: DEFAULT_PUBSUB_BUFFER_LEN,
     };
     let (addr, handle) = run_server(&config, shutdown)
         .await
@@ -74,5 +90,6 @@ fn server_error_to_io(err: ServerError) -> io::Error {
     match err {
         ServerError::Io(err) => err,
         ServerError::Archive(err) => io::Error::other(err),
+        ServerError::AppendOnly(err) => io::Error::other(err),
     }
 }
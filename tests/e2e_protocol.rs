@@ -1,6 +1,7 @@
 mod common;
 
-use common::setup_test_server::setup_test_server;
+use bytes::Bytes;
+use common::setup_test_server::{setup_test_server, setup_test_server_with_config, test_config};
 use common::test_client::TestClient;
 use redlike::frame::Frame;
 use tokio::io::ErrorKind;
@@ -62,10 +63,10 @@ async fn e2e_unknown_command_does_not_close_connection() -> tokio::io::Result<()
     let (addr, handle, _shutdown) = setup_test_server(ADDR).await?;
     let mut client = TestClient::new(addr).await?;
 
-    client.write(b"*1\r\n$3\r\nFOO\r\n").await?;
+    client.write(b"*2\r\n$3\r\nFOO\r\n$3\r\nbar\r\n").await?;
     assert_eq!(
         client.read_frame().await?,
-        Frame::SimpleError("Unknown Command".into())
+        Frame::SimpleError("ERR unknown command 'FOO', with args beginning with: 'bar' ".into())
     );
 
     client.write(b"*1\r\n$4\r\nPING\r\n").await?;
@@ -87,7 +88,7 @@ async fn e2e_wrong_arity_does_not_close_connection() -> tokio::io::Result<()> {
     client.write(b"*1\r\n$3\r\nGET\r\n").await?;
     assert_eq!(
         client.read_frame().await?,
-        Frame::SimpleError("Wrong number of arguments".into())
+        Frame::SimpleError("ERR wrong number of arguments for 'get' command".into())
     );
 
     client.write(b"*1\r\n$4\r\nPING\r\n").await?;
@@ -143,7 +144,7 @@ async fn e2e_inline_and_resp_can_be_mixed() -> tokio::io::Result<()> {
     client.write(b"GET mixed\n").await?;
     assert_eq!(
         client.read_frame().await?,
-        Frame::Bulk(Some(b"value".to_vec()))
+        Frame::Bulk(Some(Bytes::from_static(b"value")))
     );
 
     client.send_quit().await?;
@@ -165,10 +166,169 @@ async fn e2e_split_frame_writes_are_reassembled() -> tokio::io::Result<()> {
     client.write(b"$5\r\nsplit\r\n").await?;
     assert_eq!(
         client.read_frame().await?,
-        Frame::Bulk(Some(b"value".to_vec()))
+        Frame::Bulk(Some(Bytes::from_static(b"value")))
     );
 
     client.send_quit().await?;
     handle.abort();
     Ok(())
 }
+
+#[tokio::test]
+async fn e2e_hello_negotiates_resp3_replies() -> tokio::io::Result<()> {
+    let (addr, handle, _shutdown) = setup_test_server(ADDR).await?;
+    let mut client = TestClient::new(addr).await?;
+
+    client.write(b"HSET user name ada\r\n").await?;
+    assert_eq!(client.read_frame().await?, Frame::Integer(1));
+    client.write(b"HGETALL user\r\n").await?;
+    assert_eq!(
+        client.read_frame().await?,
+        Frame::Array(Some(vec![
+            Frame::Bulk(Some(Bytes::from_static(b"name"))),
+            Frame::Bulk(Some(Bytes::from_static(b"ada"))),
+        ]))
+    );
+
+    client.write(b"HELLO 4\r\n").await?;
+    assert_eq!(
+        client.read_frame().await?,
+        Frame::SimpleError("NOPROTO unsupported protocol version".into())
+    );
+
+    client.write(b"HELLO 3\r\n").await?;
+    let Frame::Map(fields) = client.read_frame().await? else {
+        panic!("expected HELLO 3 to reply with a map");
+    };
+    assert!(fields.contains(&(
+        Frame::Bulk(Some(Bytes::from_static(b"proto"))),
+        Frame::Integer(3)
+    )));
+
+    client.write(b"HGETALL user\r\n").await?;
+    assert_eq!(
+        client.read_frame().await?,
+        Frame::Map(vec![(
+            Frame::Bulk(Some(Bytes::from_static(b"name"))),
+            Frame::Bulk(Some(Bytes::from_static(b"ada"))),
+        )])
+    );
+    client.write(b"GET missing\r\n").await?;
+    assert_eq!(client.read_frame().await?, Frame::Null);
+
+    client.send_quit().await?;
+    handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn e2e_inline_quoting_and_unbalanced_quotes() -> tokio::io::Result<()> {
+    let (addr, handle, _shutdown) = setup_test_server(ADDR).await?;
+    let mut client = TestClient::new(addr).await?;
+
+    client
+        .write(b"SET greeting \"hello world\\x21\"\r\n")
+        .await?;
+    assert_eq!(client.read_frame().await?, Frame::SimpleString("OK".into()));
+    client.write(b"GET greeting\r\n").await?;
+    assert_eq!(
+        client.read_frame().await?,
+        Frame::Bulk(Some(Bytes::from_static(b"hello world!")))
+    );
+
+    client
+        .write(b"SET broken \"oops\r\nSET raw \xff\xfe\r\n")
+        .await?;
+    assert_eq!(
+        client.read_frame().await?,
+        Frame::SimpleError("ERR Protocol error: unbalanced quotes in request".into())
+    );
+    assert_eq!(client.read_frame().await?, Frame::SimpleString("OK".into()));
+    client.write(b"GET raw\r\n").await?;
+    assert_eq!(
+        client.read_frame().await?,
+        Frame::Bulk(Some(Bytes::from_static(b"\xff\xfe")))
+    );
+
+    client.send_quit().await?;
+    handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn e2e_requests_over_the_limits_get_an_error_before_close() -> tokio::io::Result<()> {
+    let mut config = test_config(ADDR)?;
+    config.proto_max_bulk_len = 8;
+    config.max_multibulk_len = 4;
+    config.max_inline_len = 32;
+    let (addr, handle, _shutdown) = setup_test_server_with_config(config).await?;
+
+    for (request, error) in [
+        (
+            &b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$9\r\n"[..],
+            "ERR Protocol error: invalid bulk length",
+        ),
+        (
+            b"*2147483647\r\n",
+            "ERR Protocol error: invalid multibulk length",
+        ),
+        (
+            b"SET key 0123456789012345678901234567890123456789\r\n",
+            "ERR Protocol error: too big inline request",
+        ),
+    ] {
+        let mut client = TestClient::new(addr).await?;
+        client.write(b"PING\r\n").await?;
+        assert_eq!(
+            client.read_frame().await?,
+            Frame::SimpleString("PONG".into())
+        );
+        client.write(request).await?;
+        assert_eq!(client.read_frame().await?, Frame::SimpleError(error.into()));
+        assert_connection_closed(&mut client).await;
+    }
+
+    handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn e2e_pipelined_commands_reply_in_order() -> tokio::io::Result<()> {
+    const COMMANDS: usize = 5_000;
+    let (addr, handle, _shutdown) = setup_test_server(ADDR).await?;
+    let mut client = TestClient::new(addr).await?;
+
+    let mut request = Vec::new();
+    for i in 0..COMMANDS {
+        let key = format!("key:{i}");
+        let value = format!("value:{i}");
+        request.extend_from_slice(
+            format!(
+                "*3\r\n$3\r\nSET\r\n${}\r\n{key}\r\n${}\r\n{value}\r\n",
+                key.len(),
+                value.len()
+            )
+            .as_bytes(),
+        );
+        request.extend_from_slice(
+            format!("*2\r\n$3\r\nGET\r\n${}\r\n{key}\r\n", key.len()).as_bytes(),
+        );
+    }
+    client.write(&request).await?;
+
+    let replies = timeout(Duration::from_secs(10), async {
+        for i in 0..COMMANDS {
+            assert_eq!(client.read_frame().await?, Frame::SimpleString("OK".into()));
+            assert_eq!(
+                client.read_frame().await?,
+                Frame::Bulk(Some(Bytes::from(format!("value:{i}"))))
+            );
+        }
+        tokio::io::Result::Ok(())
+    })
+    .await;
+    replies.expect("timed out reading pipelined replies")?;
+
+    handle.abort();
+    Ok(())
+}
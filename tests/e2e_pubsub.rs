@@ -0,0 +1,147 @@
+mod common;
+
+use bytes::Bytes;
+use common::setup_test_server::{setup_test_server, setup_test_server_with_config, test_config};
+use common::test_client::TestClient;
+use redlike::frame::Frame;
+use tokio::time::{Duration, timeout};
+
+const ADDR: &str = "127.0.0.1:0";
+
+fn bulk_array(values: &[&[u8]]) -> Frame {
+    Frame::Array(Some(
+        values
+            .iter()
+            .map(|v| Frame::Bulk(Some(Bytes::copy_from_slice(v))))
+            .collect(),
+    ))
+}
+
+fn subscription(kind: &[u8], name: &[u8], count: i64) -> Frame {
+    Frame::Array(Some(vec![
+        Frame::Bulk(Some(Bytes::copy_from_slice(kind))),
+        Frame::Bulk(Some(Bytes::copy_from_slice(name))),
+        Frame::Integer(count),
+    ]))
+}
+
+#[tokio::test]
+async fn e2e_publish_reaches_channel_and_pattern_subscribers() -> tokio::io::Result<()> {
+    let (addr, handle, _shutdown) = setup_test_server(ADDR).await?;
+    let mut subscriber = TestClient::new(addr).await?;
+    let mut publisher = TestClient::new(addr).await?;
+
+    subscriber.write(b"SUBSCRIBE invalidate\n").await?;
+    assert_eq!(
+        subscriber.read_frame().await?,
+        subscription(b"subscribe", b"invalidate", 1)
+    );
+    subscriber.write(b"PSUBSCRIBE cache:*\n").await?;
+    assert_eq!(
+        subscriber.read_frame().await?,
+        subscription(b"psubscribe", b"cache:*", 2)
+    );
+
+    publisher.write(b"PUBLISH invalidate users\n").await?;
+    assert_eq!(publisher.read_frame().await?, Frame::Integer(1));
+    assert_eq!(
+        subscriber.read_frame().await?,
+        bulk_array(&[b"message", b"invalidate", b"users"])
+    );
+
+    publisher.write(b"PUBLISH cache:users 42\n").await?;
+    assert_eq!(publisher.read_frame().await?, Frame::Integer(1));
+    assert_eq!(
+        subscriber.read_frame().await?,
+        bulk_array(&[b"pmessage", b"cache:*", b"cache:users", b"42"])
+    );
+
+    subscriber.write(b"GET key\n").await?;
+    assert!(matches!(
+        subscriber.read_frame().await?,
+        Frame::SimpleError(_)
+    ));
+    subscriber.write(b"PING\n").await?;
+    assert_eq!(subscriber.read_frame().await?, bulk_array(&[b"pong", b""]));
+
+    subscriber.write(b"UNSUBSCRIBE\n").await?;
+    assert_eq!(
+        subscriber.read_frame().await?,
+        subscription(b"unsubscribe", b"invalidate", 1)
+    );
+    subscriber.write(b"PUNSUBSCRIBE\n").await?;
+    assert_eq!(
+        subscriber.read_frame().await?,
+        subscription(b"punsubscribe", b"cache:*", 0)
+    );
+
+    publisher.write(b"PUBLISH invalidate users\n").await?;
+    assert_eq!(publisher.read_frame().await?, Frame::Integer(0));
+    subscriber.write(b"PING\n").await?;
+    assert_eq!(
+        subscriber.read_frame().await?,
+        Frame::SimpleString("PONG".into())
+    );
+
+    subscriber.send_quit().await?;
+    publisher.send_quit().await?;
+    handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn e2e_keyspace_notifications_report_deletes_and_expiry() -> tokio::io::Result<()> {
+    let mut config = test_config(ADDR)?;
+    config.notify_keyspace_events = "KEgx".parse().unwrap();
+    let (addr, handle, _shutdown) = setup_test_server_with_config(config).await?;
+    let mut subscriber = TestClient::new(addr).await?;
+    let mut client = TestClient::new(addr).await?;
+
+    subscriber
+        .write(b"SUBSCRIBE __keyevent@0__:expired\n")
+        .await?;
+    subscriber.read_frame().await?;
+    subscriber.write(b"PSUBSCRIBE __keyspace@0__:*\n").await?;
+    subscriber.read_frame().await?;
+
+    client.write(b"SET session:1 token PX 200\n").await?;
+    client.read_frame().await?;
+    client.write(b"SET doomed value\n").await?;
+    client.read_frame().await?;
+    client.write(b"DEL doomed\n").await?;
+    assert_eq!(client.read_frame().await?, Frame::Integer(1));
+
+    assert_eq!(
+        subscriber.read_frame().await?,
+        bulk_array(&[
+            b"pmessage",
+            b"__keyspace@0__:*",
+            b"__keyspace@0__:doomed",
+            b"del"
+        ]),
+        "string writes are not enabled, so DEL should be the first event"
+    );
+    let expiry = timeout(Duration::from_secs(2), async {
+        let first = subscriber.read_frame().await?;
+        let second = subscriber.read_frame().await?;
+        tokio::io::Result::Ok([first, second])
+    })
+    .await
+    .expect("timed out waiting for expiry notifications")?;
+    assert!(expiry.contains(&bulk_array(&[
+        b"pmessage",
+        b"__keyspace@0__:*",
+        b"__keyspace@0__:session:1",
+        b"expired"
+    ])));
+    assert!(expiry.contains(&bulk_array(&[
+        b"message",
+        b"__keyevent@0__:expired",
+        b"session:1"
+    ])));
+
+    subscriber.send_quit().await?;
+    client.send_quit().await?;
+    handle.abort();
+    Ok(())
+}
@@ -1,8 +1,13 @@
 mod common;
 
-use common::setup_test_server::{setup_test_server, setup_test_server_with_archive};
+use bytes::Bytes;
+use common::setup_test_server::{
+    setup_test_server, setup_test_server_with_archive, setup_test_server_with_config, test_config,
+};
 use common::test_client::TestClient;
+use redlike::aof::AppendFsync;
 use redlike::archive::load;
+use redlike::config::Config;
 use redlike::frame::Frame;
 use tempfile::tempdir;
 use tokio::io::ErrorKind;
@@ -13,6 +18,10 @@ const ADDR: &str = "127.0.0.1:0";
 const CLIENT_TIMEOUT: Duration = Duration::from_millis(250);
 const SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(250);
 
+fn bulk(value: &'static [u8]) -> Frame {
+    Frame::Bulk(Some(Bytes::from_static(value)))
+}
+
 async fn assert_connection_closed(client: &mut TestClient) {
     let err = timeout(CLIENT_TIMEOUT, client.read_frame())
         .await
@@ -108,9 +117,246 @@ async fn shutdown_persists_store_to_archive() -> tokio::io::Result<()> {
     assert_server_shutdown(handle).await?;
 
     let store = load(archive_path).await.map_err(tokio::io::Error::other)?;
+    assert_eq!(store.get(b"persist").await, Ok(Some(b"value".to_vec())));
+    Ok(())
+}
+
+#[tokio::test]
+async fn append_only_file_restores_writes_without_a_clean_shutdown() -> tokio::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let config = || -> tokio::io::Result<Config> {
+        let mut config = test_config(ADDR)?;
+        config.aof_path = Some(temp_dir.path().join("redlike.aof"));
+        config.appendfsync = AppendFsync::Always;
+        Ok(config)
+    };
+
+    let (addr, handle, _shutdown) = setup_test_server_with_config(config()?).await?;
+    let mut client = TestClient::new(addr).await?;
+    for (call, response) in [
+        (
+            &b"*3\r\n$3\r\nSET\r\n$7\r\npersist\r\n$5\r\nvalue\r\n"[..],
+            Frame::SimpleString("OK".into()),
+        ),
+        (
+            b"*4\r\n$5\r\nRPUSH\r\n$4\r\nlist\r\n$1\r\na\r\n$1\r\nb\r\n",
+            Frame::Integer(2),
+        ),
+        (b"*2\r\n$4\r\nLPOP\r\n$4\r\nlist\r\n", bulk(b"a")),
+    ] {
+        client.write(call).await?;
+        assert_eq!(client.read_frame().await?, response);
+    }
+    // Abandon the server without letting it shut down.
+    handle.abort();
+
+    let (addr, handle, shutdown) = setup_test_server_with_config(config()?).await?;
+    let mut client = TestClient::new(addr).await?;
+    client
+        .write(b"*2\r\n$3\r\nGET\r\n$7\r\npersist\r\n")
+        .await?;
+    assert_eq!(client.read_frame().await?, bulk(b"value"));
+    client
+        .write(b"*4\r\n$6\r\nLRANGE\r\n$4\r\nlist\r\n$1\r\n0\r\n$2\r\n-1\r\n")
+        .await?;
     assert_eq!(
-        store.get(&b"persist".to_vec()).await,
-        Some(b"value".to_vec())
+        client.read_frame().await?,
+        Frame::Array(Some(vec![bulk(b"b")]))
     );
+
+    shutdown.cancel();
+    assert_server_shutdown(handle).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn new_append_only_file_starts_from_the_archive() -> tokio::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let archive_path = temp_dir.path().join("redlike.rdb");
+    let aof_path = temp_dir.path().join("redlike.aof");
+    let (addr, handle, shutdown) =
+        setup_test_server_with_archive(ADDR, Some(archive_path.clone())).await?;
+    let mut client = TestClient::new(addr).await?;
+    client
+        .write(b"*3\r\n$3\r\nSET\r\n$7\r\npersist\r\n$5\r\nvalue\r\n")
+        .await?;
+    assert_eq!(client.read_frame().await?, Frame::SimpleString("OK".into()));
+    shutdown.cancel();
+    assert_server_shutdown(handle).await?;
+
+    let mut config = test_config(ADDR)?;
+    config.archive_path = Some(archive_path.clone());
+    config.aof_path = Some(aof_path.clone());
+    let (addr, handle, shutdown) = setup_test_server_with_config(config).await?;
+    let mut client = TestClient::new(addr).await?;
+    client
+        .write(b"*2\r\n$3\r\nGET\r\n$7\r\npersist\r\n")
+        .await?;
+    assert_eq!(client.read_frame().await?, bulk(b"value"));
+    shutdown.cancel();
+    assert_server_shutdown(handle).await?;
+
+    let store = load(archive_path).await.map_err(tokio::io::Error::other)?;
+    assert_eq!(store.get(b"persist").await, Ok(Some(b"value".to_vec())));
+    let mut config = test_config(ADDR)?;
+    config.aof_path = Some(aof_path);
+    let (addr, handle, shutdown) = setup_test_server_with_config(config).await?;
+    let mut client = TestClient::new(addr).await?;
+    client
+        .write(b"*2\r\n$3\r\nGET\r\n$7\r\npersist\r\n")
+        .await?;
+    assert_eq!(client.read_frame().await?, bulk(b"value"));
+    shutdown.cancel();
+    assert_server_shutdown(handle).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn save_rules_persist_store_while_running() -> tokio::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let archive_path = temp_dir.path().join("redlike.rdb");
+    let mut config = test_config(ADDR)?;
+    config.archive_path = Some(archive_path.clone());
+    config.save = "0 1".parse().map_err(tokio::io::Error::other)?;
+    let (addr, handle, shutdown) = setup_test_server_with_config(config).await?;
+    let mut client = TestClient::new(addr).await?;
+
+    client
+        .write(b"*3\r\n$3\r\nSET\r\n$7\r\npersist\r\n$5\r\nvalue\r\n")
+        .await?;
+    assert_eq!(client.read_frame().await?, Frame::SimpleString("OK".into()));
+
+    timeout(Duration::from_secs(3), async {
+        while !archive_path.exists() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("archive was not saved while running");
+    let store = load(archive_path).await.map_err(tokio::io::Error::other)?;
+    assert_eq!(store.get(b"persist").await, Ok(Some(b"value".to_vec())));
+
+    shutdown.cancel();
+    assert_server_shutdown(handle).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_command_stops_server_and_saves() -> tokio::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let archive_path = temp_dir.path().join("redlike.rdb");
+    let (addr, handle, _shutdown) =
+        setup_test_server_with_archive(ADDR, Some(archive_path.clone())).await?;
+    let mut client = TestClient::new(addr).await?;
+    let mut idle_client = TestClient::new(addr).await?;
+
+    client
+        .write(b"*3\r\n$3\r\nSET\r\n$7\r\npersist\r\n$5\r\nvalue\r\n")
+        .await?;
+    assert_eq!(client.read_frame().await?, Frame::SimpleString("OK".into()));
+    client.write(b"*1\r\n$8\r\nSHUTDOWN\r\n").await?;
+
+    assert_connection_closed(&mut client).await;
+    assert_connection_closed(&mut idle_client).await;
+    assert_server_shutdown(handle).await?;
+    let store = load(archive_path).await.map_err(tokio::io::Error::other)?;
+    assert_eq!(store.get(b"persist").await, Ok(Some(b"value".to_vec())));
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_nosave_skips_the_final_save() -> tokio::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let archive_path = temp_dir.path().join("redlike.rdb");
+    let (addr, handle, _shutdown) =
+        setup_test_server_with_archive(ADDR, Some(archive_path.clone())).await?;
+    let mut client = TestClient::new(addr).await?;
+
+    client
+        .write(b"*3\r\n$3\r\nSET\r\n$7\r\npersist\r\n$5\r\nvalue\r\n")
+        .await?;
+    assert_eq!(client.read_frame().await?, Frame::SimpleString("OK".into()));
+    client
+        .write(b"*2\r\n$8\r\nSHUTDOWN\r\n$6\r\nNOSAVE\r\n")
+        .await?;
+
+    assert_connection_closed(&mut client).await;
+    assert_server_shutdown(handle).await?;
+    assert!(!archive_path.exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn bgsave_writes_archive_and_updates_lastsave() -> tokio::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let archive_path = temp_dir.path().join("redlike.rdb");
+    let (addr, handle, shutdown) =
+        setup_test_server_with_archive(ADDR, Some(archive_path.clone())).await?;
+    let mut client = TestClient::new(addr).await?;
+
+    client.write(b"*1\r\n$8\r\nLASTSAVE\r\n").await?;
+    let Frame::Integer(started) = client.read_frame().await? else {
+        panic!("expected LASTSAVE to reply with an integer");
+    };
+    client
+        .write(b"*3\r\n$3\r\nSET\r\n$7\r\npersist\r\n$5\r\nvalue\r\n")
+        .await?;
+    assert_eq!(client.read_frame().await?, Frame::SimpleString("OK".into()));
+    client.write(b"*1\r\n$6\r\nBGSAVE\r\n").await?;
+    assert_eq!(
+        client.read_frame().await?,
+        Frame::SimpleString("Background saving started".into())
+    );
+
+    timeout(Duration::from_secs(3), async {
+        while !archive_path.exists() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("archive was not saved in the background");
+    client.write(b"*1\r\n$8\r\nLASTSAVE\r\n").await?;
+    let Frame::Integer(saved) = client.read_frame().await? else {
+        panic!("expected LASTSAVE to reply with an integer");
+    };
+    assert!(saved >= started);
+
+    shutdown.cancel();
+    assert_server_shutdown(handle).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_command_exits_the_server_binary() -> tokio::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let archive_path = temp_dir.path().join("redlike.rdb");
+    let port = std::net::TcpListener::bind(ADDR)?.local_addr()?.port();
+    let mut server = tokio::process::Command::new(env!("CARGO_BIN_EXE_redlike"))
+        .args([
+            "--address",
+            "127.0.0.1",
+            "--port",
+            &port.to_string(),
+            "--save",
+            "",
+        ])
+        .arg("--archive-path")
+        .arg(&archive_path)
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut client = TestClient::new(([127, 0, 0, 1], port).into()).await?;
+
+    client
+        .write(b"*3\r\n$3\r\nSET\r\n$7\r\npersist\r\n$5\r\nvalue\r\n")
+        .await?;
+    assert_eq!(client.read_frame().await?, Frame::SimpleString("OK".into()));
+    client.write(b"*1\r\n$8\r\nSHUTDOWN\r\n").await?;
+
+    let status = timeout(Duration::from_secs(5), server.wait())
+        .await
+        .expect("server binary did not exit after SHUTDOWN")?;
+    assert!(status.success());
+    let store = load(archive_path).await.map_err(tokio::io::Error::other)?;
+    assert_eq!(store.get(b"persist").await, Ok(Some(b"value".to_vec())));
     Ok(())
 }
@@ -10,13 +10,40 @@ fn arb_frame() -> impl Strategy<Value = Frame> {
         prop::string::string_regex("[^\r\n]{0,20}")
             .unwrap()
             .prop_map(Frame::SimpleError),
-        prop::collection::vec(any::<u8>(), 0..20).prop_map(|v| Frame::Bulk(Some(v))),
+        prop::collection::vec(any::<u8>(), 0..20).prop_map(|v| Frame::Bulk(Some(v.into()))),
         Just(Frame::Bulk(None)),
         any::<i64>().prop_map(Frame::Integer),
         Just(Frame::Array(None)),
+        Just(Frame::Null),
+        any::<bool>().prop_map(Frame::Boolean),
+        prop_oneof![
+            any::<f64>().prop_filter("NaN never equals itself", |d| !d.is_nan()),
+            Just(f64::INFINITY),
+            Just(f64::NEG_INFINITY),
+        ]
+        .prop_map(Frame::Double),
+        prop::string::string_regex("-?[0-9]{1,40}")
+            .unwrap()
+            .prop_map(Frame::BigNumber),
+        (
+            prop::string::string_regex("[a-z]{3}").unwrap(),
+            prop::collection::vec(any::<u8>(), 0..20)
+        )
+            .prop_map(|(format, text)| Frame::Verbatim {
+                format,
+                text: text.into()
+            }),
+        prop::collection::vec(any::<u8>(), 0..20).prop_map(|v| Frame::BlobError(v.into())),
     ];
     leaf.prop_recursive(8, 256, 10, |inner| {
-        prop_oneof![prop::collection::vec(inner.clone(), 0..10).prop_map(|a| Frame::Array(Some(a)))]
+        let pairs = prop::collection::vec((inner.clone(), inner.clone()), 0..5);
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..10).prop_map(|a| Frame::Array(Some(a))),
+            prop::collection::vec(inner.clone(), 0..10).prop_map(Frame::Set),
+            prop::collection::vec(inner.clone(), 0..10).prop_map(Frame::Push),
+            pairs.clone().prop_map(Frame::Map),
+            pairs.prop_map(Frame::Attribute),
+        ]
     })
 }
 